@@ -0,0 +1,16 @@
+use std::process::Command;
+
+fn main() {
+    // Embed the git commit hash, if building from a git checkout
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    println!("cargo:rerun-if-changed=../.git/refs");
+    let output = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output();
+    if let Ok(output) = output
+        && output.status.success()
+    {
+        let hash = String::from_utf8_lossy(&output.stdout);
+        println!("cargo:rustc-env=HAGIAS_GIT_HASH={}", hash.trim());
+    }
+}
@@ -0,0 +1,113 @@
+use rocket::{
+    Request,
+    http::Status,
+    request::{self, FromRequest},
+};
+
+use crate::config::Config;
+
+/// A request guard for the routes that change the monitors or the stored layouts. If `api_token`
+/// is set in the config, requests must give it as an `Authorization: Bearer <token>` header, and are
+/// rejected with `401 Unauthorized` otherwise. A query parameter isn't accepted, as the URI would
+/// put the token in the request logs.
+#[derive(Debug, Clone, Copy)]
+pub struct ApiAuth;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ApiAuth {
+    type Error = &'static str;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let Some(config) = request.rocket().state::<Config>() else {
+            return request::Outcome::Error((
+                Status::InternalServerError,
+                "The config isn't managed by rocket",
+            ));
+        };
+        let Some(expected) = config.api_token.as_deref().filter(|t| !t.is_empty()) else {
+            return request::Outcome::Success(ApiAuth);
+        };
+        let bearer = request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|header| header.strip_prefix("Bearer "))
+            .map(str::trim);
+        if bearer.is_some_and(|token| tokens_eq(token, expected)) {
+            request::Outcome::Success(ApiAuth)
+        } else {
+            request::Outcome::Error((Status::Unauthorized, "Missing or wrong API token"))
+        }
+    }
+}
+
+/// Compare tokens in constant time, so the token can't be guessed from how long responses take
+fn tokens_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::{
+        figment::{
+            Figment,
+            providers::{Format, Toml},
+        },
+        http::{Header, Status},
+        local::blocking::Client,
+        post,
+    };
+
+    use super::ApiAuth;
+    use crate::config::Config;
+
+    #[post("/api/guarded")]
+    fn guarded(_auth: ApiAuth) -> &'static str {
+        "ok"
+    }
+
+    fn client_with_token(api_token: Option<&str>) -> Client {
+        let mut toml = "layouts_path = 'layouts.json'\nstatic_dir = 'static'\n\
+                        template_dir = 'templates'\nport = 5781\n"
+            .to_string();
+        if let Some(api_token) = api_token {
+            toml += &format!("api_token = '{}'\n", api_token);
+        }
+        let config: Config = Figment::from(Toml::string(&toml)).extract().unwrap();
+        let rocket = rocket::custom(rocket::Config::debug_default())
+            .mount("/", rocket::routes![guarded])
+            .manage(config);
+        Client::tracked(rocket).unwrap()
+    }
+
+    #[test]
+    fn test_api_auth() {
+        // Without a token configured, the API is open
+        let client = client_with_token(None);
+        assert_eq!(client.post("/api/guarded").dispatch().status(), Status::Ok);
+
+        let client = client_with_token(Some("secret"));
+        assert_eq!(
+            client.post("/api/guarded").dispatch().status(),
+            Status::Unauthorized
+        );
+        let status = |authorization: &'static str| {
+            client
+                .post("/api/guarded")
+                .header(Header::new("Authorization", authorization))
+                .dispatch()
+                .status()
+        };
+        assert_eq!(status("Bearer secret"), Status::Ok);
+        assert_eq!(status("Bearer wrong"), Status::Unauthorized);
+        assert_eq!(status("secret"), Status::Unauthorized);
+        // The token in the URI would be logged
+        assert_eq!(
+            client.post("/api/guarded?token=secret").dispatch().status(),
+            Status::Unauthorized
+        );
+    }
+}
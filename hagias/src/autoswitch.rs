@@ -0,0 +1,113 @@
+use std::{collections::BTreeSet, ffi::OsString, time::Duration};
+
+use anyhow::Result;
+use rocket::{
+    Orbit, Rocket,
+    fairing::{Fairing, Info, Kind},
+};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    config::Config,
+    display::connected_device_paths,
+    history::ApplySource,
+    in_flight::InFlightApplies,
+    layouts::{Layouts, NamedLayout},
+    poller::{self, Poll},
+};
+
+/// How often the connected monitors are checked for changes
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Applies the stored layout matching the connected monitors whenever monitors are plugged in or
+/// unplugged, while rocket is running
+pub struct AutoSwitch;
+
+#[rocket::async_trait]
+impl Fairing for AutoSwitch {
+    fn info(&self) -> Info {
+        Info {
+            name: "Hotplug Auto-Switcher",
+            kind: Kind::Liftoff,
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        let Some((config, in_flight)) = poller::managed_state(rocket, "Auto-switcher") else {
+            return;
+        };
+        info!("Automatically applying layouts when monitors are plugged in or unplugged");
+        let switcher = Switcher {
+            config,
+            in_flight,
+            previous: None,
+        };
+        poller::spawn(rocket, "Auto-switcher", POLL_INTERVAL, switcher);
+    }
+}
+
+/// Polls the connected monitors, applying the matching layout each time they change
+struct Switcher {
+    config: Config,
+    in_flight: InFlightApplies,
+    /// The normalized device paths of the monitors connected at the last poll
+    previous: Option<BTreeSet<OsString>>,
+}
+
+#[rocket::async_trait]
+impl Poll for Switcher {
+    async fn poll(&mut self) {
+        let connected = match connected_device_paths() {
+            Ok(connected) => connected,
+            Err(e) => {
+                warn!("Failed to get connected monitors: {:?}", e);
+                return;
+            }
+        };
+        if self
+            .previous
+            .as_ref()
+            .is_some_and(|previous| *previous != connected)
+        {
+            debug!("Connected monitors changed to {:?}", connected);
+            if let Err(e) = self.switch(&connected).await {
+                error!("Failed to switch layout: {:?}", e);
+            }
+        }
+        self.previous = Some(connected);
+    }
+}
+
+impl Switcher {
+    /// Apply the first visible layout whose monitors are exactly the connected ones, if any
+    async fn switch(&self, connected: &BTreeSet<OsString>) -> Result<()> {
+        let layouts = Layouts::load(&self.config.layouts_path.relative()).await?;
+        let Some(layout) = find_matching_layout(&layouts, connected) else {
+            info!("No layout matches the connected monitors");
+            return Ok(());
+        };
+        info!(
+            "Monitors changed, applying layout {} \"{}\"",
+            layout.id, layout.name
+        );
+        poller::apply(
+            &self.config,
+            &self.in_flight,
+            layout,
+            ApplySource::AutoSwitch,
+        )
+        .await
+    }
+}
+
+fn find_matching_layout<'a>(
+    layouts: &'a Layouts,
+    connected: &BTreeSet<OsString>,
+) -> Option<&'a NamedLayout> {
+    layouts.visible().find(|layout| {
+        layout
+            .layout
+            .monitor_keys()
+            .is_some_and(|monitors| monitors == *connected)
+    })
+}
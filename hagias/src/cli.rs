@@ -1,13 +1,19 @@
 use anyhow::{Context as _, Result};
 use tracing::{error, info};
 
-use crate::config::Config;
+use crate::{config::Config, exit_code};
 
 #[cfg(feature = "cec")]
 pub mod cec;
+pub mod config;
+pub mod dump;
+#[cfg(feature = "enum-displays")]
+pub mod enum_displays;
 pub mod layout;
+pub mod monitors;
 pub mod service;
 
+pub mod keep_prompt;
 pub mod rearranger;
 
 #[derive(Debug, Clone, clap::Subcommand)]
@@ -15,116 +21,77 @@ pub enum Command {
     /// Edit layout configuration
     #[command(subcommand)]
     Layout(layout::Command),
-    /// Run as a service
+    /// Inspect connected monitors
+    #[command(subcommand)]
+    Monitors(monitors::Command),
+    /// Inspect the configuration
     #[command(subcommand)]
-    Service(service::Command),
+    Config(config::Command),
+    /// Dump the raw display configuration, for debugging
+    Dump(dump::Args),
+    /// Run as a service
+    Service(service::Args),
     /// Send a CEC command to a device
     #[cfg(feature = "cec")]
     #[command(subcommand)]
     Cec(cec::Command),
-    /// Enumerate displays
+    /// Enumerate physical monitors over DDC/CI
     #[cfg(feature = "enum-displays")]
-    EnumDisplays,
+    EnumDisplays(enum_displays::Args),
 }
 impl Command {
+    /// Whether the command writes machine-readable output to stdout, so logging to the console
+    /// should be kept to warnings and errors
+    pub fn quiet(&self) -> bool {
+        match self {
+            Command::Layout(layout_command) => layout_command.quiet(),
+            _ => false,
+        }
+    }
+
+    /// Whether the command honours `--dry-run`, either by only reporting what it would change or
+    /// by not changing anything in the first place
+    pub fn supports_dry_run(&self) -> bool {
+        match self {
+            Command::Layout(layout_command) => layout_command.supports_dry_run(),
+            Command::Monitors(_) | Command::Dump(_) => true,
+            Command::Config(config_command) => config_command.supports_dry_run(),
+            Command::Service(_) => false,
+            #[cfg(feature = "cec")]
+            Command::Cec(_) => false,
+            #[cfg(feature = "enum-displays")]
+            Command::EnumDisplays(_) => true,
+        }
+    }
+
+    /// Run the command if it doesn't need the config to be loaded. Returns `None` if it does.
+    pub async fn run_without_config(&self) -> Result<Option<i32>> {
+        match self {
+            Command::Config(config_command) => config_command.run_without_config().await,
+            _ => Ok(None),
+        }
+    }
+
     pub async fn run(&self, config: &Config) -> Result<Option<i32>> {
         let command_debug = format!("{:?}", self);
         info!("Running command: {}", command_debug);
         let result = match self {
             Command::Layout(layout_command) => layout_command.run(config).await,
-            Command::Service(service_command) => service_command.run(config).await,
+            Command::Monitors(monitors_command) => monitors_command.run(config).await,
+            Command::Config(config_command) => config_command.run(config).await,
+            Command::Dump(dump_args) => dump_args.run(config).await,
+            Command::Service(service_args) => service_args.run(config).await,
             #[cfg(feature = "cec")]
             Command::Cec(cec_command) => cec_command.run(config).await,
             #[cfg(feature = "enum-displays")]
-            Command::EnumDisplays => enum_displays::run(config).await,
+            Command::EnumDisplays(enum_displays_args) => enum_displays_args.run(config).await,
         };
         if let Err(ref e) = result {
-            error!("Command failed: {}", e);
-        }
-        result.with_context(|| format!("Command failed: {}", command_debug))
-    }
-}
-
-#[cfg(feature = "enum-displays")]
-mod enum_displays {
-    use anyhow::Result;
-    use windows::{
-        core::BOOL, Win32::{
-            Devices::Display::{
-                DestroyPhysicalMonitors, GetNumberOfPhysicalMonitorsFromHMONITOR, PHYSICAL_MONITOR,
-            },
-            Foundation::{ERROR_SUCCESS, LPARAM, RECT},
-            Graphics::Gdi::{EnumDisplayMonitors, HDC, HMONITOR},
-        }
-    };
-
-    use crate::config::Config;
-
-    struct PhysicalMonitors(Vec<PHYSICAL_MONITOR>);
-
-    impl Drop for PhysicalMonitors {
-        fn drop(&mut self) {
-            unsafe {
-                DestroyPhysicalMonitors(&self.0[..]);
-                self.0.set_len(0);
-            }
-        }
-    }
-
-    async fn run(_config: &Config) -> Result<Option<i32>> {
-        let physical_monitors = get_physical_monitors()?;
-        println!("Physical monitors detected {}", physical_monitors.len());
-        for physical_monitor in physical_monitors {
-            println!("Physical monitor: {:?}", physical_monitor);
-        }
-        Ok(Some(0))
-    }
-
-    /// Get all physical monitors on the system.
-    ///
-    /// # Safety
-    ///
-    /// This function is unsafe because it uses the `EnumDisplayMonitors` function, which is a system call.
-    unsafe fn get_physical_monitors() -> Result<PhysicalMonitors> {
-        const _: () = assert!(
-            std::mem::size_of::<LPARAM>() == std::mem::size_of::<*mut PhysicalMonitors>(),
-            "LPARAM and *mut Vec<PHYSICAL_MONITOR> must be the same size"
-        );
-
-        unsafe extern "system" fn callback(
-            monitor: HMONITOR,
-            _hdc: HDC,
-            _rect: RECT,
-            lparam: LPARAM,
-        ) -> BOOL {
-            unsafe {
-                let new_physical_monitors = get_physical_monitors_from_hmonitor(monitor)?;
-                let physical_monitors =
-                    &mut *std::mem::transmute::<LPARAM, *mut PhysicalMonitors>(lparam);
-                physical_monitors.push(physical_monitor);
-                BOOL::from(true)
-            }
-        }
-
-        let mut physical_monitors = PhysicalMonitors(Vec::<PHYSICAL_MONITOR>::new());
-        {
-            unsafe {
-                EnumDisplayMonitors(None, None, Some(callback), 0);
+            error!("Command failed: {:#}", e);
+            if let Command::Service(_) = self {
+                return Ok(Some(exit_code::SERVICE_ERROR));
             }
         }
-        Ok(physical_monitors)
-    }
-
-    unsafe fn get_physical_monitors_from_hmonitor(
-        hmonitor: HMONITOR,
-    ) -> Result<Vec<PHYSICAL_MONITOR>> {
-        let mut num_physical_monitors = 0;
-        let result = GetNumberOfPhysicalMonitorsFromHMONITOR(hmonitor, &mut num_physical_monitors);
-        if result != ERROR_SUCCESS {
-            bail!(
-                "GetNumberOfPhysicalMonitorsFromHMONITOR error: {}",
-                windows_error_to_string(result)
-            );
-        }
+        result.with_context(|| format!("Command failed: {}", command_debug))
     }
 }
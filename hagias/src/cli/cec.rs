@@ -1,5 +1,8 @@
-use anyhow::{Context, Result};
-use cec_rs::CecConnectionCfgBuilder;
+use anyhow::{Context, Result, anyhow};
+use cec_rs::{
+    CecConnection, CecConnectionCfgBuilder, CecDeviceType, CecDeviceTypeVec, CecLogicalAddress,
+};
+use tracing::info;
 
 use crate::config::Config;
 
@@ -20,18 +23,54 @@ impl Command {
 #[derive(Debug, Clone, clap::Subcommand)]
 pub enum SendCommand {
     /// Power on a device
-    PowerOn,
+    PowerOn {
+        /// The logical address of the device, 0 (the TV) by default
+        #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=15))]
+        device: u8,
+    },
+    /// Put a device into standby
+    PowerOff {
+        /// The logical address of the device, 0 (the TV) by default
+        #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=15))]
+        device: u8,
+    },
 }
 
 impl SendCommand {
     pub async fn run(&self, _config: &Config) -> Result<Option<i32>> {
         match self {
-            SendCommand::PowerOn => {
-                CecConnectionCfgBuilder::default()
-                    .build()
-                    .context("failed to connect to CEC device")?;
+            SendCommand::PowerOn { device } => {
+                let address = logical_address(*device)?;
+                connect()?
+                    .send_power_on_devices(address)
+                    .map_err(|e| anyhow!("failed to power on {:?}: {:?}", address, e))?;
+                info!("Sent power on to {:?}", address);
+                Ok(Some(0))
+            }
+            SendCommand::PowerOff { device } => {
+                let address = logical_address(*device)?;
+                connect()?
+                    .send_standby_devices(address)
+                    .map_err(|e| anyhow!("failed to put {:?} into standby: {:?}", address, e))?;
+                info!("Sent standby to {:?}", address);
                 Ok(Some(0))
             }
         }
     }
 }
+
+/// Open a connection to the first CEC adapter found, as a playback device
+fn connect() -> Result<CecConnection> {
+    CecConnectionCfgBuilder::default()
+        .device_name("Hagias".to_string())
+        .device_types(CecDeviceTypeVec::new(CecDeviceType::PlaybackDevice))
+        .build()
+        .context("failed to configure CEC connection")?
+        .open()
+        .map_err(|e| anyhow!("failed to connect to CEC device: {:?}", e))
+}
+
+fn logical_address(device: u8) -> Result<CecLogicalAddress> {
+    CecLogicalAddress::from_repr(device.into())
+        .with_context(|| format!("invalid CEC logical address: {}", device))
+}
@@ -0,0 +1,83 @@
+use anyhow::Result;
+use tracing::{error, info};
+
+use crate::{config::Config, exit_code};
+
+#[derive(Debug, Clone, clap::Subcommand)]
+pub enum Command {
+    /// Show the effective config, and where each value came from
+    Show,
+    /// Write a default `Rocket.toml` next to the executable and create the directories it refers
+    /// to, for first-time setup
+    Init {
+        /// Overwrite an existing `Rocket.toml`
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+impl Command {
+    /// Whether the command honours `--dry-run`. `init` writes the config regardless, so it
+    /// rejects it instead
+    pub fn supports_dry_run(&self) -> bool {
+        matches!(self, Command::Show)
+    }
+
+    /// Run the command if it doesn't need the config to be loaded, e.g. because it creates it
+    pub async fn run_without_config(&self) -> Result<Option<i32>> {
+        match self {
+            Command::Init { force } => {
+                let Some(dir) = crate::config::rocket_toml_path()
+                    .as_deref()
+                    .and_then(|path| path.parent())
+                    .map(|dir| dir.to_path_buf())
+                else {
+                    error!("Failed to find the executable's directory");
+                    return Ok(Some(1));
+                };
+                let path = match crate::config::init(&dir, *force) {
+                    Ok(path) => path,
+                    Err(e) => {
+                        error!("{:#}", e);
+                        return Ok(Some(exit_code::CONFIG_ERROR));
+                    }
+                };
+                info!("Wrote default config to {}", path.display());
+                info!("Next steps:");
+                info!(
+                    "  1. Copy the templates and static files into {}",
+                    dir.display()
+                );
+                info!(
+                    "  2. Store your current monitor layout with `hagias layout store <id> <name>`"
+                );
+                info!(
+                    "  3. Run `hagias` to start the web interface, or `hagias service register` to install it as a service"
+                );
+                Ok(Some(0))
+            }
+            Command::Show => Ok(None),
+        }
+    }
+
+    pub async fn run(&self, _config: &Config) -> Result<Option<i32>> {
+        match self {
+            Command::Init { .. } => self.run_without_config().await,
+            Command::Show => {
+                let (figment, config) = crate::config::get()?;
+                info!("Profile: {}", figment.profile());
+                for (key, value) in config.entries() {
+                    let source = match figment.find_metadata(key) {
+                        Some(metadata) => match &metadata.source {
+                            Some(source) => format!("{} ({})", metadata.name, source),
+                            None => metadata.name.to_string(),
+                        },
+                        None => "default".to_string(),
+                    };
+                    info!("  {} = {} [{}]", key, value, source);
+                }
+                Ok(Some(0))
+            }
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+use anyhow::{Context, Result};
+use tracing::info;
+
+use crate::{
+    config::Config,
+    logging::log_directory,
+    windows_util::{DisplayQueryType, OsVersion, WindowsDisplayConfig},
+};
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct Args {
+    /// The file to write the dump to, or `-` for stdout. Defaults to a timestamped file in the
+    /// log directory
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Write the dump as JSON instead of text
+    #[arg(long)]
+    json: bool,
+}
+
+impl Args {
+    pub async fn run(&self, _config: &Config) -> Result<Option<i32>> {
+        let windows_display_config = WindowsDisplayConfig::get(DisplayQueryType::All)?;
+        let now = jiff::Zoned::now();
+
+        if self.output.as_deref() == Some("-".as_ref()) {
+            let mut stdout = std::io::stdout().lock();
+            self.write_dump(&mut stdout, &now, &windows_display_config)
+                .context("Failed to write dump to stdout")?;
+            return Ok(Some(0));
+        }
+
+        let output = match &self.output {
+            Some(output) => output.clone(),
+            None => {
+                let log_directory = log_directory();
+                std::fs::create_dir_all(&log_directory).with_context(|| {
+                    format!("Failed to create directory {}", log_directory.display())
+                })?;
+                let extension = if self.json { "json" } else { "txt" };
+                log_directory.join(format!(
+                    "dump_{}.{}",
+                    now.strftime("%Y%m%d_%H%M%S"),
+                    extension
+                ))
+            }
+        };
+        let mut writer = BufWriter::new(
+            File::create(&output)
+                .with_context(|| format!("Failed to create {}", output.display()))?,
+        );
+        self.write_dump(&mut writer, &now, &windows_display_config)
+            .and_then(|()| writer.flush())
+            .with_context(|| format!("Failed to write dump to {}", output.display()))?;
+        info!("Display configuration dumped to {}", output.display());
+        Ok(Some(0))
+    }
+
+    fn write_dump(
+        &self,
+        w: &mut impl Write,
+        now: &jiff::Zoned,
+        windows_display_config: &WindowsDisplayConfig,
+    ) -> std::io::Result<()> {
+        if self.json {
+            write_json_dump(w, now, windows_display_config)
+        } else {
+            write_dump(w, now, windows_display_config)
+        }
+    }
+}
+
+fn write_dump(
+    w: &mut impl Write,
+    now: &jiff::Zoned,
+    windows_display_config: &WindowsDisplayConfig,
+) -> std::io::Result<()> {
+    writeln!(w, "hagias {}", env!("CARGO_PKG_VERSION"))?;
+    writeln!(w, "Windows {}", OsVersion::get())?;
+    writeln!(w, "Dumped at {}", now)?;
+    writeln!(w)?;
+    windows_display_config.dump(w)
+}
+
+fn write_json_dump(
+    w: &mut impl Write,
+    now: &jiff::Zoned,
+    windows_display_config: &WindowsDisplayConfig,
+) -> std::io::Result<()> {
+    let dump = serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "windows_version": OsVersion::get().to_string(),
+        "dumped_at": now.to_string(),
+        "display_config": windows_display_config.to_debug_json(),
+    });
+    serde_json::to_writer_pretty(&mut *w, &dump)?;
+    writeln!(w)
+}
@@ -0,0 +1,26 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use tracing::info;
+
+use crate::{config::Config, ddc};
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct Args {
+    /// How long to wait for the enumeration, in seconds
+    #[arg(short, long, default_value_t = 10)]
+    timeout: u64,
+}
+
+impl Args {
+    pub async fn run(&self, _config: &Config) -> Result<Option<i32>> {
+        let descriptions =
+            ddc::get_physical_monitor_descriptions_with_timeout(Duration::from_secs(self.timeout))
+                .await?;
+        info!("Physical monitors detected: {}", descriptions.len());
+        for (i, description) in descriptions.iter().enumerate() {
+            info!("  {}. {}", i + 1, description);
+        }
+        Ok(Some(0))
+    }
+}
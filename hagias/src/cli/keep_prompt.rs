@@ -0,0 +1,64 @@
+use std::{io::Write, time::Duration};
+
+use anyhow::Result;
+use crossterm::{
+    cursor::MoveToColumn,
+    event::{Event, EventStream, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute,
+    style::Print,
+    terminal::{Clear, ClearType, disable_raw_mode, enable_raw_mode},
+};
+use futures::StreamExt;
+use tokio::time::Instant;
+
+/// Ask whether to keep the display settings that were just applied, counting down from
+/// `timeout`. Anything but `y`, including the countdown running out, means no.
+pub async fn keep_settings(timeout: Duration) -> Result<bool> {
+    let mut stdout = std::io::stdout();
+    enable_raw_mode()?;
+    let keep = countdown(&mut stdout, timeout).await;
+    disable_raw_mode()?;
+    execute!(stdout, Print("\n"))?;
+    keep
+}
+
+async fn countdown(stdout: &mut impl Write, timeout: Duration) -> Result<bool> {
+    let deadline = Instant::now() + timeout;
+    let mut reader = EventStream::new();
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Ok(false);
+        }
+        execute!(
+            stdout,
+            MoveToColumn(0),
+            Clear(ClearType::CurrentLine),
+            Print(format!(
+                "Keep these settings? (y/N) Reverting in {}s",
+                remaining.as_secs_f64().ceil()
+            ))
+        )?;
+        tokio::select! {
+            _ = tokio::time::sleep(remaining.min(Duration::from_secs(1))) => {}
+            event = reader.next() => match event {
+                Some(Ok(Event::Key(KeyEvent {
+                    code,
+                    modifiers,
+                    kind: KeyEventKind::Press,
+                    ..
+                }))) => match code {
+                    KeyCode::Char('y' | 'Y') => return Ok(true),
+                    KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => {
+                        return Ok(false);
+                    }
+                    KeyCode::Char('n' | 'N') | KeyCode::Enter | KeyCode::Esc => return Ok(false),
+                    _ => {}
+                },
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e.into()),
+                None => return Ok(false),
+            }
+        }
+    }
+}
@@ -1,13 +1,27 @@
-use anyhow::Result;
-use tracing::{error, info};
+use std::path::PathBuf;
 
-use crate::{config::Config, layouts::Layouts};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use tracing::{error, info, warn};
 
-use super::rearranger::Rearranger;
+use crate::{
+    config::Config,
+    confirm::CONFIRM_TIMEOUT,
+    display::{DisplayLayout, MissingMonitors, MonitorComparison},
+    exit_code,
+    history::{ApplySource, History},
+    last_applied::LastApplied,
+    layouts::{Layouts, LayoutsFile, NamedLayout, StoreKind},
+    windows_util::{DisplayQueryType, DisplayTopology, WindowsDisplayConfig},
+};
+
+use super::{keep_prompt::keep_settings, rearranger::Rearranger};
 
 #[derive(Debug, Clone, clap::Subcommand)]
 pub enum Command {
-    /// Store the current monitor configuration as the config named `name`
+    /// Store the current monitor configuration as the config named `name`.
+    ///
+    /// Only the monitors that are currently active are stored, unless `--all` is given.
     Store {
         /// The ID of the layout
         id: String,
@@ -16,6 +30,97 @@ pub enum Command {
         /// The emoji to display for the layout
         #[arg(short, long)]
         emoji: Option<String>,
+        /// Query all display paths, including inactive and disconnected targets, rather than only
+        /// the active ones
+        #[arg(long)]
+        all: bool,
+    },
+    /// Import a layout from elsewhere: a file written by `layout export`, or the Windows database.
+    ///
+    /// A layout with the same ID is replaced.
+    Import {
+        /// The file to import the layout from
+        #[arg(conflicts_with_all = ["from_windows_db", "name_template"])]
+        file: Option<PathBuf>,
+        /// Import the configuration Windows has stored for the currently connected monitors
+        #[arg(long, required_unless_present = "file")]
+        from_windows_db: bool,
+        /// The ID of the layout. Taken from the file, or generated from the topology if not given
+        #[arg(long)]
+        id: Option<String>,
+        /// The human-readable name of the layout. Taken from the file, or generated from the
+        /// topology if not given
+        #[arg(long)]
+        name: Option<String>,
+        /// Generate the name from a template instead, substituting `{index}` (the position of the
+        /// layout), `{count}` (the number of monitors), `{monitors}` (their names) and `{date}`
+        #[arg(long, conflicts_with = "name")]
+        name_template: Option<String>,
+        /// The emoji to display for the layout
+        #[arg(short, long)]
+        emoji: Option<String>,
+    },
+    /// Write the layout with ID `id` on its own to a file, e.g. to share it with `layout import`
+    Export {
+        /// The ID of the layout
+        id: String,
+        /// The file to write the layout to
+        path: PathBuf,
+    },
+    /// Update the layout with ID `id` to the current monitor configuration, keeping its name,
+    /// emoji, position and visibility
+    Touch {
+        /// The ID of the layout
+        id: String,
+        /// Query all display paths, including inactive and disconnected targets, rather than only
+        /// the active ones
+        #[arg(long)]
+        all: bool,
+    },
+    /// Combine the monitors of the layouts `a` and `b` into a new layout with ID `dst`, e.g. to
+    /// make a docked layout from laptop-only and external-only layouts
+    Merge {
+        /// The ID of the first layout
+        a: String,
+        /// The ID of the second layout
+        b: String,
+        /// The ID of the merged layout
+        dst: String,
+        /// The human-readable name of the merged layout. Generated from the merged layouts if not
+        /// given
+        #[arg(long)]
+        name: Option<String>,
+        /// The emoji to display for the merged layout
+        #[arg(short, long)]
+        emoji: Option<String>,
+    },
+    /// Change the ID of a layout, keeping its name, emoji, position, visibility and monitors
+    RenameId {
+        /// The current ID of the layout
+        old: String,
+        /// The new ID of the layout
+        new: String,
+    },
+    /// Change the human-readable name of a layout, without recapturing it
+    Rename {
+        /// The ID of the layout
+        id: String,
+        /// The new human-readable name of the layout
+        new_name: String,
+    },
+    /// Change the emoji of a layout, without recapturing it
+    SetEmoji {
+        /// The ID of the layout
+        id: String,
+        /// The emoji to display for the layout. Removes it if not given
+        emoji: Option<String>,
+    },
+    /// Make `monitor` the primary display of the layout with ID `id`, without recapturing it
+    SetPrimary {
+        /// The ID of the layout
+        id: String,
+        /// The name, alias or device path of the monitor
+        monitor: String,
     },
     /// Clear all stored layouts
     Clear,
@@ -26,11 +131,88 @@ pub enum Command {
     },
     /// Apply the config with ID `id`
     Apply {
-        /// The ID of the layout
+        /// The ID or index of the layout, or part of its ID or name if that matches only one
+        /// layout. Exits with 2 if no layout matches, or 6 if more than one does
+        #[arg(value_parser = non_blank)]
         id: String,
+        /// Skip the monitors in the layout that aren't connected, and apply the rest
+        #[arg(long)]
+        skip_missing: bool,
+        /// Fail rather than falling back to the nearest supported refresh rate when a monitor no
+        /// longer supports the stored one
+        #[arg(long)]
+        exact_refresh: bool,
+        /// Apply the layout without saving it, and revert it unless it's kept within a few
+        /// seconds, e.g. when applying over a remote session that might be lost. Exits with 8 if
+        /// it's reverted
+        #[arg(long)]
+        confirm: bool,
+        /// Try to apply the layout even if some of its monitors aren't connected
+        #[arg(long, conflicts_with = "skip_missing")]
+        force: bool,
+    },
+    /// Reset the monitor configuration to a default topology computed by Windows. Useful when no
+    /// stored layout matches the connected monitors.
+    Reset {
+        /// The topology to reset to
+        #[arg(short, long, value_enum, default_value_t = DisplayTopology::Extend)]
+        topology: DisplayTopology,
+    },
+    /// Switch to one of the Win+P projection modes, without needing a stored layout
+    Project {
+        /// The projection mode
+        #[arg(value_enum)]
+        mode: DisplayTopology,
     },
     /// List all available configurations
-    List,
+    List {
+        /// Also show when each layout was created and last modified
+        #[arg(short, long)]
+        verbose: bool,
+        /// Print the layouts as JSON instead, without their monitor configurations
+        #[arg(long, conflicts_with = "verbose")]
+        json: bool,
+    },
+    /// Show the most recently applied layouts, and what applied them
+    History {
+        /// The number of applies to show
+        #[arg(short = 'n', long, default_value_t = 10)]
+        count: usize,
+    },
+    /// Check that every stored layout could be applied to the current monitors, without applying
+    /// any of them, e.g. after a driver update or monitor swap
+    TestAll,
+    /// Show the monitors in the layout with ID `id`
+    Show {
+        /// The ID of the layout
+        id: String,
+    },
+    /// Compare the monitors of the layouts `a` and `b`, marking the fields that differ with `*`.
+    /// Exits with 0 if they're the same, and 1 if they differ.
+    Diff {
+        /// The ID of the first layout
+        a: String,
+        /// The ID of the second layout
+        b: String,
+    },
+    /// Copy the layouts to a different kind of store, e.g. a directory with a file per layout,
+    /// which diffs and merges better in version control. Point `layouts_path` at the new store to
+    /// use it.
+    Convert {
+        /// Convert to a directory with a file per layout
+        #[arg(
+            long,
+            value_name = "DIR",
+            required_unless_present = "to_file",
+            conflicts_with = "to_file"
+        )]
+        to_dir: Option<PathBuf>,
+        /// Convert to a single file
+        #[arg(long, value_name = "FILE")]
+        to_file: Option<PathBuf>,
+    },
+    /// Print the JSON schema of the layouts file, for editor validation and autocompletion
+    Schema,
     /// Interactively rearrange monitor layouts
     Rearrange,
     /// Hide a layout
@@ -43,73 +225,390 @@ pub enum Command {
         /// The ID of the layout to unhide
         id: String,
     },
+    /// Pin a layout, so it's listed before all others
+    Pin {
+        /// The ID of the layout to pin
+        id: String,
+    },
+    /// Unpin a layout
+    Unpin {
+        /// The ID of the layout to unpin
+        id: String,
+    },
+}
+
+/// A stored layout as printed by `list --json`, without its monitor configuration
+#[derive(Debug, Serialize)]
+struct LayoutListEntry<'a> {
+    id: &'a str,
+    name: &'a str,
+    emoji: Option<&'a str>,
+    pinned: bool,
+    hidden: bool,
+    created: jiff::Timestamp,
+    modified: jiff::Timestamp,
+    monitors: Vec<String>,
+}
+
+impl<'a> LayoutListEntry<'a> {
+    fn new(layout: &'a NamedLayout) -> Self {
+        Self {
+            id: &layout.id,
+            name: &layout.name,
+            emoji: layout.emoji.as_deref(),
+            pinned: layout.pinned,
+            hidden: layout.hidden,
+            created: layout.created,
+            modified: layout.modified,
+            monitors: layout.layout.monitor_names(),
+        }
+    }
 }
 
 impl Command {
+    /// Whether the command writes machine-readable output to stdout, so logging to the console
+    /// should be kept to warnings and errors
+    pub fn quiet(&self) -> bool {
+        matches!(self, Command::List { json: true, .. })
+    }
+
+    /// Whether the command honours `--dry-run`. `test-all` and `export` would apply layouts or
+    /// write files regardless, so they reject it instead
+    pub fn supports_dry_run(&self) -> bool {
+        !matches!(self, Command::TestAll | Command::Export { .. })
+    }
+
     pub async fn run(&self, config: &Config) -> Result<Option<i32>> {
         match self {
-            Command::Store { id, name, emoji } => {
-                // TODO: Lock layouts
+            Command::Store {
+                id,
+                name,
+                emoji,
+                all,
+            } => {
                 info!("Loading layouts...");
-                let mut layouts = Layouts::load(&config.layouts_path.relative()).await?;
-                layouts.add_current(&id, &name, emoji.as_deref()).await?;
-                layouts.save(&config.layouts_path.relative()).await?;
+                let mut layouts = Layouts::load_for_update(&config.layouts_path.relative()).await?;
+                let query = if *all {
+                    DisplayQueryType::All
+                } else {
+                    DisplayQueryType::Active
+                };
+                layouts
+                    .add_current(id, name, emoji.as_deref(), query)
+                    .await?;
+                save_layouts(&layouts, config).await?;
                 info!("Monitor layout {} \"{}\" stored successfully", id, name);
                 Ok(Some(0))
             }
+            Command::Import {
+                file: Some(file),
+                id,
+                name,
+                emoji,
+                ..
+            } => {
+                let mut layout = NamedLayout::import(file).await?;
+                if let Some(id) = id {
+                    layout.id = id.clone();
+                }
+                if let Some(name) = name {
+                    layout.name = name.clone();
+                }
+                if emoji.is_some() {
+                    layout.emoji = emoji.clone();
+                }
+                let mut layouts = Layouts::load_for_update(&config.layouts_path.relative()).await?;
+                if layouts.get_layout(&layout.id).is_some() {
+                    warn!("Replacing existing monitor layout {}", layout.id);
+                }
+                let (id, name) = (layout.id.clone(), layout.name.clone());
+                layouts.add_layout(layout);
+                save_layouts(&layouts, config).await?;
+                info!(
+                    "Monitor layout {} \"{}\" imported from {} successfully",
+                    id,
+                    name,
+                    file.display()
+                );
+                Ok(Some(0))
+            }
+            Command::Import {
+                file: None,
+                id,
+                name,
+                name_template,
+                emoji,
+                ..
+            } => {
+                info!("Loading layouts...");
+                let mut layouts = Layouts::load_for_update(&config.layouts_path.relative()).await?;
+                let layout = layouts
+                    .add_from_windows_database(
+                        id.as_deref(),
+                        name.as_deref(),
+                        name_template.as_deref(),
+                        emoji.as_deref(),
+                    )
+                    .await?;
+                let (id, name) = (layout.id.clone(), layout.name.clone());
+                save_layouts(&layouts, config).await?;
+                info!("Monitor layout {} \"{}\" imported successfully", id, name);
+                Ok(Some(0))
+            }
+            Command::Export { id, path } => {
+                let layouts = Layouts::load(&config.layouts_path.relative()).await?;
+                let Some(layout) = layouts.get_layout_by_id_or_index(id) else {
+                    error!("Monitor layout {} not found", id);
+                    return Ok(Some(exit_code::NOT_FOUND));
+                };
+                if tokio::fs::try_exists(path).await? {
+                    error!("{} already exists", path.display());
+                    return Ok(Some(1));
+                }
+                layout.export(path).await?;
+                info!(
+                    "Monitor layout {} \"{}\" exported to {} successfully",
+                    layout.id,
+                    layout.name,
+                    path.display()
+                );
+                Ok(Some(0))
+            }
+            Command::Touch { id, all } => {
+                info!("Loading layouts...");
+                let mut layouts = Layouts::load_for_update(&config.layouts_path.relative()).await?;
+                let query = if *all {
+                    DisplayQueryType::All
+                } else {
+                    DisplayQueryType::Active
+                };
+                if let Some(layout) = layouts.touch(id, query).await? {
+                    let (id, name) = (layout.id.clone(), layout.name.clone());
+                    save_layouts(&layouts, config).await?;
+                    info!("Monitor layout {} \"{}\" updated successfully", id, name);
+                    Ok(Some(0))
+                } else {
+                    error!("Monitor layout {} not found", id);
+                    Ok(Some(exit_code::NOT_FOUND))
+                }
+            }
+            Command::Merge {
+                a,
+                b,
+                dst,
+                name,
+                emoji,
+            } => {
+                let mut layouts = Layouts::load_for_update(&config.layouts_path.relative()).await?;
+                let Some(layout_a) = layouts.get_layout_by_id_or_index(a) else {
+                    error!("Monitor layout {} not found", a);
+                    return Ok(Some(exit_code::NOT_FOUND));
+                };
+                let Some(layout_b) = layouts.get_layout_by_id_or_index(b) else {
+                    error!("Monitor layout {} not found", b);
+                    return Ok(Some(exit_code::NOT_FOUND));
+                };
+                let layout = layout_a.layout.merge(&layout_b.layout).with_context(|| {
+                    format!(
+                        "Failed to merge layouts {} and {}",
+                        layout_a.id, layout_b.id
+                    )
+                })?;
+                let name = name
+                    .clone()
+                    .unwrap_or_else(|| format!("{} + {}", layout_a.name, layout_b.name));
+                layouts.add_layout(NamedLayout::new(dst, &name, emoji.as_deref(), layout));
+                save_layouts(&layouts, config).await?;
+                info!("Monitor layout {} \"{}\" merged successfully", dst, name);
+                Ok(Some(0))
+            }
+            Command::RenameId { old, new } => {
+                let mut layouts = Layouts::load_for_update(&config.layouts_path.relative()).await?;
+                if layouts.rename_id(old, new)?.is_none() {
+                    error!("Monitor layout {} not found", old);
+                    return Ok(Some(exit_code::NOT_FOUND));
+                }
+                save_layouts(&layouts, config).await?;
+                // Keep the last applied layout pointing at the renamed layout
+                let last_applied_path = config.last_applied_path();
+                if !config.dry_run
+                    && let Some(mut last_applied) = LastApplied::load(&last_applied_path).await?
+                    && last_applied.id == *old
+                {
+                    last_applied.id = new.clone();
+                    last_applied.save_or_warn(&last_applied_path).await;
+                }
+                info!("Monitor layout {} renamed to {} successfully", old, new);
+                Ok(Some(0))
+            }
+            Command::SetPrimary { id, monitor } => {
+                let mut layouts = Layouts::load_for_update(&config.layouts_path.relative()).await?;
+                let Some(layout) = layouts.get_layout_mut(id) else {
+                    error!("Monitor layout {} not found", id);
+                    return Ok(Some(exit_code::NOT_FOUND));
+                };
+                if !layout.layout.set_primary(monitor, &config.aliases) {
+                    error!("Monitor {} not found in layout {}", monitor, id);
+                    return Ok(Some(exit_code::NOT_FOUND));
+                }
+                layout.mark_modified();
+                let name = layout.name.clone();
+                save_layouts(&layouts, config).await?;
+                info!(
+                    "Monitor {} is now the primary display of layout {} \"{}\"",
+                    monitor, id, name
+                );
+                Ok(Some(0))
+            }
             Command::Clear => {
-                let mut layouts = Layouts::load(&config.layouts_path.relative()).await?;
+                let mut layouts = Layouts::load_for_update(&config.layouts_path.relative()).await?;
                 layouts.clear();
-                layouts.save(&config.layouts_path.relative()).await?;
+                save_layouts(&layouts, config).await?;
                 info!("All monitor configurations cleared");
                 Ok(Some(0))
             }
             Command::Remove { id } => {
-                let mut layouts = Layouts::load(&config.layouts_path.relative()).await?;
+                let mut layouts = Layouts::load_for_update(&config.layouts_path.relative()).await?;
                 if let Some(layout_id) = layouts
                     .get_layout_by_id_or_index(&id)
                     .map(|layout| layout.id.clone())
                 {
                     info!("Removing monitor layout {}", layout_id);
                     layouts.remove_layout(&layout_id);
-                    layouts.save(&config.layouts_path.relative()).await?;
+                    save_layouts(&layouts, config).await?;
                     info!("Monitor layout {} removed successfully", layout_id);
+                    Ok(Some(0))
                 } else {
                     error!("Monitor layout {} not found", id);
+                    Ok(Some(exit_code::NOT_FOUND))
                 }
-                Ok(Some(0))
             }
-            Command::Apply { id } => {
+            Command::Apply {
+                id,
+                skip_missing,
+                exact_refresh,
+                confirm,
+                force,
+            } => {
                 let layouts = Layouts::load(&config.layouts_path.relative()).await?;
-                let layout = layouts.get_layout_by_id_or_index(&id);
-                if let Some(layout) = layout {
+                let layout = match find_layout(&layouts, id) {
+                    Ok(layout) => layout,
+                    Err(code) => return Ok(Some(code)),
+                };
+                info!(
+                    "Monitor layout {} \"{}\" loaded successfully",
+                    layout.id, layout.name
+                );
+                let connected_layout;
+                let to_apply = if *skip_missing {
+                    let skipped;
+                    (connected_layout, skipped) = layout.layout.without_disconnected_monitors()?;
+                    for name in &skipped {
+                        warn!("Skipping monitor {}, as it is not connected", name);
+                    }
+                    if connected_layout.paths.is_empty() {
+                        error!("None of the monitors in layout {} are connected", layout.id);
+                        return Ok(Some(exit_code::APPLY_FAILED));
+                    }
+                    &connected_layout
+                } else {
+                    &layout.layout
+                };
+                if config.dry_run {
+                    // Resolve the layout against the current monitors as applying would, but
+                    // only show the result
+                    if !*force && let Err(e) = to_apply.ensure_connected() {
+                        return Ok(apply_failed(&layout.id, e));
+                    }
+                    let planned = match to_apply.to_windows(*exact_refresh) {
+                        Ok(planned) => planned,
+                        Err(e) => return Ok(apply_failed(&layout.id, e)),
+                    };
                     info!(
-                        "Monitor layout {} \"{}\" loaded successfully",
+                        "Dry run: not applying monitor layout {} \"{}\", which would set the display configuration to:",
                         layout.id, layout.name
                     );
-                    layout.layout.apply(true)?;
+                    planned.dump(&mut std::io::stdout().lock())?;
+                    return Ok(Some(0));
+                }
+                if *confirm {
+                    let previous = DisplayLayout::get()?;
+                    if let Err(e) = to_apply.apply(false, *exact_refresh, *force) {
+                        return Ok(apply_failed(&layout.id, e));
+                    }
+                    let keep = keep_settings(CONFIRM_TIMEOUT).await.unwrap_or_else(|e| {
+                        warn!("Failed to ask whether to keep the layout: {:?}", e);
+                        false
+                    });
+                    if !keep {
+                        previous.apply(false, false, true)?;
+                        info!("Monitor layout {} \"{}\" reverted", layout.id, layout.name);
+                        return Ok(Some(exit_code::REVERTED));
+                    }
+                }
+                if let Err(e) = to_apply.apply(true, *exact_refresh, *force) {
+                    return Ok(apply_failed(&layout.id, e));
+                }
+                LastApplied::now(&layout.id)
+                    .save_or_warn(&config.last_applied_path())
+                    .await;
+                History::record(&config.history_path(), &layout.id, ApplySource::Cli).await;
+                info!(
+                    "Monitor layout {} \"{}\" applied successfully",
+                    layout.id, layout.name
+                );
+                Ok(Some(0))
+            }
+            Command::Reset { topology } => {
+                if config.dry_run {
                     info!(
-                        "Monitor layout {} \"{}\" applied successfully",
-                        layout.id, layout.name
+                        "Dry run: would reset monitor configuration to {:?} topology",
+                        topology
                     );
-                    Ok(Some(0))
-                } else {
-                    error!("Monitor layout {} not found", id);
-                    Ok(Some(1))
+                    return Ok(Some(0));
+                }
+                info!("Resetting monitor configuration to {:?} topology", topology);
+                if let Err(e) = WindowsDisplayConfig::set_topology(*topology) {
+                    error!("Failed to reset monitor configuration: {:?}", e);
+                    return Ok(Some(exit_code::APPLY_FAILED));
                 }
+                info!("Monitor configuration reset successfully");
+                Ok(Some(0))
+            }
+            Command::Project { mode } => {
+                if config.dry_run {
+                    info!("Dry run: would switch to {:?} projection mode", mode);
+                    return Ok(Some(0));
+                }
+                if let Err(e) = WindowsDisplayConfig::set_topology(*mode) {
+                    error!("Failed to switch to {:?} projection mode: {:?}", mode, e);
+                    return Ok(Some(exit_code::APPLY_FAILED));
+                }
+                info!("Switched to {:?} projection mode", mode);
+                Ok(Some(0))
+            }
+            Command::List { json: true, .. } => {
+                let layouts = Layouts::load(&config.layouts_path.relative()).await?;
+                let entries = layouts
+                    .pinned_first()
+                    .map(|(_, layout)| LayoutListEntry::new(layout))
+                    .collect::<Vec<_>>();
+                println!("{}", serde_json::to_string_pretty(&entries)?);
+                Ok(Some(0))
             }
-            Command::List => {
+            Command::List { verbose, .. } => {
                 let layouts = Layouts::load(&config.layouts_path.relative()).await?;
                 if layouts.is_empty() {
                     info!("No monitor configurations found");
                 } else {
                     info!("Available monitor configurations:");
-                    for (i, layout) in layouts.iter().enumerate() {
+                    for (i, layout) in layouts.pinned_first() {
                         info!(
-                            "  {}. {} - {:?}{}{}",
+                            "  {}. {} - {:?}{}{}{}",
                             i + 1,
                             layout.id,
                             layout.name,
+                            if layout.pinned { " [pinned]" } else { "" },
                             if layout.hidden { " [hidden]" } else { "" },
                             layout
                                 .emoji
@@ -117,50 +616,392 @@ impl Command {
                                 .map(|s| format!(" {}", s))
                                 .unwrap_or_default(),
                         );
+                        if *verbose {
+                            info!(
+                                "     created {}, modified {}",
+                                format_timestamp(layout.created),
+                                format_timestamp(layout.modified),
+                            );
+                        }
                     }
                 }
                 Ok(Some(0))
             }
+            Command::History { count } => {
+                let history = History::load(&config.history_path()).await?;
+                let layouts = Layouts::load(&config.layouts_path.relative()).await?;
+                let mut entries = history.iter_recent().take(*count).peekable();
+                if entries.peek().is_none() {
+                    info!("No layouts have been applied yet");
+                    return Ok(Some(0));
+                }
+                info!("Recently applied layouts:");
+                for entry in entries {
+                    let name = layouts
+                        .get_layout(&entry.id)
+                        .map(|layout| format!(" {:?}", layout.name))
+                        .unwrap_or_default();
+                    info!(
+                        "  {} - {}{} ({:?})",
+                        format_timestamp(entry.applied_at),
+                        entry.id,
+                        name,
+                        entry.source,
+                    );
+                }
+                Ok(Some(0))
+            }
+            Command::TestAll => {
+                let layouts = Layouts::load(&config.layouts_path.relative()).await?;
+                if layouts.is_empty() {
+                    info!("No monitor configurations found");
+                    return Ok(Some(0));
+                }
+                let id_width = layouts.iter_all().map(|l| l.id.len()).max().unwrap_or(0);
+                let mut failed = 0;
+                for layout in layouts.iter_all() {
+                    match layout.layout.validate(false) {
+                        Ok(()) => info!("  {:<id_width$}  PASS", layout.id),
+                        Err(e) => {
+                            failed += 1;
+                            error!("  {:<id_width$}  FAIL  {:#}", layout.id, e);
+                        }
+                    }
+                }
+                if failed == 0 {
+                    info!("All {} layouts can be applied", layouts.len());
+                    Ok(Some(0))
+                } else {
+                    error!("{} of {} layouts can't be applied", failed, layouts.len());
+                    Ok(Some(exit_code::APPLY_FAILED))
+                }
+            }
+            Command::Show { id } => {
+                let layouts = Layouts::load(&config.layouts_path.relative()).await?;
+                let Some(layout) = layouts.get_layout_by_id_or_index(id) else {
+                    error!("Monitor layout {} not found", id);
+                    return Ok(Some(exit_code::NOT_FOUND));
+                };
+                info!("Monitor layout {} \"{}\":", layout.id, layout.name);
+                info!("  Created:  {}", format_timestamp(layout.created));
+                info!("  Modified: {}", format_timestamp(layout.modified));
+                let primary = layout.layout.primary_monitor_device_path.as_ref();
+                for (i, path) in layout.layout.paths.iter().enumerate() {
+                    let (Some(source_mode), Some(target_mode)) = (
+                        layout
+                            .layout
+                            .source_modes
+                            .get(path.source.source_mode_index),
+                        layout
+                            .layout
+                            .target_modes
+                            .get(path.target.target_mode_index),
+                    ) else {
+                        error!(
+                            "Path {} of monitor layout {} refers to a mode that doesn't exist",
+                            i + 1,
+                            layout.id
+                        );
+                        return Ok(Some(exit_code::NOT_FOUND));
+                    };
+                    let device = &target_mode.device;
+                    let is_primary = primary.is_some_and(|primary| {
+                        device
+                            .monitor_device_path
+                            .as_ref()
+                            .is_some_and(|path| path.eq_ignore_ascii_case(primary))
+                    });
+                    info!(
+                        "  {}. {} - {}x{} at ({}, {}), {:?} connector #{}{}",
+                        i + 1,
+                        device.display_name_with_aliases(&config.aliases),
+                        source_mode.width,
+                        source_mode.height,
+                        source_mode.position.x,
+                        source_mode.position.y,
+                        device.output_technology,
+                        device.connector_instance,
+                        if is_primary { " [primary]" } else { "" },
+                    );
+                }
+                Ok(Some(0))
+            }
+            Command::Diff { a, b } => {
+                let layouts = Layouts::load(&config.layouts_path.relative()).await?;
+                let mut found = Vec::new();
+                for id in [a, b] {
+                    match layouts.get_layout_by_id_or_index(id) {
+                        Some(layout) => found.push(layout),
+                        None => error!("Monitor layout {} not found", id),
+                    }
+                }
+                let [a, b] = found[..] else {
+                    return Ok(Some(exit_code::NOT_FOUND));
+                };
+                info!(
+                    "Comparing {} \"{}\" with {} \"{}\":",
+                    a.id, a.name, b.id, b.name
+                );
+                let marker = |differs: bool| if differs { '*' } else { ' ' };
+                let (paths_a, paths_b) = (a.layout.paths.len(), b.layout.paths.len());
+                info!(
+                    "{} Paths: {} | {}",
+                    marker(paths_a != paths_b),
+                    paths_a,
+                    paths_b
+                );
+                let comparisons = a.layout.compare(&b.layout);
+                for comparison in &comparisons {
+                    match comparison {
+                        MonitorComparison::Both { name, fields } => {
+                            info!("{} {}:", marker(comparison.differs()), name);
+                            for field in fields {
+                                info!(
+                                    "{}   {:<12}  {} | {}",
+                                    marker(field.differs()),
+                                    field.field,
+                                    field.a,
+                                    field.b
+                                );
+                            }
+                        }
+                        MonitorComparison::OnlyInSelf(name) => {
+                            info!("* {}: only in {}", name, a.id)
+                        }
+                        MonitorComparison::OnlyInOther(name) => {
+                            info!("* {}: only in {}", name, b.id)
+                        }
+                    }
+                }
+                if comparisons.iter().any(MonitorComparison::differs) {
+                    Ok(Some(1))
+                } else {
+                    info!("The layouts are identical");
+                    Ok(Some(0))
+                }
+            }
+            Command::Convert { to_dir, to_file } => {
+                let (path, kind) = match (to_dir, to_file) {
+                    (Some(dir), _) => (dir, StoreKind::Directory),
+                    (None, Some(file)) => (file, StoreKind::File),
+                    (None, None) => unreachable!("clap requires --to-dir or --to-file"),
+                };
+                if tokio::fs::try_exists(path).await? {
+                    error!("{} already exists", path.display());
+                    return Ok(Some(1));
+                }
+                let layouts = Layouts::load(&config.layouts_path.relative()).await?;
+                if config.dry_run {
+                    info!(
+                        "Dry run: would convert {} monitor layouts to {}",
+                        layouts.len(),
+                        path.display()
+                    );
+                    return Ok(Some(0));
+                }
+                layouts.save_as(path, kind, config.layouts_format).await?;
+                info!(
+                    "Converted {} monitor layouts to {}",
+                    layouts.len(),
+                    path.display()
+                );
+                info!(
+                    "Set layouts_path to {} in Rocket.toml to use them",
+                    path.display()
+                );
+                Ok(Some(0))
+            }
+            Command::Schema => {
+                let schema = schemars::schema_for!(LayoutsFile);
+                println!("{}", serde_json::to_string_pretty(&schema)?);
+                Ok(Some(0))
+            }
             Command::Rearrange => {
+                // The store is only locked while saving, so other processes (e.g. the service)
+                // aren't blocked for the whole interactive session
                 let mut layouts = Layouts::load(&config.layouts_path.relative()).await?;
                 if layouts.is_empty() {
                     error!("No monitor configurations found to rearrange");
                     return Ok(Some(1));
                 }
                 let mut stdout = std::io::stdout();
-                let mut rearranger =
-                    Rearranger::new(&mut layouts, config.layouts_path.relative(), &mut stdout);
+                let mut rearranger = Rearranger::new(&mut layouts, config, &mut stdout);
                 rearranger.run().await?;
                 Ok(Some(0))
             }
+            Command::Rename { id, new_name } => {
+                let new_name = new_name.trim();
+                if new_name.is_empty() {
+                    error!("The layout name is empty");
+                    return Ok(Some(1));
+                }
+                let mut layouts = Layouts::load_for_update(&config.layouts_path.relative()).await?;
+                if let Some(layout) = layouts.get_layout_mut(id) {
+                    let id = layout.id.clone();
+                    let old_name = std::mem::replace(&mut layout.name, new_name.into());
+                    layout.mark_modified();
+                    save_layouts(&layouts, config).await?;
+                    info!(
+                        "Monitor layout {} renamed from \"{}\" to \"{}\"",
+                        id, old_name, new_name
+                    );
+                    Ok(Some(0))
+                } else {
+                    error!("Monitor layout {} not found", id);
+                    Ok(Some(exit_code::NOT_FOUND))
+                }
+            }
+            Command::SetEmoji { id, emoji } => {
+                let mut layouts = Layouts::load_for_update(&config.layouts_path.relative()).await?;
+                if let Some(layout) = layouts.get_layout_mut(id) {
+                    let id = layout.id.clone();
+                    let name = layout.name.clone();
+                    let emoji = emoji
+                        .as_deref()
+                        .map(str::trim)
+                        .filter(|emoji| !emoji.is_empty());
+                    layout.emoji = emoji.map(String::from);
+                    layout.mark_modified();
+                    save_layouts(&layouts, config).await?;
+                    match emoji {
+                        Some(emoji) => {
+                            info!("Monitor layout {} \"{}\" emoji set to {}", id, name, emoji)
+                        }
+                        None => info!("Monitor layout {} \"{}\" emoji removed", id, name),
+                    }
+                    Ok(Some(0))
+                } else {
+                    error!("Monitor layout {} not found", id);
+                    Ok(Some(exit_code::NOT_FOUND))
+                }
+            }
             Command::Hide { id } => {
-                let mut layouts = Layouts::load(&config.layouts_path.relative()).await?;
+                let mut layouts = Layouts::load_for_update(&config.layouts_path.relative()).await?;
                 if let Some(layout) = layouts.get_layout_mut(&id) {
                     let id = layout.id.clone();
                     let name = layout.name.clone();
                     layout.hidden = true;
-                    layouts.save(&config.layouts_path.relative()).await?;
+                    layout.mark_modified();
+                    save_layouts(&layouts, config).await?;
                     info!("Monitor layout {} \"{}\" hidden successfully", id, name);
                     Ok(Some(0))
                 } else {
                     error!("Monitor layout {} not found", id);
-                    Ok(Some(1))
+                    Ok(Some(exit_code::NOT_FOUND))
                 }
             }
             Command::Unhide { id } => {
-                let mut layouts = Layouts::load(&config.layouts_path.relative()).await?;
+                let mut layouts = Layouts::load_for_update(&config.layouts_path.relative()).await?;
                 if let Some(layout) = layouts.get_layout_mut(&id) {
                     let id = layout.id.clone();
                     let name = layout.name.clone();
                     layout.hidden = false;
-                    layouts.save(&config.layouts_path.relative()).await?;
+                    layout.mark_modified();
+                    save_layouts(&layouts, config).await?;
                     info!("Monitor layout {} \"{}\" unhidden successfully", id, name);
                     Ok(Some(0))
                 } else {
                     error!("Monitor layout {} not found", id);
-                    Ok(Some(1))
+                    Ok(Some(exit_code::NOT_FOUND))
                 }
             }
+            Command::Pin { id } | Command::Unpin { id } => {
+                let pinned = matches!(self, Command::Pin { .. });
+                let mut layouts = Layouts::load_for_update(&config.layouts_path.relative()).await?;
+                if let Some(layout) = layouts.get_layout_mut(id) {
+                    let id = layout.id.clone();
+                    let name = layout.name.clone();
+                    layout.pinned = pinned;
+                    layout.mark_modified();
+                    save_layouts(&layouts, config).await?;
+                    info!(
+                        "Monitor layout {} \"{}\" {} successfully",
+                        id,
+                        name,
+                        if pinned { "pinned" } else { "unpinned" }
+                    );
+                    Ok(Some(0))
+                } else {
+                    error!("Monitor layout {} not found", id);
+                    Ok(Some(exit_code::NOT_FOUND))
+                }
+            }
+        }
+    }
+}
+
+/// Format a timestamp in the local time zone, to the second
+fn format_timestamp(timestamp: jiff::Timestamp) -> String {
+    timestamp
+        .to_zoned(jiff::tz::TimeZone::system())
+        .strftime("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+/// Save the layouts, or with `--dry-run`, only log how they would change
+pub(crate) async fn save_layouts(layouts: &Layouts, config: &Config) -> Result<()> {
+    let layouts_path = config.layouts_path.relative();
+    if !config.dry_run {
+        return layouts.save(&layouts_path, config.layouts_format).await;
+    }
+    let changes = layouts.changes_from(&Layouts::load(&layouts_path).await?);
+    if changes.is_empty() {
+        info!("Dry run: {} would not change", layouts_path.display());
+    } else {
+        info!(
+            "Dry run: not saving {}, which would change:",
+            layouts_path.display()
+        );
+        for change in changes {
+            info!("  {}", change);
+        }
+    }
+    Ok(())
+}
+
+/// Parse an argument that can't be empty, e.g. a layout to search for, which would match every
+/// layout
+fn non_blank(arg: &str) -> Result<String, String> {
+    if arg.trim().is_empty() {
+        Err("can't be empty".into())
+    } else {
+        Ok(arg.into())
+    }
+}
+
+/// Find the layout with the ID or index `query`, or failing that, the only layout whose ID or name
+/// contains it. Otherwise logs why there's no match and returns the exit code for it.
+fn find_layout<'a>(layouts: &'a Layouts, query: &str) -> Result<&'a NamedLayout, i32> {
+    if let Some(layout) = layouts.get_layout_by_id_or_index(query) {
+        return Ok(layout);
+    }
+    match layouts.search(query)[..] {
+        [layout] => {
+            info!(
+                "Matched monitor layout {} \"{}\" from {:?}",
+                layout.id, layout.name, query
+            );
+            Ok(layout)
         }
+        [] => {
+            error!("Monitor layout {} not found", query);
+            Err(exit_code::NOT_FOUND)
+        }
+        ref candidates => {
+            error!("{:?} matches more than one monitor layout:", query);
+            for layout in candidates {
+                error!("  {} \"{}\"", layout.id, layout.name);
+            }
+            Err(exit_code::AMBIGUOUS)
+        }
+    }
+}
+
+/// Log a failure to apply a layout, returning the exit code for it
+fn apply_failed(id: &str, e: anyhow::Error) -> Option<i32> {
+    error!("Failed to apply monitor layout {}: {:?}", id, e);
+    if e.is::<MissingMonitors>() {
+        info!("Use --skip-missing to apply the rest, or --force to try anyway");
     }
+    Some(exit_code::APPLY_FAILED)
 }
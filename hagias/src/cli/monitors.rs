@@ -0,0 +1,290 @@
+use anyhow::Result;
+use tracing::{error, info};
+
+use crate::{
+    config::Config,
+    ddc::{self, PowerState},
+    display::DisplayLayout,
+    exit_code,
+    monitors::{Monitor, MonitorFilter},
+    windows_util::{DisplayQueryType, WindowsDisplayConfig},
+};
+
+/// The columns of the `modes` table
+const MODES_HEADER: [&str; 8] = [
+    "#",
+    "Name",
+    "Resolution",
+    "Position",
+    "Refresh rate",
+    "Rotation",
+    "Scaling",
+    "Output",
+];
+
+#[derive(Debug, Clone, clap::Subcommand)]
+pub enum Command {
+    /// List the monitors known to Windows
+    List {
+        #[command(flatten)]
+        filter: MonitorFilter,
+    },
+    /// Print the current mode of each active monitor as a table, e.g. to tell which monitor is
+    /// which before storing a layout
+    Modes,
+    /// Set the power state of a monitor over DDC/CI
+    Power {
+        /// The target ID or friendly name of the monitor
+        monitor: String,
+        #[arg(value_enum)]
+        state: PowerState,
+    },
+    /// Activate a connected monitor, leaving the other monitors as they are
+    Enable {
+        /// The target ID, alias or friendly name of the monitor
+        monitor: String,
+    },
+    /// Deactivate a monitor, leaving the other monitors as they are
+    Disable {
+        /// The target ID, alias or friendly name of the monitor
+        monitor: String,
+        /// Deactivate the monitor even if no other monitors are active
+        #[arg(long)]
+        force: bool,
+    },
+    /// Give a monitor a name to show instead of its own, e.g. to tell identical monitors apart.
+    /// The alias applies to all monitors with the same EDID manufacturer and product code, unless
+    /// `--serial` is given.
+    Alias {
+        /// The target ID, alias or friendly name of the monitor
+        monitor: String,
+        /// The alias. Removes the monitor's alias if not given
+        name: Option<String>,
+        /// Only apply the alias to this monitor, by its EDID serial number, rather than to all
+        /// monitors of the same model
+        #[arg(long)]
+        serial: bool,
+    },
+}
+
+impl Command {
+    pub async fn run(&self, config: &Config) -> Result<Option<i32>> {
+        match self {
+            Command::List { filter } => {
+                let monitors = filter.apply(Monitor::get_all_with_aliases(&config.aliases)?);
+                if monitors.is_empty() {
+                    info!("No monitors found");
+                } else {
+                    info!("Monitors:");
+                    for (i, monitor) in monitors.iter().enumerate() {
+                        info!(
+                            "  {}. {} - {:?}{}{}",
+                            i + 1,
+                            monitor.display_name(),
+                            monitor.output_technology,
+                            if monitor.connected {
+                                " [connected]"
+                            } else {
+                                ""
+                            },
+                            if monitor.active { " [active]" } else { "" },
+                        );
+                    }
+                }
+                Ok(Some(0))
+            }
+            Command::Modes => {
+                let layout = DisplayLayout::from_windows(&WindowsDisplayConfig::get(
+                    DisplayQueryType::Active,
+                )?)?;
+                let rows = modes_rows(&layout, config);
+                if rows.is_empty() {
+                    info!("No active monitors");
+                    return Ok(Some(0));
+                }
+                let mut widths = MODES_HEADER.map(str::len);
+                for row in &rows {
+                    for (width, cell) in widths.iter_mut().zip(row) {
+                        *width = (*width).max(cell.chars().count());
+                    }
+                }
+                for row in std::iter::once(MODES_HEADER.map(String::from)).chain(rows) {
+                    let line = row
+                        .iter()
+                        .zip(widths)
+                        .map(|(cell, width)| format!("{:<width$}", cell))
+                        .collect::<Vec<_>>()
+                        .join("  ");
+                    info!("{}", line.trim_end());
+                }
+                Ok(Some(0))
+            }
+            Command::Power { monitor, state } => {
+                let Some(found) = Monitor::find(monitor, &config.aliases)? else {
+                    error!("Monitor {:?} not found", monitor);
+                    return Ok(Some(exit_code::NOT_FOUND));
+                };
+                if config.dry_run {
+                    info!(
+                        "Dry run: would set monitor {} to {:?}",
+                        found.display_name(),
+                        state
+                    );
+                    return Ok(Some(0));
+                }
+                ddc::set_power_state(&found, *state)?;
+                info!("Set monitor {} to {:?}", found.display_name(), state);
+                Ok(Some(0))
+            }
+            Command::Enable { monitor } => {
+                let Some(found) = Monitor::find(monitor, &config.aliases)? else {
+                    error!("Monitor {:?} not found", monitor);
+                    return Ok(Some(exit_code::NOT_FOUND));
+                };
+                if found.active {
+                    info!("Monitor {} is already active", found.display_name());
+                    return Ok(Some(0));
+                }
+                if !found.connected {
+                    error!("Monitor {} is not connected", found.display_name());
+                    return Ok(Some(1));
+                }
+                if config.dry_run {
+                    info!("Dry run: would enable monitor {}", found.display_name());
+                    return Ok(Some(0));
+                }
+                if let Err(e) = found.enable() {
+                    error!("Failed to enable monitor {}: {:?}", found.display_name(), e);
+                    return Ok(Some(exit_code::APPLY_FAILED));
+                }
+                info!("Enabled monitor {}", found.display_name());
+                Ok(Some(0))
+            }
+            Command::Disable { monitor, force } => {
+                let Some(found) = Monitor::find(monitor, &config.aliases)? else {
+                    error!("Monitor {:?} not found", monitor);
+                    return Ok(Some(exit_code::NOT_FOUND));
+                };
+                if !found.active {
+                    info!("Monitor {} is already inactive", found.display_name());
+                    return Ok(Some(0));
+                }
+                let active_count = Monitor::get_all()?.iter().filter(|m| m.active).count();
+                if active_count <= 1 && !force {
+                    error!(
+                        "Disabling monitor {} would leave no active displays, use --force to disable it anyway",
+                        found.display_name()
+                    );
+                    return Ok(Some(1));
+                }
+                if config.dry_run {
+                    info!("Dry run: would disable monitor {}", found.display_name());
+                    return Ok(Some(0));
+                }
+                if let Err(e) = found.disable() {
+                    error!(
+                        "Failed to disable monitor {}: {:?}",
+                        found.display_name(),
+                        e
+                    );
+                    return Ok(Some(exit_code::APPLY_FAILED));
+                }
+                info!("Disabled monitor {}", found.display_name());
+                Ok(Some(0))
+            }
+            Command::Alias {
+                monitor,
+                name,
+                serial,
+            } => {
+                let Some(found) = Monitor::find(monitor, &config.aliases)? else {
+                    error!("Monitor {:?} not found", monitor);
+                    return Ok(Some(exit_code::NOT_FOUND));
+                };
+                if *serial && found.serial_alias_key().is_none() {
+                    error!(
+                        "The serial number of monitor {} can't be read, so it can't be given its own alias",
+                        found.display_name()
+                    );
+                    return Ok(Some(1));
+                }
+                let key = if *serial {
+                    found.serial_alias_key()
+                } else {
+                    found.alias_key()
+                };
+                let Some(key) = key else {
+                    error!(
+                        "Monitor {} has no EDID IDs, so can't be given an alias",
+                        found.display_name()
+                    );
+                    return Ok(Some(1));
+                };
+                if config.dry_run {
+                    match name {
+                        Some(name) => info!(
+                            "Dry run: would alias monitor {} ({}) to {:?}",
+                            found.display_name(),
+                            key,
+                            name
+                        ),
+                        None => info!(
+                            "Dry run: would remove the alias of monitor {} ({})",
+                            found.display_name(),
+                            key
+                        ),
+                    }
+                    return Ok(Some(0));
+                }
+                let path = crate::config::set_alias(&key, name.as_deref())?;
+                match name {
+                    Some(name) => info!(
+                        "Monitor {} ({}) aliased to {:?} in {}",
+                        found.display_name(),
+                        key,
+                        name,
+                        path.display()
+                    ),
+                    None => info!(
+                        "Removed alias of monitor {} ({}) from {}",
+                        found.display_name(),
+                        key,
+                        path.display()
+                    ),
+                }
+                info!("Restart the service for the web interface to use the new alias");
+                Ok(Some(0))
+            }
+        }
+    }
+}
+
+/// A row of the `modes` table for each path of the layout
+fn modes_rows(layout: &DisplayLayout, config: &Config) -> Vec<[String; 8]> {
+    layout
+        .paths
+        .iter()
+        .enumerate()
+        .map(|(i, path)| {
+            let target_mode = layout.target_modes.get(path.target.target_mode_index);
+            let source_mode = layout.source_modes.get(path.source.source_mode_index);
+            [
+                (i + 1).to_string(),
+                target_mode.map_or_else(
+                    || "<unknown>".into(),
+                    |mode| mode.device.display_name_with_aliases(&config.aliases),
+                ),
+                target_mode.map_or_else(String::new, |mode| {
+                    format!("{}x{}", mode.active_size.x, mode.active_size.y)
+                }),
+                source_mode.map_or_else(String::new, |mode| {
+                    format!("{},{}", mode.position.x, mode.position.y)
+                }),
+                path.target.refresh_rate.to_string(),
+                format!("{:?}", path.target.rotation),
+                format!("{:?}", path.target.scaling),
+                format!("{:?}", path.target.output_technology),
+            ]
+        })
+        .collect()
+}
@@ -1,4 +1,4 @@
-use crate::layouts::Layouts;
+use crate::{config::Config, layouts::Layouts};
 use anyhow::Result;
 use crossterm::{
     QueueableCommand,
@@ -9,12 +9,14 @@ use crossterm::{
     terminal::{Clear, ClearType, disable_raw_mode, enable_raw_mode},
 };
 use futures::StreamExt;
-use std::{io::Write, path::PathBuf};
+use std::io::Write;
 use tokio::io::AsyncBufReadExt;
 
+use super::layout::save_layouts;
+
 pub struct Rearranger<'a> {
     layouts: &'a mut Layouts,
-    layouts_path: PathBuf,
+    config: &'a Config,
     stdout: &'a mut std::io::Stdout,
     selected: usize,
     grabbed: bool,
@@ -26,12 +28,12 @@ pub struct Rearranger<'a> {
 impl<'a> Rearranger<'a> {
     pub(crate) fn new(
         layouts: &'a mut Layouts,
-        layouts_path: PathBuf,
+        config: &'a Config,
         stdout: &'a mut std::io::Stdout,
     ) -> Self {
         Self {
             layouts,
-            layouts_path,
+            config,
             stdout,
             selected: 0,
             grabbed: false,
@@ -61,6 +63,19 @@ impl<'a> Rearranger<'a> {
         Ok(())
     }
 
+    /// Save the new order of the layouts. The store is re-loaded under its lock, so that changes
+    /// made by other processes since the session started aren't lost.
+    async fn save(&self) -> Result<()> {
+        let mut stored = Layouts::load_for_update(&self.config.layouts_path.relative()).await?;
+        let order = self
+            .layouts
+            .iter_all()
+            .map(|layout| layout.id.clone())
+            .collect::<Vec<_>>();
+        stored.reorder(&order);
+        save_layouts(&stored, self.config).await
+    }
+
     pub async fn run(&mut self) -> Result<()> {
         writeln!(self.stdout, "Controls:")?;
         writeln!(self.stdout, "  ↑/↓ - Move selection up/down")?;
@@ -90,7 +105,7 @@ impl<'a> Rearranger<'a> {
                     KeyCode::Char('s') => {
                         self.set_status(Some("Saving changes...".into()))?;
 
-                        self.layouts.save(&self.layouts_path).await?;
+                        self.save().await?;
                         self.has_changes = false;
 
                         self.set_status(Some("Changes saved successfully".into()))?;
@@ -147,7 +162,7 @@ impl<'a> Rearranger<'a> {
                 .read_line(&mut input)
                 .await?;
             if input.trim().to_lowercase() == "y" {
-                self.layouts.save(&self.layouts_path).await?;
+                self.save().await?;
                 println!("Changes saved successfully");
             } else {
                 println!("Changes discarded");
@@ -1,7 +1,24 @@
-use anyhow::Result;
-use tracing::info;
+use std::path::PathBuf;
 
-use crate::config::Config;
+use anyhow::{Result, anyhow, bail};
+use tracing::{error, info, warn};
+
+use crate::{config::Config, exit_code, service::DEFAULT_SERVICE_NAME};
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct Args {
+    /// The name of the service, so several can be registered (e.g. one per user)
+    #[arg(long, global = true, default_value = DEFAULT_SERVICE_NAME)]
+    name: String,
+    #[command(subcommand)]
+    command: Command,
+}
+
+impl Args {
+    pub async fn run(&self, config: &Config) -> Result<Option<i32>> {
+        self.command.run(&self.name, config).await
+    }
+}
 
 #[derive(Debug, Clone, clap::Subcommand)]
 pub enum Command {
@@ -13,13 +30,24 @@ pub enum Command {
         /// Don't start the service immediately
         #[arg(short, long)]
         no_start: bool,
+        /// The account to run the service as, e.g. `.\alice` (defaults to the current user)
+        ///
+        /// The service can only drive the displays visible to this account's session. The
+        /// account's password is prompted for, or read from the `HAGIAS_SERVICE_PASSWORD`
+        /// environment variable. Built-in accounts like `LocalSystem` don't need one
+        #[arg(long)]
+        account: Option<String>,
     },
     /// Unregister the service
     Unregister,
     /// Run the service
     ///
-    /// This should only be called by Windows
-    Run,
+    /// This should only be called by Windows, unless `--foreground` is given
+    Run {
+        /// Run the server directly in this console instead of as a service, for debugging
+        #[arg(long)]
+        foreground: bool,
+    },
     /// Start the service
     Start,
     /// Stop the service
@@ -27,72 +55,188 @@ pub enum Command {
     /// Restart the service
     Restart,
     /// Get the status of the service
-    Status,
+    Status {
+        /// Keep polling the status and print each change, until Ctrl-C is pressed
+        #[arg(short, long)]
+        watch: bool,
+    },
+    /// Set the port and/or layouts path in `Rocket.toml`, used by the service once restarted
+    Config {
+        /// The port the web interface listens on
+        #[arg(long)]
+        port: Option<u16>,
+        /// The file the layouts are stored in, or a directory with a file per layout
+        #[arg(long)]
+        layouts_path: Option<PathBuf>,
+    },
+    /// Print the end of the service's log file, from the logs directory next to the executable
+    Logs {
+        /// Keep printing new lines as they are written, until Ctrl-C is pressed
+        #[arg(short, long)]
+        follow: bool,
+        /// The number of lines to print
+        #[arg(short = 'n', long, default_value_t = 50)]
+        lines: usize,
+    },
 }
 
 impl Command {
-    pub async fn run(&self, config: &Config) -> Result<Option<i32>> {
+    /// Whether the command talks to the service control manager with rights only an administrator
+    /// has
+    fn needs_elevation(&self) -> bool {
+        matches!(
+            self,
+            Command::Register { .. }
+                | Command::Unregister
+                | Command::Start
+                | Command::Stop
+                | Command::Restart
+        )
+    }
+
+    pub async fn run(&self, name: &str, config: &Config) -> Result<Option<i32>> {
+        // Ask for the service account's password up front, before anything else can fail
+        let password = match self {
+            Command::Register {
+                account: Some(account),
+                ..
+            } if !crate::service::is_builtin_account(account) => Some(read_password(account)?),
+            _ => None,
+        };
+        if self.needs_elevation() && !crate::windows_util::is_elevated()? {
+            error!("This command must be run as Administrator (e.g. from an elevated terminal)");
+            return Ok(Some(exit_code::NEEDS_ELEVATION));
+        }
         match self {
-            Command::Register { force, no_start } => {
+            Command::Register {
+                force,
+                no_start,
+                account,
+            } => {
                 if *force {
                     info!("Unregistering service if it exists...");
-                    crate::service::unregister_if_exists().await?;
+                    crate::service::unregister_if_exists(name).await?;
                 }
                 info!("Registering service...");
-                crate::service::register(!no_start).await?;
+                crate::service::register(
+                    name,
+                    !no_start,
+                    config.service_start_attempts,
+                    account.as_deref(),
+                    password.as_deref(),
+                )
+                .await?;
                 info!("Service registered successfully");
                 if !no_start {
-                    info!(
-                        "Hagias should be now available at http://localhost:{}",
-                        config.port
-                    );
+                    info!("Hagias should be now available at {}", config.local_url());
                 }
                 Ok(Some(0))
             }
             Command::Unregister => {
                 info!("Unregistering service...");
-                crate::service::unregister().await?;
+                crate::service::unregister(name).await?;
                 info!("Service unregistered successfully");
                 Ok(Some(0))
             }
-            Command::Run => {
+            Command::Run { foreground: true } => {
+                info!("Running server in the foreground...");
+                // Fall through to running rocket directly
+                Ok(None)
+            }
+            Command::Run { foreground: false } => {
                 info!("Running service...");
-                crate::service::run()?;
+                crate::service::run(name)?;
                 Ok(Some(0))
             }
             Command::Start => {
                 info!("Starting service...");
-                crate::service::start().await?;
+                crate::service::start(name, config.service_start_attempts).await?;
                 info!("Service started successfully");
-                info!(
-                    "Hagias should be now available at http://localhost:{}",
-                    config.port
-                );
+                info!("Hagias should be now available at {}", config.local_url());
                 Ok(Some(0))
             }
             Command::Stop => {
                 info!("Stopping service...");
-                crate::service::stop().await?;
+                crate::service::stop(name).await?;
                 info!("Service stopped successfully");
                 Ok(Some(0))
             }
             Command::Restart => {
                 info!("Restarting service...");
-                crate::service::restart().await?;
+                crate::service::restart(name, config.service_start_attempts).await?;
                 info!("Service restarted successfully");
-                info!(
-                    "Hagias should be now available at http://localhost:{}",
-                    config.port
-                );
+                info!("Hagias should be now available at {}", config.local_url());
                 Ok(Some(0))
             }
-            Command::Status => {
-                match crate::service::status().await? {
+            Command::Status { watch: false } => {
+                match crate::service::status(name).await? {
                     Some(status) => info!("Service status: {:?}", status.current_state),
                     None => info!("Service is not running"),
                 }
                 Ok(Some(0))
             }
+            Command::Status { watch: true } => {
+                info!("Watching service status, press Ctrl-C to stop...");
+                crate::service::watch_status(name, |state| match state {
+                    Some(state) => info!("Service status: {:?}", state),
+                    None => info!("Service is not registered"),
+                })
+                .await?;
+                Ok(Some(0))
+            }
+            Command::Config { port, layouts_path } => {
+                if port.is_none() && layouts_path.is_none() {
+                    bail!("Nothing to set, give --port and/or --layouts-path");
+                }
+                let path = crate::config::set_service_config(*port, layouts_path.as_deref())?;
+                info!("Updated {}", path.display());
+                info!("Run `hagias service restart` for the service to pick up the changes");
+                Ok(Some(0))
+            }
+            Command::Logs { follow, lines } => {
+                let log_directory = crate::logging::log_directory();
+                let service_pid = crate::service::status(name)
+                    .await?
+                    .and_then(|status| status.process_id);
+                if service_pid.is_none() {
+                    warn!(
+                        "Service '{}' isn't running, so showing the most recent log file, which might not be the service's",
+                        name
+                    );
+                }
+                let Some(path) =
+                    crate::logging::find_service_log_file(&log_directory, service_pid)?
+                else {
+                    match service_pid {
+                        Some(pid) => bail!(
+                            "No log file found for service process {} in {}",
+                            pid,
+                            log_directory.display()
+                        ),
+                        None => bail!("No log files found in {}", log_directory.display()),
+                    }
+                };
+                info!("Showing {}", path.display());
+                crate::logging::print_log_file(&path, *lines, *follow).await?;
+                Ok(Some(0))
+            }
         }
     }
 }
+
+/// The environment variable that the service account's password can be given in, instead of
+/// prompting for it
+const PASSWORD_ENV: &str = "HAGIAS_SERVICE_PASSWORD";
+
+/// Read the password of `account` from the environment, or prompt for it without echoing it
+fn read_password(account: &str) -> Result<String> {
+    if let Some(password) = std::env::var_os(PASSWORD_ENV) {
+        return password
+            .into_string()
+            .map_err(|_| anyhow!("{} is not valid Unicode", PASSWORD_ENV));
+    }
+    eprint!("Password for {}: ", account);
+    let password = crate::windows_util::read_line_without_echo()?;
+    eprintln!();
+    Ok(password)
+}
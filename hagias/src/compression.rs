@@ -0,0 +1,199 @@
+use std::io::Write;
+
+use flate2::{
+    Compression as Level,
+    write::{DeflateEncoder, GzEncoder},
+};
+use rocket::{
+    Request, Response,
+    fairing::{Fairing, Info, Kind},
+    http::{ContentType, Header, Status},
+};
+use tracing::warn;
+
+/// Bodies smaller than this aren't worth compressing
+const MIN_COMPRESS_BYTES: usize = 1024;
+
+/// A content encoding that responses can be compressed with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Gzip,
+    Deflate,
+}
+
+impl Encoding {
+    fn name(self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+
+    fn compress(self, body: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            Encoding::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Level::default());
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+            Encoding::Deflate => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), Level::default());
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+/// Choose the encoding to use from an `Accept-Encoding` header, preferring gzip. A coding named
+/// explicitly takes precedence over `*`, so `gzip;q=0, *` never picks gzip.
+pub fn choose_encoding(accept_encoding: &str) -> Option<Encoding> {
+    let codings = accept_encoding
+        .split(',')
+        .filter_map(|coding| {
+            let mut parts = coding.split(';').map(str::trim);
+            let name = parts.next().filter(|name| !name.is_empty())?;
+            let rejected = parts.any(|param| {
+                param
+                    .strip_prefix("q=")
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .is_some_and(|q| q == 0.0)
+            });
+            Some((name, rejected))
+        })
+        .collect::<Vec<_>>();
+    let accepted = |name: &str| {
+        codings
+            .iter()
+            .find(|(coding, _)| coding.eq_ignore_ascii_case(name))
+            .or_else(|| codings.iter().find(|(coding, _)| *coding == "*"))
+            .is_some_and(|(_, rejected)| !rejected)
+    };
+    [Encoding::Gzip, Encoding::Deflate]
+        .into_iter()
+        .find(|encoding| accepted(encoding.name()))
+}
+
+/// Make an entity tag weak, as a compressed body isn't byte-for-byte the same representation as the
+/// one the tag was computed from
+fn weaken_etag(etag: &str) -> String {
+    if etag.starts_with("W/") {
+        etag.to_string()
+    } else {
+        format!("W/{}", etag)
+    }
+}
+
+/// Compresses API JSON and SVG responses for clients that accept it
+pub struct Compression;
+
+fn is_compressible(request: &Request<'_>, content_type: Option<ContentType>) -> bool {
+    match content_type {
+        Some(content_type) if content_type == ContentType::SVG => true,
+        Some(content_type) if content_type == ContentType::JSON => {
+            request.uri().path().starts_with("/api/")
+        }
+        _ => false,
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for Compression {
+    fn info(&self) -> Info {
+        Info {
+            name: "Compression",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        if response.status() == Status::NotModified
+            || response.headers().contains("Content-Encoding")
+            || !is_compressible(request, response.content_type())
+        {
+            return;
+        }
+        // The response differs by `Accept-Encoding` whether or not it ends up compressed
+        if !response
+            .headers()
+            .get("Vary")
+            .any(|vary| vary.eq_ignore_ascii_case("Accept-Encoding"))
+        {
+            response.adjoin_header(Header::new("Vary", "Accept-Encoding"));
+        }
+        let Some(encoding) = request
+            .headers()
+            .get_one("Accept-Encoding")
+            .and_then(choose_encoding)
+        else {
+            return;
+        };
+        let body = match response.body_mut().to_bytes().await {
+            Ok(body) => body,
+            Err(e) => {
+                warn!("Failed to read response body to compress: {:?}", e);
+                return;
+            }
+        };
+        if body.len() < MIN_COMPRESS_BYTES {
+            response.set_sized_body(body.len(), std::io::Cursor::new(body));
+            return;
+        }
+        match encoding.compress(&body) {
+            Ok(compressed) => {
+                response.set_header(Header::new("Content-Encoding", encoding.name()));
+                if let Some(etag) = response.headers().get_one("ETag").map(weaken_etag) {
+                    response.set_header(Header::new("ETag", etag));
+                }
+                response.set_sized_body(compressed.len(), std::io::Cursor::new(compressed));
+            }
+            Err(e) => {
+                warn!("Failed to compress response body: {:?}", e);
+                response.set_sized_body(body.len(), std::io::Cursor::new(body));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Encoding, choose_encoding, weaken_etag};
+
+    #[test]
+    fn test_choose_encoding() {
+        assert_eq!(choose_encoding("gzip, deflate, br"), Some(Encoding::Gzip));
+        assert_eq!(choose_encoding("deflate"), Some(Encoding::Deflate));
+        assert_eq!(choose_encoding("GZIP"), Some(Encoding::Gzip));
+        assert_eq!(
+            choose_encoding("gzip;q=0, deflate;q=0.5"),
+            Some(Encoding::Deflate)
+        );
+        assert_eq!(choose_encoding("*"), Some(Encoding::Gzip));
+        assert_eq!(choose_encoding("gzip;q=0, *"), Some(Encoding::Deflate));
+        assert_eq!(choose_encoding("*, gzip;q=0, deflate;q=0"), None);
+        assert_eq!(choose_encoding("*;q=0, deflate"), Some(Encoding::Deflate));
+        assert_eq!(choose_encoding("br"), None);
+        assert_eq!(choose_encoding("identity"), None);
+        assert_eq!(choose_encoding(""), None);
+    }
+
+    #[test]
+    fn test_weaken_etag() {
+        assert_eq!(weaken_etag("\"abc\""), "W/\"abc\"");
+        assert_eq!(weaken_etag("W/\"abc\""), "W/\"abc\"");
+    }
+
+    #[test]
+    fn test_compress_round_trip() {
+        use std::io::Read;
+
+        let body = "{\"layouts\":[]}".repeat(100);
+        let compressed = Encoding::Gzip.compress(body.as_bytes()).unwrap();
+        assert!(compressed.len() < body.len());
+        let mut decompressed = String::new();
+        flate2::read::GzDecoder::new(compressed.as_slice())
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, body);
+    }
+}
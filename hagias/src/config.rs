@@ -1,4 +1,11 @@
-use anyhow::Context as _;
+use std::{
+    collections::HashMap,
+    io::ErrorKind,
+    net::{IpAddr, Ipv4Addr},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context as _, Result, bail};
 use rocket::figment::{
     providers::{Format, Toml},
     value::magic::RelativePathBuf,
@@ -6,36 +13,421 @@ use rocket::figment::{
 use serde::Deserialize;
 use tracing::debug;
 
+use crate::layouts::LayoutsFormat;
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
     pub layouts_path: RelativePathBuf,
     pub static_dir: RelativePathBuf,
     pub template_dir: RelativePathBuf,
+    /// The address the web server listens on, e.g. `0.0.0.0` for all interfaces. Only local
+    /// connections are accepted by default.
+    #[serde(default = "default_address")]
+    pub address: IpAddr,
+    /// The port the web server listens on
     pub port: u16,
+    /// Number of times to try starting the service before giving up
+    #[serde(default = "default_service_start_attempts")]
+    pub service_start_attempts: u32,
+    /// Number of times the service tries to start the web server before giving up
+    #[serde(default = "default_rocket_start_attempts")]
+    pub rocket_start_attempts: u32,
+    /// Origins allowed to make cross-origin requests to the API, e.g. `http://dashboard:8080`.
+    /// If empty, only same-origin requests are allowed.
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    /// Whether to advertise the web server over mDNS, so it can be found on the local network
+    #[serde(default)]
+    pub advertise_mdns: bool,
+    /// The largest request body the API accepts, in bytes. Larger requests are rejected with
+    /// `413 Payload Too Large`.
+    #[serde(default = "default_max_body_bytes")]
+    pub max_body_bytes: u64,
+    /// Whether to compress API JSON and SVG responses for clients that accept gzip or deflate
+    #[serde(default = "default_compress_responses")]
+    pub compress_responses: bool,
+    /// Names for monitors, keyed by their EDID manufacturer and product code (e.g. `10AC:A0B1`),
+    /// shown instead of the monitors' own names. A key with the EDID serial number too (e.g.
+    /// `10AC:A0B1:ABC123`) takes precedence. Managed with `monitors alias`.
+    #[serde(default)]
+    pub aliases: HashMap<String, String>,
+    /// Whether to stop the web interface from changing the stored layouts, e.g. storing or
+    /// pinning them. Layouts can still be applied.
+    #[serde(default)]
+    pub read_only: bool,
+    /// Whether to apply the stored layout matching the connected monitors whenever monitors are
+    /// plugged in or unplugged. Can also be enabled with `--autoswitch`.
+    #[serde(default)]
+    pub autoswitch: bool,
+    /// How the stored layouts are formatted: `pretty` (the default) for editing by hand, or
+    /// `compact` to keep large stores small. Can also be set with `--pretty` or `--compact`.
+    #[serde(default)]
+    pub layouts_format: LayoutsFormat,
+    /// The ID of a layout to keep applied while the web server is running, re-applying it
+    /// whenever something else changes the display configuration, e.g. for a kiosk
+    #[serde(default)]
+    pub enforce_layout: Option<String>,
+    /// How often to check that the enforced layout is still applied, in seconds
+    #[serde(default = "default_enforce_interval_secs")]
+    pub enforce_interval_secs: u64,
+    /// A token that requests to the API routes that change the monitors or layouts must give, as
+    /// an `Authorization: Bearer <token>` header. If unset, anyone who can reach the web server can
+    /// use them. The web interface passes on the `token` in the fragment of its own URL, e.g.
+    /// `http://localhost:5781/#token=<token>`, which browsers don't send to the server.
+    #[serde(default)]
+    pub api_token: Option<String>,
+    /// Whether to show what commands would change instead of saving the layouts, from
+    /// `--dry-run`
+    #[serde(skip)]
+    pub dry_run: bool,
+}
+
+fn default_address() -> IpAddr {
+    Ipv4Addr::LOCALHOST.into()
+}
+
+fn default_service_start_attempts() -> u32 {
+    3
+}
+
+fn default_rocket_start_attempts() -> u32 {
+    5
+}
+
+fn default_max_body_bytes() -> u64 {
+    // Layouts are only a few KiB, so this leaves plenty of room
+    256 * 1024
+}
+
+fn default_compress_responses() -> bool {
+    true
+}
+
+fn default_enforce_interval_secs() -> u64 {
+    30
+}
+
+impl Config {
+    /// The resolved value of each config key, formatted for display
+    pub fn entries(&self) -> Vec<(&'static str, String)> {
+        vec![
+            (
+                "layouts_path",
+                self.layouts_path.relative().display().to_string(),
+            ),
+            (
+                "static_dir",
+                self.static_dir.relative().display().to_string(),
+            ),
+            (
+                "template_dir",
+                self.template_dir.relative().display().to_string(),
+            ),
+            ("address", self.address.to_string()),
+            ("port", self.port.to_string()),
+            (
+                "service_start_attempts",
+                self.service_start_attempts.to_string(),
+            ),
+            (
+                "rocket_start_attempts",
+                self.rocket_start_attempts.to_string(),
+            ),
+            ("allowed_origins", format!("{:?}", self.allowed_origins)),
+            ("advertise_mdns", self.advertise_mdns.to_string()),
+            ("max_body_bytes", self.max_body_bytes.to_string()),
+            ("compress_responses", self.compress_responses.to_string()),
+            ("aliases", format!("{:?}", self.aliases)),
+            ("read_only", self.read_only.to_string()),
+            (
+                "api_token",
+                if self.api_token.is_some() {
+                    "<set>".into()
+                } else {
+                    "None".into()
+                },
+            ),
+            ("autoswitch", self.autoswitch.to_string()),
+            ("layouts_format", format!("{:?}", self.layouts_format)),
+            ("enforce_layout", format!("{:?}", self.enforce_layout)),
+            (
+                "enforce_interval_secs",
+                self.enforce_interval_secs.to_string(),
+            ),
+        ]
+    }
+
+    /// The URL of the web interface on this machine
+    pub fn local_url(&self) -> String {
+        match self.address {
+            address if address.is_unspecified() => format!("http://localhost:{}", self.port),
+            IpAddr::V4(address) => format!("http://{}:{}", address, self.port),
+            IpAddr::V6(address) => format!("http://[{}]:{}", address, self.port),
+        }
+    }
+
+    /// The file that the last applied layout is persisted to, next to the layouts file
+    pub fn last_applied_path(&self) -> PathBuf {
+        self.layouts_path
+            .relative()
+            .with_file_name("last_applied.json")
+    }
+
+    /// The file that the history of applied layouts is persisted to, next to the layouts file
+    pub fn history_path(&self) -> PathBuf {
+        self.layouts_path.relative().with_file_name("history.json")
+    }
+}
+
+/// The `Rocket.toml` written by `config init`
+const DEFAULT_ROCKET_TOML: &str = r#"[default]
+# The file the monitor layouts are stored in, or a directory with a file per layout
+layouts_path = "layouts.json"
+# The web interface's templates and static files
+template_dir = "templates"
+static_dir = "static"
+# The address and port the web interface listens on. Use "0.0.0.0" to listen on all interfaces,
+# so the web interface can be used from other devices
+address = "127.0.0.1"
+port = 5781
+"#;
+
+/// The directories that the default config refers to, relative to `Rocket.toml`
+const DEFAULT_DIRS: &[&str] = &["templates", "static"];
+
+/// Write a default, commented `Rocket.toml` into `dir` and create the directories it refers to.
+/// Fails if the file already exists, unless `force` is set. Returns the path of the file.
+pub fn init(dir: &Path, force: bool) -> Result<PathBuf> {
+    let path = dir.join("Rocket.toml");
+    if !force && path.try_exists()? {
+        bail!(
+            "{} already exists, use --force to overwrite it",
+            path.display()
+        );
+    }
+    std::fs::write(&path, DEFAULT_ROCKET_TOML)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    for name in DEFAULT_DIRS {
+        let dir = dir.join(name);
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    Ok(path)
+}
+
+/// The `Rocket.toml` next to the executable, which the config is loaded from
+pub fn rocket_toml_path() -> Option<PathBuf> {
+    std::env::current_exe()
+        .ok()
+        .and_then(|p| p.parent().map(|p| p.join("Rocket.toml")))
+}
+
+/// Set the alias of the monitor with the given key in `Rocket.toml`, or remove it if `alias` is
+/// `None`, keeping the rest of the file's formatting. Returns the path of the file.
+pub fn set_alias(key: &str, alias: Option<&str>) -> Result<PathBuf> {
+    edit_rocket_toml(|contents| set_alias_in_toml(contents, key, alias))
+}
+
+/// Set the port and/or layouts path in `Rocket.toml`, keeping the rest of the file's formatting.
+/// Returns the path of the file.
+pub fn set_service_config(port: Option<u16>, layouts_path: Option<&Path>) -> Result<PathBuf> {
+    edit_rocket_toml(|contents| set_service_config_in_toml(contents, port, layouts_path))
+}
+
+/// Rewrite `Rocket.toml` with `edit`, treating a missing file as empty
+fn edit_rocket_toml(edit: impl FnOnce(&str) -> Result<String>) -> Result<PathBuf> {
+    let path = rocket_toml_path().context("Failed to find the executable's directory")?;
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+        Err(e) => {
+            return Err(e).with_context(|| format!("Failed to read {}", path.display()));
+        }
+    };
+    let contents =
+        edit(&contents).with_context(|| format!("Failed to update {}", path.display()))?;
+    std::fs::write(&path, contents)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+/// The `[default]` table of a `Rocket.toml` document, created if it doesn't exist
+fn default_table(document: &mut toml_edit::DocumentMut) -> Result<&mut toml_edit::Table> {
+    document
+        .entry("default")
+        .or_insert(toml_edit::table())
+        .as_table_mut()
+        .context("`default` is not a table")
+}
+
+/// Set or remove an alias in the `[default.aliases]` table of a `Rocket.toml` document
+fn set_alias_in_toml(contents: &str, key: &str, alias: Option<&str>) -> Result<String> {
+    let mut document: toml_edit::DocumentMut = contents.parse().context("Invalid TOML")?;
+    let aliases = default_table(&mut document)?
+        .entry("aliases")
+        .or_insert(toml_edit::table())
+        .as_table_mut()
+        .context("`default.aliases` is not a table")?;
+    match alias {
+        Some(alias) => {
+            aliases.insert(key, toml_edit::value(alias));
+        }
+        None => {
+            aliases.remove(key);
+        }
+    }
+    Ok(document.to_string())
+}
+
+/// Set the port and/or layouts path in the `[default]` table of a `Rocket.toml` document
+fn set_service_config_in_toml(
+    contents: &str,
+    port: Option<u16>,
+    layouts_path: Option<&Path>,
+) -> Result<String> {
+    let mut document: toml_edit::DocumentMut = contents.parse().context("Invalid TOML")?;
+    let mut values = Vec::new();
+    if let Some(port) = port {
+        values.push(("port", toml_edit::value(i64::from(port))));
+    }
+    if let Some(layouts_path) = layouts_path {
+        let layouts_path = layouts_path
+            .to_str()
+            .context("The layouts path is not valid UTF-8")?;
+        values.push(("layouts_path", toml_edit::value(layouts_path)));
+    }
+    // Other profiles, like the `[release]` profile the service is built with, override
+    // `[default]`, so the values are removed from them for the new ones to take effect
+    for (profile, item) in document.iter_mut() {
+        if profile != "default"
+            && let Some(table) = item.as_table_like_mut()
+        {
+            for (key, _) in &values {
+                table.remove(key);
+            }
+        }
+    }
+    let default = default_table(&mut document)?;
+    for (key, value) in values {
+        default.insert(key, value);
+    }
+    Ok(document.to_string())
 }
 
 pub fn get() -> Result<(rocket::figment::Figment, Config), anyhow::Error> {
     debug!("Loading config...");
     let mut figment = rocket::Config::figment();
-    if let Some(rocket_toml_path) = std::env::current_exe()
-        .ok()
-        .and_then(|p| p.parent().map(|p| p.join("Rocket.toml")))
-    {
+    if let Some(rocket_toml_path) = rocket_toml_path() {
         figment = figment.merge(Toml::file(rocket_toml_path).nested());
     }
     let config = figment
         .extract::<Config>()
         .context("Failed to extract config")?;
     debug!("Loaded config");
-    debug!(
-        "  layouts_path: {}",
-        config.layouts_path.relative().display()
-    );
-    debug!("  static_dir: {}", config.static_dir.relative().display());
-    debug!(
-        "  template_dir: {}",
-        config.template_dir.relative().display()
-    );
-    debug!("  port: {}", config.port);
+    for (key, value) in config.entries() {
+        debug!("  {}: {}", key, value);
+    }
+    for origin in &config.allowed_origins {
+        crate::cors::validate_origin(origin).context("Invalid allowed_origins")?;
+    }
     Ok((figment, config))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use rocket::figment::{
+        Figment,
+        providers::{Format, Toml},
+    };
+
+    use super::{Config, DEFAULT_ROCKET_TOML, init, set_alias_in_toml, set_service_config_in_toml};
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_init() {
+        let temp_dir = TempDir::new("config");
+        let dir = temp_dir.path();
+
+        let path = init(dir, false).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), DEFAULT_ROCKET_TOML);
+        assert!(dir.join("templates").is_dir());
+        assert!(dir.join("static").is_dir());
+
+        // Existing files are only overwritten with `force`
+        std::fs::write(&path, "").unwrap();
+        assert!(init(dir, false).is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+        init(dir, true).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), DEFAULT_ROCKET_TOML);
+    }
+
+    #[test]
+    fn test_default_rocket_toml_is_valid() {
+        let config: Config = Figment::from(Toml::string(DEFAULT_ROCKET_TOML).nested())
+            .extract()
+            .unwrap();
+        assert_eq!(config.port, 5781);
+        assert_eq!(config.address.to_string(), "127.0.0.1");
+    }
+
+    #[test]
+    fn test_local_url() {
+        let mut config: Config = Figment::from(Toml::string(DEFAULT_ROCKET_TOML).nested())
+            .extract()
+            .unwrap();
+        assert_eq!(config.local_url(), "http://127.0.0.1:5781");
+        config.address = "0.0.0.0".parse().unwrap();
+        assert_eq!(config.local_url(), "http://localhost:5781");
+        config.address = "192.168.1.20".parse().unwrap();
+        assert_eq!(config.local_url(), "http://192.168.1.20:5781");
+        config.address = "::1".parse().unwrap();
+        assert_eq!(config.local_url(), "http://[::1]:5781");
+    }
+
+    #[test]
+    fn test_set_alias_in_toml() {
+        let contents = "[default]\n# The web server port\nport = 5781\n";
+        let contents = set_alias_in_toml(contents, "10AC:A0B1", Some("Left Dell")).unwrap();
+        assert_eq!(
+            contents,
+            "[default]\n# The web server port\nport = 5781\n\n[default.aliases]\n\"10AC:A0B1\" = \"Left Dell\"\n"
+        );
+        let contents = set_alias_in_toml(&contents, "10AC:A0B1", None).unwrap();
+        assert!(!contents.contains("Left Dell"));
+        assert!(contents.contains("port = 5781"));
+
+        let contents = set_alias_in_toml("", "10AC:A0B1", Some("TV")).unwrap();
+        assert_eq!(
+            contents,
+            "[default]\n\n[default.aliases]\n\"10AC:A0B1\" = \"TV\"\n"
+        );
+    }
+
+    #[test]
+    fn test_set_service_config_in_toml() {
+        let contents = "[default]\n# The web server port\nport = 5781\nstatic_dir = \"static\"\n\n[release]\naddress = \"0.0.0.0\"\n";
+        let contents = set_service_config_in_toml(contents, Some(8080), None).unwrap();
+        assert_eq!(
+            contents,
+            "[default]\n# The web server port\nport = 8080\nstatic_dir = \"static\"\n\n[release]\naddress = \"0.0.0.0\"\n"
+        );
+        let contents =
+            set_service_config_in_toml(&contents, None, Some(Path::new("layouts"))).unwrap();
+        assert!(contents.contains("port = 8080"));
+        assert!(contents.contains("layouts_path = \"layouts\""));
+        assert!(contents.contains("address = \"0.0.0.0\""));
+
+        let contents = set_service_config_in_toml("", Some(5781), None).unwrap();
+        assert_eq!(contents, "[default]\nport = 5781\n");
+
+        // A port in the release profile would override the new one
+        let contents = "[default]\nstatic_dir = \"static\"\n\n[release]\nport = 5781\n";
+        let contents = set_service_config_in_toml(contents, Some(8080), None).unwrap();
+        assert_eq!(
+            contents,
+            "[default]\nstatic_dir = \"static\"\nport = 8080\n\n[release]\n"
+        );
+    }
+}
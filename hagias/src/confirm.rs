@@ -0,0 +1,179 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use tracing::{info, warn};
+
+use crate::{display::DisplayLayout, in_flight::InFlightApplies};
+
+/// How long the user has to confirm an applied layout before it is reverted
+pub const CONFIRM_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// A change that must be confirmed with its token before it times out.
+///
+/// Only one change can be pending at a time, and starting a new one replaces the old one.
+pub struct Confirmations<T> {
+    pending: Mutex<Option<(String, T)>>,
+    counter: AtomicU64,
+}
+
+impl<T> Default for Confirmations<T> {
+    fn default() -> Self {
+        Self {
+            pending: Mutex::new(None),
+            counter: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<T> Confirmations<T> {
+    /// Start a pending change, returning its token
+    pub fn begin(&self, value: T) -> String {
+        let mut hasher = RandomState::new().build_hasher();
+        let count = self.counter.fetch_add(1, Ordering::Relaxed);
+        hasher.write_u64(count);
+        let token = format!("{:x}{:016x}", count, hasher.finish());
+        *self.pending.lock().expect("failed to lock confirmations") = Some((token.clone(), value));
+        token
+    }
+
+    /// Take the pending change if it has the given token
+    pub fn take(&self, token: &str) -> Option<T> {
+        let mut pending = self.pending.lock().expect("failed to lock confirmations");
+        if pending.as_ref().is_some_and(|(t, _)| t == token) {
+            pending.take().map(|(_, value)| value)
+        } else {
+            None
+        }
+    }
+
+    /// Take the pending change if `matches` returns true for it, whatever its token
+    pub fn take_if(&self, matches: impl FnOnce(&T) -> bool) -> Option<T> {
+        let mut pending = self.pending.lock().expect("failed to lock confirmations");
+        if pending.as_ref().is_some_and(|(_, value)| matches(value)) {
+            pending.take().map(|(_, value)| value)
+        } else {
+            None
+        }
+    }
+
+    /// Look at the pending change, whatever its token, leaving it pending
+    pub fn peek_any<R>(&self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        self.pending
+            .lock()
+            .expect("failed to lock confirmations")
+            .as_ref()
+            .map(|(_, value)| f(value))
+    }
+}
+
+/// A layout that has been applied from the web UI, but not yet confirmed
+pub struct PendingApply {
+    pub id: String,
+    pub name: String,
+    pub layout: DisplayLayout,
+    /// The layout to revert to if the apply isn't confirmed
+    pub previous: DisplayLayout,
+}
+
+/// Layouts applied from the web UI that are waiting for confirmation
+#[derive(Clone, Default)]
+pub struct ApplyConfirmations {
+    confirmations: Arc<Confirmations<PendingApply>>,
+    /// Tracks reverts after a timeout, so shutdown waits for them
+    in_flight: InFlightApplies,
+}
+
+impl ApplyConfirmations {
+    pub fn new(in_flight: InFlightApplies) -> Self {
+        Self {
+            confirmations: Arc::default(),
+            in_flight,
+        }
+    }
+
+    /// Start waiting for confirmation of an applied layout, reverting it if it isn't confirmed
+    /// within [`CONFIRM_TIMEOUT`]
+    pub fn begin(&self, pending: PendingApply) -> String {
+        let token = self.confirmations.begin(pending);
+        let confirmations = self.clone();
+        let timeout_token = token.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(CONFIRM_TIMEOUT).await;
+            if let Some(pending) = confirmations.take(&timeout_token) {
+                info!(
+                    "Layout {} \"{}\" was not confirmed, reverting",
+                    pending.id, pending.name
+                );
+                let _guard = confirmations.in_flight.begin();
+                if let Err(e) = pending.previous.apply(false, false, true) {
+                    warn!("Failed to revert layout {}: {:?}", pending.id, e);
+                }
+            }
+        });
+        token
+    }
+
+    pub fn take(&self, token: &str) -> Option<PendingApply> {
+        self.confirmations.take(token)
+    }
+
+    /// Take the pending apply if it's of the layout with ID `id`
+    pub fn take_by_id(&self, id: &str) -> Option<PendingApply> {
+        self.confirmations.take_if(|pending| pending.id == id)
+    }
+
+    /// The layout that the pending apply would revert to, if any
+    pub fn pending_previous(&self) -> Option<DisplayLayout> {
+        self.confirmations
+            .peek_any(|pending| pending.previous.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Confirmations;
+
+    #[test]
+    fn test_take_requires_matching_token() {
+        let confirmations = Confirmations::default();
+        let token = confirmations.begin(1);
+        assert_eq!(confirmations.take("wrong"), None);
+        assert_eq!(confirmations.take(&token), Some(1));
+        assert_eq!(confirmations.take(&token), None);
+    }
+
+    #[test]
+    fn test_begin_replaces_pending() {
+        let confirmations = Confirmations::default();
+        let old_token = confirmations.begin(1);
+        let new_token = confirmations.begin(2);
+        assert_ne!(old_token, new_token);
+        assert_eq!(confirmations.take(&old_token), None);
+        assert_eq!(confirmations.take(&new_token), Some(2));
+    }
+
+    #[test]
+    fn test_take_if() {
+        let confirmations = Confirmations::default();
+        confirmations.begin(1);
+        assert_eq!(confirmations.take_if(|value| *value == 2), None);
+        assert_eq!(confirmations.take_if(|value| *value == 1), Some(1));
+        assert_eq!(confirmations.take_if(|_| true), None);
+    }
+
+    #[test]
+    fn test_peek_any() {
+        let confirmations = Confirmations::default();
+        assert_eq!(confirmations.peek_any(|value: &i32| *value), None);
+        let token = confirmations.begin(1);
+        assert_eq!(confirmations.peek_any(|value| *value), Some(1));
+        assert_eq!(confirmations.take(&token), Some(1));
+    }
+}
@@ -0,0 +1,91 @@
+use anyhow::{Result, bail};
+use rocket::{
+    Request, Response,
+    fairing::{Fairing, Info, Kind},
+    http::{Header, Method, Status, uri::Absolute},
+    options,
+};
+
+/// Adds CORS headers to `/api` responses for requests from one of the allowed origins
+pub struct Cors {
+    allowed_origins: Vec<String>,
+}
+
+impl Cors {
+    pub fn new(allowed_origins: Vec<String>) -> Self {
+        Self { allowed_origins }
+    }
+
+    fn is_allowed(&self, origin: &str) -> bool {
+        self.allowed_origins
+            .iter()
+            .any(|allowed| allowed == "*" || allowed.eq_ignore_ascii_case(origin))
+    }
+}
+
+/// Check that an allowed origin is either `*` or of the form `scheme://host[:port]`
+pub fn validate_origin(origin: &str) -> Result<()> {
+    if origin == "*" {
+        return Ok(());
+    }
+    let Ok(uri) = Absolute::parse(origin) else {
+        bail!("Invalid origin {:?}: not an absolute URI", origin);
+    };
+    if !matches!(uri.scheme(), "http" | "https") {
+        bail!("Invalid origin {:?}: scheme must be http or https", origin);
+    }
+    if uri.authority().is_none() {
+        bail!("Invalid origin {:?}: missing host", origin);
+    }
+    if !uri.path().is_empty() || uri.query().is_some() {
+        bail!("Invalid origin {:?}: must not have a path or query", origin);
+    }
+    Ok(())
+}
+
+#[rocket::async_trait]
+impl Fairing for Cors {
+    fn info(&self) -> Info {
+        Info {
+            name: "CORS",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        if !request.uri().path().starts_with("/api/") {
+            return;
+        }
+        let Some(origin) = request.headers().get_one("Origin") else {
+            return;
+        };
+        if !self.is_allowed(origin) {
+            return;
+        }
+        response.set_header(Header::new(
+            "Access-Control-Allow-Origin",
+            origin.to_owned(),
+        ));
+        response.adjoin_header(Header::new("Vary", "Origin"));
+        if request.method() == Method::Options {
+            response.set_header(Header::new(
+                "Access-Control-Allow-Methods",
+                "GET, POST, OPTIONS",
+            ));
+            let allowed_headers = request
+                .headers()
+                .get_one("Access-Control-Request-Headers")
+                .unwrap_or("Content-Type");
+            response.set_header(Header::new(
+                "Access-Control-Allow-Headers",
+                allowed_headers.to_owned(),
+            ));
+        }
+    }
+}
+
+/// Responds to CORS preflight requests for the API
+#[options("/api/<_..>")]
+pub fn preflight() -> Status {
+    Status::NoContent
+}
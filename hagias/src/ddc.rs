@@ -0,0 +1,245 @@
+use std::{ffi::OsStr, time::Duration};
+
+use anyhow::{Context, Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+use windows::{
+    Win32::{
+        Devices::Display::{
+            DestroyPhysicalMonitors, GetNumberOfPhysicalMonitorsFromHMONITOR,
+            GetPhysicalMonitorsFromHMONITOR, PHYSICAL_MONITOR, SetVCPFeature,
+        },
+        Foundation::{LPARAM, RECT},
+        Graphics::Gdi::{EnumDisplayMonitors, GetMonitorInfoW, HDC, HMONITOR, MONITORINFOEXW},
+    },
+    core::BOOL,
+};
+
+use crate::{monitors::Monitor, windows_util::wchar_null_terminated_to_os_string};
+
+/// The MCCS VCP code for the display power mode
+const VCP_POWER_MODE: u8 = 0xD6;
+
+/// How long a monitor has to respond when its power state is set from the web UI
+pub const POWER_STATE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The power state of a monitor, as set over DDC/CI
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum PowerState {
+    On,
+    Standby,
+    Off,
+}
+
+impl PowerState {
+    fn vcp_value(self) -> u32 {
+        match self {
+            PowerState::On => 0x01,
+            PowerState::Standby => 0x02,
+            PowerState::Off => 0x05,
+        }
+    }
+}
+
+/// Physical monitor handles, destroyed on drop
+struct PhysicalMonitors {
+    monitors: Vec<PHYSICAL_MONITOR>,
+    destroy: fn(&[PHYSICAL_MONITOR]) -> windows::core::Result<()>,
+}
+
+fn destroy_physical_monitors(monitors: &[PHYSICAL_MONITOR]) -> windows::core::Result<()> {
+    unsafe { DestroyPhysicalMonitors(monitors) }
+}
+
+impl PhysicalMonitors {
+    fn get(hmonitor: HMONITOR) -> Result<Self> {
+        let mut count = 0;
+        unsafe { GetNumberOfPhysicalMonitorsFromHMONITOR(hmonitor, &mut count) }
+            .context("Failed to get number of physical monitors")?;
+        let mut monitors = vec![PHYSICAL_MONITOR::default(); count as usize];
+        unsafe { GetPhysicalMonitorsFromHMONITOR(hmonitor, &mut monitors) }
+            .context("Failed to get physical monitors")?;
+        Ok(Self {
+            monitors,
+            destroy: destroy_physical_monitors,
+        })
+    }
+
+    fn descriptions(&self) -> impl Iterator<Item = String> {
+        self.monitors.iter().map(|monitor| {
+            // Copy the description out, as `PHYSICAL_MONITOR` is packed
+            let description = monitor.szPhysicalMonitorDescription;
+            wchar_null_terminated_to_os_string(&description)
+                .to_string_lossy()
+                .into_owned()
+        })
+    }
+}
+
+impl Drop for PhysicalMonitors {
+    fn drop(&mut self) {
+        if self.monitors.is_empty() {
+            return;
+        }
+        if let Err(e) = (self.destroy)(&self.monitors) {
+            tracing::warn!("Failed to destroy physical monitors: {:?}", e);
+        }
+    }
+}
+
+unsafe extern "system" fn enum_monitors_callback(
+    hmonitor: HMONITOR,
+    _hdc: HDC,
+    _rect: *mut RECT,
+    data: LPARAM,
+) -> BOOL {
+    let hmonitors = unsafe { &mut *(data.0 as *mut Vec<HMONITOR>) };
+    hmonitors.push(hmonitor);
+    true.into()
+}
+
+/// Get the handles of all display monitors
+fn get_hmonitors() -> Result<Vec<HMONITOR>> {
+    let mut hmonitors: Vec<HMONITOR> = Vec::new();
+    unsafe {
+        EnumDisplayMonitors(
+            None,
+            None,
+            Some(enum_monitors_callback),
+            LPARAM(&mut hmonitors as *mut _ as isize),
+        )
+    }
+    .ok()
+    .context("Failed to enumerate display monitors")?;
+    Ok(hmonitors)
+}
+
+/// Find the display monitor handle for the given GDI device name
+fn find_hmonitor(gdi_device_name: &OsStr) -> Result<Option<HMONITOR>> {
+    for hmonitor in get_hmonitors()? {
+        let mut info = MONITORINFOEXW::default();
+        info.monitorInfo.cbSize = size_of::<MONITORINFOEXW>() as u32;
+        if !unsafe { GetMonitorInfoW(hmonitor, &mut info.monitorInfo) }.as_bool() {
+            continue;
+        }
+        if wchar_null_terminated_to_os_string(&info.szDevice) == gdi_device_name {
+            return Ok(Some(hmonitor));
+        }
+    }
+    Ok(None)
+}
+
+/// Get the descriptions of all physical monitors
+pub fn get_physical_monitor_descriptions() -> Result<Vec<String>> {
+    let mut descriptions = Vec::new();
+    for hmonitor in get_hmonitors()? {
+        // The handles are destroyed as soon as the descriptions have been read
+        descriptions.extend(PhysicalMonitors::get(hmonitor)?.descriptions());
+    }
+    Ok(descriptions)
+}
+
+/// Get the descriptions of all physical monitors on a blocking task, failing if it takes longer
+/// than `timeout`, as DDC/CI can hang on misbehaving monitors.
+///
+/// The task can't be cancelled, but it still destroys its handles when it eventually finishes.
+pub async fn get_physical_monitor_descriptions_with_timeout(
+    timeout: Duration,
+) -> Result<Vec<String>> {
+    let task = tokio::task::spawn_blocking(get_physical_monitor_descriptions);
+    match tokio::time::timeout(timeout, task).await {
+        Ok(result) => result.context("Physical monitor enumeration panicked")?,
+        Err(_) => bail!(
+            "Enumerating physical monitors timed out after {:?}",
+            timeout
+        ),
+    }
+}
+
+/// Set the power state of a monitor over DDC/CI on a blocking task, failing if it takes longer than
+/// `timeout`, like [`get_physical_monitor_descriptions_with_timeout`]
+pub async fn set_power_state_with_timeout(
+    monitor: Monitor,
+    state: PowerState,
+    timeout: Duration,
+) -> Result<()> {
+    let task = tokio::task::spawn_blocking(move || set_power_state(&monitor, state));
+    match tokio::time::timeout(timeout, task).await {
+        Ok(result) => result.context("Setting the power state panicked")?,
+        Err(_) => bail!("Setting the power state timed out after {:?}", timeout),
+    }
+}
+
+/// Set the power state of a monitor over DDC/CI
+pub fn set_power_state(monitor: &Monitor, state: PowerState) -> Result<()> {
+    let Some(gdi_device_name) = &monitor.gdi_device_name else {
+        bail!(
+            "Monitor {} is not active, so cannot be controlled over DDC/CI",
+            monitor.display_name()
+        );
+    };
+    let hmonitor = find_hmonitor(gdi_device_name)?
+        .ok_or_else(|| anyhow!("No display monitor found for {}", gdi_device_name.display()))?;
+    let physical_monitors = PhysicalMonitors::get(hmonitor)?;
+    let physical_monitor = match physical_monitors.monitors.as_slice() {
+        [physical_monitor] => physical_monitor,
+        [] => bail!("Monitor {} does not support DDC/CI", monitor.display_name()),
+        _ => bail!(
+            "Monitor {} is cloned with other monitors, so cannot be controlled individually",
+            monitor.display_name()
+        ),
+    };
+    if unsafe {
+        SetVCPFeature(
+            physical_monitor.hPhysicalMonitor,
+            VCP_POWER_MODE,
+            state.vcp_value(),
+        )
+    } == 0
+    {
+        return Err(windows::core::Error::from_win32()).with_context(|| {
+            format!(
+                "Failed to set power state of monitor {} (does it support DDC/CI?)",
+                monitor.display_name()
+            )
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use windows::Win32::{Devices::Display::PHYSICAL_MONITOR, Foundation::HANDLE};
+
+    use super::PhysicalMonitors;
+
+    #[test]
+    fn test_physical_monitors_destroyed_once() {
+        static DESTROYED: AtomicUsize = AtomicUsize::new(0);
+        fn destroy(monitors: &[PHYSICAL_MONITOR]) -> windows::core::Result<()> {
+            DESTROYED.fetch_add(monitors.len(), Ordering::SeqCst);
+            Ok(())
+        }
+
+        let monitors = PhysicalMonitors {
+            monitors: (1..=2)
+                .map(|i| PHYSICAL_MONITOR {
+                    hPhysicalMonitor: HANDLE(i as _),
+                    ..Default::default()
+                })
+                .collect(),
+            destroy,
+        };
+        drop(monitors);
+        assert_eq!(DESTROYED.load(Ordering::SeqCst), 2);
+
+        // Nothing to destroy if there are no monitors
+        drop(PhysicalMonitors {
+            monitors: Vec::new(),
+            destroy,
+        });
+        assert_eq!(DESTROYED.load(Ordering::SeqCst), 2);
+    }
+}
@@ -1,39 +1,154 @@
 use std::{
-    collections::{HashMap, hash_map},
+    collections::{BTreeSet, HashMap, HashSet, hash_map},
     ffi::OsString,
 };
 
 use anyhow::{Result, anyhow, bail};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
 use windows::Win32::{
     Devices::Display::{
-        DISPLAYCONFIG_MODE_INFO, DISPLAYCONFIG_MODE_INFO_0, DISPLAYCONFIG_MODE_INFO_TYPE_SOURCE,
+        DISPLAYCONFIG_DESKTOP_IMAGE_INFO, DISPLAYCONFIG_MODE_INFO, DISPLAYCONFIG_MODE_INFO_0,
+        DISPLAYCONFIG_MODE_INFO_TYPE_DESKTOP_IMAGE, DISPLAYCONFIG_MODE_INFO_TYPE_SOURCE,
         DISPLAYCONFIG_MODE_INFO_TYPE_TARGET, DISPLAYCONFIG_PATH_INFO,
-        DISPLAYCONFIG_PATH_SOURCE_INFO, DISPLAYCONFIG_PATH_SOURCE_INFO_0,
-        DISPLAYCONFIG_PATH_TARGET_INFO, DISPLAYCONFIG_PATH_TARGET_INFO_0,
+        DISPLAYCONFIG_PATH_SOURCE_INFO, DISPLAYCONFIG_PATH_TARGET_INFO, DISPLAYCONFIG_RATIONAL,
         DISPLAYCONFIG_SOURCE_MODE, DISPLAYCONFIG_TARGET_MODE, DISPLAYCONFIG_VIDEO_SIGNAL_INFO,
-        DISPLAYCONFIG_VIDEO_SIGNAL_INFO_0,
+        DISPLAYCONFIG_VIDEO_SIGNAL_INFO_0, DISPLAYCONFIG_VIDEO_SIGNAL_INFO_0_0,
     },
     Graphics::Gdi::{
-        DISPLAYCONFIG_PATH_ACTIVE, DISPLAYCONFIG_PATH_SUPPORT_VIRTUAL_MODE,
+        DISPLAYCONFIG_PATH_ACTIVE, DISPLAYCONFIG_PATH_CLONE_GROUP_INVALID,
+        DISPLAYCONFIG_PATH_DESKTOP_IMAGE_IDX_INVALID, DISPLAYCONFIG_PATH_MODE_IDX_INVALID,
+        DISPLAYCONFIG_PATH_PREFERRED_UNSCALED, DISPLAYCONFIG_PATH_SOURCE_MODE_IDX_INVALID,
+        DISPLAYCONFIG_PATH_SUPPORT_VIRTUAL_MODE, DISPLAYCONFIG_PATH_TARGET_MODE_IDX_INVALID,
         DISPLAYCONFIG_SOURCE_IN_USE, DISPLAYCONFIG_TARGET_IN_USE,
     },
 };
 
 use crate::windows_util::{
-    DisplayQueryType, DisplayRotation, DisplayScaling, IdAndAdapterId, LuidWrapper,
-    OutputTechnology, PixelFormat, Point, Rational, Region, ScanlineOrdering, VideoStandard,
-    WindowsDisplayConfig, get_adapter_device_path, get_monitor_device_path,
-    get_monitor_friendly_device_name, get_source_device_name, get_target_device_name,
-    is_target_device_edid_ids_valid, wchar_null_terminated_to_os_string,
+    DISPLAYCONFIG_PATH_BOOST_REFRESH_RATE, DisplayQueryType, DisplayRotation, DisplayScaling,
+    DisplayTopology, IdAndAdapterId, LuidWrapper, OsVersion, OutputTechnology, PixelFormat, Point,
+    Rational, Rectangle, Region, ScanlineOrdering, VideoStandard, WindowsDisplayConfig,
+    get_adapter_device_path, get_advanced_color_enabled, get_monitor_device_path,
+    get_monitor_friendly_device_name, get_sdr_white_level, get_source_device_name,
+    get_supported_bits_per_pixel, get_supported_refresh_rates, get_target_device_name,
+    is_target_device_edid_ids_valid, normalize_device_path, set_advanced_color_enabled,
+    set_sdr_white_level, valid_index, wchar_null_terminated_to_os_string,
 };
+use crate::{
+    edid,
+    monitors::{Monitor, find_alias},
+};
+
+/// Path flags that aren't stored in a layout, as the active flag is always set when applying. All
+/// other flags, including the virtual mode flag, are stored and re-applied.
+const UNSTORED_PATH_FLAGS: u32 = DISPLAYCONFIG_PATH_ACTIVE;
+
+/// Path flags that are understood. Newer versions of Windows may add others, which are kept as-is.
+const KNOWN_PATH_FLAGS: u32 = UNSTORED_PATH_FLAGS
+    | DISPLAYCONFIG_PATH_SUPPORT_VIRTUAL_MODE
+    | DISPLAYCONFIG_PATH_PREFERRED_UNSCALED
+    | DISPLAYCONFIG_PATH_BOOST_REFRESH_RATE;
+
+/// The flags of a path to store in a layout, including ones that aren't understood, so that they
+/// survive a round-trip
+fn stored_path_flags(flags: u32) -> u32 {
+    flags & !UNSTORED_PATH_FLAGS
+}
+
+/// Whether a path's mode indices are packed for virtual modes
+fn is_virtual_mode(flags: u32) -> bool {
+    flags & DISPLAYCONFIG_PATH_SUPPORT_VIRTUAL_MODE != 0
+}
+
+/// The mode indices of a path. Virtual mode paths pack a clone group ID alongside the source mode
+/// index, and a desktop image mode index alongside the target mode index, 16 bits each.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PathModeIndices {
+    source_mode: Option<u32>,
+    target_mode: Option<u32>,
+    /// Only used by virtual mode paths
+    clone_group_id: Option<u32>,
+    /// Only used by virtual mode paths
+    desktop_image_mode: Option<u32>,
+}
+
+impl PathModeIndices {
+    fn from_path(path: &DISPLAYCONFIG_PATH_INFO) -> Self {
+        if is_virtual_mode(path.flags) {
+            let (source, target) = unsafe {
+                (
+                    path.sourceInfo.Anonymous.Anonymous._bitfield,
+                    path.targetInfo.Anonymous.Anonymous._bitfield,
+                )
+            };
+            Self {
+                source_mode: valid_index(
+                    source & 0xffff,
+                    DISPLAYCONFIG_PATH_SOURCE_MODE_IDX_INVALID,
+                ),
+                target_mode: valid_index(
+                    target & 0xffff,
+                    DISPLAYCONFIG_PATH_TARGET_MODE_IDX_INVALID,
+                ),
+                clone_group_id: valid_index(source >> 16, DISPLAYCONFIG_PATH_CLONE_GROUP_INVALID),
+                desktop_image_mode: valid_index(
+                    target >> 16,
+                    DISPLAYCONFIG_PATH_DESKTOP_IMAGE_IDX_INVALID,
+                ),
+            }
+        } else {
+            let (source, target) = unsafe {
+                (
+                    path.sourceInfo.Anonymous.modeInfoIdx,
+                    path.targetInfo.Anonymous.modeInfoIdx,
+                )
+            };
+            Self {
+                source_mode: valid_index(source, DISPLAYCONFIG_PATH_MODE_IDX_INVALID),
+                target_mode: valid_index(target, DISPLAYCONFIG_PATH_MODE_IDX_INVALID),
+                clone_group_id: None,
+                desktop_image_mode: None,
+            }
+        }
+    }
+
+    /// Write the indices into a path, packing them if it has the virtual mode flag
+    fn write_to(&self, path: &mut DISPLAYCONFIG_PATH_INFO) {
+        if is_virtual_mode(path.flags) {
+            path.sourceInfo.Anonymous.Anonymous._bitfield = (self
+                .clone_group_id
+                .unwrap_or(DISPLAYCONFIG_PATH_CLONE_GROUP_INVALID)
+                << 16)
+                | self
+                    .source_mode
+                    .unwrap_or(DISPLAYCONFIG_PATH_SOURCE_MODE_IDX_INVALID);
+            path.targetInfo.Anonymous.Anonymous._bitfield = (self
+                .desktop_image_mode
+                .unwrap_or(DISPLAYCONFIG_PATH_DESKTOP_IMAGE_IDX_INVALID)
+                << 16)
+                | self
+                    .target_mode
+                    .unwrap_or(DISPLAYCONFIG_PATH_TARGET_MODE_IDX_INVALID);
+        } else {
+            path.sourceInfo.Anonymous.modeInfoIdx = self
+                .source_mode
+                .unwrap_or(DISPLAYCONFIG_PATH_MODE_IDX_INVALID);
+            path.targetInfo.Anonymous.modeInfoIdx = self
+                .target_mode
+                .unwrap_or(DISPLAYCONFIG_PATH_MODE_IDX_INVALID);
+        }
+    }
+}
 
 struct DisplayConfigBuilder {
     source_modes: Vec<DisplaySourceMode>,
     target_modes: Vec<DisplayTargetMode>,
+    desktop_image_modes: Vec<DisplayDesktopImageMode>,
     paths: Vec<DisplayPath>,
     windows_display_source_mode_to_index: HashMap<u32, usize>,
     windows_display_target_mode_to_index: HashMap<u32, usize>,
+    windows_display_desktop_image_mode_to_index: HashMap<u32, usize>,
     target_devices: HashMap<IdAndAdapterId, DisplayTargetDevice>,
     source_devices: HashMap<IdAndAdapterId, DisplaySourceDevice>,
     adapters: HashMap<LuidWrapper, Adapter>,
@@ -43,9 +158,11 @@ impl DisplayConfigBuilder {
         Self {
             source_modes: Vec::new(),
             target_modes: Vec::new(),
+            desktop_image_modes: Vec::new(),
             paths: Vec::new(),
             windows_display_source_mode_to_index: HashMap::new(),
             windows_display_target_mode_to_index: HashMap::new(),
+            windows_display_desktop_image_mode_to_index: HashMap::new(),
             target_devices: HashMap::new(),
             source_devices: HashMap::new(),
             adapters: HashMap::new(),
@@ -78,11 +195,36 @@ impl DisplayConfigBuilder {
         path: &DISPLAYCONFIG_PATH_INFO,
         windows_display_config: &WindowsDisplayConfig,
     ) -> Result<usize> {
-        let source_mode_index = self.get_source_index_from_path(&path, windows_display_config)?;
-        let target_mode_index = self.get_target_index_from_path(&path, windows_display_config)?;
+        let indices = PathModeIndices::from_path(path);
+        let windows_source_mode_index = indices
+            .source_mode
+            .ok_or_else(|| anyhow!("Path to target #{} has no source mode", path.targetInfo.id))?;
+        let source_mode_index =
+            self.get_source_mode_index(windows_source_mode_index, windows_display_config)?;
+        let windows_target_mode_index = indices
+            .target_mode
+            .ok_or_else(|| anyhow!("Path to target #{} has no target mode", path.targetInfo.id))?;
+        let target_mode_index =
+            self.get_target_mode_index(windows_target_mode_index, windows_display_config)?;
+        let desktop_image_mode_index = indices
+            .desktop_image_mode
+            .map(|index| self.get_desktop_image_mode_index(index, windows_display_config))
+            .transpose()?;
 
+        let unknown_flags = path.flags & !KNOWN_PATH_FLAGS;
+        if unknown_flags != 0 {
+            info!(
+                "Path to target #{} has unrecognized flags {:#x}, storing them as-is",
+                path.targetInfo.id, unknown_flags
+            );
+        }
         self.paths.push(DisplayPath {
-            source: DisplayPathSource { source_mode_index },
+            flags: stored_path_flags(path.flags),
+            desktop_image_mode_index,
+            source: DisplayPathSource {
+                source_mode_index,
+                clone_group_id: indices.clone_group_id,
+            },
             target: DisplayPathTarget {
                 target_mode_index,
                 output_technology: path.targetInfo.outputTechnology.into(),
@@ -97,11 +239,27 @@ impl DisplayConfigBuilder {
     }
 
     pub fn build(&self) -> DisplayLayout {
-        DisplayLayout {
+        let mut layout = DisplayLayout {
             source_modes: self.source_modes.clone(),
             target_modes: self.target_modes.clone(),
+            desktop_image_modes: self.desktop_image_modes.clone(),
             paths: self.paths.clone(),
-        }
+            primary_monitor_device_path: None,
+        };
+        // Windows makes the source at the desktop origin the primary display
+        layout.primary_monitor_device_path = layout
+            .paths
+            .iter()
+            .find(|path| {
+                layout.source_modes[path.source.source_mode_index].position == Point { x: 0, y: 0 }
+            })
+            .and_then(|path| {
+                layout.target_modes[path.target.target_mode_index]
+                    .device
+                    .monitor_device_path
+                    .clone()
+            });
+        layout
     }
 
     fn get_adapter(&mut self, adapter_id: LuidWrapper) -> Result<&Adapter> {
@@ -184,29 +342,10 @@ impl DisplayConfigBuilder {
             .clone();
 
         let windows_target_mode = unsafe { windows_mode_info.Anonymous.targetMode };
-        let (video_standard, v_sync_freq_divider) = if
-        /* WINDOWS_VERSION >= 8.1 */
-        true {
-            unsafe {
-                (
-                    (windows_target_mode
-                        .targetVideoSignalInfo
-                        .Anonymous
-                        .AdditionalSignalInfo
-                        ._bitfield
-                        & 0xFFFF) as i32,
-                    ((windows_target_mode
-                        .targetVideoSignalInfo
-                        .Anonymous
-                        .AdditionalSignalInfo
-                        ._bitfield
-                        >> 16)
-                        & 0b111111) as u32,
-                )
-            }
-        } else {
-            (0, 0)
-        };
+        let (video_standard, v_sync_freq_divider) = parse_video_signal_info(
+            &windows_target_mode.targetVideoSignalInfo,
+            supports_additional_signal_info(),
+        );
         let signal_info = windows_target_mode.targetVideoSignalInfo;
         let target_mode = DisplayTargetMode {
             device: device.clone(),
@@ -215,7 +354,7 @@ impl DisplayConfigBuilder {
             v_sync_freq: signal_info.vSyncFreq.into(),
             active_size: signal_info.activeSize.into(),
             total_size: signal_info.totalSize.into(),
-            video_standard: video_standard.into(),
+            video_standard,
             v_sync_freq_divider,
             scanline_ordering: signal_info.scanLineOrdering.into(),
         };
@@ -226,32 +365,39 @@ impl DisplayConfigBuilder {
         Ok(index)
     }
 
-    fn get_source_index_from_path(
+    fn get_desktop_image_mode_index(
         &mut self,
-        path: &DISPLAYCONFIG_PATH_INFO,
+        windows_desktop_image_mode_index: u32,
         windows_display_config: &WindowsDisplayConfig,
     ) -> Result<usize> {
-        if path.flags & DISPLAYCONFIG_PATH_SUPPORT_VIRTUAL_MODE
-            == DISPLAYCONFIG_PATH_SUPPORT_VIRTUAL_MODE
+        if let Some(&index) = self
+            .windows_display_desktop_image_mode_to_index
+            .get(&windows_desktop_image_mode_index)
         {
-            bail!("Virtual modes are not supported");
+            return Ok(index);
         }
-        let windows_source_mode_index = unsafe { path.sourceInfo.Anonymous.modeInfoIdx };
-        self.get_source_mode_index(windows_source_mode_index, windows_display_config)
-    }
-
-    fn get_target_index_from_path(
-        &mut self,
-        path: &DISPLAYCONFIG_PATH_INFO,
-        windows_display_config: &WindowsDisplayConfig,
-    ) -> Result<usize> {
-        if path.flags & DISPLAYCONFIG_PATH_SUPPORT_VIRTUAL_MODE
-            == DISPLAYCONFIG_PATH_SUPPORT_VIRTUAL_MODE
-        {
-            bail!("Virtual modes are not supported");
+        let windows_mode_info = windows_display_config
+            .modes
+            .get(windows_desktop_image_mode_index as usize)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Desktop image mode #{} not found",
+                    windows_desktop_image_mode_index
+                )
+            })?;
+        if windows_mode_info.infoType != DISPLAYCONFIG_MODE_INFO_TYPE_DESKTOP_IMAGE {
+            bail!(
+                "Mode #{} is not a desktop image mode: {:?}",
+                windows_desktop_image_mode_index,
+                windows_mode_info.infoType
+            );
         }
-        let windows_target_mode_index = unsafe { path.targetInfo.Anonymous.modeInfoIdx };
-        self.get_target_mode_index(windows_target_mode_index, windows_display_config)
+        let desktop_image_info = unsafe { windows_mode_info.Anonymous.desktopImageInfo };
+        self.desktop_image_modes.push(desktop_image_info.into());
+        let index = self.desktop_image_modes.len() - 1;
+        self.windows_display_desktop_image_mode_to_index
+            .insert(windows_desktop_image_mode_index, index);
+        Ok(index)
     }
 
     fn get_source_device(
@@ -298,6 +444,11 @@ impl DisplayConfigBuilder {
             let monitor_friendly_device_name =
                 get_monitor_friendly_device_name(&target_device_name);
             let monitor_device_path = get_monitor_device_path(&target_device_name);
+            let sdr_white_level = get_sdr_white_level(id, adapter_id.into()).ok();
+            let advanced_color_enabled = get_advanced_color_enabled(id, adapter_id.into())
+                .ok()
+                .flatten();
+            let edid_serial = monitor_device_path.as_deref().and_then(edid::get_serial);
             let target_device = DisplayTargetDevice {
                 id,
                 adapter,
@@ -307,6 +458,9 @@ impl DisplayConfigBuilder {
                 connector_instance: target_device_name.connectorInstance,
                 monitor_friendly_device_name,
                 monitor_device_path,
+                sdr_white_level,
+                advanced_color_enabled,
+                edid_serial,
             };
             self.target_devices.insert(id_and_adapter_id, target_device);
         }
@@ -314,12 +468,64 @@ impl DisplayConfigBuilder {
     }
 }
 
+/// Whether `DISPLAYCONFIG_VIDEO_SIGNAL_INFO` contains `AdditionalSignalInfo` rather than just
+/// `videoStandard`, which is the case from Windows 8.1
+fn supports_additional_signal_info() -> bool {
+    OsVersion::get() >= OsVersion::WINDOWS_8_1
+}
+
+/// Get the video standard and vertical sync frequency divider from the video signal info
+fn parse_video_signal_info(
+    signal_info: &DISPLAYCONFIG_VIDEO_SIGNAL_INFO,
+    additional_signal_info: bool,
+) -> (VideoStandard, u32) {
+    if additional_signal_info {
+        let bitfield = unsafe { signal_info.Anonymous.AdditionalSignalInfo._bitfield };
+        (
+            VideoStandard::from((bitfield & 0xFFFF) as i32),
+            (bitfield >> 16) & 0b111111,
+        )
+    } else {
+        let video_standard = unsafe { signal_info.Anonymous.videoStandard };
+        (VideoStandard::from(video_standard as i32), 0)
+    }
+}
+
+/// Inverse of [`parse_video_signal_info`]
+fn video_signal_info_union(
+    video_standard: VideoStandard,
+    v_sync_freq_divider: u32,
+    additional_signal_info: bool,
+) -> DISPLAYCONFIG_VIDEO_SIGNAL_INFO_0 {
+    let video_standard = video_standard.discriminant() as u32;
+    if additional_signal_info {
+        DISPLAYCONFIG_VIDEO_SIGNAL_INFO_0 {
+            AdditionalSignalInfo: DISPLAYCONFIG_VIDEO_SIGNAL_INFO_0_0 {
+                _bitfield: (video_standard & 0xFFFF) | ((v_sync_freq_divider & 0b111111) << 16),
+            },
+        }
+    } else {
+        DISPLAYCONFIG_VIDEO_SIGNAL_INFO_0 {
+            videoStandard: video_standard,
+        }
+    }
+}
+
 /// All active display modes and paths, that can be serialized and restored later.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct DisplayLayout {
     pub source_modes: Vec<DisplaySourceMode>,
     pub target_modes: Vec<DisplayTargetMode>,
+    /// The desktop image modes of virtual mode paths
+    #[serde(default)]
+    pub desktop_image_modes: Vec<DisplayDesktopImageMode>,
     pub paths: Vec<DisplayPath>,
+    /// The device path of the monitor to make the primary display. Its source is moved to the
+    /// desktop origin when applying, along with the others, so the primary doesn't depend on the
+    /// stored positions or the order of the paths.
+    #[serde(default, with = "crate::serde_override::option_os_string")]
+    #[schemars(with = "Option<String>")]
+    pub primary_monitor_device_path: Option<OsString>,
 }
 
 impl DisplayLayout {
@@ -328,9 +534,82 @@ impl DisplayLayout {
         Self::from_windows(&windows_display_config)
     }
 
-    pub fn apply(&self, save_to_database: bool) -> Result<()> {
-        let windows_display_config = self.to_windows()?;
-        windows_display_config.apply(save_to_database)
+    /// Apply the layout. Unless `exact_refresh` is set, refresh rates that the monitors no longer
+    /// support are replaced by the nearest supported rate. Unless `force` is set, it fails with
+    /// [`MissingMonitors`] if any of the layout's monitors aren't connected.
+    pub fn apply(&self, save_to_database: bool, exact_refresh: bool, force: bool) -> Result<()> {
+        if !force {
+            self.ensure_connected()?;
+        }
+        let windows_display_config = self.to_windows(exact_refresh)?;
+        windows_display_config.apply(save_to_database)?;
+        // HDR has to be on for the SDR white level to be set
+        self.apply_advanced_color_states(&windows_display_config);
+        self.apply_sdr_white_levels(&windows_display_config);
+        Ok(())
+    }
+
+    /// Check whether the layout could be applied to the current monitors, without applying it
+    pub fn validate(&self, exact_refresh: bool) -> Result<()> {
+        self.to_windows(exact_refresh)?.validate()
+    }
+
+    /// Restore whether advanced color (HDR) is enabled on the targets, which isn't part of the
+    /// display config. Targets that don't support it are skipped, and failures are only logged.
+    fn apply_advanced_color_states(&self, windows_display_config: &WindowsDisplayConfig) {
+        let windows_target_modes = &windows_display_config.modes[self.source_modes.len()..];
+        for (target_mode, windows_target_mode) in self.target_modes.iter().zip(windows_target_modes)
+        {
+            let Some(enabled) = target_mode.device.advanced_color_enabled else {
+                continue;
+            };
+            let (id, adapter_id) = (windows_target_mode.id, windows_target_mode.adapterId);
+            match get_advanced_color_enabled(id, adapter_id) {
+                Ok(Some(current)) if current == enabled => continue,
+                Ok(Some(_)) => {}
+                Ok(None) => {
+                    debug!("Target #{} doesn't support advanced color", id);
+                    continue;
+                }
+                Err(e) => {
+                    warn!(
+                        "Failed to get advanced color state of target #{}: {:?}",
+                        id, e
+                    );
+                    continue;
+                }
+            }
+            if let Err(e) = set_advanced_color_enabled(id, adapter_id, enabled) {
+                warn!(
+                    "Failed to {} advanced color on target #{}: {:?}",
+                    if enabled { "enable" } else { "disable" },
+                    id,
+                    e
+                );
+            }
+        }
+    }
+
+    /// Restore the SDR white levels of the targets, which aren't part of the display config.
+    /// Failures are only logged, as not all targets support it.
+    fn apply_sdr_white_levels(&self, windows_display_config: &WindowsDisplayConfig) {
+        let windows_target_modes = &windows_display_config.modes[self.source_modes.len()..];
+        for (target_mode, windows_target_mode) in self.target_modes.iter().zip(windows_target_modes)
+        {
+            let Some(sdr_white_level) = target_mode.device.sdr_white_level else {
+                continue;
+            };
+            if let Err(e) = set_sdr_white_level(
+                windows_target_mode.id,
+                windows_target_mode.adapterId,
+                sdr_white_level,
+            ) {
+                warn!(
+                    "Failed to set SDR white level of target #{}: {:?}",
+                    windows_target_mode.id, e
+                );
+            }
+        }
     }
 
     pub fn from_windows(windows_display_config: &WindowsDisplayConfig) -> Result<Self> {
@@ -339,23 +618,484 @@ impl DisplayLayout {
         Ok(builder.build())
     }
 
-    pub fn to_windows(&self) -> Result<WindowsDisplayConfig> {
+    /// Get the layout that Windows has stored in its database for the current set of monitors, and
+    /// its topology if it is one of the default ones.
+    ///
+    /// The database may reference monitors that are currently disconnected, which are skipped.
+    pub fn get_database() -> Result<(Self, Option<DisplayTopology>)> {
+        let windows_display_config = WindowsDisplayConfig::get(DisplayQueryType::Database)?;
+        let mut builder = DisplayConfigBuilder::new();
+        for path in &windows_display_config.paths {
+            if path.flags & DISPLAYCONFIG_PATH_ACTIVE == 0 {
+                continue;
+            }
+            if !path.targetInfo.targetAvailable.as_bool() {
+                warn!(
+                    "Skipping target #{} on adapter {:?}, as it is not connected",
+                    path.targetInfo.id,
+                    windows_display_config
+                        .adapter_device_names
+                        .get(&path.targetInfo.adapterId.into())
+                );
+                continue;
+            }
+            builder.add_path(path, &windows_display_config)?;
+        }
+        let layout = builder.build();
+        if layout.paths.is_empty() {
+            bail!("The Windows database configuration has no connected monitors");
+        }
+        Ok((layout, windows_display_config.topology))
+    }
+
+    /// A copy of the layout with only the paths for which `keep` returns true, dropping the modes
+    /// that are no longer used and re-linking the remaining paths to them
+    pub fn retain_paths(
+        &self,
+        mut keep: impl FnMut(&DisplayPath) -> bool,
+    ) -> Result<DisplayLayout> {
+        let mut retained = DisplayLayout {
+            source_modes: Vec::new(),
+            target_modes: Vec::new(),
+            desktop_image_modes: Vec::new(),
+            paths: Vec::new(),
+            primary_monitor_device_path: self.primary_monitor_device_path.clone(),
+        };
+        let mut source_mode_indices = HashMap::new();
+        let mut target_mode_indices = HashMap::new();
+        let mut desktop_image_mode_indices = HashMap::new();
+        for path in self.paths.iter().filter(|path| keep(path)) {
+            let mut path = path.clone();
+            path.source.source_mode_index =
+                match source_mode_indices.entry(path.source.source_mode_index) {
+                    hash_map::Entry::Occupied(entry) => *entry.get(),
+                    hash_map::Entry::Vacant(entry) => {
+                        retained.source_modes.push(self.source_mode(&path)?.clone());
+                        *entry.insert(retained.source_modes.len() - 1)
+                    }
+                };
+            path.target.target_mode_index =
+                match target_mode_indices.entry(path.target.target_mode_index) {
+                    hash_map::Entry::Occupied(entry) => *entry.get(),
+                    hash_map::Entry::Vacant(entry) => {
+                        retained.target_modes.push(self.target_mode(&path)?.clone());
+                        *entry.insert(retained.target_modes.len() - 1)
+                    }
+                };
+            if let Some(index) = path.desktop_image_mode_index {
+                path.desktop_image_mode_index =
+                    Some(match desktop_image_mode_indices.entry(index) {
+                        hash_map::Entry::Occupied(entry) => *entry.get(),
+                        hash_map::Entry::Vacant(entry) => {
+                            retained
+                                .desktop_image_modes
+                                .push(self.desktop_image_mode(index)?.clone());
+                            *entry.insert(retained.desktop_image_modes.len() - 1)
+                        }
+                    });
+            }
+            retained.paths.push(path);
+        }
+        Ok(retained)
+    }
+
+    /// The source mode of `path`, failing if the layout doesn't have it, e.g. because the layout
+    /// file was edited by hand
+    fn source_mode(&self, path: &DisplayPath) -> Result<&DisplaySourceMode> {
+        self.source_modes
+            .get(path.source.source_mode_index)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Invalid source mode index {}",
+                    path.source.source_mode_index
+                )
+            })
+    }
+
+    /// The target mode of `path`, failing if the layout doesn't have it
+    fn target_mode(&self, path: &DisplayPath) -> Result<&DisplayTargetMode> {
+        self.target_modes
+            .get(path.target.target_mode_index)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Invalid target mode index {}",
+                    path.target.target_mode_index
+                )
+            })
+    }
+
+    /// The desktop image mode at `index`, failing if the layout doesn't have it
+    fn desktop_image_mode(&self, index: usize) -> Result<&DisplayDesktopImageMode> {
+        self.desktop_image_modes
+            .get(index)
+            .ok_or_else(|| anyhow!("Invalid desktop image mode index {}", index))
+    }
+
+    /// A copy of the layout without the paths whose monitors aren't currently connected, along
+    /// with the names of the skipped monitors. Monitors without a device path are kept, as they
+    /// can't be identified.
+    pub fn without_disconnected_monitors(&self) -> Result<(DisplayLayout, Vec<String>)> {
+        let connected = connected_device_paths()?;
+        let mut skipped = Vec::new();
+        let layout = self.retain_paths(|path| {
+            let Some(target_mode) = self.target_modes.get(path.target.target_mode_index) else {
+                // Keep the path, so that its invalid index is reported
+                return true;
+            };
+            let device = &target_mode.device;
+            let is_connected = device
+                .monitor_device_path
+                .as_ref()
+                .is_none_or(|device_path| connected.contains(&normalize_device_path(device_path)));
+            if !is_connected {
+                skipped.push(device.display_name());
+            }
+            is_connected
+        })?;
+        Ok((layout, skipped))
+    }
+
+    /// Fail with [`MissingMonitors`] if any of the monitors in the layout aren't currently
+    /// connected, which would make applying it fail. Monitors without a device path are assumed
+    /// to be connected, as they can't be identified.
+    pub fn ensure_connected(&self) -> Result<()> {
+        let missing = self.missing_monitors(&connected_device_paths()?);
+        if !missing.is_empty() {
+            return Err(MissingMonitors(missing).into());
+        }
+        Ok(())
+    }
+
+    /// The monitors in the layout whose normalized device paths aren't in `connected`
+    fn missing_monitors(&self, connected: &BTreeSet<OsString>) -> Vec<MissingMonitor> {
+        self.paths
+            .iter()
+            .filter_map(|path| {
+                let device = &self.target_modes.get(path.target.target_mode_index)?.device;
+                let device_path = device.monitor_device_path.as_ref()?;
+                (!connected.contains(&normalize_device_path(device_path))).then(|| MissingMonitor {
+                    name: device.display_name(),
+                    device_path: device_path.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// The names of the monitors driven by the layout's paths, in order
+    pub fn monitor_names(&self) -> Vec<String> {
+        self.paths
+            .iter()
+            .filter_map(|path| self.target_modes.get(path.target.target_mode_index))
+            .map(|target_mode| target_mode.device.display_name())
+            .collect()
+    }
+
+    /// The position of the primary monitor's source, which is moved to the desktop origin when
+    /// applying. `None` if there is no primary monitor or it isn't in the layout.
+    fn primary_origin(&self) -> Option<Point> {
+        let primary = normalize_device_path(self.primary_monitor_device_path.as_ref()?);
+        let path = self
+            .paths
+            .iter()
+            .find(|path| self.monitor_key(path).is_ok_and(|key| key == primary))?;
+        Some(
+            self.source_modes
+                .get(path.source.source_mode_index)?
+                .position,
+        )
+    }
+
+    /// Make the monitor matching `monitor` (by name, alias or device path, ignoring case) the
+    /// primary display. Returns `false` if no monitor in the layout matches.
+    pub fn set_primary(&mut self, monitor: &str, aliases: &HashMap<String, String>) -> bool {
+        let device = self.paths.iter().find_map(|path| {
+            let device = &self.target_modes.get(path.target.target_mode_index)?.device;
+            let matches = device
+                .display_name_with_aliases(aliases)
+                .eq_ignore_ascii_case(monitor)
+                || device.display_name().eq_ignore_ascii_case(monitor)
+                || device
+                    .monitor_device_path
+                    .as_ref()
+                    .is_some_and(|path| path.eq_ignore_ascii_case(monitor));
+            matches.then_some(device)
+        });
+        let Some(device_path) = device.and_then(|device| device.monitor_device_path.clone()) else {
+            return false;
+        };
+        self.primary_monitor_device_path = Some(device_path);
+        true
+    }
+
+    /// Whether `other` drives the same monitors in the same way, ignoring IDs and signal timings.
+    /// Positions are compared relative to each layout's primary monitor, and refresh rates within
+    /// 1 Hz count as the same, as Windows rounds fractional rates.
+    pub fn is_equivalent_to(&self, other: &DisplayLayout) -> bool {
+        let (Some(monitors), Some(other_monitors)) =
+            (self.monitor_states(), other.monitor_states())
+        else {
+            return false;
+        };
+        monitors.len() == other_monitors.len()
+            && monitors.iter().all(|(key, state)| {
+                other_monitors
+                    .get(key)
+                    .is_some_and(|other_state| state.is_equivalent_to(other_state))
+            })
+    }
+
+    /// Compare the monitors of the layout with those of `other`, field by field. Monitors are
+    /// matched by device path, or by target or source ID if they don't have one, and listed in the
+    /// order of the layout's paths, followed by those only in `other`.
+    pub fn compare(&self, other: &DisplayLayout) -> Vec<MonitorComparison> {
+        let mut other_paths = other
+            .paths
+            .iter()
+            .map(|path| (other.compare_key(path), path))
+            .collect::<Vec<_>>();
+        let mut comparisons = Vec::new();
+        for path in &self.paths {
+            let key = self.compare_key(path);
+            let name = self.monitor_name(path);
+            match other_paths
+                .iter()
+                .position(|(other_key, _)| *other_key == key)
+            {
+                Some(index) => {
+                    let (_, other_path) = other_paths.remove(index);
+                    let fields = self
+                        .compared_fields(path)
+                        .into_iter()
+                        .zip(other.compared_fields(other_path))
+                        .map(|((field, a), (_, b))| FieldComparison { field, a, b })
+                        .collect();
+                    comparisons.push(MonitorComparison::Both { name, fields });
+                }
+                None => comparisons.push(MonitorComparison::OnlyInSelf(name)),
+            }
+        }
+        for (_, path) in other_paths {
+            comparisons.push(MonitorComparison::OnlyInOther(other.monitor_name(path)));
+        }
+        comparisons
+    }
+
+    /// The key that [`Self::compare`] matches monitors by: the normalized device path, falling
+    /// back to the target ID, or the source ID if the path's target mode is missing
+    fn compare_key(&self, path: &DisplayPath) -> OsString {
+        if let Ok(key) = self.monitor_key(path) {
+            return key;
+        }
+        match self.target_modes.get(path.target.target_mode_index) {
+            Some(target_mode) => format!("target:{}", target_mode.device.id).into(),
+            None => match self.source_modes.get(path.source.source_mode_index) {
+                Some(source_mode) => format!("source:{}", source_mode.device.id).into(),
+                None => OsString::new(),
+            },
+        }
+    }
+
+    /// The name of the monitor a path drives, for display
+    fn monitor_name(&self, path: &DisplayPath) -> String {
+        match self.target_modes.get(path.target.target_mode_index) {
+            Some(target_mode) => target_mode.device.display_name(),
+            None => format!("Target mode #{}", path.target.target_mode_index),
+        }
+    }
+
+    /// The fields of a path that [`Self::compare`] compares, formatted for display
+    fn compared_fields(&self, path: &DisplayPath) -> [(&'static str, String); 4] {
+        let resolution = match self.target_modes.get(path.target.target_mode_index) {
+            Some(target_mode) => format!(
+                "{}x{}",
+                target_mode.active_size.x, target_mode.active_size.y
+            ),
+            None => "?".to_string(),
+        };
+        [
+            ("Resolution", resolution),
+            ("Refresh rate", path.target.refresh_rate.to_string()),
+            ("Rotation", format!("{:?}", path.target.rotation)),
+            ("Scaling", format!("{:?}", path.target.scaling)),
+        ]
+    }
+
+    /// The state of each monitor in the layout, keyed by normalized device path, or `None` if any
+    /// monitor can't be identified
+    fn monitor_states(&self) -> Option<HashMap<OsString, MonitorState>> {
+        let origin = self.primary_origin().unwrap_or(Point { x: 0, y: 0 });
+        self.paths
+            .iter()
+            .map(|path| {
+                let source_mode = self.source_modes.get(path.source.source_mode_index)?;
+                let state = MonitorState {
+                    width: source_mode.width,
+                    height: source_mode.height,
+                    position: Point {
+                        x: source_mode.position.x - origin.x,
+                        y: source_mode.position.y - origin.y,
+                    },
+                    rotation: path.target.rotation,
+                    scaling: path.target.scaling,
+                    refresh_rate: path.target.refresh_rate,
+                };
+                Some((self.monitor_key(path).ok()?, state))
+            })
+            .collect()
+    }
+
+    /// The keys identifying the monitors in the layout, see [`Self::monitor_key`], or `None` if any
+    /// monitor can't be identified
+    pub fn monitor_keys(&self) -> Option<BTreeSet<OsString>> {
+        self.paths
+            .iter()
+            .map(|path| self.monitor_key(path).ok())
+            .collect()
+    }
+
+    /// The key identifying the monitor driven by `path`: its normalized device path
+    fn monitor_key(&self, path: &DisplayPath) -> Result<OsString> {
+        let target_mode = self.target_mode(path)?;
+        let device_path = target_mode
+            .device
+            .monitor_device_path
+            .as_ref()
+            .ok_or_else(|| {
+                anyhow!(
+                    "Target #{} has no monitor device path",
+                    target_mode.device.id
+                )
+            })?;
+        Ok(normalize_device_path(device_path))
+    }
+
+    /// Whether two paths drive their monitor identically, ignoring the IDs of their sources
+    fn same_monitor_config(
+        &self,
+        path: &DisplayPath,
+        other: &DisplayLayout,
+        other_path: &DisplayPath,
+    ) -> Result<bool> {
+        let source_modes = (self.source_mode(path)?, other.source_mode(other_path)?);
+        let target_modes = (self.target_mode(path)?, other.target_mode(other_path)?);
+        let (a, b) = (&path.target, &other_path.target);
+        Ok(source_modes.0.width == source_modes.1.width
+            && source_modes.0.height == source_modes.1.height
+            && source_modes.0.pixel_format == source_modes.1.pixel_format
+            && source_modes.0.position == source_modes.1.position
+            && target_modes.0 == target_modes.1
+            && path.flags == other_path.flags
+            && a.output_technology == b.output_technology
+            && a.rotation == b.rotation
+            && a.scaling == b.scaling
+            && a.refresh_rate == b.refresh_rate
+            && a.scanline_ordering == b.scanline_ordering)
+    }
+
+    /// Combine the monitors of two layouts into one, keyed by monitor device path.
+    ///
+    /// Monitors in both layouts must be configured identically. Sources of `other` whose IDs are
+    /// already used on the same adapter are given unused IDs.
+    pub fn merge(&self, other: &DisplayLayout) -> Result<DisplayLayout> {
+        let mut merged = self.clone();
+        let mut monitor_paths = HashMap::new();
+        for (index, path) in merged.paths.iter().enumerate() {
+            monitor_paths.insert(merged.monitor_key(path)?, index);
+        }
+        let mut used_source_ids: HashSet<(OsString, u32)> = merged
+            .source_modes
+            .iter()
+            .map(|mode| {
+                (
+                    normalize_device_path(&mode.device.adapter.device_instance_path),
+                    mode.device.id,
+                )
+            })
+            .collect();
+
+        // Map of source mode indices in `other` => indices in `merged`, so cloned paths stay cloned
+        let mut source_mode_indices: HashMap<usize, usize> = HashMap::new();
+        for path in &other.paths {
+            let key = other.monitor_key(path)?;
+            if let Some(&index) = monitor_paths.get(&key) {
+                let existing = &merged.paths[index];
+                if !merged.same_monitor_config(existing, other, path)? {
+                    bail!(
+                        "Monitor {:?} is configured differently in both layouts",
+                        key
+                    );
+                }
+                source_mode_indices.insert(
+                    path.source.source_mode_index,
+                    existing.source.source_mode_index,
+                );
+                continue;
+            }
+
+            let source_mode_index = match source_mode_indices.entry(path.source.source_mode_index) {
+                hash_map::Entry::Occupied(entry) => *entry.get(),
+                hash_map::Entry::Vacant(entry) => {
+                    let mut source_mode = other.source_mode(path)?.clone();
+                    let adapter_path =
+                        normalize_device_path(&source_mode.device.adapter.device_instance_path);
+                    if used_source_ids.contains(&(adapter_path.clone(), source_mode.device.id)) {
+                        source_mode.device.id = (0..)
+                            .find(|id| !used_source_ids.contains(&(adapter_path.clone(), *id)))
+                            .expect("ran out of source IDs");
+                    }
+                    used_source_ids.insert((adapter_path, source_mode.device.id));
+                    merged.source_modes.push(source_mode);
+                    *entry.insert(merged.source_modes.len() - 1)
+                }
+            };
+            merged.target_modes.push(other.target_mode(path)?.clone());
+
+            let mut path = path.clone();
+            path.source.source_mode_index = source_mode_index;
+            path.target.target_mode_index = merged.target_modes.len() - 1;
+            if let Some(index) = path.desktop_image_mode_index {
+                merged
+                    .desktop_image_modes
+                    .push(other.desktop_image_mode(index)?.clone());
+                path.desktop_image_mode_index = Some(merged.desktop_image_modes.len() - 1);
+            }
+            monitor_paths.insert(key, merged.paths.len());
+            merged.paths.push(path);
+        }
+        Ok(merged)
+    }
+
+    pub fn to_windows(&self, exact_refresh: bool) -> Result<WindowsDisplayConfig> {
         let windows_display_config = WindowsDisplayConfig::get(DisplayQueryType::All)?;
 
         let mut new_windows_modes = Vec::new();
         let mut new_windows_paths = Vec::new();
 
-        // Get device path => adapter IDs
+        // Get device path => adapter IDs (keyed by normalized device path, as they are case-insensitive)
         let device_path_to_adapter_id = windows_display_config
             .adapter_device_names
             .iter()
-            .map(|(adapter_id, device_path)| (device_path.clone(), adapter_id.clone()))
+            .map(|(adapter_id, device_path)| (normalize_device_path(device_path), *adapter_id))
             .collect::<HashMap<OsString, LuidWrapper>>();
 
+        // Move the primary monitor to the desktop origin, keeping the others in the same place
+        // relative to it
+        let origin = self.primary_origin().unwrap_or_else(|| {
+            if let Some(primary) = &self.primary_monitor_device_path {
+                warn!(
+                    "Primary monitor {} isn't in the layout, keeping the stored positions",
+                    primary.display()
+                );
+            }
+            Point { x: 0, y: 0 }
+        });
+
         // Populate source modes
         for source_mode in self.source_modes.iter() {
             let adapter_id = *device_path_to_adapter_id
-                .get(&source_mode.device.adapter.device_instance_path)
+                .get(&normalize_device_path(
+                    &source_mode.device.adapter.device_instance_path,
+                ))
                 .ok_or_else(|| {
                     anyhow!(
                         "Adapter ID not found for device path: {:?}",
@@ -364,6 +1104,7 @@ impl DisplayLayout {
                 })?;
 
             // TODO: Map GDI device name instead of direct ID
+            let pixel_format = validated_pixel_format(source_mode, adapter_id);
 
             let windows_source_mode = DISPLAYCONFIG_MODE_INFO {
                 id: source_mode.device.id,
@@ -373,8 +1114,12 @@ impl DisplayLayout {
                     sourceMode: DISPLAYCONFIG_SOURCE_MODE {
                         width: source_mode.width,
                         height: source_mode.height,
-                        pixelFormat: source_mode.pixel_format.into(),
-                        position: source_mode.position.into(),
+                        pixelFormat: pixel_format.into(),
+                        position: Point {
+                            x: source_mode.position.x - origin.x,
+                            y: source_mode.position.y - origin.y,
+                        }
+                        .into(),
                     },
                 },
             };
@@ -384,7 +1129,9 @@ impl DisplayLayout {
         // Populate target modes
         for target_mode in self.target_modes.iter() {
             let adapter_id = *device_path_to_adapter_id
-                .get(&target_mode.device.adapter.device_instance_path)
+                .get(&normalize_device_path(
+                    &target_mode.device.adapter.device_instance_path,
+                ))
                 .ok_or_else(|| {
                     anyhow!(
                         "Adapter ID not found for device path: {:?}",
@@ -408,9 +1155,11 @@ impl DisplayLayout {
                             vSyncFreq: target_mode.v_sync_freq.into(),
                             activeSize: target_mode.active_size.into(),
                             totalSize: target_mode.total_size.into(),
-                            Anonymous: DISPLAYCONFIG_VIDEO_SIGNAL_INFO_0 {
-                                videoStandard: target_mode.video_standard.discriminant() as u32,
-                            },
+                            Anonymous: video_signal_info_union(
+                                target_mode.video_standard,
+                                target_mode.v_sync_freq_divider,
+                                supports_additional_signal_info(),
+                            ),
                             scanLineOrdering: target_mode.scanline_ordering.into(),
                         },
                     },
@@ -422,6 +1171,8 @@ impl DisplayLayout {
         // Populate paths
         for path in self.paths.iter() {
             // Get source and target modes
+            let source_mode = self.source_mode(path)?;
+            let target_mode = self.target_mode(path)?;
             let source_windows_mode = new_windows_modes[path.source.source_mode_index];
             assert!(source_windows_mode.infoType == DISPLAYCONFIG_MODE_INFO_TYPE_SOURCE);
             let target_windows_mode =
@@ -433,31 +1184,85 @@ impl DisplayLayout {
             let target_mode_index =
                 (path.target.target_mode_index + self.source_modes.len()) as u32;
 
-            let windows_path = DISPLAYCONFIG_PATH_INFO {
+            // Virtual mode paths also need a desktop image mode. Layouts stored without one show the
+            // whole source on the target.
+            let desktop_image_mode_index = if is_virtual_mode(path.flags) {
+                let desktop_image_mode = match path.desktop_image_mode_index {
+                    Some(index) => self.desktop_image_mode(index)?.clone(),
+                    None => {
+                        let size = Region {
+                            x: source_mode.width,
+                            y: source_mode.height,
+                        };
+                        let source_rect = Rectangle {
+                            position: Point { x: 0, y: 0 },
+                            size,
+                        };
+                        DisplayDesktopImageMode {
+                            path_source_size: size,
+                            desktop_image_region: source_rect,
+                            desktop_image_clip: source_rect,
+                        }
+                    }
+                };
+                new_windows_modes.push(DISPLAYCONFIG_MODE_INFO {
+                    id: target_windows_mode.id,
+                    adapterId: target_windows_mode.adapterId,
+                    infoType: DISPLAYCONFIG_MODE_INFO_TYPE_DESKTOP_IMAGE,
+                    Anonymous: DISPLAYCONFIG_MODE_INFO_0 {
+                        desktopImageInfo: desktop_image_mode.into(),
+                    },
+                });
+                Some((new_windows_modes.len() - 1) as u32)
+            } else {
+                None
+            };
+
+            let mut refresh_rate = path.target.refresh_rate;
+            if !exact_refresh
+                && let Some(substitute) =
+                    substitute_refresh_rate(path, source_mode, target_mode, source_windows_mode)
+            {
+                refresh_rate = substitute;
+                let target_windows_mode = &mut new_windows_modes[target_mode_index as usize];
+                target_windows_mode
+                    .Anonymous
+                    .targetMode
+                    .targetVideoSignalInfo = substitute_signal_info(
+                    &windows_display_config,
+                    target_windows_mode,
+                    substitute,
+                );
+            }
+
+            let mut windows_path = DISPLAYCONFIG_PATH_INFO {
                 sourceInfo: DISPLAYCONFIG_PATH_SOURCE_INFO {
                     adapterId: source_windows_mode.adapterId,
                     id: source_windows_mode.id,
-                    Anonymous: DISPLAYCONFIG_PATH_SOURCE_INFO_0 {
-                        modeInfoIdx: source_mode_index,
-                    },
                     statusFlags: DISPLAYCONFIG_SOURCE_IN_USE,
+                    ..Default::default()
                 },
                 targetInfo: DISPLAYCONFIG_PATH_TARGET_INFO {
                     adapterId: target_windows_mode.adapterId,
                     id: target_windows_mode.id,
-                    Anonymous: DISPLAYCONFIG_PATH_TARGET_INFO_0 {
-                        modeInfoIdx: target_mode_index,
-                    },
                     outputTechnology: path.target.output_technology.into(),
                     rotation: path.target.rotation.into(),
                     scaling: path.target.scaling.into(),
-                    refreshRate: path.target.refresh_rate.into(),
+                    refreshRate: refresh_rate.into(),
                     scanLineOrdering: path.target.scanline_ordering.into(),
                     targetAvailable: true.into(),
                     statusFlags: DISPLAYCONFIG_TARGET_IN_USE,
+                    ..Default::default()
                 },
-                flags: DISPLAYCONFIG_PATH_ACTIVE,
+                flags: DISPLAYCONFIG_PATH_ACTIVE | stored_path_flags(path.flags),
             };
+            PathModeIndices {
+                source_mode: Some(source_mode_index),
+                target_mode: Some(target_mode_index),
+                clone_group_id: path.source.clone_group_id,
+                desktop_image_mode: desktop_image_mode_index,
+            }
+            .write_to(&mut windows_path);
             new_windows_paths.push(windows_path);
         }
 
@@ -468,9 +1273,179 @@ impl DisplayLayout {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The pixel format to apply for a source mode: the stored one if the source supports it, or
+/// [`PixelFormat::Bpp32`] otherwise. Coercions are reported, as the layout won't be applied
+/// exactly as stored.
+fn validated_pixel_format(source_mode: &DisplaySourceMode, adapter_id: LuidWrapper) -> PixelFormat {
+    let source_device_name = match get_source_device_name(source_mode.device.id, adapter_id) {
+        Ok(source_device_name) => source_device_name,
+        Err(e) => {
+            debug!(
+                "Can't validate pixel format of source #{}: {:?}",
+                source_mode.device.id, e
+            );
+            return source_mode.pixel_format;
+        }
+    };
+    let supported = get_supported_bits_per_pixel(&source_device_name.viewGdiDeviceName);
+    let pixel_format = coerce_pixel_format(source_mode.pixel_format, &supported);
+    if pixel_format != source_mode.pixel_format {
+        warn!(
+            "Source {} doesn't support {:?} (supported bits per pixel: {:?}), using {:?} instead",
+            wchar_null_terminated_to_os_string(&source_device_name.viewGdiDeviceName).display(),
+            source_mode.pixel_format,
+            supported,
+            pixel_format
+        );
+    }
+    pixel_format
+}
+
+/// How far, in Hz, a supported refresh rate can be from the stored one and still be substituted
+/// for it
+const REFRESH_RATE_TOLERANCE_HZ: f64 = 5.0;
+
+/// A refresh rate to apply instead of the stored one for a path, if its monitor no longer supports
+/// the stored rate at the source's resolution. Substitutions are reported, as the layout won't be
+/// applied exactly as stored.
+fn substitute_refresh_rate(
+    path: &DisplayPath,
+    source_mode: &DisplaySourceMode,
+    target_mode: &DisplayTargetMode,
+    source_windows_mode: DISPLAYCONFIG_MODE_INFO,
+) -> Option<Rational> {
+    let rate_hz = path
+        .target
+        .refresh_rate
+        .as_f64()
+        .or_else(|| target_mode.v_sync_freq.as_f64())?;
+    let source_device_name = match get_source_device_name(
+        source_windows_mode.id,
+        source_windows_mode.adapterId.into(),
+    ) {
+        Ok(source_device_name) => source_device_name,
+        Err(e) => {
+            debug!(
+                "Can't validate refresh rate of source #{}: {:?}",
+                source_windows_mode.id, e
+            );
+            return None;
+        }
+    };
+    let supported = get_supported_refresh_rates(
+        &source_device_name.viewGdiDeviceName,
+        source_mode.width,
+        source_mode.height,
+    );
+    let substitute = nearest_refresh_rate(rate_hz, &supported)?;
+    warn!(
+        "Source {} doesn't support {:.2} Hz at {}x{} (supported: {:?}), using {} Hz instead",
+        wchar_null_terminated_to_os_string(&source_device_name.viewGdiDeviceName).display(),
+        rate_hz,
+        source_mode.width,
+        source_mode.height,
+        supported,
+        substitute
+    );
+    Some(Rational::from_hz(substitute))
+}
+
+/// The signal timing to apply to `target_windows_mode` when its refresh rate is substituted with
+/// `refresh_rate`. This is the target's timing in the `current` configuration if it's already
+/// driven at that rate and resolution, as that's a mode Windows reported itself. Otherwise the
+/// stored timing is retimed, so its pixel rate and sync frequencies stay consistent.
+fn substitute_signal_info(
+    current: &WindowsDisplayConfig,
+    target_windows_mode: &DISPLAYCONFIG_MODE_INFO,
+    refresh_rate: Rational,
+) -> DISPLAYCONFIG_VIDEO_SIGNAL_INFO {
+    let signal_info = unsafe {
+        target_windows_mode
+            .Anonymous
+            .targetMode
+            .targetVideoSignalInfo
+    };
+    let refresh_rate_hz = refresh_rate.as_f64();
+    current
+        .modes
+        .iter()
+        .filter(|mode| {
+            mode.infoType == DISPLAYCONFIG_MODE_INFO_TYPE_TARGET
+                && mode.id == target_windows_mode.id
+                && LuidWrapper::from(mode.adapterId)
+                    == LuidWrapper::from(target_windows_mode.adapterId)
+        })
+        .map(|mode| unsafe { mode.Anonymous.targetMode.targetVideoSignalInfo })
+        .find(|current| {
+            let current_hz = Rational::from(current.vSyncFreq).as_f64();
+            current.activeSize.cx == signal_info.activeSize.cx
+                && current.activeSize.cy == signal_info.activeSize.cy
+                && current_hz
+                    .zip(refresh_rate_hz)
+                    .is_some_and(|(a, b)| (a - b).abs() < 1.0)
+        })
+        .unwrap_or_else(|| retime_signal_info(signal_info, refresh_rate))
+}
+
+/// `signal_info` with its vertical sync frequency changed to `refresh_rate`, and its pixel rate and
+/// horizontal sync frequency scaled to match, keeping the total size (i.e. the blanking). Only the
+/// vertical sync frequency is changed if the total size is unknown.
+fn retime_signal_info(
+    mut signal_info: DISPLAYCONFIG_VIDEO_SIGNAL_INFO,
+    refresh_rate: Rational,
+) -> DISPLAYCONFIG_VIDEO_SIGNAL_INFO {
+    let v_sync_freq: DISPLAYCONFIG_RATIONAL = refresh_rate.into();
+    signal_info.vSyncFreq = v_sync_freq;
+    let (width, height) = (
+        signal_info.totalSize.cx as u64,
+        signal_info.totalSize.cy as u64,
+    );
+    let numerator = v_sync_freq.Numerator as u64;
+    let denominator = v_sync_freq.Denominator as u64;
+    if width == 0 || height == 0 || denominator == 0 {
+        return signal_info;
+    }
+    let Ok(h_sync_numerator) = u32::try_from(height * numerator) else {
+        return signal_info;
+    };
+    signal_info.hSyncFreq = DISPLAYCONFIG_RATIONAL {
+        Numerator: h_sync_numerator,
+        Denominator: v_sync_freq.Denominator,
+    };
+    signal_info.pixelRate = width * height * numerator / denominator;
+    signal_info
+}
+
+/// The supported refresh rate nearest to `rate_hz`, if `rate_hz` itself isn't supported and one is
+/// within [`REFRESH_RATE_TOLERANCE_HZ`]. Rates within 1 Hz count as supported, as Windows rounds
+/// fractional rates such as 59.94 Hz, and nothing is substituted if nothing is known to be
+/// supported.
+fn nearest_refresh_rate(rate_hz: f64, supported: &BTreeSet<u32>) -> Option<u32> {
+    let distance = |supported_hz: u32| (supported_hz as f64 - rate_hz).abs();
+    let nearest = supported
+        .iter()
+        .copied()
+        .min_by(|&a, &b| distance(a).total_cmp(&distance(b)))?;
+    if distance(nearest) < 1.0 || distance(nearest) > REFRESH_RATE_TOLERANCE_HZ {
+        None
+    } else {
+        Some(nearest)
+    }
+}
+
+/// Coerce a pixel format to [`PixelFormat::Bpp32`] if it isn't one of the `supported` bits per
+/// pixel. Formats are kept if nothing is known to be supported.
+fn coerce_pixel_format(pixel_format: PixelFormat, supported: &BTreeSet<u32>) -> PixelFormat {
+    match pixel_format.bits_per_pixel() {
+        Some(bits) if !supported.is_empty() && !supported.contains(&bits) => PixelFormat::Bpp32,
+        _ => pixel_format,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct Adapter {
     #[serde(with = "crate::serde_override::os_string")]
+    #[schemars(with = "String")]
     pub device_instance_path: OsString,
 }
 impl Adapter {
@@ -483,7 +1458,102 @@ impl Adapter {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A monitor in a layout that isn't currently connected, see [`DisplayLayout::ensure_connected`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingMonitor {
+    pub name: String,
+    pub device_path: OsString,
+}
+
+/// The error from [`DisplayLayout::apply`] when some of the layout's monitors aren't connected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingMonitors(pub Vec<MissingMonitor>);
+
+impl std::fmt::Display for MissingMonitors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names = self
+            .0
+            .iter()
+            .map(|monitor| monitor.name.as_str())
+            .collect::<Vec<_>>();
+        write!(f, "these monitors aren't connected: {}", names.join(", "))
+    }
+}
+
+impl std::error::Error for MissingMonitors {}
+
+/// The normalized device paths of the monitors that are currently connected, comparable with
+/// [`DisplayLayout::monitor_keys`]
+pub fn connected_device_paths() -> Result<BTreeSet<OsString>> {
+    Ok(Monitor::get_all()?
+        .into_iter()
+        .filter(|monitor| monitor.connected)
+        .filter_map(|monitor| monitor.device_path)
+        .map(|device_path| normalize_device_path(&device_path))
+        .collect())
+}
+
+/// How a monitor compares between two layouts, see [`DisplayLayout::compare`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MonitorComparison {
+    Both {
+        name: String,
+        fields: Vec<FieldComparison>,
+    },
+    OnlyInSelf(String),
+    OnlyInOther(String),
+}
+
+impl MonitorComparison {
+    pub fn differs(&self) -> bool {
+        match self {
+            MonitorComparison::Both { fields, .. } => fields.iter().any(FieldComparison::differs),
+            MonitorComparison::OnlyInSelf(_) | MonitorComparison::OnlyInOther(_) => true,
+        }
+    }
+}
+
+/// A field of a monitor in two layouts, formatted for display
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldComparison {
+    pub field: &'static str,
+    pub a: String,
+    pub b: String,
+}
+
+impl FieldComparison {
+    pub fn differs(&self) -> bool {
+        self.a != self.b
+    }
+}
+
+/// How a monitor is driven, for comparing layouts
+struct MonitorState {
+    width: u32,
+    height: u32,
+    /// Relative to the primary monitor
+    position: Point,
+    rotation: DisplayRotation,
+    scaling: DisplayScaling,
+    refresh_rate: Rational,
+}
+
+impl MonitorState {
+    fn is_equivalent_to(&self, other: &MonitorState) -> bool {
+        let same_refresh_rate = match (self.refresh_rate.as_f64(), other.refresh_rate.as_f64()) {
+            (Some(a), Some(b)) => (a - b).abs() < 1.0,
+            _ => self.refresh_rate == other.refresh_rate,
+        };
+        self.width == other.width
+            && self.height == other.height
+            && self.position == other.position
+            && self.rotation == other.rotation
+            && self.scaling == other.scaling
+            && same_refresh_rate
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct DisplayTargetDevice {
     pub id: u32,
     pub adapter: Adapter,
@@ -492,12 +1562,45 @@ pub struct DisplayTargetDevice {
     pub edid_product_code_id: Option<u16>,
     pub connector_instance: u32,
     #[serde(with = "crate::serde_override::option_os_string")]
+    #[schemars(with = "Option<String>")]
     pub monitor_friendly_device_name: Option<OsString>,
     #[serde(with = "crate::serde_override::option_os_string")]
+    #[schemars(with = "Option<String>")]
     pub monitor_device_path: Option<OsString>,
+    /// The brightness of SDR content, in thousandths of 80 nits. Only relevant for HDR displays.
+    #[serde(default)]
+    pub sdr_white_level: Option<u32>,
+    /// Whether advanced color (HDR) is enabled, or `None` if the monitor doesn't support it
+    #[serde(default)]
+    pub advanced_color_enabled: Option<bool>,
+    /// The serial number from the monitor's EDID, which tells identical monitors apart
+    #[serde(default)]
+    pub edid_serial: Option<String>,
+}
+
+impl DisplayTargetDevice {
+    /// The friendly name of the monitor, or a placeholder if it has none
+    pub fn display_name(&self) -> String {
+        self.monitor_friendly_device_name
+            .as_ref()
+            .map(|name| name.display().to_string())
+            .unwrap_or_else(|| "<unknown>".into())
+    }
+
+    /// The user's alias for the monitor if it has one, otherwise [`Self::display_name`]
+    pub fn display_name_with_aliases(&self, aliases: &HashMap<String, String>) -> String {
+        find_alias(
+            aliases,
+            self.edid_manufacture_id,
+            self.edid_product_code_id,
+            self.edid_serial.as_deref(),
+        )
+        .cloned()
+        .unwrap_or_else(|| self.display_name())
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct DisplayTargetMode {
     pub device: DisplayTargetDevice,
     pub pixel_rate: u64,
@@ -510,15 +1613,16 @@ pub struct DisplayTargetMode {
     pub scanline_ordering: ScanlineOrdering,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct DisplaySourceDevice {
     pub id: u32,
     pub adapter: Adapter,
     #[serde(with = "crate::serde_override::os_string")]
+    #[schemars(with = "String")]
     pub gdi_device_name: OsString,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct DisplaySourceMode {
     pub device: DisplaySourceDevice,
     pub width: u32,
@@ -527,18 +1631,67 @@ pub struct DisplaySourceMode {
     pub position: Point,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The part of a source's desktop image that a virtual mode path shows on its target, which is
+/// how Windows scales and clips it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct DisplayDesktopImageMode {
+    /// The size of the source surface
+    pub path_source_size: Region,
+    /// Where the desktop image is shown on the target
+    pub desktop_image_region: Rectangle,
+    /// The part of the desktop image that is shown
+    pub desktop_image_clip: Rectangle,
+}
+
+impl From<DISPLAYCONFIG_DESKTOP_IMAGE_INFO> for DisplayDesktopImageMode {
+    fn from(value: DISPLAYCONFIG_DESKTOP_IMAGE_INFO) -> Self {
+        Self {
+            path_source_size: Region {
+                x: value.PathSourceSize.x as u32,
+                y: value.PathSourceSize.y as u32,
+            },
+            desktop_image_region: value.DesktopImageRegion.into(),
+            desktop_image_clip: value.DesktopImageClip.into(),
+        }
+    }
+}
+
+impl From<DisplayDesktopImageMode> for DISPLAYCONFIG_DESKTOP_IMAGE_INFO {
+    fn from(value: DisplayDesktopImageMode) -> Self {
+        Self {
+            PathSourceSize: Point {
+                x: value.path_source_size.x as i32,
+                y: value.path_source_size.y as i32,
+            }
+            .into(),
+            DesktopImageRegion: value.desktop_image_region.into(),
+            DesktopImageClip: value.desktop_image_clip.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct DisplayPath {
+    /// Additional `DISPLAYCONFIG_PATH_*` flags of the path, other than the active flag. Flags that
+    /// aren't understood are kept, so they are re-applied.
+    #[serde(default)]
+    pub flags: u32,
+    /// The index of the desktop image mode of a virtual mode path, if any
+    #[serde(default)]
+    pub desktop_image_mode_index: Option<usize>,
     pub source: DisplayPathSource,
     pub target: DisplayPathTarget,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct DisplayPathSource {
     pub source_mode_index: usize,
+    /// The clone group of a virtual mode path, if any
+    #[serde(default)]
+    pub clone_group_id: Option<u32>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct DisplayPathTarget {
     pub target_mode_index: usize,
     pub output_technology: OutputTechnology,
@@ -547,3 +1700,621 @@ pub struct DisplayPathTarget {
     pub refresh_rate: Rational,
     pub scanline_ordering: ScanlineOrdering,
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, collections::BTreeMap};
+
+    use windows::Win32::{
+        Devices::Display::{
+            DISPLAYCONFIG_2DREGION, DISPLAYCONFIG_DESKTOP_IMAGE_INFO, DISPLAYCONFIG_MODE_INFO,
+            DISPLAYCONFIG_MODE_INFO_TYPE_TARGET, DISPLAYCONFIG_PATH_INFO, DISPLAYCONFIG_RATIONAL,
+            DISPLAYCONFIG_TARGET_DEVICE_NAME, DISPLAYCONFIG_VIDEO_SIGNAL_INFO,
+        },
+        Foundation::{LUID, POINTL, RECTL},
+        Graphics::Gdi::{
+            DISPLAYCONFIG_PATH_ACTIVE, DISPLAYCONFIG_PATH_DESKTOP_IMAGE_IDX_INVALID,
+            DISPLAYCONFIG_PATH_SUPPORT_VIRTUAL_MODE,
+        },
+    };
+
+    use super::{
+        Adapter, DisplayDesktopImageMode, DisplayLayout, DisplayPath, DisplayPathSource,
+        DisplayPathTarget, DisplaySourceDevice, DisplaySourceMode, DisplayTargetDevice,
+        DisplayTargetMode, MissingMonitors, MonitorComparison, PathModeIndices,
+        coerce_pixel_format, nearest_refresh_rate, parse_video_signal_info, retime_signal_info,
+        stored_path_flags, video_signal_info_union,
+    };
+    use crate::windows_util::{
+        DISPLAYCONFIG_PATH_BOOST_REFRESH_RATE, DisplayRotation, DisplayScaling, IdAndAdapterId,
+        LuidWrapper, OutputTechnology, PixelFormat, Point, Rational, Region, ScanlineOrdering,
+        VideoStandard, WindowsDisplayConfig,
+    };
+
+    const ADAPTER: &str = r"\\?\PCI#VEN_8086";
+
+    fn adapter() -> Adapter {
+        Adapter {
+            device_instance_path: ADAPTER.into(),
+        }
+    }
+
+    /// A layout with a single monitor per source, at the given source IDs and positions
+    fn layout(monitors: &[(&str, u32, i32)]) -> DisplayLayout {
+        let mut layout = DisplayLayout {
+            source_modes: Vec::new(),
+            target_modes: Vec::new(),
+            desktop_image_modes: Vec::new(),
+            paths: Vec::new(),
+            primary_monitor_device_path: None,
+        };
+        for (i, &(device_path, source_id, x)) in monitors.iter().enumerate() {
+            layout.source_modes.push(DisplaySourceMode {
+                device: DisplaySourceDevice {
+                    id: source_id,
+                    adapter: adapter(),
+                    gdi_device_name: format!(r"\\.\DISPLAY{}", source_id + 1).into(),
+                },
+                width: 1920,
+                height: 1080,
+                pixel_format: PixelFormat::Bpp32,
+                position: Point { x, y: 0 },
+            });
+            layout.target_modes.push(DisplayTargetMode {
+                device: DisplayTargetDevice {
+                    id: 100 + i as u32,
+                    adapter: adapter(),
+                    output_technology: OutputTechnology::Hdmi,
+                    edid_manufacture_id: None,
+                    edid_product_code_id: None,
+                    connector_instance: 0,
+                    monitor_friendly_device_name: None,
+                    monitor_device_path: Some(device_path.into()),
+                    sdr_white_level: None,
+                    advanced_color_enabled: None,
+                    edid_serial: None,
+                },
+                pixel_rate: 148_500_000,
+                h_sync_freq: DISPLAYCONFIG_RATIONAL::default().into(),
+                v_sync_freq: DISPLAYCONFIG_RATIONAL::default().into(),
+                active_size: Region { x: 1920, y: 1080 },
+                total_size: Region { x: 2200, y: 1125 },
+                video_standard: VideoStandard::VesaDmt,
+                v_sync_freq_divider: 1,
+                scanline_ordering: ScanlineOrdering::Progressive,
+            });
+            layout.paths.push(DisplayPath {
+                flags: 0,
+                desktop_image_mode_index: None,
+                source: DisplayPathSource {
+                    source_mode_index: i,
+                    clone_group_id: None,
+                },
+                target: DisplayPathTarget {
+                    target_mode_index: i,
+                    output_technology: OutputTechnology::Hdmi,
+                    rotation: DisplayRotation::Identity,
+                    scaling: DisplayScaling::Identity,
+                    refresh_rate: DISPLAYCONFIG_RATIONAL::default().into(),
+                    scanline_ordering: ScanlineOrdering::Progressive,
+                },
+            });
+        }
+        layout
+    }
+
+    #[test]
+    fn test_coerce_pixel_format() {
+        let supported = [8, 32].into_iter().collect();
+        assert_eq!(
+            coerce_pixel_format(PixelFormat::Bpp8, &supported),
+            PixelFormat::Bpp8
+        );
+        assert_eq!(
+            coerce_pixel_format(PixelFormat::Bpp16, &supported),
+            PixelFormat::Bpp32
+        );
+        assert_eq!(
+            coerce_pixel_format(PixelFormat::Nongdi, &supported),
+            PixelFormat::Nongdi
+        );
+        assert_eq!(
+            coerce_pixel_format(PixelFormat::Bpp16, &Default::default()),
+            PixelFormat::Bpp16
+        );
+    }
+
+    #[test]
+    fn test_is_equivalent_to() {
+        let stored = layout(&[("left", 0, 0), ("right", 1, 1920)]);
+        assert!(stored.is_equivalent_to(&stored));
+
+        // Source IDs and the order of the paths don't matter
+        let reordered = layout(&[("right", 3, 1920), ("left", 2, 0)]);
+        assert!(stored.is_equivalent_to(&reordered));
+
+        // Positions are relative to the primary monitor
+        let mut stored_primary = layout(&[("left", 0, -1920), ("right", 1, 0)]);
+        assert!(stored_primary.set_primary("right", &Default::default()));
+        let mut moved = layout(&[("left", 0, 0), ("right", 1, 1920)]);
+        assert!(moved.set_primary("right", &Default::default()));
+        assert!(stored_primary.is_equivalent_to(&moved));
+
+        // Refresh rates within 1 Hz are the same
+        let mut fractional = stored.clone();
+        fractional.paths[0].target.refresh_rate = DISPLAYCONFIG_RATIONAL {
+            Numerator: 60000,
+            Denominator: 1001,
+        }
+        .into();
+        let mut whole = stored.clone();
+        whole.paths[0].target.refresh_rate = Rational::from_hz(60);
+        assert!(fractional.is_equivalent_to(&whole));
+        whole.paths[0].target.refresh_rate = Rational::from_hz(75);
+        assert!(!fractional.is_equivalent_to(&whole));
+
+        let mut rotated = stored.clone();
+        rotated.paths[1].target.rotation = DisplayRotation::Rotate90;
+        assert!(!stored.is_equivalent_to(&rotated));
+        assert!(!stored.is_equivalent_to(&layout(&[("left", 0, 0)])));
+        assert!(!stored.is_equivalent_to(&layout(&[("left", 0, 0), ("other", 1, 1920)])));
+    }
+
+    #[test]
+    fn test_missing_monitors() {
+        // Device paths are compared case-insensitively
+        let mut layout = layout(&[("LEFT", 0, 0), ("right", 1, 1920), ("unknown", 2, 3840)]);
+        layout.target_modes[2].device.monitor_device_path = None;
+        let connected = ["left".into()].into_iter().collect();
+        let missing = layout.missing_monitors(&connected);
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].device_path, "right");
+        assert_eq!(
+            MissingMonitors(missing).to_string(),
+            format!(
+                "these monitors aren't connected: {}",
+                layout.target_modes[1].device.display_name()
+            )
+        );
+        assert!(
+            layout
+                .missing_monitors(&["left".into(), "right".into()].into_iter().collect())
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_compare() {
+        let a = layout(&[("left", 0, 0), ("right", 1, 1920)]);
+        let mut b = layout(&[("middle", 0, 0), ("left", 1, 1920)]);
+        b.paths[1].target.rotation = DisplayRotation::Rotate90;
+
+        let comparisons = a.compare(&b);
+        assert_eq!(comparisons.len(), 3);
+        let MonitorComparison::Both { fields, .. } = &comparisons[0] else {
+            panic!("left should be in both layouts: {:?}", comparisons[0]);
+        };
+        let differing = fields
+            .iter()
+            .filter(|field| field.differs())
+            .map(|field| (field.field, field.a.as_str(), field.b.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(differing, [("Rotation", "Identity", "Rotate90")]);
+        assert!(matches!(comparisons[1], MonitorComparison::OnlyInSelf(_)));
+        assert!(matches!(comparisons[2], MonitorComparison::OnlyInOther(_)));
+
+        assert!(a.compare(&a).iter().all(|c| !c.differs()));
+
+        // Monitors without a device path are matched by target ID instead
+        let mut c = a.clone();
+        c.target_modes[1].device.monitor_device_path = None;
+        let comparisons = c.compare(&c);
+        assert_eq!(comparisons.len(), 2);
+        assert!(comparisons.iter().all(|c| !c.differs()));
+        assert_eq!(a.compare(&c).len(), 3);
+    }
+
+    #[test]
+    fn test_primary_origin() {
+        let mut layout = layout(&[("left", 0, -1920), ("right", 1, 1920)]);
+        assert_eq!(layout.primary_origin(), None);
+        assert!(layout.set_primary("RIGHT", &Default::default()));
+        assert_eq!(
+            layout.primary_monitor_device_path.as_deref(),
+            Some("right".as_ref())
+        );
+        assert_eq!(layout.primary_origin(), Some(Point { x: 1920, y: 0 }));
+        assert!(!layout.set_primary("missing", &Default::default()));
+        assert_eq!(layout.primary_origin(), Some(Point { x: 1920, y: 0 }));
+    }
+
+    #[test]
+    fn test_stored_path_flags() {
+        let unknown = 0x100;
+        assert_eq!(
+            stored_path_flags(
+                DISPLAYCONFIG_PATH_ACTIVE
+                    | DISPLAYCONFIG_PATH_SUPPORT_VIRTUAL_MODE
+                    | DISPLAYCONFIG_PATH_BOOST_REFRESH_RATE
+                    | unknown
+            ),
+            DISPLAYCONFIG_PATH_SUPPORT_VIRTUAL_MODE
+                | DISPLAYCONFIG_PATH_BOOST_REFRESH_RATE
+                | unknown
+        );
+    }
+
+    fn path_with_indices(flags: u32, source: u32, target: u32) -> DISPLAYCONFIG_PATH_INFO {
+        let mut path = DISPLAYCONFIG_PATH_INFO {
+            flags,
+            ..Default::default()
+        };
+        path.sourceInfo.Anonymous.modeInfoIdx = source;
+        path.targetInfo.Anonymous.modeInfoIdx = target;
+        path
+    }
+
+    #[test]
+    fn test_path_mode_indices_round_trip() {
+        let virtual_mode = DISPLAYCONFIG_PATH_ACTIVE | DISPLAYCONFIG_PATH_SUPPORT_VIRTUAL_MODE;
+        let windows_display_config = WindowsDisplayConfig {
+            paths: vec![
+                path_with_indices(DISPLAYCONFIG_PATH_ACTIVE, 0, 2),
+                path_with_indices(virtual_mode, (7 << 16) | 1, (4 << 16) | 3),
+                path_with_indices(
+                    virtual_mode,
+                    1,
+                    (DISPLAYCONFIG_PATH_DESKTOP_IMAGE_IDX_INVALID << 16) | 3,
+                ),
+            ],
+            modes: Vec::new(),
+            adapter_device_names: BTreeMap::new(),
+            source_device_names: BTreeMap::new(),
+            target_device_names: BTreeMap::new(),
+            topology: None,
+        };
+        let indices = windows_display_config
+            .paths
+            .iter()
+            .map(PathModeIndices::from_path)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            indices,
+            [
+                PathModeIndices {
+                    source_mode: Some(0),
+                    target_mode: Some(2),
+                    clone_group_id: None,
+                    desktop_image_mode: None,
+                },
+                PathModeIndices {
+                    source_mode: Some(1),
+                    target_mode: Some(3),
+                    clone_group_id: Some(7),
+                    desktop_image_mode: Some(4),
+                },
+                PathModeIndices {
+                    source_mode: Some(1),
+                    target_mode: Some(3),
+                    clone_group_id: Some(0),
+                    desktop_image_mode: None,
+                },
+            ]
+        );
+
+        // Writing the indices back reconstructs the original (packed) indices
+        for (path, indices) in windows_display_config.paths.iter().zip(indices) {
+            let mut reconstructed = path_with_indices(path.flags, 0, 0);
+            indices.write_to(&mut reconstructed);
+            unsafe {
+                assert_eq!(
+                    reconstructed.sourceInfo.Anonymous.modeInfoIdx,
+                    path.sourceInfo.Anonymous.modeInfoIdx
+                );
+                assert_eq!(
+                    reconstructed.targetInfo.Anonymous.modeInfoIdx,
+                    path.targetInfo.Anonymous.modeInfoIdx
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_desktop_image_mode_round_trip() {
+        let info = DISPLAYCONFIG_DESKTOP_IMAGE_INFO {
+            PathSourceSize: POINTL { x: 3840, y: 2160 },
+            DesktopImageRegion: RECTL {
+                left: 0,
+                top: 0,
+                right: 1920,
+                bottom: 1080,
+            },
+            DesktopImageClip: RECTL {
+                left: 100,
+                top: 50,
+                right: 1820,
+                bottom: 1030,
+            },
+        };
+        let mode = DisplayDesktopImageMode::from(info);
+        assert_eq!(mode.path_source_size, Region { x: 3840, y: 2160 });
+        assert_eq!(mode.desktop_image_clip.position, Point { x: 100, y: 50 });
+        assert_eq!(mode.desktop_image_clip.size, Region { x: 1720, y: 980 });
+        assert_eq!(DISPLAYCONFIG_DESKTOP_IMAGE_INFO::from(mode), info);
+    }
+
+    #[test]
+    fn test_deserialize_without_desktop_image_modes() {
+        let mut json = serde_json::to_value(layout(&[("left", 0, 0)])).unwrap();
+        json.as_object_mut().unwrap().remove("desktop_image_modes");
+        json["paths"][0]
+            .as_object_mut()
+            .unwrap()
+            .remove("desktop_image_mode_index");
+        let layout: DisplayLayout = serde_json::from_value(json).unwrap();
+        assert!(layout.desktop_image_modes.is_empty());
+        assert_eq!(layout.paths[0].desktop_image_mode_index, None);
+    }
+
+    #[test]
+    fn test_color_settings_round_trip() {
+        let mut layout = layout(&[("left", 0, 0)]);
+        layout.target_modes[0].device.sdr_white_level = Some(2500);
+        layout.target_modes[0].device.advanced_color_enabled = Some(true);
+        let json = serde_json::to_value(&layout).unwrap();
+        let round_tripped: DisplayLayout = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(round_tripped.target_modes, layout.target_modes);
+
+        // Layouts stored before the fields existed have neither
+        let mut json = json;
+        let device = json["target_modes"][0]["device"].as_object_mut().unwrap();
+        device.remove("sdr_white_level");
+        device.remove("advanced_color_enabled");
+        let layout: DisplayLayout = serde_json::from_value(json).unwrap();
+        assert_eq!(layout.target_modes[0].device.sdr_white_level, None);
+        assert_eq!(layout.target_modes[0].device.advanced_color_enabled, None);
+    }
+
+    #[test]
+    fn test_retime_signal_info() {
+        // 1080p at 60 Hz, with CTA-861 blanking
+        let signal_info = DISPLAYCONFIG_VIDEO_SIGNAL_INFO {
+            pixelRate: 148_500_000,
+            hSyncFreq: DISPLAYCONFIG_RATIONAL {
+                Numerator: 67_500,
+                Denominator: 1,
+            },
+            vSyncFreq: DISPLAYCONFIG_RATIONAL {
+                Numerator: 60,
+                Denominator: 1,
+            },
+            activeSize: DISPLAYCONFIG_2DREGION { cx: 1920, cy: 1080 },
+            totalSize: DISPLAYCONFIG_2DREGION { cx: 2200, cy: 1125 },
+            ..Default::default()
+        };
+        let retimed = retime_signal_info(signal_info, Rational::from_hz(120));
+        assert_eq!(retimed.pixelRate, 297_000_000);
+        assert_eq!(
+            Rational::from(retimed.hSyncFreq),
+            Rational::from_hz(135_000)
+        );
+        assert_eq!(Rational::from(retimed.vSyncFreq), Rational::from_hz(120));
+
+        let fractional: Rational = DISPLAYCONFIG_RATIONAL {
+            Numerator: 60000,
+            Denominator: 1001,
+        }
+        .into();
+        let retimed = retime_signal_info(signal_info, fractional);
+        assert_eq!(retimed.pixelRate, 148_351_648);
+        assert_eq!(retimed.hSyncFreq.Numerator, 67_500_000);
+        assert_eq!(retimed.hSyncFreq.Denominator, 1001);
+
+        // Without a total size, only the refresh rate changes
+        let unknown = DISPLAYCONFIG_VIDEO_SIGNAL_INFO {
+            totalSize: DISPLAYCONFIG_2DREGION::default(),
+            ..signal_info
+        };
+        let retimed = retime_signal_info(unknown, Rational::from_hz(120));
+        assert_eq!(retimed.pixelRate, 148_500_000);
+        assert_eq!(Rational::from(retimed.vSyncFreq), Rational::from_hz(120));
+    }
+
+    #[test]
+    fn test_nearest_refresh_rate() {
+        let supported = [60, 120, 144].into_iter().collect();
+        assert_eq!(nearest_refresh_rate(60.0, &supported), None);
+        assert_eq!(nearest_refresh_rate(59.94, &supported), None);
+        assert_eq!(nearest_refresh_rate(165.0, &supported), None);
+        assert_eq!(nearest_refresh_rate(75.0, &supported), None);
+        assert_eq!(nearest_refresh_rate(141.0, &supported), Some(144));
+        assert_eq!(nearest_refresh_rate(123.5, &supported), Some(120));
+        assert_eq!(nearest_refresh_rate(144.0, &Default::default()), None);
+    }
+
+    #[test]
+    fn test_merge_relinks_indices_and_source_ids() {
+        let laptop = layout(&[("laptop", 0, 0)]);
+        let external = layout(&[("left", 0, -1920), ("right", 1, 1920)]);
+        let merged = laptop.merge(&external).unwrap();
+
+        assert_eq!(merged.paths.len(), 3);
+        let mut source_ids = Vec::new();
+        for (path, device_path) in merged.paths.iter().zip(["laptop", "left", "right"]) {
+            let source_mode = &merged.source_modes[path.source.source_mode_index];
+            let target_mode = &merged.target_modes[path.target.target_mode_index];
+            assert_eq!(
+                target_mode.device.monitor_device_path,
+                Some(device_path.into())
+            );
+            source_ids.push(source_mode.device.id);
+        }
+        assert_eq!(source_ids, [0, 1, 2]);
+        assert_eq!(merged.source_modes[1].position.x, -1920);
+    }
+
+    #[test]
+    fn test_merge_shared_monitor() {
+        let a = layout(&[("laptop", 0, 0)]);
+        let b = layout(&[("laptop", 0, 0), ("external", 1, 1920)]);
+        let merged = a.merge(&b).unwrap();
+        assert_eq!(merged.paths.len(), 2);
+        assert_eq!(merged.source_modes.len(), 2);
+        assert_eq!(merged.target_modes.len(), 2);
+    }
+
+    #[test]
+    fn test_retain_paths_relinks_indices() {
+        let full = layout(&[("left", 0, -1920), ("middle", 1, 0), ("right", 2, 1920)]);
+        let retained = full
+            .retain_paths(|path| path.target.target_mode_index != 1)
+            .unwrap();
+        assert_eq!(retained.paths.len(), 2);
+        assert_eq!(retained.source_modes.len(), 2);
+        assert_eq!(retained.target_modes.len(), 2);
+        for (path, device_path) in retained.paths.iter().zip(["left", "right"]) {
+            assert_eq!(
+                retained.target_modes[path.target.target_mode_index]
+                    .device
+                    .monitor_device_path,
+                Some(device_path.into())
+            );
+        }
+        assert_eq!(
+            retained.source_modes[retained.paths[1].source.source_mode_index]
+                .position
+                .x,
+            1920
+        );
+
+        // Layouts edited by hand can have invalid indices, which are errors rather than panics
+        let mut broken = full.clone();
+        broken.paths[0].source.source_mode_index = 5;
+        assert!(broken.retain_paths(|_| true).is_err());
+        let mut broken = full.clone();
+        broken.paths[2].target.target_mode_index = 5;
+        assert!(broken.retain_paths(|_| true).is_err());
+        assert!(broken.merge(&full).is_err());
+        assert!(full.merge(&broken).is_err());
+    }
+
+    #[test]
+    fn test_merge_conflicting_monitor() {
+        let a = layout(&[("laptop", 0, 0)]);
+        let b = layout(&[("laptop", 0, 1920)]);
+        assert!(a.merge(&b).is_err());
+    }
+
+    fn signal_info(
+        video_standard: VideoStandard,
+        v_sync_freq_divider: u32,
+        additional_signal_info: bool,
+    ) -> DISPLAYCONFIG_VIDEO_SIGNAL_INFO {
+        DISPLAYCONFIG_VIDEO_SIGNAL_INFO {
+            Anonymous: video_signal_info_union(
+                video_standard,
+                v_sync_freq_divider,
+                additional_signal_info,
+            ),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_video_signal_info_additional_signal_info() {
+        let info = signal_info(VideoStandard::VesaDmt, 2, true);
+        assert_eq!(
+            unsafe { info.Anonymous.AdditionalSignalInfo._bitfield },
+            VideoStandard::VesaDmt.discriminant() as u32 | (2 << 16)
+        );
+        assert_eq!(
+            parse_video_signal_info(&info, true),
+            (VideoStandard::VesaDmt, 2)
+        );
+    }
+
+    #[test]
+    fn test_parse_video_signal_info_video_standard_only() {
+        let info = signal_info(VideoStandard::VesaDmt, 2, false);
+        assert_eq!(
+            unsafe { info.Anonymous.videoStandard },
+            VideoStandard::VesaDmt.discriminant() as u32
+        );
+        assert_eq!(
+            parse_video_signal_info(&info, false),
+            (VideoStandard::VesaDmt, 0)
+        );
+    }
+
+    fn target_device_name(device_path: &str) -> DISPLAYCONFIG_TARGET_DEVICE_NAME {
+        let mut name = DISPLAYCONFIG_TARGET_DEVICE_NAME::default();
+        for (dst, src) in name
+            .monitorDevicePath
+            .iter_mut()
+            .zip(device_path.encode_utf16())
+        {
+            *dst = src;
+        }
+        name
+    }
+
+    #[test]
+    fn test_matching_target_mode_uses_cached_device_names() {
+        let adapter_id = LuidWrapper::from(LUID {
+            LowPart: 1,
+            HighPart: 0,
+        });
+        let device_paths = [
+            (1, r"\\?\DISPLAY#A"),
+            (2, r"\\?\DISPLAY#B"),
+            (3, r"\\?\DISPLAY#C"),
+        ];
+        let mut windows_display_config = WindowsDisplayConfig {
+            paths: Vec::new(),
+            modes: device_paths
+                .iter()
+                .map(|&(id, _)| DISPLAYCONFIG_MODE_INFO {
+                    infoType: DISPLAYCONFIG_MODE_INFO_TYPE_TARGET,
+                    id,
+                    adapterId: adapter_id.into(),
+                    ..Default::default()
+                })
+                .collect(),
+            adapter_device_names: BTreeMap::new(),
+            source_device_names: BTreeMap::new(),
+            target_device_names: BTreeMap::new(),
+            topology: None,
+        };
+        let target_mode = &layout(&[(r"\\?\DISPLAY#B", 0, 0)]).target_modes[0];
+        let lookups = Cell::new(0);
+        let lookup = |id: u32, _: LUID| {
+            lookups.set(lookups.get() + 1);
+            let (_, device_path) = device_paths.iter().find(|(i, _)| *i == id).unwrap();
+            Ok(target_device_name(device_path))
+        };
+
+        // Without cached names, every candidate is queried
+        let uncached = windows_display_config
+            .get_matching_target_mode_id_with(adapter_id, target_mode, lookup)
+            .unwrap();
+        assert_eq!(lookups.get(), 3);
+
+        // With cached names, nothing is queried, and the result is the same
+        lookups.set(0);
+        windows_display_config.target_device_names = device_paths
+            .iter()
+            .map(|&(id, device_path)| {
+                (
+                    IdAndAdapterId { adapter_id, id },
+                    target_device_name(device_path),
+                )
+            })
+            .collect();
+        let cached = windows_display_config
+            .get_matching_target_mode_id_with(adapter_id, target_mode, lookup)
+            .unwrap();
+        assert_eq!(lookups.get(), 0);
+        assert_eq!(cached, uncached);
+        assert_eq!(cached, 2);
+    }
+}
@@ -0,0 +1,153 @@
+use std::ffi::OsStr;
+
+use anyhow::{Result, anyhow};
+use windows::{
+    Win32::System::Registry::{HKEY_LOCAL_MACHINE, RRF_RT_REG_BINARY, RegGetValueW},
+    core::{HSTRING, w},
+};
+
+/// The fixed header at the start of every EDID
+const EDID_HEADER: [u8; 8] = [0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00];
+
+/// The tag of the display descriptor holding the serial number as text
+const SERIAL_NUMBER_DESCRIPTOR_TAG: u8 = 0xFF;
+
+/// Get the serial number of a monitor from its EDID, or `None` if it can't be read, e.g. if the
+/// monitor has never been connected or doesn't report a serial number
+pub fn get_serial(monitor_device_path: &OsStr) -> Option<String> {
+    match read_edid(monitor_device_path) {
+        Ok(edid) => parse_serial(&edid),
+        Err(e) => {
+            tracing::debug!(
+                "Failed to read EDID of {}: {:?}",
+                monitor_device_path.display(),
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Read the EDID that Windows cached in the registry for a monitor, given its device path, e.g.
+/// `\\?\DISPLAY#DEL40F4#5&1a2b3c4d&0&UID4353#{e6f07b5f-ee97-4a90-b076-33f57bf4eaa7}`
+fn read_edid(monitor_device_path: &OsStr) -> Result<Vec<u8>> {
+    let key = registry_key(&monitor_device_path.to_string_lossy())
+        .ok_or_else(|| anyhow!("Unrecognized device path"))?;
+    let key = HSTRING::from(key);
+    let mut size = 0u32;
+    unsafe {
+        RegGetValueW(
+            HKEY_LOCAL_MACHINE,
+            &key,
+            w!("EDID"),
+            RRF_RT_REG_BINARY,
+            None,
+            None,
+            Some(&mut size),
+        )
+    }
+    .ok()?;
+    let mut edid = vec![0u8; size as usize];
+    unsafe {
+        RegGetValueW(
+            HKEY_LOCAL_MACHINE,
+            &key,
+            w!("EDID"),
+            RRF_RT_REG_BINARY,
+            None,
+            Some(edid.as_mut_ptr().cast()),
+            Some(&mut size),
+        )
+    }
+    .ok()?;
+    edid.truncate(size as usize);
+    Ok(edid)
+}
+
+/// The registry key holding the device parameters of the monitor with the given device path
+fn registry_key(monitor_device_path: &str) -> Option<String> {
+    let path = monitor_device_path
+        .strip_prefix(r"\\?\")
+        .unwrap_or(monitor_device_path);
+    let mut parts = path.split('#');
+    let (class, hardware_id, instance_id) = (parts.next()?, parts.next()?, parts.next()?);
+    if [class, hardware_id, instance_id]
+        .iter()
+        .any(|part| part.is_empty() || part.contains('\\'))
+    {
+        return None;
+    }
+    Some(format!(
+        r"SYSTEM\CurrentControlSet\Enum\{}\{}\{}\Device Parameters",
+        class, hardware_id, instance_id
+    ))
+}
+
+/// Get the serial number from an EDID: the serial number descriptor if it has one, otherwise the
+/// numeric serial number, if it's set
+fn parse_serial(edid: &[u8]) -> Option<String> {
+    if edid.len() < 128 || edid[..8] != EDID_HEADER {
+        return None;
+    }
+    // The four 18-byte display descriptors
+    for descriptor in edid[54..126].chunks_exact(18) {
+        if descriptor[..3] == [0, 0, 0] && descriptor[3] == SERIAL_NUMBER_DESCRIPTOR_TAG {
+            // The text is terminated by a line feed and padded with spaces
+            let text = &descriptor[5..];
+            let len = text.iter().position(|&c| c == b'\n').unwrap_or(text.len());
+            let serial = String::from_utf8_lossy(&text[..len]).trim().to_string();
+            if !serial.is_empty() {
+                return Some(serial);
+            }
+        }
+    }
+    let serial = u32::from_le_bytes(edid[12..16].try_into().ok()?);
+    (serial != 0).then(|| serial.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EDID_HEADER, parse_serial, registry_key};
+
+    fn edid(numeric_serial: u32, descriptor_serial: Option<&[u8]>) -> Vec<u8> {
+        let mut edid = vec![0u8; 128];
+        edid[..8].copy_from_slice(&EDID_HEADER);
+        edid[12..16].copy_from_slice(&numeric_serial.to_le_bytes());
+        // A monitor name descriptor, which should be skipped
+        edid[72..77].copy_from_slice(&[0, 0, 0, 0xFC, 0]);
+        edid[77..90].copy_from_slice(b"DELL U2720Q\n ");
+        if let Some(serial) = descriptor_serial {
+            edid[90..95].copy_from_slice(&[0, 0, 0, 0xFF, 0]);
+            edid[95..95 + serial.len()].copy_from_slice(serial);
+        }
+        edid
+    }
+
+    #[test]
+    fn test_parse_serial() {
+        assert_eq!(
+            parse_serial(&edid(1234, Some(b"ABC123\n      "))).as_deref(),
+            Some("ABC123")
+        );
+        assert_eq!(parse_serial(&edid(1234, None)).as_deref(), Some("1234"));
+        assert_eq!(parse_serial(&edid(0, None)), None);
+        assert_eq!(parse_serial(&edid(0, Some(b"\n            "))), None);
+        assert_eq!(parse_serial(&[0u8; 128]), None);
+        assert_eq!(parse_serial(&EDID_HEADER), None);
+    }
+
+    #[test]
+    fn test_registry_key() {
+        assert_eq!(
+            registry_key(
+                r"\\?\DISPLAY#DEL40F4#5&1a2b3c4d&0&UID4353#{e6f07b5f-ee97-4a90-b076-33f57bf4eaa7}"
+            )
+            .as_deref(),
+            Some(
+                r"SYSTEM\CurrentControlSet\Enum\DISPLAY\DEL40F4\5&1a2b3c4d&0&UID4353\Device Parameters"
+            )
+        );
+        assert_eq!(registry_key(r"\\?\DISPLAY#DEL40F4"), None);
+        assert_eq!(registry_key(r"DISPLAY#..\..#x"), None);
+    }
+}
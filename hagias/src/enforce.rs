@@ -0,0 +1,86 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use rocket::{
+    Orbit, Rocket,
+    fairing::{Fairing, Info, Kind},
+};
+use tracing::{info, warn};
+
+use crate::{
+    config::Config,
+    display::DisplayLayout,
+    history::ApplySource,
+    in_flight::InFlightApplies,
+    layouts::Layouts,
+    poller::{self, Poll},
+};
+
+/// Re-applies the layout given by `enforce_layout` whenever the display configuration drifts from
+/// it, while rocket is running
+pub struct Enforcer;
+
+#[rocket::async_trait]
+impl Fairing for Enforcer {
+    fn info(&self) -> Info {
+        Info {
+            name: "Layout Enforcer",
+            kind: Kind::Liftoff,
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        let Some((config, in_flight)) = poller::managed_state(rocket, "Layout enforcer") else {
+            return;
+        };
+        let Some(id) = config.enforce_layout.clone() else {
+            return;
+        };
+        info!(
+            "Enforcing layout {}, checking every {}s",
+            id, config.enforce_interval_secs
+        );
+        let interval = Duration::from_secs(config.enforce_interval_secs.max(1));
+        let enforce = Enforce {
+            config,
+            in_flight,
+            id,
+        };
+        poller::spawn(rocket, "Layout enforcer", interval, enforce);
+    }
+}
+
+/// Checks the display configuration, re-applying the enforced layout whenever it has drifted
+struct Enforce {
+    config: Config,
+    in_flight: InFlightApplies,
+    id: String,
+}
+
+#[rocket::async_trait]
+impl Poll for Enforce {
+    async fn poll(&mut self) {
+        if let Err(e) = self.enforce().await {
+            warn!("Failed to enforce layout {}: {:?}", self.id, e);
+        }
+    }
+}
+
+impl Enforce {
+    /// Re-apply the layout if the active configuration no longer matches it
+    async fn enforce(&self) -> Result<()> {
+        let layouts = Layouts::load(&self.config.layouts_path.relative()).await?;
+        let Some(layout) = layouts.get_layout(&self.id) else {
+            warn!("Enforced layout {} not found", self.id);
+            return Ok(());
+        };
+        if layout.layout.is_equivalent_to(&DisplayLayout::get()?) {
+            return Ok(());
+        }
+        info!(
+            "Display configuration drifted from layout {} \"{}\", re-applying it",
+            layout.id, layout.name
+        );
+        poller::apply(&self.config, &self.in_flight, layout, ApplySource::Enforce).await
+    }
+}
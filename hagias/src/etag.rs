@@ -0,0 +1,118 @@
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    io::ErrorKind,
+    path::Path,
+};
+
+use anyhow::Result;
+use rocket::{
+    Request,
+    http::{Header, Status},
+    request::{self, FromRequest},
+    response::{self, Responder},
+};
+
+/// A strong entity tag identifying a version of a resource, e.g. `"5f3c0d2a9e81b467"`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ETag(String);
+
+impl ETag {
+    /// An entity tag derived from the modification time and contents of a file, which may not
+    /// exist. For a directory, it's derived from the files directly inside it.
+    pub async fn from_file(path: &Path) -> Result<Self> {
+        let mut hasher = DefaultHasher::new();
+        match tokio::fs::metadata(path).await {
+            Ok(metadata) if metadata.is_dir() => {
+                let mut paths = Vec::new();
+                let mut entries = tokio::fs::read_dir(path).await?;
+                while let Some(entry) = entries.next_entry().await? {
+                    if entry.file_type().await?.is_file() {
+                        paths.push(entry.path());
+                    }
+                }
+                paths.sort();
+                for path in paths {
+                    path.hash(&mut hasher);
+                    tokio::fs::metadata(&path)
+                        .await?
+                        .modified()?
+                        .hash(&mut hasher);
+                    tokio::fs::read(&path).await?.hash(&mut hasher);
+                }
+            }
+            Ok(metadata) => {
+                metadata.modified()?.hash(&mut hasher);
+                tokio::fs::read(path).await?.hash(&mut hasher);
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        Ok(Self(format!("\"{:016x}\"", hasher.finish())))
+    }
+
+    /// Whether the client already has this version of the resource, according to its
+    /// `If-None-Match` header
+    pub fn matches(&self, if_none_match: &IfNoneMatch) -> bool {
+        let Some(header) = &if_none_match.0 else {
+            return false;
+        };
+        // `If-None-Match` uses weak comparison, so ignore any `W/` prefix
+        header
+            .split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == self.0)
+    }
+}
+
+/// The `If-None-Match` header of a request, if any
+#[derive(Debug, Clone, Default)]
+pub struct IfNoneMatch(Option<String>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IfNoneMatch {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        request::Outcome::Success(IfNoneMatch(
+            request.headers().get_one("If-None-Match").map(String::from),
+        ))
+    }
+}
+
+/// A response with an `ETag` header, or `304 Not Modified` if the client already has it
+pub enum ETagged<R> {
+    Modified(ETag, R),
+    NotModified(ETag),
+}
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for ETagged<R> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'o> {
+        let (etag, mut response) = match self {
+            ETagged::Modified(etag, inner) => (etag, inner.respond_to(request)?),
+            ETagged::NotModified(etag) => (etag, Status::NotModified.respond_to(request)?),
+        };
+        response.set_header(Header::new("ETag", etag.0));
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ETag, IfNoneMatch};
+
+    fn if_none_match(header: &str) -> IfNoneMatch {
+        IfNoneMatch(Some(header.into()))
+    }
+
+    #[test]
+    fn test_etag_matches() {
+        let etag = ETag("\"abc\"".into());
+        assert!(!etag.matches(&IfNoneMatch::default()));
+        assert!(etag.matches(&if_none_match("\"abc\"")));
+        assert!(etag.matches(&if_none_match("W/\"abc\"")));
+        assert!(etag.matches(&if_none_match("\"xyz\", \"abc\"")));
+        assert!(etag.matches(&if_none_match("*")));
+        assert!(!etag.matches(&if_none_match("\"xyz\"")));
+        assert!(!etag.matches(&if_none_match("abc")));
+    }
+}
@@ -0,0 +1,32 @@
+//! The exit codes of the CLI, so that scripts can tell kinds of failure apart
+//!
+//! Any failure without a more specific code, including invalid arguments, exits with 1.
+
+/// A layout or monitor given on the command line doesn't exist
+pub const NOT_FOUND: i32 = 2;
+/// The display configuration couldn't be changed
+pub const APPLY_FAILED: i32 = 3;
+/// Installing, starting or otherwise managing the service failed
+pub const SERVICE_ERROR: i32 = 4;
+/// The config couldn't be loaded
+pub const CONFIG_ERROR: i32 = 5;
+/// A partial layout name given on the command line matches more than one layout
+pub const AMBIGUOUS: i32 = 6;
+/// The command needs administrator rights that the process doesn't have, e.g. registering the
+/// service. Tools like `cargo xtask run` re-run the command elevated when they see it.
+pub const NEEDS_ELEVATION: i32 = 7;
+/// A layout applied with `--confirm` wasn't kept, so the previous configuration was restored
+pub const REVERTED: i32 = 8;
+
+/// The exit codes, for the help text
+pub const HELP: &str = "\
+Exit codes:
+  0  Success
+  1  Invalid arguments, or any other failure
+  2  Layout or monitor not found
+  3  Failed to change the display configuration
+  4  Failed to manage the service
+  5  Failed to load the config
+  6  More than one layout matches
+  7  The command must be run as Administrator
+  8  The layout wasn't kept after --confirm, and was reverted";
@@ -0,0 +1,109 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+/// The number of applies kept in the history, oldest first
+pub const MAX_HISTORY_ENTRIES: usize = 100;
+
+/// What applied a layout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ApplySource {
+    Cli,
+    Web,
+    AutoSwitch,
+    /// Re-applied because the display configuration drifted from the enforced layout
+    Enforce,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub id: String,
+    pub applied_at: jiff::Timestamp,
+    pub source: ApplySource,
+}
+
+/// The most recently applied layouts, oldest first, persisted next to the layouts file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct History(Vec<HistoryEntry>);
+
+impl History {
+    /// Load the history, which is empty if no layout has been applied yet
+    pub async fn load(path: &Path) -> Result<Self> {
+        debug!("Loading apply history from {}", path.display());
+        Self::load_private(path)
+            .await
+            .with_context(|| format!("Failed to load apply history at {}", path.display()))
+    }
+
+    async fn load_private(path: &Path) -> Result<Self> {
+        if !tokio::fs::try_exists(path).await? {
+            return Ok(Self::default());
+        }
+        let json = tokio::fs::read_to_string(path).await?;
+        serde_json::from_str(&json).context("Invalid JSON")
+    }
+
+    pub async fn save(&self, path: &Path) -> Result<()> {
+        debug!("Saving apply history to {}", path.display());
+        let json = serde_json::to_string_pretty(self)?;
+        tokio::fs::write(path, json)
+            .await
+            .with_context(|| format!("Failed to save apply history at {}", path.display()))
+    }
+
+    /// Add an entry, dropping the oldest ones beyond [`MAX_HISTORY_ENTRIES`]
+    pub fn push(&mut self, entry: HistoryEntry) {
+        self.0.push(entry);
+        if self.0.len() > MAX_HISTORY_ENTRIES {
+            self.0.drain(..self.0.len() - MAX_HISTORY_ENTRIES);
+        }
+    }
+
+    /// Iterate over the entries, most recent first
+    pub fn iter_recent(&self) -> impl Iterator<Item = &HistoryEntry> {
+        self.0.iter().rev()
+    }
+
+    /// Record that the layout `id` was just applied, logging rather than failing if the history
+    /// can't be updated, since the layout itself has already been applied
+    pub async fn record(path: &Path, id: &str, source: ApplySource) {
+        let result = async {
+            let mut history = Self::load(path).await?;
+            history.push(HistoryEntry {
+                id: id.into(),
+                applied_at: jiff::Timestamp::now(),
+                source,
+            });
+            history.save(path).await
+        }
+        .await;
+        if let Err(e) = result {
+            warn!("{:?}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ApplySource, History, HistoryEntry, MAX_HISTORY_ENTRIES};
+
+    #[test]
+    fn test_push_drops_oldest() {
+        let mut history = History::default();
+        for i in 0..MAX_HISTORY_ENTRIES + 5 {
+            history.push(HistoryEntry {
+                id: i.to_string(),
+                applied_at: jiff::Timestamp::UNIX_EPOCH,
+                source: ApplySource::Cli,
+            });
+        }
+        let ids: Vec<_> = history.iter_recent().map(|e| e.id.as_str()).collect();
+        assert_eq!(ids.len(), MAX_HISTORY_ENTRIES);
+        assert_eq!(ids[0], (MAX_HISTORY_ENTRIES + 4).to_string());
+        assert_eq!(ids[ids.len() - 1], "5");
+    }
+}
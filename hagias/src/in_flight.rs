@@ -0,0 +1,148 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use rocket::fairing::AdHoc;
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+/// How long shutdown waits for in-flight display configuration changes to finish
+pub const SHUTDOWN_APPLY_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Default)]
+struct Inner {
+    count: Mutex<usize>,
+    idle: Notify,
+}
+
+/// Tracks display configuration changes that are in progress, so that shutdown can wait for them
+/// to finish rather than leaving the displays half-configured.
+#[derive(Clone, Default)]
+pub struct InFlightApplies(Arc<Inner>);
+
+impl InFlightApplies {
+    /// Mark a change as in progress until the returned guard is dropped
+    pub fn begin(&self) -> ApplyGuard {
+        *self
+            .0
+            .count
+            .lock()
+            .expect("failed to lock in-flight applies") += 1;
+        ApplyGuard(self.0.clone())
+    }
+
+    /// The number of changes in progress
+    pub fn count(&self) -> usize {
+        *self
+            .0
+            .count
+            .lock()
+            .expect("failed to lock in-flight applies")
+    }
+
+    /// Wait until no changes are in progress. Returns `false` if `timeout` elapsed first.
+    pub async fn wait_idle(&self, timeout: Duration) -> bool {
+        tokio::time::timeout(timeout, async {
+            loop {
+                // Register for the notification before checking, so it can't be missed
+                let idle = self.0.idle.notified();
+                if self.count() == 0 {
+                    return;
+                }
+                idle.await;
+            }
+        })
+        .await
+        .is_ok()
+    }
+
+    /// A fairing that waits for in-flight changes when rocket shuts down
+    pub fn shutdown_fairing(&self) -> AdHoc {
+        let in_flight = self.clone();
+        AdHoc::on_shutdown("Wait for in-flight applies", move |_| {
+            Box::pin(async move { in_flight.wait_idle_or_warn().await })
+        })
+    }
+
+    /// Wait until no changes are in progress, warning if they don't finish within
+    /// [`SHUTDOWN_APPLY_TIMEOUT`]
+    pub async fn wait_idle_or_warn(&self) {
+        let count = self.count();
+        if count == 0 {
+            return;
+        }
+        info!(
+            "Waiting for {} in-flight display change(s) to finish",
+            count
+        );
+        if !self.wait_idle(SHUTDOWN_APPLY_TIMEOUT).await {
+            warn!(
+                "{} display change(s) still in progress after {:?}",
+                self.count(),
+                SHUTDOWN_APPLY_TIMEOUT
+            );
+        }
+    }
+}
+
+/// A display configuration change in progress, which finishes when dropped
+pub struct ApplyGuard(Arc<Inner>);
+
+impl Drop for ApplyGuard {
+    fn drop(&mut self) {
+        let mut count = self
+            .0
+            .count
+            .lock()
+            .expect("failed to lock in-flight applies");
+        *count -= 1;
+        if *count == 0 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::InFlightApplies;
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn test_wait_idle_when_idle() {
+        let in_flight = InFlightApplies::default();
+        assert!(block_on(in_flight.wait_idle(Duration::ZERO)));
+    }
+
+    #[test]
+    fn test_wait_idle_times_out() {
+        let in_flight = InFlightApplies::default();
+        let _guard = in_flight.begin();
+        assert!(!block_on(in_flight.wait_idle(Duration::from_millis(50))));
+    }
+
+    #[test]
+    fn test_wait_idle_waits_for_guards() {
+        let in_flight = InFlightApplies::default();
+        let guards = [in_flight.begin(), in_flight.begin()];
+        assert_eq!(in_flight.count(), 2);
+        let handle = std::thread::spawn(move || {
+            for guard in guards {
+                std::thread::sleep(Duration::from_millis(20));
+                drop(guard);
+            }
+        });
+        assert!(block_on(in_flight.wait_idle(Duration::from_secs(30))));
+        assert_eq!(in_flight.count(), 0);
+        handle.join().unwrap();
+    }
+}
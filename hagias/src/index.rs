@@ -1,48 +1,813 @@
-use anyhow::Result;
-use rocket::http::Status;
-use rocket::post;
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use rocket::http::{Status, StatusClass};
+use rocket::response::content::RawJson;
 use rocket::response::status;
+use rocket::serde::json::Json;
+use rocket::{Request, catch, post};
 use rocket::{State, get};
 use rocket_dyn_templates::{Template, context};
+use serde::{Deserialize, Serialize};
 
+use crate::auth::ApiAuth;
 use crate::config::Config;
-use crate::layouts::Layouts;
+use crate::confirm::{ApplyConfirmations, CONFIRM_TIMEOUT, PendingApply};
+use crate::ddc::{self, PowerState};
+use crate::display::{DisplayLayout, DisplayPath};
+use crate::etag::{ETag, ETagged, IfNoneMatch};
+use crate::history::{ApplySource, History};
+use crate::in_flight::InFlightApplies;
+use crate::last_applied::{LastApplied, LastAppliedState};
+use crate::layouts::{Layouts, NamedLayout, is_valid_id};
+use crate::monitors::{Monitor, MonitorFilter};
+use crate::windows_util::{
+    DisplayQueryType, DisplayTopology, OutputTechnology, WindowsDisplayConfig,
+};
+
+/// The version of the HTTP API, incremented whenever endpoints are added or changed
+pub const API_VERSION: u32 = 2;
+
+/// The version of the running service, for client compatibility checks
+#[derive(Debug, Clone, Serialize)]
+pub struct VersionResponse {
+    pub version: &'static str,
+    /// The git commit the service was built from, if known
+    pub git_hash: Option<&'static str>,
+    pub api_version: u32,
+}
+
+#[get("/api/version")]
+pub fn version() -> Json<VersionResponse> {
+    Json(VersionResponse {
+        version: env!("CARGO_PKG_VERSION"),
+        git_hash: option_env!("HAGIAS_GIT_HASH"),
+        api_version: API_VERSION,
+    })
+}
+
+/// A human-friendly summary of a single monitor in a layout, for rendering in templates
+#[derive(Debug, Clone, Serialize)]
+pub struct MonitorSummary {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub refresh_rate: String,
+    pub x: i32,
+    pub y: i32,
+    pub rotation: String,
+}
+
+impl MonitorSummary {
+    pub fn from_path(
+        layout: &DisplayLayout,
+        path: &DisplayPath,
+        aliases: &HashMap<String, String>,
+    ) -> Option<Self> {
+        let source_mode = layout.source_modes.get(path.source.source_mode_index)?;
+        let target_mode = layout.target_modes.get(path.target.target_mode_index)?;
+        // The path refresh rate may be unspecified, in which case the mode's is used
+        let refresh_rate = if path.target.refresh_rate.as_f64().is_some() {
+            path.target.refresh_rate
+        } else {
+            target_mode.v_sync_freq
+        };
+        Some(Self {
+            name: target_mode.device.display_name_with_aliases(aliases),
+            width: source_mode.width,
+            height: source_mode.height,
+            refresh_rate: refresh_rate.to_string(),
+            x: source_mode.position.x,
+            y: source_mode.position.y,
+            rotation: format!("{:?}", path.target.rotation),
+        })
+    }
+}
+
+/// A layout along with a summary of its monitors, for rendering in templates
+#[derive(Debug, Clone, Serialize)]
+pub struct LayoutSummary<'a> {
+    #[serde(flatten)]
+    pub layout: &'a NamedLayout,
+    /// The number of monitors in the layout, including any that can't be summarized
+    pub monitor_count: usize,
+    pub monitors: Vec<MonitorSummary>,
+}
+
+impl<'a> LayoutSummary<'a> {
+    pub fn new(layout: &'a NamedLayout, aliases: &HashMap<String, String>) -> Self {
+        Self {
+            layout,
+            monitor_count: layout.layout.paths.len(),
+            monitors: layout
+                .layout
+                .paths
+                .iter()
+                .filter_map(|path| MonitorSummary::from_path(&layout.layout, path, aliases))
+                .collect(),
+        }
+    }
+}
 
 #[get("/")]
 pub async fn index(
     config: &State<Config>,
+    last_applied: &State<LastAppliedState>,
 ) -> Result<Template, rocket::response::Debug<anyhow::Error>> {
     let layouts = Layouts::load(&config.layouts_path.relative()).await?;
-    Ok(Template::render("index", context! {
-        layouts: layouts.iter().collect::<Vec<_>>()
-    }))
+    Ok(Template::render(
+        "index",
+        context! {
+            layouts: layouts
+                .visible()
+                .map(|layout| LayoutSummary::new(layout, &config.aliases))
+                .collect::<Vec<_>>(),
+            last_applied: last_applied.get().await,
+            read_only: config.read_only,
+        },
+    ))
+}
+
+/// All layouts, including hidden ones. Responds with `304 Not Modified` without reloading the
+/// layouts if the layouts file hasn't changed since the tag in the client's `If-None-Match`.
+#[get("/api/layouts")]
+pub async fn layouts(
+    config: &State<Config>,
+    if_none_match: IfNoneMatch,
+) -> Result<ETagged<RawJson<String>>, rocket::response::Debug<anyhow::Error>> {
+    let layouts_path = config.layouts_path.relative();
+    let etag = ETag::from_file(&layouts_path).await?;
+    if etag.matches(&if_none_match) {
+        return Ok(ETagged::NotModified(etag));
+    }
+    let layouts = Layouts::load(&layouts_path).await?;
+    let json = serde_json::to_string(
+        &layouts
+            .iter_all()
+            .map(|layout| LayoutSummary::new(layout, &config.aliases))
+            .collect::<Vec<_>>(),
+    )
+    .context("Failed to serialize layouts")?;
+    Ok(ETagged::Modified(etag, RawJson(json)))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PinRequest {
+    pub pinned: bool,
+}
+
+/// Pin or unpin a layout, so it's shown before all others
+#[post("/api/layouts/<id>/pin", data = "<request>")]
+pub async fn pin_layout(
+    _auth: ApiAuth,
+    id: &str,
+    request: Json<PinRequest>,
+    config: &State<Config>,
+) -> Result<status::Custom<String>, ApiError> {
+    if config.read_only {
+        return Err(api_error(Status::Forbidden, "Layouts are read-only"));
+    }
+    let mut layouts = Layouts::load_for_update(&config.layouts_path.relative())
+        .await
+        .map_err(internal_error)?;
+    let Some(layout) = layouts.get_layout_mut(id) else {
+        return Err(api_error(
+            Status::NotFound,
+            format!("Monitor layout {} not found", id),
+        ));
+    };
+    layout.pinned = request.pinned;
+    layout.mark_modified();
+    layouts
+        .save(&config.layouts_path.relative(), config.layouts_format)
+        .await
+        .map_err(internal_error)?;
+    Ok(status::Custom(
+        Status::Ok,
+        format!(
+            "Monitor layout {} {}",
+            id,
+            if request.pinned { "pinned" } else { "unpinned" }
+        ),
+    ))
+}
+
+/// The body of every failed API response, e.g.
+/// `{"error":"Monitor layout work not found","code":"not_found"}`
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorResponse {
+    /// Why the request failed, for showing to the user
+    pub error: String,
+    /// The kind of failure, so clients don't need to parse `error`
+    pub code: &'static str,
+}
+
+/// A failed API response, with a JSON body so that clients can show the reason
+pub type ApiError = status::Custom<Json<ErrorResponse>>;
+
+/// A failed API response, with the code for `status`
+pub fn api_error(status: Status, error: impl Into<String>) -> ApiError {
+    api_error_with_code(status, status_code(status), error)
+}
+
+/// A failed API response, with a more specific code than `status` gives, e.g. `apply_failed`
+pub fn api_error_with_code(
+    status: Status,
+    code: &'static str,
+    error: impl Into<String>,
+) -> ApiError {
+    status::Custom(
+        status,
+        Json(ErrorResponse {
+            error: error.into(),
+            code,
+        }),
+    )
+}
+
+/// A failed API response for an unexpected error
+fn internal_error(e: anyhow::Error) -> ApiError {
+    api_error(Status::InternalServerError, format!("{:#}", e))
+}
+
+/// The code of a failed API response with `status`, e.g. `not_found` for `404 Not Found`
+fn status_code(status: Status) -> &'static str {
+    match status.code {
+        400 => "bad_request",
+        401 => "unauthorized",
+        403 => "forbidden",
+        404 => "not_found",
+        409 => "conflict",
+        413 => "payload_too_large",
+        422 => "unprocessable_entity",
+        _ if status.class() == StatusClass::ServerError => "internal_error",
+        _ => "error",
+    }
+}
+
+/// Respond to API requests that fail before reaching a route, e.g. without the API token, with the
+/// same JSON body as the routes' errors
+#[catch(default)]
+pub fn api_catcher(status: Status, _request: &Request) -> ApiError {
+    let error = match status.code {
+        401 => "Missing or wrong API token",
+        _ => status.reason_lossy(),
+    };
+    api_error(status, error)
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StoreRequest {
+    pub name: String,
+    #[serde(default)]
+    pub emoji: Option<String>,
+    /// Replace the layout if one with the ID already exists, rather than failing
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+/// Store the current monitor configuration as a layout, responding with its summary. Fails with
+/// `409 Conflict` if the layout already exists, unless `overwrite` is set, in which case it is
+/// replaced in place, keeping whether it is pinned or hidden.
+#[post("/api/store/<id>", data = "<request>")]
+pub async fn store(
+    _auth: ApiAuth,
+    id: &str,
+    request: Json<StoreRequest>,
+    config: &State<Config>,
+) -> Result<status::Custom<RawJson<String>>, ApiError> {
+    if config.read_only {
+        return Err(api_error(Status::Forbidden, "Layouts are read-only"));
+    }
+    if !is_valid_id(id) {
+        return Err(api_error(
+            Status::BadRequest,
+            format!(
+                "Invalid layout ID {:?}: only letters, digits, '-' and '_' are allowed",
+                id
+            ),
+        ));
+    }
+    if request.name.trim().is_empty() {
+        return Err(api_error(Status::BadRequest, "The layout name is empty"));
+    }
+    let layouts_path = config.layouts_path.relative();
+    let mut layouts = Layouts::load_for_update(&layouts_path)
+        .await
+        .map_err(internal_error)?;
+    if layouts.get_layout(id).is_some() && !request.overwrite {
+        return Err(api_error(
+            Status::Conflict,
+            format!("Layout {} already exists, set overwrite to replace it", id),
+        ));
+    }
+    let exists = layouts
+        .store_current(
+            id,
+            request.name.trim(),
+            request.emoji.as_deref().filter(|emoji| !emoji.is_empty()),
+            DisplayQueryType::Active,
+        )
+        .await
+        .map_err(internal_error)?;
+    layouts
+        .save(&layouts_path, config.layouts_format)
+        .await
+        .map_err(internal_error)?;
+    let layout = layouts.get_layout(id).expect("layout was just added");
+    let json = serde_json::to_string(&LayoutSummary::new(layout, &config.aliases))
+        .context("Failed to serialize layout")
+        .map_err(internal_error)?;
+    let status = if exists { Status::Ok } else { Status::Created };
+    Ok(status::Custom(status, RawJson(json)))
+}
+
+/// The response to applying a layout, which must be confirmed with the token
+#[derive(Debug, Clone, Serialize)]
+pub struct ApplyResponse {
+    /// Always `applied`
+    pub status: &'static str,
+    pub id: String,
+    pub name: String,
+    pub message: String,
+    pub token: String,
+    pub timeout_secs: u64,
+}
+
+/// A failed response for a layout that couldn't be applied, with the code `apply_failed`
+fn apply_failed(e: anyhow::Error) -> ApiError {
+    api_error_with_code(
+        Status::InternalServerError,
+        "apply_failed",
+        format!("{:#}", e),
+    )
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct TokenRequest {
+    pub token: String,
+}
+
+/// Apply a layout without saving it to the Windows database. It is reverted unless it is
+/// confirmed with `/api/confirm` before the timeout. Fails with the code `not_found` if there's no
+/// such layout, or `apply_failed` if it can't be applied.
 #[post("/api/apply/<id>")]
-pub async fn apply_config(id: &str, config: &State<Config>) -> status::Custom<String> {
-    match Layouts::load(&config.layouts_path.relative()).await {
-        Ok(layouts) => match layouts.get_layout(&id) {
-            Some(layout) => match layout.layout.apply(true) { // TODO: Have an /api/confirm that saves the layout to the database (or defaultable arg here)
-                Ok(_) => status::Custom(
-                    Status::Accepted,
-                    format!(
-                        "Configuration {} \"{}\" applied successfully",
-                        layout.id, layout.name
-                    ),
-                ),
-                Err(e) => status::Custom(
-                    Status::InternalServerError,
-                    format!(
-                        "Failed to apply layout {} \"{}\": {:?}",
-                        layout.id, layout.name, e
-                    ),
-                ),
-            },
-            None => status::Custom(Status::NotFound, format!("Layout {} not found", id)),
+pub async fn apply_config(
+    _auth: ApiAuth,
+    id: &str,
+    config: &State<Config>,
+    confirmations: &State<ApplyConfirmations>,
+    in_flight: &State<InFlightApplies>,
+) -> Result<status::Custom<Json<ApplyResponse>>, ApiError> {
+    let layouts = Layouts::load(&config.layouts_path.relative())
+        .await
+        .context("Failed to load layouts")
+        .map_err(apply_failed)?;
+    let Some(layout) = layouts.get_layout(id) else {
+        return Err(api_error(
+            Status::NotFound,
+            format!("Monitor layout {} not found", id),
+        ));
+    };
+
+    let applied = layout.layout.clone();
+    apply_pending(
+        confirmations,
+        in_flight,
+        layout.id.clone(),
+        layout.name.clone(),
+        move || {
+            layout.layout.apply(false, false, false)?;
+            Ok(applied)
+        },
+    )
+    .map_err(apply_failed)
+}
+
+/// Switch to one of the Win+P projection modes without saving it to the Windows database. It is
+/// reverted unless it is confirmed with `/api/confirm` before the timeout.
+#[post("/api/project/<mode>")]
+pub async fn project(
+    _auth: ApiAuth,
+    mode: &str,
+    confirmations: &State<ApplyConfirmations>,
+    in_flight: &State<InFlightApplies>,
+) -> Result<status::Custom<Json<ApplyResponse>>, ApiError> {
+    let topology = <DisplayTopology as clap::ValueEnum>::from_str(mode, true).map_err(|e| {
+        api_error(
+            Status::BadRequest,
+            format!("Invalid projection mode {:?}: {}", mode, e),
+        )
+    })?;
+    apply_pending(
+        confirmations,
+        in_flight,
+        format!("project-{:?}", topology).to_lowercase(),
+        format!("{:?}", topology),
+        move || {
+            WindowsDisplayConfig::set_topology(topology)?;
+            DisplayLayout::get()
         },
-        Err(e) => status::Custom(
-            Status::InternalServerError,
-            format!("Failed to load layouts: {:?}", e),
+    )
+    .map_err(apply_failed)
+}
+
+/// Apply a change to the display configuration with `apply`, which returns the applied layout,
+/// and wait for it to be confirmed
+fn apply_pending(
+    confirmations: &ApplyConfirmations,
+    in_flight: &InFlightApplies,
+    id: String,
+    name: String,
+    apply: impl FnOnce() -> Result<DisplayLayout>,
+) -> Result<status::Custom<Json<ApplyResponse>>> {
+    // If a layout is already waiting for confirmation, revert to what was there before it. It is
+    // only replaced once the new layout is applied, so a failed apply leaves it to time out and
+    // revert as before
+    let previous = match confirmations.pending_previous() {
+        Some(previous) => previous,
+        None => DisplayLayout::get().context("Failed to get current layout")?,
+    };
+
+    let layout = {
+        let _guard = in_flight.begin();
+        apply()
+    }
+    .with_context(|| format!("Failed to apply layout {} \"{}\"", id, name))?;
+    let message = format!(
+        "Configuration {} \"{}\" applied, waiting for confirmation",
+        id, name
+    );
+    let token = confirmations.begin(PendingApply {
+        id: id.clone(),
+        name: name.clone(),
+        layout,
+        previous,
+    });
+    Ok(status::Custom(
+        Status::Accepted,
+        Json(ApplyResponse {
+            status: "applied",
+            id,
+            name,
+            message,
+            token,
+            timeout_secs: CONFIRM_TIMEOUT.as_secs(),
+        }),
+    ))
+}
+
+/// Keep a layout applied with `/api/apply`, saving it to the Windows database
+#[post("/api/confirm", data = "<request>")]
+pub async fn confirm(
+    _auth: ApiAuth,
+    request: Json<TokenRequest>,
+    confirmations: &State<ApplyConfirmations>,
+    in_flight: &State<InFlightApplies>,
+    last_applied: &State<LastAppliedState>,
+    config: &State<Config>,
+) -> Result<status::Custom<String>, ApiError> {
+    let Some(pending) = confirmations.take(&request.token) else {
+        return Err(api_error(
+            Status::NotFound,
+            "No layout is waiting for confirmation with that token, it may have been reverted",
+        ));
+    };
+    keep_pending(pending, in_flight, last_applied, config).await
+}
+
+/// Keep the layout with ID `id` applied with `/api/apply`, saving it to the Windows database, for
+/// clients that don't keep the token
+#[post("/api/confirm/<id>")]
+pub async fn confirm_id(
+    _auth: ApiAuth,
+    id: &str,
+    confirmations: &State<ApplyConfirmations>,
+    in_flight: &State<InFlightApplies>,
+    last_applied: &State<LastAppliedState>,
+    config: &State<Config>,
+) -> Result<status::Custom<String>, ApiError> {
+    let Some(pending) = confirmations.take_by_id(id) else {
+        return Err(api_error(
+            Status::NotFound,
+            format!(
+                "Layout {} isn't waiting for confirmation, it may have been reverted",
+                id
+            ),
+        ));
+    };
+    keep_pending(pending, in_flight, last_applied, config).await
+}
+
+/// Save a pending layout to the Windows database, recording it as the last applied
+async fn keep_pending(
+    pending: PendingApply,
+    in_flight: &InFlightApplies,
+    last_applied: &LastAppliedState,
+    config: &Config,
+) -> Result<status::Custom<String>, ApiError> {
+    let _guard = in_flight.begin();
+    match pending.layout.apply(true, false, false) {
+        Ok(_) => {
+            last_applied.set(LastApplied::now(&pending.id)).await;
+            History::record(&config.history_path(), &pending.id, ApplySource::Web).await;
+            Ok(status::Custom(
+                Status::Ok,
+                format!("Configuration {} \"{}\" kept", pending.id, pending.name),
+            ))
+        }
+        Err(e) => Err(apply_failed(e.context(format!(
+            "Failed to save layout {} \"{}\"",
+            pending.id, pending.name
+        )))),
+    }
+}
+
+/// Revert a layout applied with `/api/apply` without waiting for the timeout
+#[post("/api/revert", data = "<request>")]
+pub async fn revert(
+    _auth: ApiAuth,
+    request: Json<TokenRequest>,
+    confirmations: &State<ApplyConfirmations>,
+    in_flight: &State<InFlightApplies>,
+) -> Result<status::Custom<String>, ApiError> {
+    let Some(pending) = confirmations.take(&request.token) else {
+        return Err(api_error(
+            Status::NotFound,
+            "No layout is waiting for confirmation with that token, it may have been reverted",
+        ));
+    };
+    let _guard = in_flight.begin();
+    match pending.previous.apply(false, false, true) {
+        Ok(_) => Ok(status::Custom(
+            Status::Ok,
+            format!("Configuration {} \"{}\" reverted", pending.id, pending.name),
+        )),
+        Err(e) => Err(apply_failed(
+            e.context(format!("Failed to revert layout {}", pending.id)),
+        )),
+    }
+}
+
+#[get("/api/monitors?<connected>&<tech>")]
+pub async fn monitors(
+    connected: Option<bool>,
+    tech: Option<&str>,
+    config: &State<Config>,
+) -> Result<Json<Vec<Monitor>>, ApiError> {
+    let tech = match tech {
+        Some(tech) => Some(
+            <OutputTechnology as clap::ValueEnum>::from_str(tech, true).map_err(|e| {
+                api_error(
+                    Status::BadRequest,
+                    format!("Invalid output technology {:?}: {}", tech, e),
+                )
+            })?,
         ),
+        None => None,
+    };
+    let filter = MonitorFilter {
+        connected: connected.unwrap_or(false),
+        tech,
+    };
+    match Monitor::get_all_with_aliases(&config.aliases) {
+        Ok(monitors) => Ok(Json(filter.apply(monitors))),
+        Err(e) => Err(internal_error(e.context("Failed to get monitors"))),
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PowerRequest {
+    pub state: PowerState,
+}
+
+/// Set the power state of a monitor over DDC/CI
+#[post("/api/monitors/<id>/power", data = "<request>")]
+pub async fn monitor_power(
+    _auth: ApiAuth,
+    id: &str,
+    request: Json<PowerRequest>,
+    config: &State<Config>,
+) -> Result<status::Custom<String>, ApiError> {
+    let monitor = match Monitor::find(id, &config.aliases) {
+        Ok(Some(monitor)) => monitor,
+        Ok(None) => {
+            return Err(api_error(
+                Status::NotFound,
+                format!("Monitor {:?} not found", id),
+            ));
+        }
+        Err(e) => {
+            return Err(api_error(
+                Status::BadRequest,
+                format!("Failed to find monitor {:?}: {:#}", id, e),
+            ));
+        }
+    };
+    match ddc::set_power_state_with_timeout(
+        monitor.clone(),
+        request.state,
+        ddc::POWER_STATE_TIMEOUT,
+    )
+    .await
+    {
+        Ok(()) => Ok(status::Custom(
+            Status::Ok,
+            format!(
+                "Set monitor {} to {:?}",
+                monitor.display_name(),
+                request.state
+            ),
+        )),
+        // Most likely the monitor does not support DDC/CI
+        Err(e) => Err(api_error(
+            Status::UnprocessableEntity,
+            format!(
+                "Failed to set power state of monitor {}: {:#}",
+                monitor.display_name(),
+                e
+            ),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::{
+        figment::{
+            Figment,
+            providers::{Format, Toml},
+        },
+        http::Status,
+        local::asynchronous::Client,
+    };
+
+    use super::{ApiError, api_catcher, api_error, apply_failed};
+    use crate::{
+        config::Config,
+        layouts::{Layouts, LayoutsFormat},
+        test_util::{TempDir, named_layout},
+    };
+
+    #[rocket::async_test]
+    async fn test_layouts_json() {
+        let temp_dir = TempDir::new("api-layouts");
+        let layouts_path = temp_dir.join("layouts.json");
+        let mut layouts = Layouts::new();
+        layouts.add_layout(named_layout("work", false));
+        layouts.add_layout(named_layout("games", true));
+        layouts
+            .save(&layouts_path, LayoutsFormat::Pretty)
+            .await
+            .unwrap();
+
+        let config: Config = Figment::from(Toml::string(&format!(
+            "layouts_path = '{}'\nstatic_dir = 'static'\ntemplate_dir = 'templates'\nport = 5781",
+            layouts_path.display()
+        )))
+        .extract()
+        .unwrap();
+        let rocket = rocket::custom(rocket::Config::debug_default())
+            .mount("/", rocket::routes![super::layouts])
+            .manage(config);
+        let client = Client::tracked(rocket).await.unwrap();
+        let response = client.get("/api/layouts").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let json: serde_json::Value = response.into_json().await.unwrap();
+
+        // Hidden layouts are included, so clients can choose whether to show them
+        let summaries = json.as_array().unwrap();
+        assert_eq!(summaries.len(), 2);
+        for (summary, (id, name, hidden)) in summaries
+            .iter()
+            .zip([("work", "WORK", false), ("games", "GAMES", true)])
+        {
+            assert_eq!(summary["id"], id);
+            assert_eq!(summary["name"], name);
+            assert_eq!(summary["emoji"], "🖥️");
+            assert_eq!(summary["hidden"], hidden);
+            assert_eq!(summary["monitor_count"], 0);
+            assert!(summary["monitors"].as_array().unwrap().is_empty());
+        }
+    }
+
+    /// A client for the store route, with the layouts `work` (pinned and hidden) and `games`
+    async fn store_client(temp_dir: &TempDir) -> Client {
+        let layouts_path = temp_dir.join("layouts.json");
+        let mut layouts = Layouts::new();
+        let mut work = named_layout("work", true);
+        work.pinned = true;
+        layouts.add_layout(work);
+        layouts.add_layout(named_layout("games", false));
+        layouts
+            .save(&layouts_path, LayoutsFormat::Pretty)
+            .await
+            .unwrap();
+
+        let config: Config = Figment::from(Toml::string(&format!(
+            "layouts_path = '{}'\nstatic_dir = 'static'\ntemplate_dir = 'templates'\nport = 5781",
+            layouts_path.display()
+        )))
+        .extract()
+        .unwrap();
+        let rocket = rocket::custom(rocket::Config::debug_default())
+            .mount("/", rocket::routes![super::store])
+            .manage(config);
+        Client::tracked(rocket).await.unwrap()
+    }
+
+    #[rocket::async_test]
+    async fn test_store_conflict() {
+        let temp_dir = TempDir::new("api-store-conflict");
+        let client = store_client(&temp_dir).await;
+        let before = std::fs::read_to_string(temp_dir.join("layouts.json")).unwrap();
+        let response = client
+            .post("/api/store/work")
+            .body(r#"{"name":"Work"}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Conflict);
+        let json: serde_json::Value = response.into_json().await.unwrap();
+        assert_eq!(json["code"], "conflict");
+        let after = std::fs::read_to_string(temp_dir.join("layouts.json")).unwrap();
+        assert_eq!(before, after);
+    }
+
+    #[rocket::async_test]
+    async fn test_store_overwrite_and_create() {
+        let temp_dir = TempDir::new("api-store");
+        let client = store_client(&temp_dir).await;
+
+        // Replacing a layout keeps its flags and position
+        let response = client
+            .post("/api/store/work")
+            .body(r#"{"name":"Office","overwrite":true}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let json: serde_json::Value = response.into_json().await.unwrap();
+        assert_eq!(json["name"], "Office");
+        assert_eq!(json["pinned"], true);
+        assert_eq!(json["hidden"], true);
+
+        let response = client
+            .post("/api/store/movies")
+            .body(r#"{"name":"Movies","emoji":"🎬"}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Created);
+        let json: serde_json::Value = response.into_json().await.unwrap();
+        assert_eq!(json["id"], "movies");
+        assert_eq!(json["emoji"], "🎬");
+
+        let layouts = Layouts::load(&temp_dir.join("layouts.json")).await.unwrap();
+        let ids: Vec<_> = layouts.iter_all().map(|l| l.id.as_str()).collect();
+        assert_eq!(ids, ["work", "games", "movies"]);
+        let work = layouts.get_layout("work").unwrap();
+        assert_eq!(work.name, "Office");
+        assert!(work.pinned && work.hidden);
+    }
+
+    #[test]
+    fn test_error_json() {
+        let body = |error: ApiError| serde_json::to_value(error.1.into_inner()).unwrap();
+        assert_eq!(
+            body(api_error(Status::NotFound, "Monitor layout work not found")),
+            serde_json::json!({"error": "Monitor layout work not found", "code": "not_found"})
+        );
+        assert_eq!(
+            body(apply_failed(anyhow::anyhow!("oops"))),
+            serde_json::json!({"error": "oops", "code": "apply_failed"})
+        );
+        assert_eq!(
+            body(api_error(Status::ServiceUnavailable, "busy")),
+            serde_json::json!({"error": "busy", "code": "internal_error"})
+        );
+    }
+
+    #[rocket::async_test]
+    async fn test_api_catcher() {
+        let config: Config = Figment::from(Toml::string(
+            "layouts_path = 'layouts.json'\nstatic_dir = 'static'\ntemplate_dir = 'templates'\n\
+             port = 5781\napi_token = 'secret'",
+        ))
+        .extract()
+        .unwrap();
+        let rocket = rocket::custom(rocket::Config::debug_default())
+            .mount("/", rocket::routes![super::revert])
+            .register("/api", rocket::catchers![api_catcher])
+            .manage(config);
+        let client = Client::tracked(rocket).await.unwrap();
+        let response = client
+            .post("/api/revert")
+            .body(r#"{"token":"abc"}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Unauthorized);
+        let json: serde_json::Value = response.into_json().await.unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"error": "Missing or wrong API token", "code": "unauthorized"})
+        );
     }
 }
@@ -0,0 +1,96 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use tracing::{debug, warn};
+
+/// The layout that was most recently applied, persisted so that it survives restarts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LastApplied {
+    pub id: String,
+    pub applied_at: jiff::Timestamp,
+}
+
+impl LastApplied {
+    pub fn now(id: &str) -> Self {
+        Self {
+            id: id.into(),
+            applied_at: jiff::Timestamp::now(),
+        }
+    }
+
+    /// Load the last applied layout, returning `None` if no layout has been applied yet
+    pub async fn load(path: &Path) -> Result<Option<Self>> {
+        debug!("Loading last applied layout from {}", path.display());
+        Self::load_private(path)
+            .await
+            .with_context(|| format!("Failed to load last applied layout at {}", path.display()))
+    }
+
+    async fn load_private(path: &Path) -> Result<Option<Self>> {
+        if !tokio::fs::try_exists(path).await? {
+            return Ok(None);
+        }
+        let json = tokio::fs::read_to_string(path).await?;
+        Ok(Some(serde_json::from_str(&json).context("Invalid JSON")?))
+    }
+
+    pub async fn save(&self, path: &Path) -> Result<()> {
+        debug!("Saving last applied layout to {}", path.display());
+        self.save_private(path)
+            .await
+            .with_context(|| format!("Failed to save last applied layout at {}", path.display()))
+    }
+
+    async fn save_private(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        tokio::fs::write(path, json).await?;
+        Ok(())
+    }
+
+    /// Save the last applied layout, logging rather than failing if it can't be persisted,
+    /// since the layout itself has already been applied
+    pub async fn save_or_warn(&self, path: &Path) {
+        if let Err(e) = self.save(path).await {
+            warn!("{:?}", e);
+        }
+    }
+}
+
+/// Rocket-managed copy of the last applied layout
+pub struct LastAppliedState {
+    path: PathBuf,
+    last_applied: RwLock<Option<LastApplied>>,
+}
+
+impl LastAppliedState {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            last_applied: RwLock::new(None),
+        }
+    }
+
+    /// Get the last applied layout
+    ///
+    /// This is reloaded from disk, as the layout may have been applied from the CLI. If the
+    /// file can't be read, the last known value is returned.
+    pub async fn get(&self) -> Option<LastApplied> {
+        match LastApplied::load(&self.path).await {
+            Ok(last_applied) => {
+                *self.last_applied.write().await = last_applied.clone();
+                last_applied
+            }
+            Err(e) => {
+                warn!("{:?}", e);
+                self.last_applied.read().await.clone()
+            }
+        }
+    }
+
+    pub async fn set(&self, last_applied: LastApplied) {
+        last_applied.save_or_warn(&self.path).await;
+        *self.last_applied.write().await = Some(last_applied);
+    }
+}
@@ -1,20 +1,228 @@
-use std::path::Path;
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    fs::TryLockError,
+    io::ErrorKind,
+    ops::{Deref, DerefMut},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
 use derive_more::IntoIterator;
-use serde::{Deserialize, Serialize};
-use tokio::io::AsyncReadExt;
-use tracing::debug;
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize, de::DeserializeOwned};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tracing::{debug, warn};
 
 use crate::{
     display::DisplayLayout,
     windows_util::{DisplayQueryType, WindowsDisplayConfig},
 };
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default, IntoIterator)]
+/// The name of the file that records the order of the layouts in a directory store. It starts with
+/// a `.`, so no layout ID can have the same file name.
+const INDEX_FILE_NAME: &str = ".index.json";
+/// The name the index file had before, which collided with the file of a layout with ID `index`
+const LEGACY_INDEX_FILE_NAME: &str = "index.json";
+
+/// Names that Windows reserves for devices, whatever their extension, so can't be used as layout
+/// IDs in a directory store
+const RESERVED_FILE_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM0", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7",
+    "COM8", "COM9", "LPT0", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// How long to wait for another process to finish updating the layouts
+const LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How layouts are stored on disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoreKind {
+    /// All layouts in a single JSON file
+    File,
+    /// Each layout in its own `<id>.json` file in a directory, in the order given by its
+    /// `.index.json`, which diffs and merges better in version control
+    Directory,
+}
+
+impl StoreKind {
+    /// The kind of store at `layouts_path`: a directory if one exists there, or a file otherwise
+    pub async fn detect(layouts_path: &Path) -> Result<Self> {
+        match tokio::fs::metadata(layouts_path).await {
+            Ok(metadata) if metadata.is_dir() => Ok(StoreKind::Directory),
+            Ok(_) => Ok(StoreKind::File),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(StoreKind::File),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// How the JSON of stored layouts is formatted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LayoutsFormat {
+    /// Indented, for editing by hand
+    #[default]
+    Pretty,
+    /// On a single line, which is smaller and faster to parse for large stores
+    Compact,
+}
+
+impl LayoutsFormat {
+    fn to_string<T: Serialize + ?Sized>(self, value: &T) -> serde_json::Result<String> {
+        match self {
+            LayoutsFormat::Pretty => serde_json::to_string_pretty(value),
+            LayoutsFormat::Compact => serde_json::to_string(value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Default, IntoIterator)]
 #[serde(transparent)]
 pub struct Layouts(Vec<NamedLayout>);
 
+/// Layouts loaded by [`Layouts::load_for_update`], which keep the store locked until dropped
+#[derive(Debug)]
+pub struct LockedLayouts {
+    layouts: Layouts,
+    _lock: std::fs::File,
+}
+
+impl Deref for LockedLayouts {
+    type Target = Layouts;
+
+    fn deref(&self) -> &Layouts {
+        &self.layouts
+    }
+}
+
+impl DerefMut for LockedLayouts {
+    fn deref_mut(&mut self) -> &mut Layouts {
+        &mut self.layouts
+    }
+}
+
+/// The path of `path` with `suffix` appended, e.g. `layouts.json.lock` for `layouts.json`
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    path.into()
+}
+
+/// The lock file of the store at `layouts_path`, which sits next to it
+fn lock_path(layouts_path: &Path) -> PathBuf {
+    sibling_path(layouts_path, ".lock")
+}
+
+/// Write `contents` to a temporary file next to `path` and then move it into place, so that `path`
+/// is never left partly written if the process is killed, e.g. when the service is stopped
+async fn write_atomically(path: &Path, contents: impl AsRef<[u8]>) -> Result<()> {
+    let temp_path = sibling_path(path, ".tmp");
+    let mut file = tokio::fs::File::create(&temp_path)
+        .await
+        .with_context(|| format!("Failed to create {}", temp_path.display()))?;
+    file.write_all(contents.as_ref()).await?;
+    file.sync_all().await?;
+    drop(file);
+    tokio::fs::rename(&temp_path, path)
+        .await
+        .with_context(|| format!("Failed to replace {}", path.display()))
+}
+
+/// Take an exclusive lock on the store at `layouts_path`, which is held until the returned file is
+/// closed. Fails if another process holds it for longer than `timeout`.
+async fn lock_store(layouts_path: &Path, timeout: Duration) -> Result<std::fs::File> {
+    let lock_path = lock_path(layouts_path);
+    if let Some(parent) = lock_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    }
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .with_context(|| format!("Failed to open {}", lock_path.display()))?;
+    let start = Instant::now();
+    loop {
+        match file.try_lock() {
+            Ok(()) => return Ok(file),
+            Err(TryLockError::WouldBlock) if start.elapsed() < timeout => {
+                debug!("Waiting for {} to be unlocked", lock_path.display());
+                tokio::time::sleep(LOCK_POLL_INTERVAL).await;
+            }
+            Err(TryLockError::WouldBlock) => bail!(
+                "Timed out after {:?} waiting for another process to finish updating the layouts",
+                timeout
+            ),
+            Err(TryLockError::Error(e)) => {
+                return Err(e).with_context(|| format!("Failed to lock {}", lock_path.display()));
+            }
+        }
+    }
+}
+
+/// The version of the single file store's format. Version 0 is the bare array of layouts that was
+/// stored before the format was versioned.
+const LAYOUTS_FILE_VERSION: u64 = 1;
+
+/// The contents of a single file store. Older versions are upgraded when they're loaded, and the
+/// current version is always saved.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct LayoutsFile<'a> {
+    pub version: u64,
+    pub layouts: Cow<'a, Layouts>,
+}
+
+impl<'a> LayoutsFile<'a> {
+    fn new(layouts: &'a Layouts) -> Self {
+        Self {
+            version: LAYOUTS_FILE_VERSION,
+            layouts: Cow::Borrowed(layouts),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for LayoutsFile<'_> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error as _;
+
+        let (version, layouts) = match serde_json::Value::deserialize(deserializer)? {
+            layouts @ serde_json::Value::Array(_) => (0, layouts),
+            serde_json::Value::Object(mut file) => {
+                let version = file
+                    .get("version")
+                    .and_then(serde_json::Value::as_u64)
+                    .ok_or_else(|| D::Error::missing_field("version"))?;
+                let layouts = file
+                    .remove("layouts")
+                    .ok_or_else(|| D::Error::missing_field("layouts"))?;
+                (version, layouts)
+            }
+            _ => {
+                return Err(D::Error::custom(
+                    "expected an array of layouts, or an object with a version and layouts",
+                ));
+            }
+        };
+        if version > LAYOUTS_FILE_VERSION {
+            return Err(D::Error::custom(format!(
+                "version {} is newer than this version of hagias supports ({})",
+                version, LAYOUTS_FILE_VERSION
+            )));
+        }
+        // Only the wrapping object has changed so far, so the layouts need no migration
+        let layouts = Layouts::deserialize(layouts).map_err(D::Error::custom)?;
+        Ok(Self {
+            version: LAYOUTS_FILE_VERSION,
+            layouts: Cow::Owned(layouts),
+        })
+    }
+}
+
 impl Layouts {
     pub fn new() -> Self {
         Self(Vec::new())
@@ -28,14 +236,38 @@ impl Layouts {
         self.0.is_empty()
     }
 
-    pub fn iter(&self) -> std::slice::Iter<'_, NamedLayout> {
+    /// Iterate over all layouts, including hidden ones
+    pub fn iter_all(&self) -> std::slice::Iter<'_, NamedLayout> {
         self.0.iter()
     }
 
+    /// Iterate over all layouts along with their positions, pinned layouts first
+    pub fn pinned_first(&self) -> impl Iterator<Item = (usize, &NamedLayout)> {
+        let pinned = self.0.iter().enumerate().filter(|(_, l)| l.pinned);
+        let unpinned = self.0.iter().enumerate().filter(|(_, l)| !l.pinned);
+        pinned.chain(unpinned)
+    }
+
+    /// Iterate over the layouts that aren't hidden, pinned layouts first
+    pub fn visible(&self) -> impl Iterator<Item = &NamedLayout> {
+        self.pinned_first().map(|(_, l)| l).filter(|l| !l.hidden)
+    }
+
     pub fn swap(&mut self, a: usize, b: usize) {
         self.0.swap(a, b);
     }
 
+    /// Order the layouts by their positions in `order`. Layouts missing from it, e.g. added since
+    /// `order` was taken, go at the end in their current order.
+    pub fn reorder(&mut self, order: &[String]) {
+        self.0.sort_by_key(|layout| {
+            order
+                .iter()
+                .position(|id| *id == layout.id)
+                .unwrap_or(usize::MAX)
+        });
+    }
+
     pub async fn load(layouts_path: &Path) -> Result<Self> {
         debug!("Loading layouts from {}", layouts_path.display());
         Self::load_private(layouts_path)
@@ -43,28 +275,179 @@ impl Layouts {
             .with_context(|| format!("Failed to load layouts at {}", layouts_path.display()))
     }
 
+    /// Load the layouts to change and save them, locking the store until the returned layouts are
+    /// dropped so that other processes (e.g. the CLI and the service) can't change it meanwhile
+    pub async fn load_for_update(layouts_path: &Path) -> Result<LockedLayouts> {
+        let lock = lock_store(layouts_path, LOCK_TIMEOUT)
+            .await
+            .with_context(|| format!("Failed to lock layouts at {}", layouts_path.display()))?;
+        Ok(LockedLayouts {
+            layouts: Self::load(layouts_path).await?,
+            _lock: lock,
+        })
+    }
+
     async fn load_private(layouts_path: &Path) -> Result<Self> {
-        Ok(if !tokio::fs::try_exists(layouts_path).await? {
-            Self::new()
+        if !tokio::fs::try_exists(layouts_path).await? {
+            return Ok(Self::new());
+        }
+        match StoreKind::detect(layouts_path).await? {
+            StoreKind::File => {
+                let (file, modified): (LayoutsFile, _) = read_json(layouts_path).await?;
+                let mut layouts = file.layouts.into_owned();
+                for layout in &mut layouts.0 {
+                    layout.default_timestamps(modified);
+                }
+                Ok(layouts)
+            }
+            StoreKind::Directory => Self::load_dir(layouts_path).await,
+        }
+    }
+
+    async fn load_dir(dir: &Path) -> Result<Self> {
+        let index_path = dir.join(INDEX_FILE_NAME);
+        let legacy_index_path = dir.join(LEGACY_INDEX_FILE_NAME);
+        // Directories saved before the index was renamed only have the legacy index
+        let legacy_index = !tokio::fs::try_exists(&index_path).await?
+            && tokio::fs::try_exists(&legacy_index_path).await?;
+        let order: Vec<String> = if legacy_index {
+            read_json(&legacy_index_path).await?.0
+        } else if tokio::fs::try_exists(&index_path).await? {
+            read_json(&index_path).await?.0
         } else {
-            let mut file = tokio::fs::File::open(layouts_path).await?;
-            let mut bytes = Vec::with_capacity(file.metadata().await?.len() as usize);
-            file.read_to_end(&mut bytes).await?;
-            let json = String::from_utf8(bytes).context("Invalid UTF-8")?;
-            serde_json::from_str(&json).context("Invalid JSON")?
-        })
+            Vec::new()
+        };
+        let mut layouts = Vec::new();
+        let mut entries = tokio::fs::read_dir(dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if !is_layout_file(&path) || (legacy_index && path == legacy_index_path) {
+                continue;
+            }
+            // One broken file, e.g. edited by hand, shouldn't hide all the other layouts
+            let (mut layout, modified): (NamedLayout, _) = match read_json(&path).await {
+                Ok(loaded) => loaded,
+                Err(e) => {
+                    warn!("Skipping layout at {}: {:?}", path.display(), e);
+                    continue;
+                }
+            };
+            layout.default_timestamps(modified);
+            layouts.push(layout);
+        }
+        // Layouts missing from the index, e.g. added by hand, go at the end
+        layouts.sort_by_cached_key(|layout| {
+            let position = order.iter().position(|id| *id == layout.id);
+            (position.unwrap_or(usize::MAX), layout.id.clone())
+        });
+        Ok(Self(layouts))
     }
 
-    pub async fn save(&self, layouts_path: &Path) -> Result<()> {
+    pub async fn save(&self, layouts_path: &Path, format: LayoutsFormat) -> Result<()> {
         debug!("Saving layouts to {}", layouts_path.display());
-        self.save_private(layouts_path)
+        self.save_private(layouts_path, format)
+            .await
+            .with_context(|| format!("Failed to save layouts at {}", layouts_path.display()))
+    }
+
+    async fn save_private(&self, layouts_path: &Path, format: LayoutsFormat) -> Result<()> {
+        self.save_as_private(layouts_path, StoreKind::detect(layouts_path).await?, format)
+            .await
+    }
+
+    /// Save the layouts as the given kind of store, e.g. to convert between kinds
+    pub async fn save_as(
+        &self,
+        layouts_path: &Path,
+        kind: StoreKind,
+        format: LayoutsFormat,
+    ) -> Result<()> {
+        debug!("Saving layouts to {} as {:?}", layouts_path.display(), kind);
+        self.save_as_private(layouts_path, kind, format)
             .await
             .with_context(|| format!("Failed to save layouts at {}", layouts_path.display()))
     }
 
-    async fn save_private(&self, layouts_path: &Path) -> Result<()> {
-        let json = serde_json::to_string_pretty(self)?;
-        tokio::fs::write(layouts_path, json).await?;
+    async fn save_as_private(
+        &self,
+        layouts_path: &Path,
+        kind: StoreKind,
+        format: LayoutsFormat,
+    ) -> Result<()> {
+        match kind {
+            StoreKind::File => {
+                if let Some(parent) = layouts_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                    tokio::fs::create_dir_all(parent).await.with_context(|| {
+                        format!("Failed to create directory {}", parent.display())
+                    })?;
+                }
+                let json = format.to_string(&LayoutsFile::new(self))?;
+                // Keep the previous layouts, in case the new ones aren't what was wanted
+                if tokio::fs::try_exists(layouts_path).await? {
+                    let backup_path = sibling_path(layouts_path, ".bak");
+                    tokio::fs::copy(layouts_path, &backup_path)
+                        .await
+                        .with_context(|| {
+                            format!("Failed to back up to {}", backup_path.display())
+                        })?;
+                }
+                write_atomically(layouts_path, json).await?;
+            }
+            StoreKind::Directory => self.save_dir(layouts_path, format).await?,
+        }
+        Ok(())
+    }
+
+    async fn save_dir(&self, dir: &Path, format: LayoutsFormat) -> Result<()> {
+        for layout in &self.0 {
+            if !is_valid_id(&layout.id) {
+                bail!(
+                    "Layout ID {:?} can't be used as a file name, so can't be stored in a directory",
+                    layout.id
+                );
+            }
+        }
+        // Windows file names are case-insensitive, so these would overwrite each other
+        let mut lowercase_ids = HashSet::new();
+        for layout in &self.0 {
+            if !lowercase_ids.insert(layout.id.to_ascii_lowercase()) {
+                bail!(
+                    "Layout ID {:?} only differs in case from another layout's, so can't be stored \
+                     in a directory",
+                    layout.id
+                );
+            }
+        }
+        tokio::fs::create_dir_all(dir)
+            .await
+            .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+        for layout in &self.0 {
+            let json = format.to_string(layout)?;
+            write_atomically(&dir.join(format!("{}.json", layout.id)), json).await?;
+        }
+        let order = self.0.iter().map(|l| &l.id).collect::<Vec<_>>();
+        write_atomically(&dir.join(INDEX_FILE_NAME), format.to_string(&order)?).await?;
+
+        // Remove the files of layouts that have been removed or renamed
+        let ids = self.0.iter().map(|l| l.id.as_str()).collect::<HashSet<_>>();
+        let mut entries = tokio::fs::read_dir(dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let stale = is_layout_file(&path)
+                && path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .is_none_or(|stem| !ids.contains(stem));
+            // Files that failed to load were skipped rather than removed, so keep them to be fixed.
+            // The legacy index has been replaced by the new one, so is always removed.
+            let legacy_index = path
+                .file_name()
+                .is_some_and(|n| n == LEGACY_INDEX_FILE_NAME);
+            if stale && (legacy_index || read_json::<NamedLayout>(&path).await.is_ok()) {
+                debug!("Removing stale layout file {}", path.display());
+                tokio::fs::remove_file(&path).await?;
+            }
+        }
         Ok(())
     }
 
@@ -72,20 +455,132 @@ impl Layouts {
         self.0.clear();
     }
 
-    pub async fn add_current(&mut self, id: &str, name: &str, emoji: Option<&str>) -> Result<()> {
-        let windows_display_config = WindowsDisplayConfig::get(DisplayQueryType::All)?;
-        let layout = DisplayLayout::from_windows(&windows_display_config)?;
-        let named_layout = NamedLayout {
-            id: id.into(),
-            name: name.into(),
-            emoji: emoji.map(|s| s.into()),
-            hidden: false,
-            layout,
-        };
-        self.add_layout(named_layout);
+    fn capture_current(query: DisplayQueryType) -> Result<DisplayLayout> {
+        let windows_display_config = WindowsDisplayConfig::get(query)?;
+        DisplayLayout::from_windows(&windows_display_config)
+    }
+
+    /// Add the current display configuration as a layout. `query` determines which paths are
+    /// captured: [`DisplayQueryType::Active`] only captures what is currently driving a monitor,
+    /// while [`DisplayQueryType::All`] also captures inactive and disconnected targets.
+    pub async fn add_current(
+        &mut self,
+        id: &str,
+        name: &str,
+        emoji: Option<&str>,
+        query: DisplayQueryType,
+    ) -> Result<()> {
+        let layout = Self::capture_current(query)?;
+        self.add_layout(NamedLayout::new(id, name, emoji, layout));
         Ok(())
     }
 
+    /// Store the current display configuration as a layout, like [`Self::add_current`], except that
+    /// an existing layout is replaced in place, see [`Self::store_layout`]. Returns whether the
+    /// layout already existed.
+    pub async fn store_current(
+        &mut self,
+        id: &str,
+        name: &str,
+        emoji: Option<&str>,
+        query: DisplayQueryType,
+    ) -> Result<bool> {
+        let layout = Self::capture_current(query)?;
+        Ok(self.store_layout(NamedLayout::new(id, name, emoji, layout)))
+    }
+
+    /// Add a layout, or replace the name, emoji and monitors of the existing one with its ID,
+    /// keeping its position, `pinned` and `hidden` flags and creation time as [`Self::touch`] does.
+    /// Returns whether the layout already existed.
+    pub fn store_layout(&mut self, layout: NamedLayout) -> bool {
+        let Some(existing) = self.get_layout_mut(&layout.id) else {
+            self.0.push(layout);
+            return false;
+        };
+        existing.name = layout.name;
+        existing.emoji = layout.emoji;
+        existing.layout = layout.layout;
+        existing.mark_modified();
+        true
+    }
+
+    /// Get an ID based on `base` that isn't used by any existing layout
+    pub fn unique_id(&self, base: &str) -> String {
+        if self.get_layout(base).is_none() {
+            return base.into();
+        }
+        (2..)
+            .map(|i| format!("{}-{}", base, i))
+            .find(|id| self.get_layout(id).is_none())
+            .expect("ran out of layout IDs")
+    }
+
+    /// Add the layout that Windows has stored in its database for the current set of monitors,
+    /// generating an ID and name if they aren't given. If `name_template` is given, the name is
+    /// generated from it with [`render_name_template`].
+    pub async fn add_from_windows_database(
+        &mut self,
+        id: Option<&str>,
+        name: Option<&str>,
+        name_template: Option<&str>,
+        emoji: Option<&str>,
+    ) -> Result<&NamedLayout> {
+        let (layout, topology) = DisplayLayout::get_database()?;
+        let (default_id, default_name) = match topology {
+            Some(topology) => (
+                format!("windows-{:?}", topology).to_lowercase(),
+                format!("Windows {:?}", topology),
+            ),
+            None => ("windows".to_string(), "Windows".to_string()),
+        };
+        let id = match id {
+            Some(id) => id.to_string(),
+            None => self.unique_id(&default_id),
+        };
+        let name = match (name, name_template) {
+            (Some(name), _) => name.to_string(),
+            (None, Some(template)) => render_name_template(
+                template,
+                self.len() + 1,
+                &layout.monitor_names(),
+                jiff::Zoned::now().date(),
+            ),
+            (None, None) => default_name,
+        };
+        self.add_layout(NamedLayout::new(&id, &name, emoji, layout));
+        Ok(&self.0[self.0.len() - 1])
+    }
+
+    /// Replace the display configuration of an existing layout with the current one, keeping its
+    /// metadata and position. `query` determines which paths are captured, as for
+    /// [`Self::add_current`]. Returns `None` if the layout doesn't exist.
+    pub async fn touch(
+        &mut self,
+        id: &str,
+        query: DisplayQueryType,
+    ) -> Result<Option<&NamedLayout>> {
+        let Some(index) = self.0.iter().position(|l| l.id == id) else {
+            return Ok(None);
+        };
+        self.0[index].layout = Self::capture_current(query)?;
+        self.0[index].mark_modified();
+        Ok(Some(&self.0[index]))
+    }
+
+    /// Change the ID of a layout in place, keeping everything else about it. Returns `None` if the
+    /// layout doesn't exist, and fails if `new_id` is already used by another layout.
+    pub fn rename_id(&mut self, old_id: &str, new_id: &str) -> Result<Option<&NamedLayout>> {
+        let Some(index) = self.0.iter().position(|l| l.id == old_id) else {
+            return Ok(None);
+        };
+        if old_id != new_id && self.get_layout(new_id).is_some() {
+            bail!("A layout with ID {} already exists", new_id);
+        }
+        self.0[index].id = new_id.into();
+        self.0[index].mark_modified();
+        Ok(Some(&self.0[index]))
+    }
+
     pub fn add_layout(&mut self, layout: NamedLayout) {
         self.0.retain(|l| l.id != layout.id);
         self.0.push(layout);
@@ -114,6 +609,17 @@ impl Layouts {
         }
     }
 
+    /// The layouts whose ID or name contains `query`, ignoring case
+    pub fn search(&self, query: &str) -> Vec<&NamedLayout> {
+        let query = query.to_lowercase();
+        self.0
+            .iter()
+            .filter(|l| {
+                l.id.to_lowercase().contains(&query) || l.name.to_lowercase().contains(&query)
+            })
+            .collect()
+    }
+
     pub fn get_layout(&self, id: &str) -> Option<&NamedLayout> {
         self.0.iter().find(|l| l.id == id)
     }
@@ -121,6 +627,39 @@ impl Layouts {
     pub fn get_layout_mut(&mut self, id: &str) -> Option<&mut NamedLayout> {
         self.0.iter_mut().find(|l| l.id == id)
     }
+
+    /// Describe how these layouts differ from `before`, one change per line, e.g. for a dry run
+    pub fn changes_from(&self, before: &Layouts) -> Vec<String> {
+        let mut changes = Vec::new();
+        for layout in &before.0 {
+            if self.get_layout(&layout.id).is_none() {
+                changes.push(format!("Remove layout {} \"{}\"", layout.id, layout.name));
+            }
+        }
+        for layout in &self.0 {
+            match before.get_layout(&layout.id) {
+                None => changes.push(format!("Add layout {} \"{}\"", layout.id, layout.name)),
+                Some(old)
+                    if serde_json::to_value(old).ok() != serde_json::to_value(layout).ok() =>
+                {
+                    changes.push(format!("Change layout {} \"{}\"", layout.id, layout.name))
+                }
+                Some(_) => {}
+            }
+        }
+        let order = |layouts: &Layouts| {
+            layouts
+                .0
+                .iter()
+                .map(|l| l.id.clone())
+                .filter(|id| self.get_layout(id).is_some() && before.get_layout(id).is_some())
+                .collect::<Vec<_>>()
+        };
+        if order(self) != order(before) {
+            changes.push("Reorder layouts".into());
+        }
+        changes
+    }
 }
 
 impl std::ops::Index<usize> for Layouts {
@@ -131,7 +670,7 @@ impl std::ops::Index<usize> for Layouts {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct NamedLayout {
     pub id: String,
     pub name: String,
@@ -139,5 +678,533 @@ pub struct NamedLayout {
     pub emoji: Option<String>,
     #[serde(default)]
     pub hidden: bool,
+    /// Pinned layouts are shown before all others
+    #[serde(default)]
+    pub pinned: bool,
+    /// When the layout was first stored
+    #[serde(default)]
+    pub created: jiff::Timestamp,
+    /// When the layout was last changed
+    #[serde(default)]
+    pub modified: jiff::Timestamp,
     pub layout: DisplayLayout,
 }
+
+impl NamedLayout {
+    /// A new, visible layout created now
+    pub fn new(id: &str, name: &str, emoji: Option<&str>, layout: DisplayLayout) -> Self {
+        let now = jiff::Timestamp::now();
+        Self {
+            id: id.into(),
+            name: name.into(),
+            emoji: emoji.map(|s| s.into()),
+            hidden: false,
+            pinned: false,
+            created: now,
+            modified: now,
+            layout,
+        }
+    }
+
+    /// Default the timestamps of a layout stored before they were tracked to `modified`, the
+    /// modification time of its file
+    fn default_timestamps(&mut self, modified: jiff::Timestamp) {
+        if self.created == jiff::Timestamp::default() {
+            self.created = modified;
+        }
+        if self.modified == jiff::Timestamp::default() {
+            self.modified = modified;
+        }
+    }
+
+    /// Record that the layout has just been changed
+    pub fn mark_modified(&mut self) {
+        self.modified = jiff::Timestamp::now();
+    }
+
+    /// Write the layout on its own to `path`, in the same format as a layout in a directory store
+    pub async fn export(&self, path: &Path) -> Result<()> {
+        let json = LayoutsFormat::Pretty.to_string(self)?;
+        tokio::fs::write(path, json)
+            .await
+            .with_context(|| format!("Failed to export layout to {}", path.display()))
+    }
+
+    /// Read a layout written by [`Self::export`], or from a directory store
+    pub async fn import(path: &Path) -> Result<Self> {
+        let (mut layout, modified): (Self, _) = read_json(path)
+            .await
+            .with_context(|| format!("Failed to import layout from {}", path.display()))?;
+        layout.default_timestamps(modified);
+        Ok(layout)
+    }
+}
+
+/// Whether `path` is a layout in a directory store, rather than its index
+fn is_layout_file(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "json")
+        && path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| !name.starts_with('.'))
+}
+
+/// Read a JSON file, along with its modification time
+async fn read_json<T: DeserializeOwned>(path: &Path) -> Result<(T, jiff::Timestamp)> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let metadata = file.metadata().await?;
+    let mut bytes = Vec::with_capacity(metadata.len() as usize);
+    file.read_to_end(&mut bytes).await?;
+    let json = String::from_utf8(bytes).context("Invalid UTF-8")?;
+    let value = serde_json::from_str(&json).context("Invalid JSON")?;
+    Ok((value, jiff::Timestamp::try_from(metadata.modified()?)?))
+}
+
+/// Whether `id` only uses the characters allowed in layout IDs: ASCII letters, digits, `-` and `_`,
+/// and isn't a name that Windows reserves for a device, like `CON`
+pub fn is_valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        && !RESERVED_FILE_NAMES
+            .iter()
+            .any(|reserved| reserved.eq_ignore_ascii_case(id))
+}
+
+/// Generate a layout name from a template, substituting `{index}` with the 1-based position of the
+/// layout, `{count}` with the number of monitors, `{monitors}` with their names and `{date}` with
+/// the given date
+pub fn render_name_template(
+    template: &str,
+    index: usize,
+    monitors: &[String],
+    date: jiff::civil::Date,
+) -> String {
+    template
+        .replace("{index}", &index.to_string())
+        .replace("{count}", &monitors.len().to_string())
+        .replace("{monitors}", &monitors.join(", "))
+        .replace("{date}", &date.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        LAYOUTS_FILE_VERSION, Layouts, LayoutsFormat, NamedLayout, StoreKind, is_valid_id,
+        lock_path, lock_store, render_name_template, sibling_path,
+    };
+    use crate::test_util::{TempDir, named_layout};
+
+    fn ids(layouts: &Layouts) -> Vec<&str> {
+        layouts.iter_all().map(|l| l.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_store_layout_keeps_flags() {
+        let mut layouts = Layouts::new();
+        for id in ["a", "b", "c"] {
+            layouts.add_layout(named_layout(id, true));
+        }
+        layouts.get_layout_mut("b").unwrap().pinned = true;
+        let created = layouts.get_layout("b").unwrap().created;
+
+        let mut replacement = named_layout("b", false);
+        replacement.name = "Bee".into();
+        assert!(layouts.store_layout(replacement));
+        let stored = layouts.get_layout("b").unwrap();
+        assert_eq!(stored.name, "Bee");
+        assert!(stored.pinned);
+        assert!(stored.hidden);
+        assert_eq!(stored.created, created);
+        assert_eq!(ids(&layouts), ["a", "b", "c"]);
+
+        assert!(!layouts.store_layout(named_layout("d", false)));
+        assert_eq!(ids(&layouts), ["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn test_rename_id_in_place() {
+        let mut layouts = Layouts::new();
+        for id in ["a", "b", "c"] {
+            layouts.add_layout(named_layout(id, true));
+        }
+        let renamed = layouts.rename_id("b", "d").unwrap().unwrap();
+        assert_eq!(renamed.name, "B");
+        assert_eq!(renamed.emoji.as_deref(), Some("🖥️"));
+        assert!(renamed.hidden);
+        assert_eq!(ids(&layouts), ["a", "d", "c"]);
+    }
+
+    #[test]
+    fn test_rename_id_collision_and_missing() {
+        let mut layouts = Layouts::new();
+        for id in ["a", "b"] {
+            layouts.add_layout(named_layout(id, true));
+        }
+        assert!(layouts.rename_id("a", "b").is_err());
+        assert!(layouts.rename_id("missing", "c").unwrap().is_none());
+        assert_eq!(ids(&layouts), ["a", "b"]);
+    }
+
+    #[test]
+    fn test_search() {
+        let mut layouts = Layouts::new();
+        for id in ["work", "work-hdr", "games"] {
+            layouts.add_layout(named_layout(id, true));
+        }
+        let found = |query| {
+            layouts
+                .search(query)
+                .iter()
+                .map(|l| l.id.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(found("HDR"), ["work-hdr"]);
+        assert_eq!(found("work"), ["work", "work-hdr"]);
+        // Names are searched too
+        assert_eq!(found("GAM"), ["games"]);
+        assert!(found("tv").is_empty());
+    }
+
+    #[test]
+    fn test_visible_pinned_first() {
+        let mut layouts = Layouts::new();
+        for id in ["a", "b", "c", "d"] {
+            let mut layout = named_layout(id, true);
+            layout.hidden = id == "c";
+            layout.pinned = id == "c" || id == "d";
+            layouts.add_layout(layout);
+        }
+        let visible: Vec<_> = layouts.visible().map(|l| l.id.as_str()).collect();
+        assert_eq!(visible, ["d", "a", "b"]);
+        let positions: Vec<_> = layouts.pinned_first().map(|(i, _)| i).collect();
+        assert_eq!(positions, [2, 3, 0, 1]);
+        // Pinning doesn't change the stored order
+        assert_eq!(ids(&layouts), ["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn test_reorder() {
+        let mut layouts = Layouts::new();
+        for id in ["a", "b", "c", "d"] {
+            layouts.add_layout(named_layout(id, true));
+        }
+        // "x" was removed and "b" and "d" were added since the order was taken
+        layouts.reorder(&["c".to_string(), "x".to_string(), "a".to_string()]);
+        assert_eq!(ids(&layouts), ["c", "a", "b", "d"]);
+    }
+
+    #[rocket::async_test]
+    async fn test_directory_store_round_trip() {
+        let temp_dir = TempDir::new("layouts");
+        let dir = temp_dir.join("layouts");
+        let mut layouts = Layouts::new();
+        for id in ["b", "a", "c"] {
+            layouts.add_layout(named_layout(id, true));
+        }
+        layouts
+            .save_as(&dir, StoreKind::Directory, LayoutsFormat::Pretty)
+            .await
+            .unwrap();
+        assert_eq!(StoreKind::detect(&dir).await.unwrap(), StoreKind::Directory);
+        assert!(dir.join(".index.json").is_file());
+
+        let mut loaded = Layouts::load(&dir).await.unwrap();
+        assert_eq!(ids(&loaded), ["b", "a", "c"]);
+        assert_eq!(loaded.get_layout("a").unwrap().name, "A");
+
+        // Removed layouts lose their files
+        loaded.remove_layout("a");
+        loaded.swap(0, 1);
+        loaded.save(&dir, LayoutsFormat::Pretty).await.unwrap();
+        assert!(!dir.join("a.json").exists());
+        assert_eq!(ids(&Layouts::load(&dir).await.unwrap()), ["c", "b"]);
+    }
+
+    #[rocket::async_test]
+    async fn test_directory_store_file_names() {
+        let temp_dir = TempDir::new("file-names");
+        let dir = temp_dir.join("layouts");
+        // A layout with ID `index` doesn't collide with the order of the layouts
+        let mut layouts = Layouts::new();
+        for id in ["index", "a"] {
+            layouts.add_layout(named_layout(id, true));
+        }
+        layouts
+            .save_as(&dir, StoreKind::Directory, LayoutsFormat::Pretty)
+            .await
+            .unwrap();
+        assert_eq!(ids(&Layouts::load(&dir).await.unwrap()), ["index", "a"]);
+
+        // IDs only differing in case would share a file on Windows
+        layouts.add_layout(named_layout("A", true));
+        assert!(
+            layouts
+                .save_as(&dir, StoreKind::Directory, LayoutsFormat::Pretty)
+                .await
+                .is_err()
+        );
+        assert_eq!(ids(&Layouts::load(&dir).await.unwrap()), ["index", "a"]);
+    }
+
+    #[rocket::async_test]
+    async fn test_directory_store_legacy_index() {
+        let temp_dir = TempDir::new("legacy-index");
+        let dir = temp_dir.join("layouts");
+        let mut layouts = Layouts::new();
+        for id in ["b", "a"] {
+            layouts.add_layout(named_layout(id, true));
+        }
+        layouts
+            .save_as(&dir, StoreKind::Directory, LayoutsFormat::Pretty)
+            .await
+            .unwrap();
+        std::fs::rename(dir.join(".index.json"), dir.join("index.json")).unwrap();
+
+        let loaded = Layouts::load(&dir).await.unwrap();
+        assert_eq!(ids(&loaded), ["b", "a"]);
+        loaded.save(&dir, LayoutsFormat::Pretty).await.unwrap();
+        assert!(!dir.join("index.json").exists());
+        assert_eq!(ids(&Layouts::load(&dir).await.unwrap()), ["b", "a"]);
+    }
+
+    #[rocket::async_test]
+    async fn test_directory_store_skips_broken_files() {
+        let temp_dir = TempDir::new("broken");
+        let dir = temp_dir.join("layouts");
+        let mut layouts = Layouts::new();
+        for id in ["a", "b"] {
+            layouts.add_layout(named_layout(id, true));
+        }
+        layouts
+            .save_as(&dir, StoreKind::Directory, LayoutsFormat::Pretty)
+            .await
+            .unwrap();
+        // A layout file only half written, or broken by hand
+        std::fs::write(dir.join("b.json"), "{\"id\": \"b\", \"na").unwrap();
+
+        let loaded = Layouts::load(&dir).await.unwrap();
+        assert_eq!(ids(&loaded), ["a"]);
+
+        // Saving doesn't remove the broken file, so it can still be fixed
+        loaded.save(&dir, LayoutsFormat::Pretty).await.unwrap();
+        assert!(dir.join("b.json").is_file());
+    }
+
+    #[rocket::async_test]
+    async fn test_save_creates_parent_directories() {
+        let temp_dir = TempDir::new("nested");
+        let layouts_path = temp_dir.join("configs").join("layouts.json");
+        let mut layouts = Layouts::new();
+        layouts.add_layout(named_layout("a", true));
+        layouts
+            .save(&layouts_path, LayoutsFormat::Pretty)
+            .await
+            .unwrap();
+        assert!(layouts_path.is_file());
+        assert_eq!(ids(&Layouts::load(&layouts_path).await.unwrap()), ["a"]);
+    }
+
+    #[rocket::async_test]
+    async fn test_export_import_round_trip() {
+        let temp_dir = TempDir::new("export");
+        let path = temp_dir.join("export.json");
+        let layout = named_layout("work", true);
+        layout.export(&path).await.unwrap();
+        let imported = NamedLayout::import(&path).await.unwrap();
+        assert_eq!(imported.id, "work");
+        assert_eq!(imported.name, "WORK");
+        assert!(imported.hidden);
+        assert_eq!(imported.created, layout.created);
+
+        // A whole layouts file isn't a single layout
+        let mut layouts = Layouts::new();
+        layouts.add_layout(layout);
+        layouts.save(&path, LayoutsFormat::Pretty).await.unwrap();
+        assert!(NamedLayout::import(&path).await.is_err());
+    }
+
+    #[rocket::async_test]
+    async fn test_compact_round_trip() {
+        let temp_dir = TempDir::new("compact");
+        let layouts_path = temp_dir.join("layouts.json");
+        let mut layouts = Layouts::new();
+        for id in ["b", "a"] {
+            layouts.add_layout(named_layout(id, true));
+        }
+        layouts
+            .save(&layouts_path, LayoutsFormat::Compact)
+            .await
+            .unwrap();
+        let compact = std::fs::read_to_string(&layouts_path).unwrap();
+        assert!(!compact.contains('\n'));
+
+        let loaded = Layouts::load(&layouts_path).await.unwrap();
+        assert_eq!(ids(&loaded), ["b", "a"]);
+        loaded
+            .save(&layouts_path, LayoutsFormat::Pretty)
+            .await
+            .unwrap();
+        let pretty = std::fs::read_to_string(&layouts_path).unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&compact).unwrap(),
+            serde_json::from_str::<serde_json::Value>(&pretty).unwrap(),
+        );
+    }
+
+    #[rocket::async_test]
+    async fn test_legacy_file_upgrade() {
+        let temp_dir = TempDir::new("legacy");
+        let layouts_path = temp_dir.join("layouts.json");
+        let mut layouts = Layouts::new();
+        for id in ["b", "a"] {
+            layouts.add_layout(named_layout(id, true));
+        }
+        // Before the format was versioned, the file was a bare array of layouts
+        let legacy = serde_json::to_value(&layouts).unwrap();
+        std::fs::write(&layouts_path, legacy.to_string()).unwrap();
+
+        let loaded = Layouts::load(&layouts_path).await.unwrap();
+        assert_eq!(
+            serde_json::to_value(&loaded).unwrap(),
+            serde_json::to_value(&layouts).unwrap()
+        );
+
+        loaded
+            .save(&layouts_path, LayoutsFormat::Pretty)
+            .await
+            .unwrap();
+        let saved: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&layouts_path).unwrap()).unwrap();
+        assert_eq!(saved["version"], LAYOUTS_FILE_VERSION);
+        assert_eq!(saved["layouts"], legacy);
+        let reloaded = Layouts::load(&layouts_path).await.unwrap();
+        assert_eq!(ids(&reloaded), ["b", "a"]);
+
+        // Files from a newer version aren't loaded, rather than losing what they add
+        let newer = serde_json::json!({
+            "version": LAYOUTS_FILE_VERSION + 1,
+            "layouts": legacy,
+        });
+        std::fs::write(&layouts_path, newer.to_string()).unwrap();
+        assert!(Layouts::load(&layouts_path).await.is_err());
+    }
+
+    #[rocket::async_test]
+    async fn test_interrupted_save_keeps_layouts() {
+        let temp_dir = TempDir::new("atomic");
+        let layouts_path = temp_dir.join("layouts.json");
+        let temp_path = sibling_path(&layouts_path, ".tmp");
+        let backup_path = sibling_path(&layouts_path, ".bak");
+        let mut layouts = Layouts::new();
+        layouts.add_layout(named_layout("a", true));
+        layouts
+            .save(&layouts_path, LayoutsFormat::Pretty)
+            .await
+            .unwrap();
+        assert!(!temp_path.exists());
+
+        layouts.add_layout(named_layout("b", true));
+        layouts
+            .save(&layouts_path, LayoutsFormat::Pretty)
+            .await
+            .unwrap();
+        assert!(!temp_path.exists());
+
+        // Something in the way of the temporary file makes the write fail part way through
+        std::fs::create_dir(&temp_path).unwrap();
+        layouts.add_layout(named_layout("c", true));
+        assert!(
+            layouts
+                .save(&layouts_path, LayoutsFormat::Pretty)
+                .await
+                .is_err()
+        );
+        assert_eq!(
+            ids(&Layouts::load(&layouts_path).await.unwrap()),
+            ["a", "b"]
+        );
+        assert_eq!(ids(&Layouts::load(&backup_path).await.unwrap()), ["a", "b"]);
+
+        // Once it's out of the way, saving works again and the backup has the previous layouts
+        std::fs::remove_dir(&temp_path).unwrap();
+        layouts
+            .save(&layouts_path, LayoutsFormat::Pretty)
+            .await
+            .unwrap();
+        assert!(!temp_path.exists());
+        assert_eq!(
+            ids(&Layouts::load(&layouts_path).await.unwrap()),
+            ["a", "b", "c"]
+        );
+        assert_eq!(ids(&Layouts::load(&backup_path).await.unwrap()), ["a", "b"]);
+    }
+
+    #[rocket::async_test]
+    async fn test_lock_store() {
+        let temp_dir = TempDir::new("lock");
+        let layouts_path = temp_dir.join("layouts.json");
+        let timeout = std::time::Duration::from_millis(200);
+        let lock = lock_store(&layouts_path, timeout).await.unwrap();
+        assert!(lock_store(&layouts_path, timeout).await.is_err());
+        drop(lock);
+        let locked = Layouts::load_for_update(&layouts_path).await.unwrap();
+        assert!(locked.is_empty());
+        assert!(lock_store(&layouts_path, timeout).await.is_err());
+        assert!(lock_path(&layouts_path).is_file());
+    }
+
+    #[test]
+    fn test_changes_from() {
+        let mut before = Layouts::new();
+        for id in ["a", "b", "c"] {
+            before.add_layout(named_layout(id, true));
+        }
+        assert!(before.changes_from(&before).is_empty());
+
+        let mut after = before.clone();
+        after.remove_layout("a");
+        after.get_layout_mut("b").unwrap().name = "Bee".into();
+        after.swap(0, 1);
+        after.add_layout(named_layout("d", true));
+        assert_eq!(
+            after.changes_from(&before),
+            [
+                "Remove layout a \"A\"",
+                "Change layout b \"Bee\"",
+                "Add layout d \"D\"",
+                "Reorder layouts",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_is_valid_id() {
+        assert!(is_valid_id("desk-2_tv"));
+        assert!(!is_valid_id(""));
+        assert!(!is_valid_id("desk tv"));
+        assert!(!is_valid_id("../desk"));
+        assert!(!is_valid_id("désk"));
+        assert!(!is_valid_id("CON"));
+        assert!(!is_valid_id("nul"));
+        assert!(!is_valid_id("Com1"));
+        assert!(is_valid_id("console"));
+        assert!(is_valid_id("index"));
+    }
+
+    #[test]
+    fn test_render_name_template() {
+        let monitors = ["DELL U2720Q".to_string(), "LG TV".to_string()];
+        let date = jiff::civil::date(2025, 4, 1);
+        assert_eq!(
+            render_name_template("Imported {index} ({monitors})", 3, &monitors, date),
+            "Imported 3 (DELL U2720Q, LG TV)"
+        );
+        assert_eq!(
+            render_name_template("{count} monitors on {date}", 1, &monitors, date),
+            "2 monitors on 2025-04-01"
+        );
+        assert_eq!(render_name_template("Plain", 1, &[], date), "Plain");
+    }
+}
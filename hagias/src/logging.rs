@@ -1,29 +1,50 @@
-use tracing::{debug, level_filters::LevelFilter};
+use std::{
+    io::{Read as _, Seek as _, SeekFrom},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context as _, Result};
+use tracing::{debug, level_filters::LevelFilter, warn};
 use tracing_subscriber::{
-    Layer as _, fmt, layer::SubscriberExt as _, util::SubscriberInitExt as _,
+    Layer as _, Registry, fmt, layer::SubscriberExt as _, reload, util::SubscriberInitExt as _,
 };
 
-pub fn setup() -> tracing_appender::non_blocking::WorkerGuard {
-    // Configure file logging
+/// Changes the level of console logging, once logging is set up
+static CONSOLE_FILTER: OnceLock<reload::Handle<LevelFilter, Registry>> = OnceLock::new();
+
+/// The environment variable that sets the console log level, unless `--log-level` is given
+pub const LOG_LEVEL_ENV: &str = "RUST_LOG";
+
+/// How often a followed log file is checked for new output
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The directory that log files are written to, next to the executable
+pub fn log_directory() -> PathBuf {
     let root_directory = std::env::current_exe()
         .ok()
         .and_then(|f| f.parent().map(|p| p.to_owned()))
         .unwrap_or(".".into());
-    let log_directory = root_directory.join("logs");
-    let time = jiff::Zoned::now();
+    root_directory.join("logs")
+}
+
+pub fn setup() -> tracing_appender::non_blocking::WorkerGuard {
+    // Configure file logging
+    let log_directory = log_directory();
     let file_appender = tracing_appender::rolling::never(
         &log_directory,
-        format!(
-            "hagias_{}_{}_{}_{}.log",
-            time.year(),
-            time.month(),
-            time.day(),
-            std::process::id()
-        ),
+        log_file_name(&jiff::Zoned::now(), std::process::id()),
     );
     let (non_blocking_writer, guard) = tracing_appender::non_blocking(file_appender);
 
-    // Configure console logging with simple format and info+ level
+    // Configure console logging with simple format and info+ level, unless overridden by the
+    // environment. It goes to stderr, so that it doesn't mix with output that's piped, e.g. JSON
+    let env_level = std::env::var(LOG_LEVEL_ENV).ok();
+    let console_level = env_level.as_deref().and_then(parse_env_level);
+    let (console_filter, console_filter_handle) =
+        reload::Layer::new(console_level.unwrap_or(LevelFilter::INFO));
+    let _ = CONSOLE_FILTER.set(console_filter_handle);
     let console_layer = fmt::layer()
         .with_target(true)
         .with_thread_ids(false)
@@ -33,8 +54,8 @@ pub fn setup() -> tracing_appender::non_blocking::WorkerGuard {
         .with_span_events(fmt::format::FmtSpan::NONE)
         .with_level(true)
         .with_timer(ConsoleTimeFormat)
-        .with_writer(std::io::stdout)
-        .with_filter(LevelFilter::INFO);
+        .with_writer(std::io::stderr)
+        .with_filter(console_filter);
 
     // Configure file logging layer with detailed format and debug+ level
     let file_layer = fmt::layer()
@@ -60,6 +81,14 @@ pub fn setup() -> tracing_appender::non_blocking::WorkerGuard {
         .init(); // Set as global subscriber
 
     debug!("Logging initialized in {}", log_directory.display());
+    if let Some(env_level) = env_level
+        && console_level.is_none()
+    {
+        warn!(
+            "Ignoring {}={:?}, expected one of off, error, warn, info, debug or trace",
+            LOG_LEVEL_ENV, env_level
+        );
+    }
     debug!(
         "Current directory: {}",
         std::env::current_dir().unwrap_or(".".into()).display()
@@ -70,6 +99,167 @@ pub fn setup() -> tracing_appender::non_blocking::WorkerGuard {
     guard
 }
 
+/// Parse the value of the log level environment variable. Only a single level is supported, not
+/// per-target directives.
+fn parse_env_level(value: &str) -> Option<LevelFilter> {
+    let value = value.trim();
+    if value.is_empty() {
+        return None;
+    }
+    value.parse().ok()
+}
+
+/// Only log messages of at least `level` to the console, e.g. to keep it quiet around JSON output.
+/// File logging is unaffected.
+pub fn set_console_level(level: LevelFilter) {
+    let Some(handle) = CONSOLE_FILTER.get() else {
+        return;
+    };
+    if let Err(e) = handle.reload(level) {
+        warn!("Failed to change the console log level: {}", e);
+    }
+}
+
+/// The name of the log file written by the process `pid` started at `time`
+fn log_file_name(time: &jiff::Zoned, pid: u32) -> String {
+    format!("{}{}.log", log_file_day_prefix(time), pid)
+}
+
+/// The start of the names of the log files written on the day of `time`
+fn log_file_day_prefix(time: &jiff::Zoned) -> String {
+    format!("hagias_{}_{}_{}_", time.year(), time.month(), time.day())
+}
+
+/// The log file written to by the service. If the service is running, `service_pid` is its process
+/// ID, which identifies its log file. Otherwise this guesses: the most recently modified of today's
+/// log files, or of all log files if there are none from today. The log file of this process is
+/// ignored.
+pub fn find_service_log_file(
+    log_directory: &Path,
+    service_pid: Option<u32>,
+) -> Result<Option<PathBuf>> {
+    let now = jiff::Zoned::now();
+    let own_file_name = log_file_name(&now, std::process::id());
+    let entries = match std::fs::read_dir(log_directory) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(e).with_context(|| format!("Failed to read {}", log_directory.display()));
+        }
+    };
+    let mut files = Vec::new();
+    for entry in entries {
+        let entry = entry.with_context(|| format!("Failed to read {}", log_directory.display()))?;
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if !file_name.starts_with("hagias_")
+            || !file_name.ends_with(".log")
+            || file_name == own_file_name
+        {
+            continue;
+        }
+        let modified = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        files.push((file_name, modified));
+    }
+    Ok(
+        pick_service_log_file(files, &log_file_day_prefix(&now), service_pid)
+            .map(|file_name| log_directory.join(file_name)),
+    )
+}
+
+/// Pick the most recently modified file written by `service_pid`, if given. Otherwise pick the
+/// most recently modified file, preferring those starting with `today_prefix`.
+fn pick_service_log_file(
+    files: Vec<(String, SystemTime)>,
+    today_prefix: &str,
+    service_pid: Option<u32>,
+) -> Option<String> {
+    let newest = |files: Vec<(String, SystemTime)>| {
+        files
+            .into_iter()
+            .max_by_key(|(_, modified)| *modified)
+            .map(|(file_name, _)| file_name)
+    };
+    if let Some(pid) = service_pid {
+        // The file may be from an earlier day, if the service has been running since then
+        let suffix = format!("_{}.log", pid);
+        return newest(
+            files
+                .into_iter()
+                .filter(|(file_name, _)| file_name.ends_with(&suffix))
+                .collect(),
+        );
+    }
+    let (today, older): (Vec<_>, Vec<_>) = files
+        .into_iter()
+        .partition(|(file_name, _)| file_name.starts_with(today_prefix));
+    newest(today).or_else(|| newest(older))
+}
+
+/// The last `count` lines of `contents`
+fn last_lines(contents: &str, count: usize) -> Vec<&str> {
+    let lines = contents.lines().collect::<Vec<_>>();
+    lines[lines.len().saturating_sub(count)..].to_vec()
+}
+
+/// Print the last `count` lines of the log file at `path`. If `follow` is set, keep printing
+/// lines as they are written until Ctrl-C is pressed.
+pub async fn print_log_file(path: &Path, count: usize, follow: bool) -> Result<()> {
+    let mut file =
+        std::fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    for line in last_lines(&String::from_utf8_lossy(&contents), count) {
+        println!("{}", line);
+    }
+    if !follow {
+        return Ok(());
+    }
+
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    let mut position = contents.len() as u64;
+    // Bytes of an incomplete last line, printed once the rest of it is written
+    let mut pending = Vec::new();
+    loop {
+        tokio::select! {
+            result = &mut ctrl_c => {
+                result.context("failed to listen for Ctrl-C")?;
+                return Ok(());
+            }
+            _ = tokio::time::sleep(FOLLOW_POLL_INTERVAL) => {}
+        }
+        let len = file
+            .metadata()
+            .with_context(|| format!("Failed to read {}", path.display()))?
+            .len();
+        if len < position {
+            warn!("{} was truncated, following from the start", path.display());
+            position = 0;
+            pending.clear();
+        }
+        if len == position {
+            continue;
+        }
+        file.seek(SeekFrom::Start(position))
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let read = (&mut file)
+            .take(len - position)
+            .read_to_end(&mut pending)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        position += read as u64;
+        if let Some(end) = pending.iter().rposition(|&b| b == b'\n') {
+            for line in String::from_utf8_lossy(&pending[..end]).lines() {
+                println!("{}", line);
+            }
+            pending.drain(..=end);
+        }
+    }
+}
+
 struct ConsoleTimeFormat;
 
 impl tracing_subscriber::fmt::time::FormatTime for ConsoleTimeFormat {
@@ -105,3 +295,70 @@ impl tracing_subscriber::fmt::time::FormatTime for FileTimeFormat {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use tracing::level_filters::LevelFilter;
+
+    use super::{
+        last_lines, log_file_day_prefix, log_file_name, parse_env_level, pick_service_log_file,
+    };
+
+    #[test]
+    fn test_pick_service_log_file() {
+        let time: jiff::Zoned = "2025-03-07T12:00[UTC]".parse().unwrap();
+        assert_eq!(log_file_name(&time, 42), "hagias_2025_3_7_42.log");
+        let prefix = log_file_day_prefix(&time);
+        let at = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+
+        let files = vec![
+            ("hagias_2025_3_6_1.log".to_string(), at(300)),
+            ("hagias_2025_3_7_2.log".to_string(), at(100)),
+            ("hagias_2025_3_7_3.log".to_string(), at(200)),
+        ];
+        assert_eq!(
+            pick_service_log_file(files, &prefix, None).as_deref(),
+            Some("hagias_2025_3_7_3.log")
+        );
+
+        let files = vec![
+            ("hagias_2025_3_5_1.log".to_string(), at(100)),
+            ("hagias_2025_3_6_2.log".to_string(), at(200)),
+        ];
+        assert_eq!(
+            pick_service_log_file(files, &prefix, None).as_deref(),
+            Some("hagias_2025_3_6_2.log")
+        );
+        assert_eq!(pick_service_log_file(Vec::new(), &prefix, None), None);
+
+        // The running service's file is picked by its process ID, even if it's older
+        let files = vec![
+            ("hagias_2025_3_6_42.log".to_string(), at(100)),
+            ("hagias_2025_3_7_2.log".to_string(), at(200)),
+            ("hagias_2025_3_7_142.log".to_string(), at(300)),
+        ];
+        assert_eq!(
+            pick_service_log_file(files.clone(), &prefix, Some(42)).as_deref(),
+            Some("hagias_2025_3_6_42.log")
+        );
+        assert_eq!(pick_service_log_file(files, &prefix, Some(7)), None);
+    }
+
+    #[test]
+    fn test_last_lines() {
+        assert_eq!(last_lines("a\nb\nc\n", 2), ["b", "c"]);
+        assert_eq!(last_lines("a\nb", 5), ["a", "b"]);
+        assert!(last_lines("a\nb", 0).is_empty());
+    }
+
+    #[test]
+    fn test_parse_env_level() {
+        assert_eq!(parse_env_level("debug"), Some(LevelFilter::DEBUG));
+        assert_eq!(parse_env_level(" WARN "), Some(LevelFilter::WARN));
+        assert_eq!(parse_env_level("off"), Some(LevelFilter::OFF));
+        assert_eq!(parse_env_level(""), None);
+        assert_eq!(parse_env_level("hagias=debug"), None);
+    }
+}
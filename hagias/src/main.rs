@@ -8,14 +8,32 @@ use rocket::fs::FileServer;
 use rocket_dyn_templates::Template;
 use tracing::{debug, error, info};
 
+pub mod auth;
+pub mod autoswitch;
 pub mod cli;
+pub mod compression;
 pub mod config;
+pub mod confirm;
+pub mod cors;
+pub mod ddc;
 pub mod display;
+pub mod edid;
+pub mod enforce;
+pub mod etag;
+pub mod exit_code;
+pub mod history;
+pub mod in_flight;
 pub mod index;
+pub mod last_applied;
 pub mod layouts;
 pub mod logging;
+pub mod mdns;
+pub mod monitors;
+pub mod poller;
 pub mod serde_override;
 pub mod service;
+#[cfg(test)]
+pub(crate) mod test_util;
 pub mod windows_util;
 
 static TOKIO_RUNTIME: LazyLock<Result<tokio::runtime::Runtime>> =
@@ -44,10 +62,31 @@ fn attach_parent_console_windows() {
 }
 
 #[derive(Debug, Clone, clap::Parser)]
-#[command(author, version, about)]
+#[command(author, version, about, after_help = exit_code::HELP)]
 pub struct Args {
     #[command(subcommand)]
     command: Option<cli::Command>,
+    /// When running the web server, also apply the stored layout matching the connected monitors
+    /// whenever monitors are plugged in or unplugged
+    #[arg(long)]
+    autoswitch: bool,
+    /// Save the stored layouts as indented JSON, overriding `layouts_format`
+    #[arg(long, conflicts_with = "compact")]
+    pretty: bool,
+    /// Save the stored layouts as compact JSON, overriding `layouts_format`
+    #[arg(long)]
+    compact: bool,
+    /// Show what the command would change, without changing it: the stored layouts aren't saved,
+    /// and `layout apply`, `layout reset`, `layout project` and the `monitors` commands only log
+    /// the display or config change they would make. Commands that can't honour it, such as
+    /// `service` and `layout test-all`, reject it
+    #[arg(long, global = true)]
+    dry_run: bool,
+    /// Log messages of at least this level to the console: off, error, warn, info, debug or trace.
+    /// Defaults to the `RUST_LOG` environment variable, or info. The log file always gets debug
+    /// messages
+    #[arg(long, global = true, value_name = "LEVEL")]
+    log_level: Option<tracing::level_filters::LevelFilter>,
 }
 
 pub fn main() -> Result<()> {
@@ -77,12 +116,42 @@ pub async fn run() -> Result<i32> {
                     error!("{}", line);
                 }
             }
-            return Ok(e.exit_code());
+            // clap exits with 2 for invalid arguments, which would look like `NOT_FOUND`
+            return Ok(e.exit_code().min(1));
         }
     };
     debug!("Running: {:?}", args);
+    if let Some(level) = args.log_level {
+        logging::set_console_level(level);
+    } else if args.command.as_ref().is_some_and(cli::Command::quiet) {
+        logging::set_console_level(tracing::level_filters::LevelFilter::WARN);
+    }
+
+    if args.dry_run && !args.command.as_ref().is_some_and(|c| c.supports_dry_run()) {
+        error!("--dry-run isn't supported by this command");
+        return Ok(1);
+    }
 
-    let (figment, config) = config::get()?;
+    if let Some(command) = &args.command
+        && let Some(code) = command.run_without_config().await?
+    {
+        return Ok(code);
+    }
+
+    let (figment, mut config) = match config::get() {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Failed to load config: {:?}", e);
+            return Ok(exit_code::CONFIG_ERROR);
+        }
+    };
+    config.autoswitch |= args.autoswitch;
+    config.dry_run = args.dry_run;
+    if args.pretty {
+        config.layouts_format = layouts::LayoutsFormat::Pretty;
+    } else if args.compact {
+        config.layouts_format = layouts::LayoutsFormat::Compact;
+    }
 
     if let Some(command) = args.command {
         if let Some(code) = command.run(&config).await? {
@@ -101,12 +170,75 @@ pub fn get_rocket_build(
     config: config::Config,
 ) -> rocket::Rocket<rocket::Build> {
     debug!("Building rocket");
+    let template_dir = config.template_dir.relative();
+    if !template_dir.is_dir() {
+        error!("Template directory not found: {}", template_dir.display());
+    }
+    let figment = figment
+        .merge(("template_dir", template_dir))
+        .merge(("address", config.address))
+        .merge(("port", config.port))
+        .merge(("limits.json", config.max_body_bytes))
+        .merge(("limits.string", config.max_body_bytes))
+        .merge(("limits.bytes", config.max_body_bytes));
+    let in_flight = in_flight::InFlightApplies::default();
     let rocket = rocket::build()
         .configure(figment)
-        .mount("/", rocket::routes![index::index, index::apply_config])
+        .mount(
+            "/",
+            rocket::routes![
+                index::index,
+                index::version,
+                index::layouts,
+                index::pin_layout,
+                index::store,
+                index::apply_config,
+                index::project,
+                index::confirm,
+                index::confirm_id,
+                index::revert,
+                index::monitors,
+                index::monitor_power
+            ],
+        )
         .mount("/static", FileServer::from(config.static_dir.relative()))
-        .manage(config)
+        .register("/api", rocket::catchers![index::api_catcher])
+        .manage(confirm::ApplyConfirmations::new(in_flight.clone()))
+        .attach(in_flight.shutdown_fairing())
+        .manage(in_flight)
+        .manage(last_applied::LastAppliedState::new(
+            config.last_applied_path(),
+        ))
         .attach(Template::fairing());
+    let rocket = if config.allowed_origins.is_empty() {
+        rocket
+    } else {
+        debug!("Enabling CORS for {:?}", config.allowed_origins);
+        rocket
+            .mount("/", rocket::routes![cors::preflight])
+            .attach(cors::Cors::new(config.allowed_origins.clone()))
+    };
+    let rocket = if config.compress_responses {
+        rocket.attach(compression::Compression)
+    } else {
+        rocket
+    };
+    let rocket = if config.advertise_mdns {
+        rocket.attach(mdns::Mdns::default())
+    } else {
+        rocket
+    };
+    let rocket = if config.autoswitch {
+        rocket.attach(autoswitch::AutoSwitch)
+    } else {
+        rocket
+    };
+    let rocket = if config.enforce_layout.is_some() {
+        rocket.attach(enforce::Enforcer)
+    } else {
+        rocket
+    };
+    let rocket = rocket.manage(config);
     debug!("Built rocket");
     rocket
 }
@@ -0,0 +1,98 @@
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use mdns_sd::{DaemonEvent, ServiceDaemon, ServiceInfo};
+use rocket::{
+    Orbit, Rocket,
+    fairing::{Fairing, Info, Kind},
+};
+use tracing::{debug, info, warn};
+
+use crate::service::SERVICE_DISPLAY_NAME;
+
+const SERVICE_TYPE: &str = "_http._tcp.local.";
+
+/// Advertises the web server over mDNS while rocket is running
+#[derive(Default)]
+pub struct Mdns {
+    registration: Mutex<Option<Registration>>,
+}
+
+struct Registration {
+    daemon: ServiceDaemon,
+    fullname: String,
+}
+
+impl Mdns {
+    fn register(port: u16) -> Result<Registration> {
+        let host_name = std::env::var("COMPUTERNAME").unwrap_or_else(|_| "hagias".into());
+        let daemon = ServiceDaemon::new().context("Failed to start mDNS daemon")?;
+        let service_info = ServiceInfo::new(
+            SERVICE_TYPE,
+            SERVICE_DISPLAY_NAME,
+            &format!("{}.local.", host_name),
+            (),
+            port,
+            None,
+        )
+        .context("Invalid mDNS service info")?
+        .enable_addr_auto();
+        let fullname = service_info.get_fullname().to_string();
+
+        // If another responder already owns the name, the daemon picks a new one after probing
+        let events = daemon.monitor().context("Failed to monitor mDNS daemon")?;
+        std::thread::spawn(move || {
+            while let Ok(event) = events.recv() {
+                if let DaemonEvent::NameChange(change) = event {
+                    warn!(
+                        "mDNS name {:?} is already in use, advertising as {:?} instead",
+                        change.original, change.new_name
+                    );
+                }
+            }
+        });
+
+        daemon
+            .register(service_info)
+            .context("Failed to register mDNS service")?;
+        Ok(Registration { daemon, fullname })
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for Mdns {
+    fn info(&self) -> Info {
+        Info {
+            name: "mDNS Advertisement",
+            kind: Kind::Liftoff | Kind::Shutdown,
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        match Self::register(rocket.config().port) {
+            Ok(registration) => {
+                info!("Advertising {} over mDNS", registration.fullname);
+                *self.registration.lock().expect("failed to lock mDNS") = Some(registration);
+            }
+            Err(e) => warn!("Failed to advertise over mDNS: {:?}", e),
+        }
+    }
+
+    async fn on_shutdown(&self, _rocket: &Rocket<Orbit>) {
+        let Some(registration) = self
+            .registration
+            .lock()
+            .expect("failed to lock mDNS")
+            .take()
+        else {
+            return;
+        };
+        debug!("Unregistering {} from mDNS", registration.fullname);
+        if let Err(e) = registration.daemon.unregister(&registration.fullname) {
+            warn!("Failed to unregister mDNS service: {}", e);
+        }
+        if let Err(e) = registration.daemon.shutdown() {
+            warn!("Failed to shut down mDNS daemon: {}", e);
+        }
+    }
+}
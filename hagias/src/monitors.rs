@@ -0,0 +1,337 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::OsString,
+};
+
+use anyhow::{Result, anyhow, bail};
+use serde::Serialize;
+use windows::Win32::Graphics::Gdi::DISPLAYCONFIG_PATH_ACTIVE;
+
+use crate::{
+    display::DisplayLayout,
+    edid,
+    windows_util::{
+        DisplayQueryType, IdAndAdapterId, OutputTechnology, WindowsDisplayConfig,
+        get_monitor_device_path, get_monitor_friendly_device_name, is_target_device_edid_ids_valid,
+        normalize_device_path, wchar_null_terminated_to_os_string,
+    },
+};
+
+/// The key a monitor's alias is stored under, from its EDID manufacturer and product code, e.g.
+/// `10AC:A0B1`. Monitors without valid EDID IDs can't have aliases.
+pub fn alias_key(
+    edid_manufacture_id: Option<u16>,
+    edid_product_code_id: Option<u16>,
+) -> Option<String> {
+    Some(format!(
+        "{:04X}:{:04X}",
+        edid_manufacture_id?, edid_product_code_id?
+    ))
+}
+
+/// The key a monitor's alias is stored under to tell it apart from identical monitors, from its
+/// EDID manufacturer, product code and serial number, e.g. `10AC:A0B1:ABC123`
+pub fn serial_alias_key(
+    edid_manufacture_id: Option<u16>,
+    edid_product_code_id: Option<u16>,
+    edid_serial: Option<&str>,
+) -> Option<String> {
+    Some(format!(
+        "{}:{}",
+        alias_key(edid_manufacture_id, edid_product_code_id)?,
+        edid_serial?
+    ))
+}
+
+/// The user's alias for a monitor, preferring an alias for its serial number over one for its
+/// model
+pub fn find_alias<'a>(
+    aliases: &'a HashMap<String, String>,
+    edid_manufacture_id: Option<u16>,
+    edid_product_code_id: Option<u16>,
+    edid_serial: Option<&str>,
+) -> Option<&'a String> {
+    serial_alias_key(edid_manufacture_id, edid_product_code_id, edid_serial)
+        .and_then(|key| aliases.get(&key))
+        .or_else(|| {
+            alias_key(edid_manufacture_id, edid_product_code_id).and_then(|key| aliases.get(&key))
+        })
+}
+
+/// A monitor (display target) known to Windows, whether or not it is currently active.
+#[derive(Debug, Clone, Serialize)]
+pub struct Monitor {
+    pub id: u32,
+    #[serde(with = "crate::serde_override::os_string")]
+    pub adapter_device_path: OsString,
+    pub output_technology: OutputTechnology,
+    #[serde(with = "crate::serde_override::option_os_string")]
+    pub friendly_name: Option<OsString>,
+    #[serde(with = "crate::serde_override::option_os_string")]
+    pub device_path: Option<OsString>,
+    pub edid_manufacture_id: Option<u16>,
+    pub edid_product_code_id: Option<u16>,
+    /// The serial number from the monitor's EDID, if it can be read
+    pub edid_serial: Option<String>,
+    /// The user's name for the monitor, from the `aliases` config
+    pub alias: Option<String>,
+    /// Whether a monitor is currently connected to the target
+    pub connected: bool,
+    /// Whether the target is driven by an active path
+    pub active: bool,
+    /// The GDI device name of the source driving the target (e.g. `\\.\DISPLAY1`), if active
+    #[serde(with = "crate::serde_override::option_os_string")]
+    pub gdi_device_name: Option<OsString>,
+}
+
+impl Monitor {
+    /// Get all monitors, ordered by adapter and target ID
+    pub fn get_all() -> Result<Vec<Monitor>> {
+        let windows_display_config = WindowsDisplayConfig::get(DisplayQueryType::All)?;
+        Ok(Self::from_windows(&windows_display_config))
+    }
+
+    pub fn from_windows(windows_display_config: &WindowsDisplayConfig) -> Vec<Monitor> {
+        // There is a path for each source/target combination, so merge paths by target
+        let mut monitors: BTreeMap<IdAndAdapterId, Monitor> = BTreeMap::new();
+        for path in &windows_display_config.paths {
+            let id_and_adapter_id = IdAndAdapterId {
+                id: path.targetInfo.id,
+                adapter_id: path.targetInfo.adapterId.into(),
+            };
+            let active = path.flags & DISPLAYCONFIG_PATH_ACTIVE != 0;
+            let connected = path.targetInfo.targetAvailable.as_bool();
+            let gdi_device_name = if active {
+                windows_display_config
+                    .source_device_names
+                    .get(&IdAndAdapterId {
+                        id: path.sourceInfo.id,
+                        adapter_id: path.sourceInfo.adapterId.into(),
+                    })
+                    .map(|name| wchar_null_terminated_to_os_string(&name.viewGdiDeviceName))
+            } else {
+                None
+            };
+            if let Some(monitor) = monitors.get_mut(&id_and_adapter_id) {
+                monitor.active |= active;
+                monitor.connected |= connected;
+                if monitor.gdi_device_name.is_none() {
+                    monitor.gdi_device_name = gdi_device_name;
+                }
+                continue;
+            }
+            let Some(target_device_name) = windows_display_config
+                .target_device_names
+                .get(&id_and_adapter_id)
+            else {
+                continue;
+            };
+            let adapter_device_path = windows_display_config
+                .adapter_device_names
+                .get(&id_and_adapter_id.adapter_id)
+                .cloned()
+                .unwrap_or_default();
+            let (edid_manufacture_id, edid_product_code_id) =
+                if is_target_device_edid_ids_valid(target_device_name.flags) {
+                    (
+                        Some(target_device_name.edidManufactureId),
+                        Some(target_device_name.edidProductCodeId),
+                    )
+                } else {
+                    (None, None)
+                };
+            let device_path = get_monitor_device_path(target_device_name);
+            let edid_serial = device_path.as_deref().and_then(edid::get_serial);
+            monitors.insert(
+                id_and_adapter_id,
+                Monitor {
+                    id: id_and_adapter_id.id,
+                    adapter_device_path,
+                    output_technology: target_device_name.outputTechnology.into(),
+                    friendly_name: get_monitor_friendly_device_name(target_device_name),
+                    device_path,
+                    edid_manufacture_id,
+                    edid_product_code_id,
+                    edid_serial,
+                    alias: None,
+                    connected,
+                    active,
+                    gdi_device_name,
+                },
+            );
+        }
+        monitors.into_values().collect()
+    }
+
+    /// Get all monitors with their aliases, ordered by adapter and target ID
+    pub fn get_all_with_aliases(aliases: &HashMap<String, String>) -> Result<Vec<Monitor>> {
+        let mut monitors = Self::get_all()?;
+        for monitor in &mut monitors {
+            monitor.alias = find_alias(
+                aliases,
+                monitor.edid_manufacture_id,
+                monitor.edid_product_code_id,
+                monitor.edid_serial.as_deref(),
+            )
+            .cloned();
+        }
+        Ok(monitors)
+    }
+
+    /// The key the monitor's alias is stored under, if it has valid EDID IDs
+    pub fn alias_key(&self) -> Option<String> {
+        alias_key(self.edid_manufacture_id, self.edid_product_code_id)
+    }
+
+    /// The key the monitor's alias is stored under to tell it apart from identical monitors, if
+    /// it has valid EDID IDs and a serial number
+    pub fn serial_alias_key(&self) -> Option<String> {
+        serial_alias_key(
+            self.edid_manufacture_id,
+            self.edid_product_code_id,
+            self.edid_serial.as_deref(),
+        )
+    }
+
+    /// Find a single monitor by its target ID, or (case-insensitive) alias or friendly name
+    pub fn find(query: &str, aliases: &HashMap<String, String>) -> Result<Option<Monitor>> {
+        let mut matches: Vec<Monitor> = Self::get_all_with_aliases(aliases)?
+            .into_iter()
+            .filter(|monitor| {
+                monitor.id.to_string() == query
+                    || monitor
+                        .alias
+                        .as_ref()
+                        .is_some_and(|alias| alias.eq_ignore_ascii_case(query))
+                    || monitor
+                        .friendly_name
+                        .as_ref()
+                        .is_some_and(|name| name.to_string_lossy().eq_ignore_ascii_case(query))
+            })
+            .collect();
+        if matches.len() > 1 {
+            bail!(
+                "{} monitors match {:?}, use a target ID instead",
+                matches.len(),
+                query
+            );
+        }
+        Ok(matches.pop())
+    }
+
+    /// The target and adapter IDs of the monitor in `windows_display_config`
+    fn id_and_adapter_id(
+        &self,
+        windows_display_config: &WindowsDisplayConfig,
+    ) -> Option<IdAndAdapterId> {
+        let adapter_device_path = normalize_device_path(&self.adapter_device_path);
+        windows_display_config
+            .adapter_device_names
+            .iter()
+            .find(|(_, device_path)| normalize_device_path(device_path) == adapter_device_path)
+            .map(|(adapter_id, _)| IdAndAdapterId {
+                id: self.id,
+                adapter_id: *adapter_id,
+            })
+    }
+
+    /// Activate the monitor, leaving the active monitors as they are. Windows chooses its
+    /// resolution and position.
+    pub fn enable(&self) -> Result<()> {
+        let windows_display_config = WindowsDisplayConfig::get(DisplayQueryType::All)?;
+        let target = self
+            .id_and_adapter_id(&windows_display_config)
+            .ok_or_else(|| anyhow!("Adapter of monitor {} not found", self.display_name()))?;
+        WindowsDisplayConfig::enable_target(&windows_display_config, target, true)
+    }
+
+    /// Deactivate the monitor, leaving the other active monitors as they are
+    pub fn disable(&self) -> Result<()> {
+        let layout = DisplayLayout::get()?;
+        let adapter_device_path = normalize_device_path(&self.adapter_device_path);
+        let remaining = layout.retain_paths(|path| {
+            let Some(target_mode) = layout.target_modes.get(path.target.target_mode_index) else {
+                // Keep the path, so that its invalid index is reported
+                return true;
+            };
+            let device = &target_mode.device;
+            device.id != self.id
+                || normalize_device_path(&device.adapter.device_instance_path)
+                    != adapter_device_path
+        })?;
+        if remaining.paths.len() == layout.paths.len() {
+            bail!("Monitor {} is not active", self.display_name());
+        }
+        remaining.apply(true, false, false)
+    }
+
+    /// The alias or friendly name of the monitor, or a placeholder if it has neither
+    pub fn display_name(&self) -> String {
+        if let Some(alias) = &self.alias {
+            return alias.clone();
+        }
+        self.friendly_name
+            .as_ref()
+            .map(|name| name.display().to_string())
+            .unwrap_or_else(|| "<unknown>".into())
+    }
+}
+
+/// Filters applied when listing monitors
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct MonitorFilter {
+    /// Only include monitors that are currently connected
+    #[arg(long)]
+    pub connected: bool,
+    /// Only include monitors using this output technology
+    #[arg(long, value_enum)]
+    pub tech: Option<OutputTechnology>,
+}
+
+impl MonitorFilter {
+    pub fn matches(&self, monitor: &Monitor) -> bool {
+        if self.connected && !monitor.connected {
+            return false;
+        }
+        if self
+            .tech
+            .is_some_and(|tech| monitor.output_technology != tech)
+        {
+            return false;
+        }
+        true
+    }
+
+    pub fn apply(&self, monitors: Vec<Monitor>) -> Vec<Monitor> {
+        monitors.into_iter().filter(|m| self.matches(m)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{alias_key, find_alias};
+
+    #[test]
+    fn test_alias_key() {
+        assert_eq!(
+            alias_key(Some(0x10AC), Some(0xA0B1)).as_deref(),
+            Some("10AC:A0B1")
+        );
+        assert_eq!(alias_key(Some(0x10AC), None), None);
+        assert_eq!(alias_key(None, None), None);
+    }
+
+    #[test]
+    fn test_find_alias_prefers_serial() {
+        let aliases = HashMap::from([
+            ("10AC:A0B1".to_string(), "Dell".to_string()),
+            ("10AC:A0B1:ABC123".to_string(), "Left Dell".to_string()),
+        ]);
+        let find = |serial| find_alias(&aliases, Some(0x10AC), Some(0xA0B1), serial).cloned();
+        assert_eq!(find(Some("ABC123")).as_deref(), Some("Left Dell"));
+        assert_eq!(find(Some("XYZ789")).as_deref(), Some("Dell"));
+        assert_eq!(find(None).as_deref(), Some("Dell"));
+    }
+}
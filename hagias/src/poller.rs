@@ -0,0 +1,70 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use rocket::{Orbit, Rocket, Shutdown};
+use tracing::{debug, error};
+
+use crate::{
+    config::Config,
+    history::{ApplySource, History},
+    in_flight::InFlightApplies,
+    last_applied::LastApplied,
+    layouts::NamedLayout,
+};
+
+/// A check that runs in the background every interval while rocket is running, see [`spawn`]
+#[rocket::async_trait]
+pub trait Poll: Send + 'static {
+    /// Run the check once. Failures should be logged, as polling carries on regardless.
+    async fn poll(&mut self);
+}
+
+/// The state that pollers need from rocket, or `None` (logging why) if it isn't managed
+pub fn managed_state(rocket: &Rocket<Orbit>, name: &str) -> Option<(Config, InFlightApplies)> {
+    let (Some(config), Some(in_flight)) = (
+        rocket.state::<Config>().cloned(),
+        rocket.state::<InFlightApplies>().cloned(),
+    ) else {
+        error!(
+            "{} can't start, as the config isn't managed by rocket",
+            name
+        );
+        return None;
+    };
+    Some((config, in_flight))
+}
+
+/// Run `poll` every `interval` in the background, until rocket shuts down
+pub fn spawn(rocket: &Rocket<Orbit>, name: &'static str, interval: Duration, poll: impl Poll) {
+    tokio::spawn(run(name, interval, poll, rocket.shutdown()));
+}
+
+async fn run(name: &str, interval: Duration, mut poll: impl Poll, mut shutdown: Shutdown) {
+    loop {
+        tokio::select! {
+            _ = &mut shutdown => break,
+            _ = tokio::time::sleep(interval) => {}
+        }
+        poll.poll().await;
+    }
+    debug!("{} stopped", name);
+}
+
+/// Apply a layout chosen by a poller, and record that it was applied
+pub async fn apply(
+    config: &Config,
+    in_flight: &InFlightApplies,
+    layout: &NamedLayout,
+    source: ApplySource,
+) -> Result<()> {
+    {
+        // Keep shutdown from interrupting the apply half way through
+        let _guard = in_flight.begin();
+        layout.layout.apply(true, false, false)?;
+    }
+    LastApplied::now(&layout.id)
+        .save_or_warn(&config.last_applied_path())
+        .await;
+    History::record(&config.history_path(), &layout.id, source).await;
+    Ok(())
+}
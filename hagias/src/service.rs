@@ -2,6 +2,11 @@ use std::{
     collections::HashSet,
     ffi::OsString,
     os::windows::ffi::OsStringExt,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::JoinHandle,
     time::{Duration, Instant},
 };
 
@@ -10,7 +15,9 @@ use rocket::fairing::AdHoc;
 use tracing::{info, warn};
 use winapi::{shared::minwindef::DWORD, um::winbase::GetUserNameW};
 use windows::Win32::Foundation::{
-    ERROR_INSUFFICIENT_BUFFER, ERROR_SERVICE_DOES_NOT_EXIST, ERROR_SUCCESS, GetLastError,
+    ERROR_ACCESS_DENIED, ERROR_FAILED_SERVICE_CONTROLLER_CONNECT, ERROR_INSUFFICIENT_BUFFER,
+    ERROR_SERVICE_CANNOT_ACCEPT_CTRL, ERROR_SERVICE_DATABASE_LOCKED, ERROR_SERVICE_DOES_NOT_EXIST,
+    ERROR_SERVICE_REQUEST_TIMEOUT, ERROR_SUCCESS, GetLastError, WIN32_ERROR,
 };
 use windows_service::{
     define_windows_service,
@@ -20,25 +27,90 @@ use windows_service::{
         ServiceFailureResetPeriod, ServiceInfo, ServiceStartType, ServiceState, ServiceStatus,
         ServiceType,
     },
-    service_control_handler::{self, ServiceControlHandlerResult},
+    service_control_handler::{self, ServiceControlHandlerResult, ServiceStatusHandle},
     service_dispatcher,
     service_manager::{ServiceManager, ServiceManagerAccess},
 };
 
-use crate::windows_util::windows_error_to_string;
+use crate::{in_flight::InFlightApplies, windows_util::windows_error_to_string};
 
-pub const SERVICE_NAME: &str = "hagias";
+/// The name of the service if none is given, so existing installs keep working
+pub const DEFAULT_SERVICE_NAME: &str = "hagias";
 pub const SERVICE_DISPLAY_NAME: &str = "Hagias Monitor Service";
 pub const SERVICE_DESCRIPTION: &str =
     "Runs a web server that can be used to change the monitor layout of the system.";
 
+/// The display name of the service with the given name, which is distinguished from the default
+/// service's so multiple services can be told apart
+fn display_name(name: &str) -> String {
+    if name == DEFAULT_SERVICE_NAME {
+        SERVICE_DISPLAY_NAME.into()
+    } else {
+        format!("{} ({})", SERVICE_DISPLAY_NAME, name)
+    }
+}
+
+/// The arguments Windows runs the service with
+fn launch_arguments(name: &str) -> Vec<OsString> {
+    let mut arguments = vec!["service".into(), "run".into()];
+    if name != DEFAULT_SERVICE_NAME {
+        arguments.extend(["--name".into(), name.into()]);
+    }
+    arguments
+}
+
 const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(100);
 const DEFAULT_TIMEOUT: Option<Duration> = Some(Duration::from_secs(60));
+const DEFAULT_START_RETRY_DELAY: Duration = Duration::from_millis(250);
+const DEFAULT_ROCKET_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// SCM errors that are likely to be transient, so the operation is worth retrying
+const RETRYABLE_SERVICE_ERRORS: [WIN32_ERROR; 3] = [
+    ERROR_SERVICE_DATABASE_LOCKED,
+    ERROR_SERVICE_REQUEST_TIMEOUT,
+    ERROR_SERVICE_CANNOT_ACCEPT_CTRL,
+];
+
+// The state of the running service. Each service is its own process, so there's only ever one
+// service per process, whatever its name.
+static SERVICE_LOCK: Mutex<()> = Mutex::new(());
+static SERVICE_RETURN: Mutex<Option<anyhow::Error>> = Mutex::new(None);
+static SERVICE_ROCKET_SHUTDOWN: Mutex<Option<rocket::Shutdown>> = Mutex::new(None);
+static SERVICE_STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
 
-static SERVICE_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
-static SERVICE_RETURN: std::sync::Mutex<Option<anyhow::Error>> = std::sync::Mutex::new(None);
-static SERVICE_ROCKET_SHUTDOWN: std::sync::Mutex<Option<rocket::Shutdown>> =
-    std::sync::Mutex::new(None);
+/// A handle that can be notified to shut down, i.e. [`rocket::Shutdown`]
+trait ShutdownNotify: Send + 'static {
+    fn notify_shutdown(&self);
+}
+
+impl ShutdownNotify for rocket::Shutdown {
+    fn notify_shutdown(&self) {
+        self.clone().notify();
+    }
+}
+
+/// Notify the shutdown handle in `slot`, if any, without blocking the caller.
+///
+/// The service control handler must return promptly, so if the slot is locked (e.g. while rocket
+/// is being built) the notification is sent from a new thread once the lock is released, and the
+/// thread's handle is returned.
+fn notify_shutdown<T: ShutdownNotify>(slot: &'static Mutex<Option<T>>) -> Option<JoinHandle<()>> {
+    if let Ok(lock) = slot.try_lock() {
+        // Notify the shutdown immediately if we can lock the mutex
+        if let Some(ref shutdown) = *lock {
+            shutdown.notify_shutdown();
+        }
+        None
+    } else {
+        // Spawn a thread if we can't lock the mutex
+        Some(std::thread::spawn(move || {
+            let lock = slot.lock().expect("failed to lock rocket shutdown");
+            if let Some(ref shutdown) = *lock {
+                shutdown.notify_shutdown();
+            }
+        }))
+    }
+}
 
 define_windows_service!(ffi_service_main, service_main);
 
@@ -54,36 +126,30 @@ fn service_main(args: Vec<OsString>) {
     }
 }
 
-async fn service_main_async(_args: Vec<OsString>) -> Result<()> {
-    let (rocket, status_handle) = {
-        info!("Setting up service {}", SERVICE_NAME);
-        // Lock the rocket shutdown mutex so we don't access it via. the event handler while it's being set
-        let mut shutdown_lock = SERVICE_ROCKET_SHUTDOWN
+async fn service_main_async(args: Vec<OsString>) -> Result<()> {
+    // Windows passes the name of the service as the first argument
+    let name = args
+        .first()
+        .and_then(|name| name.to_str())
+        .unwrap_or(DEFAULT_SERVICE_NAME)
+        .to_owned();
+    let name = name.as_str();
+    let (figment, config, status_handle) = {
+        info!("Setting up service {}", name);
+        // Reset the shutdown state, as the service may have been run before by this process
+        SERVICE_ROCKET_SHUTDOWN
             .lock()
-            .expect("failed to lock rocket shutdown");
-        shutdown_lock.take(); // Reset the shutdown mutex
+            .expect("failed to lock rocket shutdown")
+            .take();
+        SERVICE_STOP_REQUESTED.store(false, Ordering::SeqCst);
 
         let event_handler = move |control_event| -> ServiceControlHandlerResult {
             match control_event {
                 ServiceControl::Stop => {
                     // Handle stop event and return control back to the system.
-                    let mut lock = SERVICE_ROCKET_SHUTDOWN.try_lock();
-                    if let Ok(ref mut mutex) = lock {
-                        // Notify the rocket shutdown immediately if we can lock the mutex
-                        if let Some(ref shutdown) = **mutex {
-                            shutdown.clone().notify();
-                        }
-                    } else {
-                        // Spawn a thread if we can't lock the mutex
-                        std::thread::spawn(move || {
-                            let lock = SERVICE_ROCKET_SHUTDOWN
-                                .lock()
-                                .expect("failed to lock rocket shutdown");
-                            if let Some(ref shutdown) = *lock {
-                                shutdown.clone().notify();
-                            }
-                        });
-                    }
+                    // Record the request in case rocket is between start attempts.
+                    SERVICE_STOP_REQUESTED.store(true, Ordering::SeqCst);
+                    notify_shutdown(&SERVICE_ROCKET_SHUTDOWN);
                     ServiceControlHandlerResult::NoError
                 }
                 // All services must accept Interrogate even if it's a no-op.
@@ -93,8 +159,8 @@ async fn service_main_async(_args: Vec<OsString>) -> Result<()> {
         };
 
         // Register service
-        info!("Registering service {}", SERVICE_NAME);
-        let status_handle = service_control_handler::register(SERVICE_NAME, event_handler)?;
+        info!("Registering service {}", name);
+        let status_handle = service_control_handler::register(name, event_handler)?;
         status_handle.set_service_status(ServiceStatus {
             service_type: ServiceType::OWN_PROCESS,
             current_state: ServiceState::StartPending,
@@ -116,14 +182,77 @@ async fn service_main_async(_args: Vec<OsString>) -> Result<()> {
             wait_hint: Duration::from_secs(60),
             process_id: None,
         })?;
+        (figment, config, status_handle)
+    };
+
+    // Retry starting rocket, as it can fail transiently (e.g. the port is still in use by the
+    // previous instance during a restart)
+    let attempts = config.rocket_start_attempts.max(1);
+    let mut delay = DEFAULT_ROCKET_RETRY_DELAY;
+    let mut attempt = 1;
+    let result = loop {
+        let lifted_off = Arc::new(AtomicBool::new(false));
+        let result = run_service_rocket(
+            status_handle,
+            figment.clone(),
+            config.clone(),
+            lifted_off.clone(),
+        )
+        .await;
+        match result {
+            Err(e)
+                if !lifted_off.load(Ordering::SeqCst)
+                    && attempt < attempts
+                    && !SERVICE_STOP_REQUESTED.load(Ordering::SeqCst) =>
+            {
+                warn!(
+                    "Failed to start rocket (attempt {}/{}), retrying in {:?}: {:?}",
+                    attempt, attempts, delay, e
+                );
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            result => break result,
+        }
+    };
+
+    let status_handle_result = status_handle.set_service_status(ServiceStatus {
+        service_type: ServiceType::OWN_PROCESS,
+        current_state: ServiceState::Stopped,
+        controls_accepted: ServiceControlAccept::STOP,
+        exit_code: ServiceExitCode::Win32(if result.is_ok() { 0 } else { 1 }),
+        checkpoint: 0,
+        wait_hint: Duration::default(),
+        process_id: None,
+    });
+    result?;
+    status_handle_result?;
+    Ok(())
+}
+
+/// Build, ignite and launch rocket for the service, returning once it has shut down.
+///
+/// `lifted_off` is set once rocket has started listening.
+async fn run_service_rocket(
+    status_handle: ServiceStatusHandle,
+    figment: rocket::figment::Figment,
+    config: crate::config::Config,
+    lifted_off: Arc<AtomicBool>,
+) -> Result<()> {
+    let rocket = {
+        // Lock the rocket shutdown mutex so we don't access it via. the event handler while it's being set
+        let mut shutdown_lock = SERVICE_ROCKET_SHUTDOWN
+            .lock()
+            .expect("failed to lock rocket shutdown");
 
         info!("Building rocket");
-        let status_handle_clone = status_handle.clone();
         let rocket = crate::get_rocket_build(figment, config).attach(AdHoc::on_liftoff(
             "Liftoff Printer",
             move |r| {
                 Box::pin(async move {
-                    if let Err(e) = status_handle_clone.set_service_status(ServiceStatus {
+                    lifted_off.store(true, Ordering::SeqCst);
+                    if let Err(e) = status_handle.set_service_status(ServiceStatus {
                         service_type: ServiceType::OWN_PROCESS,
                         current_state: ServiceState::Running,
                         controls_accepted: ServiceControlAccept::STOP,
@@ -149,21 +278,7 @@ async fn service_main_async(_args: Vec<OsString>) -> Result<()> {
         })?;
 
         info!("Igniting rocket");
-        let rocket = match crate::ignite_rocket(rocket).await {
-            Ok(rocket) => rocket,
-            Err(e) => {
-                let _ = status_handle.set_service_status(ServiceStatus {
-                    service_type: ServiceType::OWN_PROCESS,
-                    current_state: ServiceState::Stopped,
-                    controls_accepted: ServiceControlAccept::STOP,
-                    exit_code: ServiceExitCode::Win32(1),
-                    checkpoint: 0,
-                    wait_hint: Duration::default(),
-                    process_id: None,
-                });
-                return Err(e);
-            }
-        };
+        let rocket = crate::ignite_rocket(rocket).await?;
 
         // Update status
         status_handle.set_service_status(ServiceStatus {
@@ -178,28 +293,27 @@ async fn service_main_async(_args: Vec<OsString>) -> Result<()> {
 
         // Replace the rocket shutdown mutex with the new shutdown notifier
         shutdown_lock.replace(rocket.shutdown());
-        (rocket, status_handle)
+        // A stop may have been requested while there was no rocket to notify
+        if SERVICE_STOP_REQUESTED.load(Ordering::SeqCst) {
+            rocket.shutdown().notify();
+        }
+        rocket
     };
 
     // Launch rocket (starts rocket)
+    let in_flight = rocket.state::<InFlightApplies>().cloned();
     info!("Launching rocket");
     let result = crate::launch_rocket(rocket).await;
-
-    let status_handle_result = status_handle.set_service_status(ServiceStatus {
-        service_type: ServiceType::OWN_PROCESS,
-        current_state: ServiceState::Stopped,
-        controls_accepted: ServiceControlAccept::STOP,
-        exit_code: ServiceExitCode::Win32(if result.is_ok() { 0 } else { 1 }),
-        checkpoint: 0,
-        wait_hint: Duration::default(),
-        process_id: None,
-    });
+    // Don't report the service as stopped while a display change (e.g. a revert after a
+    // confirmation timeout) is still being applied
+    if let Some(in_flight) = in_flight {
+        in_flight.wait_idle_or_warn().await;
+    }
     result?;
-    status_handle_result?;
     Ok(())
 }
 
-pub fn run() -> Result<()> {
+pub fn run(name: &str) -> Result<()> {
     // Ensure that we have exclusive access to the service
     let _service_lock = SERVICE_LOCK.lock().expect("failed to lock service lock");
 
@@ -210,9 +324,16 @@ pub fn run() -> Result<()> {
         .take();
 
     // Start the service
-    info!("Starting service {}", SERVICE_NAME);
-    service_dispatcher::start(SERVICE_NAME, ffi_service_main).context("service error")?;
-    info!("Service {} finished", SERVICE_NAME);
+    info!("Starting service {}", name);
+    if let Err(e) = service_dispatcher::start(name, ffi_service_main) {
+        if is_not_started_by_scm_error(&e) {
+            bail!(
+                "The service can only be run by Windows. To run it in this console, use `service run --foreground`"
+            );
+        }
+        return Err(e).context("service error");
+    }
+    info!("Service {} finished", name);
 
     // Return any error that occurred
     if let Some(error) = SERVICE_RETURN
@@ -229,6 +350,7 @@ pub fn run() -> Result<()> {
 
 fn get_service_manager(manager_access: ServiceManagerAccess) -> Result<ServiceManager> {
     let service_manager = ServiceManager::local_computer(None::<&str>, manager_access)
+        .map_err(access_denied_to_admin_error)
         .with_context(|| {
             format!(
                 "failed to create service manager with access {:?}",
@@ -238,22 +360,27 @@ fn get_service_manager(manager_access: ServiceManagerAccess) -> Result<ServiceMa
     Ok(service_manager)
 }
 
-fn get_service(service_manager: &ServiceManager, service_access: ServiceAccess) -> Result<Service> {
+fn get_service(
+    service_manager: &ServiceManager,
+    name: &str,
+    service_access: ServiceAccess,
+) -> Result<Service> {
     service_manager
-        .open_service(SERVICE_NAME, service_access)
+        .open_service(name, service_access)
         .with_context(|| {
             format!(
                 "failed to get service '{}' with access {:?}",
-                SERVICE_NAME, service_access
+                name, service_access
             )
         })
 }
 
 fn get_service_opt(
     service_manager: &ServiceManager,
+    name: &str,
     service_access: ServiceAccess,
 ) -> Result<Option<Service>> {
-    match service_manager.open_service(SERVICE_NAME, service_access) {
+    match service_manager.open_service(name, service_access) {
         Ok(service) => Ok(Some(service)),
         Err(windows_service::Error::Winapi(e))
             if e.raw_os_error() == Some(ERROR_SERVICE_DOES_NOT_EXIST.0 as i32) =>
@@ -263,7 +390,7 @@ fn get_service_opt(
         Err(e) => Err(e).with_context(|| {
             format!(
                 "failed to get service '{}' with access {:?}",
-                SERVICE_NAME, service_access
+                name, service_access
             )
         }),
     }
@@ -274,6 +401,7 @@ fn get_service_opt(
 /// If the target state is `None`, the service will be waited until it is deleted.
 async fn wait_until_service_state_is(
     service: &Service,
+    name: &str,
     allowed_states: HashSet<ServiceState>,
     target_state: Option<ServiceState>,
     poll_interval: Duration,
@@ -290,8 +418,8 @@ async fn wait_until_service_state_is(
                 }
             }
         }
-        let status = status_result
-            .with_context(|| format!("failed to query service '{}' status", SERVICE_NAME))?;
+        let status =
+            status_result.with_context(|| format!("failed to query service '{}' status", name))?;
         if target_state.is_some() && status.current_state == target_state.unwrap() {
             // If the target state is `Some`, the service will be waited until it reaches the target state
             return Ok(());
@@ -299,7 +427,7 @@ async fn wait_until_service_state_is(
             // If the service is not in the allowed states, we return an error
             return Err(anyhow::anyhow!(
                 "service {} failed to reach state {:?} (unexpected current state: {:?})",
-                SERVICE_NAME,
+                name,
                 target_state,
                 status.current_state
             ));
@@ -309,7 +437,7 @@ async fn wait_until_service_state_is(
                 // If the timeout is reached, we return an error
                 return Err(anyhow::anyhow!(
                     "service {} timed out while waiting to reach state {:?} (current state: {:?})",
-                    SERVICE_NAME,
+                    name,
                     target_state,
                     status.current_state
                 ));
@@ -320,52 +448,56 @@ async fn wait_until_service_state_is(
     }
 }
 
-pub async fn unregister_if_exists() -> Result<()> {
+pub async fn unregister_if_exists(name: &str) -> Result<()> {
     let service_manager = get_service_manager(ServiceManagerAccess::CONNECT)?;
     if let Some(service) = get_service_opt(
         &service_manager,
+        name,
         ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE,
     )? {
-        unregister_common(service_manager, service).await?;
+        unregister_common(service_manager, service, name).await?;
     } else {
-        info!("Service '{}' does not exist", SERVICE_NAME);
+        info!("Service '{}' does not exist", name);
     }
     Ok(())
 }
 
-pub async fn unregister() -> Result<()> {
+pub async fn unregister(name: &str) -> Result<()> {
     let service_manager = get_service_manager(ServiceManagerAccess::CONNECT)?;
 
     let service = get_service(
         &service_manager,
+        name,
         ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE,
     )?;
-    unregister_common(service_manager, service).await?;
+    unregister_common(service_manager, service, name).await?;
     Ok(())
 }
 
 async fn unregister_common(
     service_manager: ServiceManager,
     service: Service,
+    name: &str,
 ) -> Result<(), anyhow::Error> {
-    info!("Deleting service '{}'", SERVICE_NAME);
+    info!("Deleting service '{}'", name);
     service
         .delete()
-        .with_context(|| format!("failed to delete service '{}'", SERVICE_NAME))?;
-    info!("Checking if service '{}' is stopped", SERVICE_NAME);
-    if query_status(&service)?.current_state != ServiceState::Stopped {
-        info!("Stopping service '{}'", SERVICE_NAME);
+        .with_context(|| format!("failed to delete service '{}'", name))?;
+    info!("Checking if service '{}' is stopped", name);
+    if query_status(&service, name)?.current_state != ServiceState::Stopped {
+        info!("Stopping service '{}'", name);
         service
             .stop()
-            .with_context(|| format!("failed to stop service '{}'", SERVICE_NAME))?;
+            .with_context(|| format!("failed to stop service '{}'", name))?;
     } else {
-        info!("Service '{}' is already stopped", SERVICE_NAME);
+        info!("Service '{}' is already stopped", name);
     }
     drop(service);
-    info!("Waiting for service '{}' to be deleted", SERVICE_NAME);
-    if let Some(service) = get_service_opt(&service_manager, ServiceAccess::QUERY_STATUS)? {
+    info!("Waiting for service '{}' to be deleted", name);
+    if let Some(service) = get_service_opt(&service_manager, name, ServiceAccess::QUERY_STATUS)? {
         wait_until_service_state_is(
             &service,
+            name,
             HashSet::from([ServiceState::StopPending, ServiceState::Stopped]),
             None,
             DEFAULT_POLL_INTERVAL,
@@ -373,7 +505,7 @@ async fn unregister_common(
         )
         .await?;
     }
-    info!("Service '{}' has been deleted", SERVICE_NAME);
+    info!("Service '{}' has been deleted", name);
     Ok(())
 }
 
@@ -401,7 +533,38 @@ pub fn get_user_name() -> Result<OsString> {
     }
 }
 
-pub async fn register(start: bool) -> Result<()> {
+/// Built-in service accounts, which are managed by Windows so don't have a password
+const BUILTIN_ACCOUNTS: [&str; 5] = [
+    "LocalSystem",
+    ".\\LocalSystem",
+    "NT AUTHORITY\\SYSTEM",
+    "NT AUTHORITY\\LocalService",
+    "NT AUTHORITY\\NetworkService",
+];
+
+pub fn is_builtin_account(account: &str) -> bool {
+    BUILTIN_ACCOUNTS
+        .iter()
+        .any(|builtin| builtin.eq_ignore_ascii_case(account))
+}
+
+/// Register the service, running as `account` (or the current user if `None`) with `password`.
+///
+/// A password is required for any account other than the current user or a built-in account.
+pub async fn register(
+    name: &str,
+    start: bool,
+    start_attempts: u32,
+    account: Option<&str>,
+    password: Option<&str>,
+) -> Result<()> {
+    if let Some(account) = account
+        && password.is_none()
+        && !is_builtin_account(account)
+    {
+        bail!("a password is required to run the service as '{}'", account);
+    }
+
     let service_manager =
         get_service_manager(ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE)?;
 
@@ -410,38 +573,54 @@ pub async fn register(start: bool) -> Result<()> {
 
     info!(
         "Registering service {}: {}",
-        SERVICE_NAME,
+        name,
         service_binary_path.display()
     );
-    let user_name = get_user_name()?;
+    let user_name = match account {
+        Some(account) => OsString::from(account),
+        None => get_user_name()?,
+    };
     info!("Registering as user: {}", user_name.display());
     let service_info = ServiceInfo {
-        name: OsString::from(SERVICE_NAME),
-        display_name: OsString::from(SERVICE_DISPLAY_NAME),
+        name: OsString::from(name),
+        display_name: OsString::from(display_name(name)),
         service_type: ServiceType::USER_OWN_PROCESS,
         start_type: ServiceStartType::AutoStart,
         error_control: ServiceErrorControl::Normal,
         executable_path: service_binary_path,
-        launch_arguments: vec!["service".into(), "run".into()],
+        launch_arguments: launch_arguments(name),
         dependencies: vec![],
         account_name: Some(user_name),
-        account_password: None,
+        account_password: password.map(OsString::from),
     };
     let service = service_manager
         .create_service(
             &service_info,
             ServiceAccess::CHANGE_CONFIG | ServiceAccess::QUERY_STATUS | ServiceAccess::START,
         )
-        .with_context(|| format!("failed to create service '{}'", SERVICE_NAME))?;
-    info!("Service '{}' registered", SERVICE_NAME);
+        .map_err(|e| {
+            let denied = is_access_denied_error(&e);
+            let e = access_denied_to_admin_error(e);
+            match account {
+                Some(account) if denied => e.context(format!(
+                    "the service would run as '{}', which can then drive that user's displays, \
+                     and its password is stored by the Service Control Manager; only do this \
+                     for an account you trust with Administrator access",
+                    account
+                )),
+                _ => e,
+            }
+        })
+        .with_context(|| format!("failed to create service '{}'", name))?;
+    info!("Service '{}' registered", name);
 
-    info!("Setting description for service '{}'", SERVICE_NAME);
+    info!("Setting description for service '{}'", name);
     service
         .set_description(SERVICE_DESCRIPTION)
-        .with_context(|| format!("failed to set description for service '{}'", SERVICE_NAME))?;
-    info!("Set description for service '{}'", SERVICE_NAME);
+        .with_context(|| format!("failed to set description for service '{}'", name))?;
+    info!("Set description for service '{}'", name);
 
-    info!("Setting failure actions for service '{}'", SERVICE_NAME);
+    info!("Setting failure actions for service '{}'", name);
     service
         .update_failure_actions(ServiceFailureActions {
             reset_period: ServiceFailureResetPeriod::After(Duration::from_secs(60 * 60)),
@@ -452,150 +631,240 @@ pub async fn register(start: bool) -> Result<()> {
                 delay: Duration::from_secs(60),
             }]),
         })
-        .with_context(|| {
-            format!(
-                "failed to set failure actions for service '{}'",
-                SERVICE_NAME
-            )
-        })?;
-    info!("Set failure actions for service '{}'", SERVICE_NAME);
+        .with_context(|| format!("failed to set failure actions for service '{}'", name))?;
+    info!("Set failure actions for service '{}'", name);
 
     if start {
-        start_common(&service).await
+        start_common(&service, name, start_attempts).await
     } else {
-        info!("Service '{}' registered but not started", SERVICE_NAME);
+        info!("Service '{}' registered but not started", name);
         Ok(())
     }
 }
 
-pub async fn start() -> Result<()> {
+pub async fn start(name: &str, start_attempts: u32) -> Result<()> {
     let service_manager = get_service_manager(ServiceManagerAccess::CONNECT)?;
     let service = get_service(
         &service_manager,
+        name,
         ServiceAccess::QUERY_STATUS | ServiceAccess::START,
     )?;
-    start_common(&service).await
+    start_common(&service, name, start_attempts).await
+}
+
+/// Returns whether an error from the SCM is likely to be transient
+fn is_retryable_service_error(error: &windows_service::Error) -> bool {
+    match error {
+        windows_service::Error::Winapi(e) => e.raw_os_error().is_some_and(|code| {
+            RETRYABLE_SERVICE_ERRORS
+                .iter()
+                .any(|retryable| retryable.0 as i32 == code)
+        }),
+        _ => false,
+    }
 }
 
-async fn start_common(service: &Service) -> Result<()> {
-    let current_state = query_status(&service)?.current_state;
+/// Whether the error is because the process doesn't have the rights to access the SCM or service
+fn is_access_denied_error(error: &windows_service::Error) -> bool {
+    match error {
+        windows_service::Error::Winapi(e) => e
+            .raw_os_error()
+            .is_some_and(|code| code == ERROR_ACCESS_DENIED.0 as i32),
+        _ => false,
+    }
+}
+
+/// Replace an access denied error from the SCM with one explaining that administrator rights are
+/// needed, as the raw Win32 error is hard to interpret
+fn access_denied_to_admin_error(error: windows_service::Error) -> anyhow::Error {
+    if is_access_denied_error(&error) {
+        anyhow::Error::new(error)
+            .context("access denied: this command must be run as Administrator (e.g. from an elevated terminal)")
+    } else {
+        error.into()
+    }
+}
+
+/// Whether the error is because the process wasn't started by the SCM, e.g. it was run interactively
+fn is_not_started_by_scm_error(error: &windows_service::Error) -> bool {
+    match error {
+        windows_service::Error::Winapi(e) => e
+            .raw_os_error()
+            .is_some_and(|code| code == ERROR_FAILED_SERVICE_CONTROLLER_CONNECT.0 as i32),
+        _ => false,
+    }
+}
+
+/// Starts the service, retrying with exponential backoff if the SCM returns a transient error
+async fn start_with_retry(service: &Service, name: &str, attempts: u32) -> Result<()> {
+    let mut delay = DEFAULT_START_RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        match service.start::<&str>(&[]) {
+            Ok(()) => return Ok(()),
+            Err(e) if attempt < attempts && is_retryable_service_error(&e) => {
+                warn!(
+                    "Failed to start service '{}' (attempt {}/{}), retrying in {:?}: {}",
+                    name, attempt, attempts, delay, e
+                );
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to start service '{}'", name));
+            }
+        }
+    }
+}
+
+async fn start_common(service: &Service, name: &str, start_attempts: u32) -> Result<()> {
+    let current_state = query_status(service, name)?.current_state;
     if current_state == ServiceState::Running {
-        info!("Service '{}' is already running", SERVICE_NAME);
+        info!("Service '{}' is already running", name);
         return Ok(());
     } else if current_state == ServiceState::StartPending {
-        info!("Service '{}' is already starting", SERVICE_NAME);
+        info!("Service '{}' is already starting", name);
     } else {
-        info!("Starting service '{}'", SERVICE_NAME);
-        service
-            .start::<&str>(&[])
-            .with_context(|| format!("failed to start service '{}'", SERVICE_NAME))?;
+        info!("Starting service '{}'", name);
+        start_with_retry(service, name, start_attempts).await?;
     }
-    info!("Waiting for service '{}' to start", SERVICE_NAME);
+    info!("Waiting for service '{}' to start", name);
     wait_until_service_state_is(
-        &service,
+        service,
+        name,
         HashSet::from([ServiceState::StartPending]),
         Some(ServiceState::Running),
         DEFAULT_POLL_INTERVAL,
         DEFAULT_TIMEOUT,
     )
     .await?;
-    info!("Service '{}' started", SERVICE_NAME);
+    info!("Service '{}' started", name);
     Ok(())
 }
 
-pub async fn stop() -> Result<()> {
+pub async fn stop(name: &str) -> Result<()> {
     let service_manager = get_service_manager(ServiceManagerAccess::CONNECT)?;
     let service = get_service(
         &service_manager,
+        name,
         ServiceAccess::QUERY_STATUS | ServiceAccess::STOP,
     )?;
-    stop_common(&service).await
+    stop_common(&service, name).await
 }
 
-async fn stop_common(service: &Service) -> Result<()> {
-    let current_state = query_status(&service)?.current_state;
+async fn stop_common(service: &Service, name: &str) -> Result<()> {
+    let current_state = query_status(service, name)?.current_state;
     if current_state == ServiceState::Stopped {
-        info!("Service '{}' is already stopped", SERVICE_NAME);
+        info!("Service '{}' is already stopped", name);
         return Ok(());
     } else if current_state == ServiceState::StopPending {
-        info!("Service '{}' is already stopping", SERVICE_NAME);
+        info!("Service '{}' is already stopping", name);
     } else {
-        info!("Stopping service '{}'", SERVICE_NAME);
+        info!("Stopping service '{}'", name);
         service
             .stop()
-            .with_context(|| format!("failed to stop service '{}'", SERVICE_NAME))?;
+            .with_context(|| format!("failed to stop service '{}'", name))?;
     }
-    info!("Waiting for service '{}' to stop", SERVICE_NAME);
+    info!("Waiting for service '{}' to stop", name);
     wait_until_service_state_is(
-        &service,
+        service,
+        name,
         HashSet::from([ServiceState::Running, ServiceState::StopPending]),
         Some(ServiceState::Stopped),
         DEFAULT_POLL_INTERVAL,
         DEFAULT_TIMEOUT,
     )
     .await?;
-    info!("Service '{}' stopped", SERVICE_NAME);
+    info!("Service '{}' stopped", name);
     Ok(())
 }
 
-pub async fn restart() -> Result<()> {
+pub async fn restart(name: &str, start_attempts: u32) -> Result<()> {
     let service_manager = get_service_manager(ServiceManagerAccess::CONNECT)?;
     let service = get_service(
         &service_manager,
+        name,
         ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::START,
     )?;
-    let current_status = query_status(&service)?;
+    let current_status = query_status(&service, name)?;
     match current_status.current_state {
         ServiceState::StartPending | ServiceState::Running => {
-            stop_common(&service).await?;
-            start_common(&service).await?;
+            stop_common(&service, name).await?;
+            start_common(&service, name, start_attempts).await?;
         }
         ServiceState::StopPending => {
-            info!("Waiting for service '{}' to stop", SERVICE_NAME);
+            info!("Waiting for service '{}' to stop", name);
             wait_until_service_state_is(
                 &service,
+                name,
                 HashSet::from([ServiceState::StopPending]),
                 Some(ServiceState::Stopped),
                 DEFAULT_POLL_INTERVAL,
                 DEFAULT_TIMEOUT,
             )
             .await?;
-            info!("Service '{}' stopped", SERVICE_NAME);
-            start_common(&service).await?;
+            info!("Service '{}' stopped", name);
+            start_common(&service, name, start_attempts).await?;
         }
         ServiceState::Stopped => {
-            info!("Service '{}' is already stopped", SERVICE_NAME);
-            start_common(&service).await?;
+            info!("Service '{}' is already stopped", name);
+            start_common(&service, name, start_attempts).await?;
         }
         _ => {
             return Err(anyhow::anyhow!(
                 "service {} failed to restart (unexpected current state: {:?})",
-                SERVICE_NAME,
+                name,
                 current_status.current_state
             ));
         }
     }
-    info!("Service '{}' restarted", SERVICE_NAME);
+    info!("Service '{}' restarted", name);
     Ok(())
 }
 
-pub async fn status() -> Result<Option<ServiceStatus>> {
+pub async fn status(name: &str) -> Result<Option<ServiceStatus>> {
     let service_manager = get_service_manager(ServiceManagerAccess::CONNECT)?;
-    let service = get_service_opt(&service_manager, ServiceAccess::QUERY_STATUS)?;
+    let service = get_service_opt(&service_manager, name, ServiceAccess::QUERY_STATUS)?;
     if let Some(service) = service {
-        query_status_opt(&service)
+        query_status_opt(&service, name)
     } else {
         Ok(None)
     }
 }
 
-fn query_status(service: &Service) -> Result<ServiceStatus> {
+/// Poll the state of the service, calling `on_change` with the initial state and then whenever it
+/// changes, until Ctrl-C is pressed. A state of `None` means the service isn't registered.
+pub async fn watch_status(
+    name: &str,
+    mut on_change: impl FnMut(Option<ServiceState>),
+) -> Result<()> {
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    let mut last_state = None;
+    loop {
+        let state = status(name).await?.map(|status| status.current_state);
+        if last_state != Some(state) {
+            on_change(state);
+            last_state = Some(state);
+        }
+        tokio::select! {
+            result = &mut ctrl_c => {
+                result.context("failed to listen for Ctrl-C")?;
+                return Ok(());
+            }
+            _ = tokio::time::sleep(DEFAULT_POLL_INTERVAL) => {}
+        }
+    }
+}
+
+fn query_status(service: &Service, name: &str) -> Result<ServiceStatus> {
     service
         .query_status()
-        .with_context(|| format!("failed to query service '{}' status", SERVICE_NAME))
+        .with_context(|| format!("failed to query service '{}' status", name))
 }
 
-fn query_status_opt(service: &Service) -> Result<Option<ServiceStatus>> {
+fn query_status_opt(service: &Service, name: &str) -> Result<Option<ServiceStatus>> {
     let result = service.query_status();
     if let Err(windows_service::Error::Winapi(e)) = &result {
         if e.raw_os_error() == Some(ERROR_SERVICE_DOES_NOT_EXIST.0 as i32) {
@@ -603,6 +872,158 @@ fn query_status_opt(service: &Service) -> Result<Option<ServiceStatus>> {
         }
     }
     result
-        .with_context(|| format!("failed to query service '{}' status", SERVICE_NAME))
+        .with_context(|| format!("failed to query service '{}' status", name))
         .map(|s| Some(s))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            Arc, Mutex,
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            mpsc,
+        },
+        time::Duration,
+    };
+
+    use windows::Win32::Foundation::{ERROR_ACCESS_DENIED, ERROR_SERVICE_DATABASE_LOCKED};
+
+    use super::{
+        DEFAULT_SERVICE_NAME, ShutdownNotify, display_name, is_builtin_account,
+        is_retryable_service_error, launch_arguments, notify_shutdown,
+    };
+
+    /// Counts the shutdown notifications it receives
+    struct CountingShutdown(Arc<AtomicUsize>);
+
+    impl ShutdownNotify for CountingShutdown {
+        fn notify_shutdown(&self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Run `f` on another thread, failing if it doesn't finish in time (i.e. it deadlocked)
+    fn run_with_timeout(f: impl FnOnce() + Send + 'static) {
+        let (sender, receiver) = mpsc::channel();
+        let handle = std::thread::spawn(move || {
+            f();
+            let _ = sender.send(());
+        });
+        match receiver.recv_timeout(Duration::from_secs(30)) {
+            Ok(()) => handle.join().expect("test thread panicked"),
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                std::panic::resume_unwind(handle.join().expect_err("test thread didn't panic"))
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => panic!("deadlocked"),
+        }
+    }
+
+    #[test]
+    fn test_notify_shutdown_uncontended() {
+        static SLOT: Mutex<Option<CountingShutdown>> = Mutex::new(None);
+        let count = Arc::new(AtomicUsize::new(0));
+        // Nothing to notify before the shutdown handle is set
+        assert!(notify_shutdown(&SLOT).is_none());
+        SLOT.lock()
+            .unwrap()
+            .replace(CountingShutdown(count.clone()));
+        assert!(notify_shutdown(&SLOT).is_none());
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_notify_shutdown_contended() {
+        static SLOT: Mutex<Option<CountingShutdown>> = Mutex::new(None);
+        let count = Arc::new(AtomicUsize::new(0));
+        let count_clone = count.clone();
+        run_with_timeout(move || {
+            // Hold the lock while setting the handle, as when rocket is being built
+            let mut lock = SLOT.lock().unwrap();
+            let handle = notify_shutdown(&SLOT).expect("should fall back to a thread");
+            lock.replace(CountingShutdown(count_clone.clone()));
+            assert_eq!(count_clone.load(Ordering::SeqCst), 0);
+            drop(lock);
+            handle.join().unwrap();
+        });
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_notify_shutdown_concurrent() {
+        const THREADS: usize = 8;
+        const NOTIFICATIONS: usize = 100;
+        static SLOT: Mutex<Option<CountingShutdown>> = Mutex::new(None);
+        let count = Arc::new(AtomicUsize::new(0));
+        SLOT.lock()
+            .unwrap()
+            .replace(CountingShutdown(count.clone()));
+        run_with_timeout(|| {
+            // Contend the lock from another thread while notifying
+            let stop = Arc::new(AtomicBool::new(false));
+            let contender = {
+                let stop = stop.clone();
+                std::thread::spawn(move || {
+                    while !stop.load(Ordering::SeqCst) {
+                        let _lock = SLOT.lock().unwrap();
+                        std::thread::yield_now();
+                    }
+                })
+            };
+            let notifiers: Vec<_> = (0..THREADS)
+                .map(|_| {
+                    std::thread::spawn(|| {
+                        (0..NOTIFICATIONS)
+                            .filter_map(|_| notify_shutdown(&SLOT))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            for notifier in notifiers {
+                for handle in notifier.join().unwrap() {
+                    handle.join().unwrap();
+                }
+            }
+            stop.store(true, Ordering::SeqCst);
+            contender.join().unwrap();
+        });
+        // Each notification is delivered exactly once, whether directly or via a thread
+        assert_eq!(count.load(Ordering::SeqCst), THREADS * NOTIFICATIONS);
+    }
+
+    fn winapi_error(code: u32) -> windows_service::Error {
+        windows_service::Error::Winapi(std::io::Error::from_raw_os_error(code as i32))
+    }
+
+    #[test]
+    fn test_is_retryable_service_error() {
+        assert!(is_retryable_service_error(&winapi_error(
+            ERROR_SERVICE_DATABASE_LOCKED.0
+        )));
+        assert!(!is_retryable_service_error(&winapi_error(
+            ERROR_ACCESS_DENIED.0
+        )));
+        assert!(!is_retryable_service_error(
+            &windows_service::Error::LaunchArgumentsNotSupported
+        ));
+    }
+
+    #[test]
+    fn test_is_builtin_account() {
+        assert!(is_builtin_account("LocalSystem"));
+        assert!(is_builtin_account("nt authority\\localservice"));
+        assert!(!is_builtin_account("DESKTOP\\alice"));
+        assert!(!is_builtin_account("NT AUTHORITY"));
+    }
+
+    #[test]
+    fn test_service_name() {
+        assert_eq!(display_name(DEFAULT_SERVICE_NAME), "Hagias Monitor Service");
+        assert_eq!(display_name("alice"), "Hagias Monitor Service (alice)");
+        assert_eq!(launch_arguments(DEFAULT_SERVICE_NAME), ["service", "run"]);
+        assert_eq!(
+            launch_arguments("alice"),
+            ["service", "run", "--name", "alice"]
+        );
+    }
+}
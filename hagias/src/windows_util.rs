@@ -1,14 +1,18 @@
 use std::{
-    collections::{HashMap, HashSet, hash_map},
-    ffi::OsString,
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet, btree_map},
+    ffi::{OsStr, OsString},
     fmt,
     hash::{Hash, Hasher},
     os::windows::ffi::OsStringExt,
+    sync::OnceLock,
 };
 
-use anyhow::{Result, anyhow, bail};
+use anyhow::{Context, Result, anyhow, bail};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use tracing::debug;
+use serde_json::json;
+use tracing::{debug, warn};
 use unit_enum::UnitEnum;
 use windows::{
     Wdk::Graphics::Direct3D::{
@@ -22,11 +26,16 @@ use windows::{
         D3DKMDT_VSS_SECAM_L, D3DKMDT_VSS_SECAM_L1, D3DKMDT_VSS_UNINITIALIZED, D3DKMDT_VSS_VESA_CVT,
         D3DKMDT_VSS_VESA_DMT, D3DKMDT_VSS_VESA_GTF,
     },
+    Wdk::System::SystemServices::RtlGetVersion,
     Win32::{
         Devices::Display::{
             DISPLAYCONFIG_2DREGION, DISPLAYCONFIG_ADAPTER_NAME,
-            DISPLAYCONFIG_DEVICE_INFO_GET_ADAPTER_NAME, DISPLAYCONFIG_DEVICE_INFO_GET_SOURCE_NAME,
-            DISPLAYCONFIG_DEVICE_INFO_GET_TARGET_NAME, DISPLAYCONFIG_DEVICE_INFO_HEADER,
+            DISPLAYCONFIG_DEVICE_INFO_GET_ADAPTER_NAME,
+            DISPLAYCONFIG_DEVICE_INFO_GET_ADVANCED_COLOR_INFO,
+            DISPLAYCONFIG_DEVICE_INFO_GET_SDR_WHITE_LEVEL,
+            DISPLAYCONFIG_DEVICE_INFO_GET_SOURCE_NAME, DISPLAYCONFIG_DEVICE_INFO_GET_TARGET_NAME,
+            DISPLAYCONFIG_DEVICE_INFO_HEADER, DISPLAYCONFIG_DEVICE_INFO_SET_ADVANCED_COLOR_STATE,
+            DISPLAYCONFIG_DEVICE_INFO_TYPE, DISPLAYCONFIG_GET_ADVANCED_COLOR_INFO,
             DISPLAYCONFIG_MODE_INFO, DISPLAYCONFIG_MODE_INFO_TYPE_DESKTOP_IMAGE,
             DISPLAYCONFIG_MODE_INFO_TYPE_SOURCE, DISPLAYCONFIG_MODE_INFO_TYPE_TARGET,
             DISPLAYCONFIG_OUTPUT_TECHNOLOGY_COMPONENT_VIDEO,
@@ -55,36 +64,59 @@ use windows::{
             DISPLAYCONFIG_SCANLINE_ORDERING_INTERLACED_LOWERFIELDFIRST,
             DISPLAYCONFIG_SCANLINE_ORDERING_INTERLACED_UPPERFIELDFIRST,
             DISPLAYCONFIG_SCANLINE_ORDERING_PROGRESSIVE,
-            DISPLAYCONFIG_SCANLINE_ORDERING_UNSPECIFIED, DISPLAYCONFIG_SOURCE_DEVICE_NAME,
+            DISPLAYCONFIG_SCANLINE_ORDERING_UNSPECIFIED, DISPLAYCONFIG_SDR_WHITE_LEVEL,
+            DISPLAYCONFIG_SET_ADVANCED_COLOR_STATE, DISPLAYCONFIG_SOURCE_DEVICE_NAME,
             DISPLAYCONFIG_TARGET_DEVICE_NAME, DISPLAYCONFIG_TARGET_DEVICE_NAME_FLAGS,
-            DISPLAYCONFIG_TOPOLOGY_ID, DISPLAYCONFIG_VIDEO_OUTPUT_TECHNOLOGY,
-            DisplayConfigGetDeviceInfo, GetDisplayConfigBufferSizes, QDC_ALL_PATHS,
-            QDC_DATABASE_CURRENT, QDC_ONLY_ACTIVE_PATHS, QUERY_DISPLAY_CONFIG_FLAGS,
-            QueryDisplayConfig, SDC_APPLY, SDC_SAVE_TO_DATABASE, SDC_USE_SUPPLIED_DISPLAY_CONFIG,
-            SetDisplayConfig,
+            DISPLAYCONFIG_TOPOLOGY_CLONE, DISPLAYCONFIG_TOPOLOGY_EXTEND,
+            DISPLAYCONFIG_TOPOLOGY_EXTERNAL, DISPLAYCONFIG_TOPOLOGY_ID,
+            DISPLAYCONFIG_TOPOLOGY_INTERNAL, DISPLAYCONFIG_VIDEO_OUTPUT_TECHNOLOGY,
+            DisplayConfigGetDeviceInfo, DisplayConfigSetDeviceInfo, GetDisplayConfigBufferSizes,
+            QDC_ALL_PATHS, QDC_DATABASE_CURRENT, QDC_ONLY_ACTIVE_PATHS, QDC_VIRTUAL_MODE_AWARE,
+            QUERY_DISPLAY_CONFIG_FLAGS, QueryDisplayConfig, SDC_ALLOW_CHANGES, SDC_APPLY,
+            SDC_SAVE_TO_DATABASE, SDC_TOPOLOGY_CLONE, SDC_TOPOLOGY_EXTEND, SDC_TOPOLOGY_EXTERNAL,
+            SDC_TOPOLOGY_INTERNAL, SDC_USE_SUPPLIED_DISPLAY_CONFIG, SDC_VALIDATE,
+            SDC_VIRTUAL_MODE_AWARE, SET_DISPLAY_CONFIG_FLAGS, SetDisplayConfig,
         },
         Foundation::{
-            ERROR_INSUFFICIENT_BUFFER, ERROR_SUCCESS, HLOCAL, LocalFree, POINTL, WIN32_ERROR,
+            CloseHandle, ERROR_ACCESS_DENIED, ERROR_INSUFFICIENT_BUFFER, ERROR_SUCCESS, HANDLE,
+            HLOCAL, LocalFree, POINTL, RECTL, WIN32_ERROR,
         },
         Graphics::Gdi::{
-            DISPLAYCONFIG_PATH_ACTIVE, DISPLAYCONFIG_PATH_CLONE_GROUP_INVALID,
+            DEVMODEW, DISPLAYCONFIG_PATH_ACTIVE, DISPLAYCONFIG_PATH_CLONE_GROUP_INVALID,
             DISPLAYCONFIG_PATH_DESKTOP_IMAGE_IDX_INVALID, DISPLAYCONFIG_PATH_MODE_IDX_INVALID,
             DISPLAYCONFIG_PATH_SOURCE_MODE_IDX_INVALID, DISPLAYCONFIG_PATH_SUPPORT_VIRTUAL_MODE,
             DISPLAYCONFIG_PATH_TARGET_MODE_IDX_INVALID, DISPLAYCONFIG_SOURCE_IN_USE,
             DISPLAYCONFIG_TARGET_FORCED_AVAILABILITY_BOOT,
             DISPLAYCONFIG_TARGET_FORCED_AVAILABILITY_PATH,
             DISPLAYCONFIG_TARGET_FORCED_AVAILABILITY_SYSTEM, DISPLAYCONFIG_TARGET_FORCIBLE,
-            DISPLAYCONFIG_TARGET_IN_USE, DISPLAYCONFIG_TARGET_IS_HMD,
+            DISPLAYCONFIG_TARGET_IN_USE, DISPLAYCONFIG_TARGET_IS_HMD, ENUM_DISPLAY_SETTINGS_MODE,
+            EnumDisplaySettingsW,
         },
-        System::Diagnostics::Debug::{
-            FORMAT_MESSAGE_ALLOCATE_BUFFER, FORMAT_MESSAGE_FROM_SYSTEM,
-            FORMAT_MESSAGE_IGNORE_INSERTS, FormatMessageW,
+        Security::{GetTokenInformation, TOKEN_ELEVATION, TOKEN_QUERY, TokenElevation},
+        System::{
+            Console::{
+                CONSOLE_MODE, ENABLE_ECHO_INPUT, GetConsoleMode, GetStdHandle, STD_INPUT_HANDLE,
+                SetConsoleMode,
+            },
+            Diagnostics::Debug::{
+                FORMAT_MESSAGE_ALLOCATE_BUFFER, FORMAT_MESSAGE_FROM_SYSTEM,
+                FORMAT_MESSAGE_IGNORE_INSERTS, FormatMessageW,
+            },
+            SystemInformation::OSVERSIONINFOW,
+            Threading::{GetCurrentProcess, OpenProcessToken},
         },
     },
-    core::PWSTR,
+    core::{PCWSTR, PWSTR},
 };
 
-use crate::display::DisplayTargetMode;
+use crate::{display::DisplayTargetMode, edid};
+
+/// Emit a formatted line to an output function
+macro_rules! emit {
+    ($out:expr, $($arg:tt)*) => {
+        ($out)(format_args!($($arg)*))
+    };
+}
 
 pub fn windows_error_to_string(error: WIN32_ERROR) -> String {
     use winapi::um::winnt::LANG_NEUTRAL;
@@ -109,7 +141,121 @@ pub fn windows_error_to_string(error: WIN32_ERROR) -> String {
         }
         let string = OsString::from_wide(std::slice::from_raw_parts(error_text.0, num_chars as _));
         LocalFree(Some(HLOCAL(error_text.0 as *mut _)));
-        format!("0x{:x} {}", error.0, string.display())
+        // The message ends with a CRLF, which makes errors span multiple lines
+        format!(
+            "0x{:x} {}",
+            error.0,
+            string.display().to_string().trim_end()
+        )
+    }
+}
+
+/// Turn the result of `SetDisplayConfig` into an error, explaining access denied errors
+fn check_set_display_config_result(result: i32) -> Result<()> {
+    if result as i64 == ERROR_SUCCESS.0 as i64 {
+        return Ok(());
+    }
+    let error = WIN32_ERROR(result as u32);
+    if error == ERROR_ACCESS_DENIED {
+        // Unlike the service commands, running as Administrator doesn't help here
+        bail!(
+            "SetDisplayConfig error: {} (the display configuration can only be changed from the \
+             interactive console session, and not while the workstation is locked or from a \
+             remote desktop session)",
+            windows_error_to_string(error)
+        );
+    }
+    bail!("SetDisplayConfig error: {}", windows_error_to_string(error));
+}
+
+/// [`SDC_VIRTUAL_MODE_AWARE`] if any of `paths` packs its mode indices for virtual modes, so that
+/// Windows unpacks them
+fn virtual_mode_flags(paths: &[DISPLAYCONFIG_PATH_INFO]) -> SET_DISPLAY_CONFIG_FLAGS {
+    if paths
+        .iter()
+        .any(|path| path.flags & DISPLAYCONFIG_PATH_SUPPORT_VIRTUAL_MODE != 0)
+    {
+        SDC_VIRTUAL_MODE_AWARE
+    } else {
+        SET_DISPLAY_CONFIG_FLAGS(0)
+    }
+}
+
+/// Whether the current process is running with administrator rights
+pub fn is_elevated() -> Result<bool> {
+    unsafe {
+        let mut token = HANDLE::default();
+        OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token)
+            .context("failed to open process token")?;
+        let mut elevation = TOKEN_ELEVATION::default();
+        let mut size = 0;
+        let result = GetTokenInformation(
+            token,
+            TokenElevation,
+            Some(&mut elevation as *mut TOKEN_ELEVATION as *mut _),
+            size_of::<TOKEN_ELEVATION>() as u32,
+            &mut size,
+        );
+        let _ = CloseHandle(token);
+        result.context("failed to get token elevation")?;
+        Ok(elevation.TokenIsElevated != 0)
+    }
+}
+
+/// Read a line from stdin without echoing it to the console, e.g. a password. If stdin isn't a
+/// console, it is read as is.
+pub fn read_line_without_echo() -> Result<String> {
+    let input = unsafe { GetStdHandle(STD_INPUT_HANDLE) }.context("failed to get stdin handle")?;
+    let mut mode = CONSOLE_MODE::default();
+    let is_console = unsafe { GetConsoleMode(input, &mut mode) }.is_ok();
+    if is_console {
+        unsafe { SetConsoleMode(input, mode & !ENABLE_ECHO_INPUT) }
+            .context("failed to turn off console echo")?;
+    }
+    let mut line = String::new();
+    let result = std::io::stdin().read_line(&mut line);
+    if is_console {
+        let _ = unsafe { SetConsoleMode(input, mode) };
+    }
+    result.context("failed to read from stdin")?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// One of the default topologies that Windows can compute by itself, i.e. the projection modes
+/// of the Win+P menu
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum DisplayTopology {
+    /// Only the internal display ("PC screen only")
+    #[value(alias = "pc-screen-only")]
+    Internal,
+    /// Clone the primary display to all displays ("Duplicate")
+    #[value(alias = "duplicate")]
+    Clone,
+    /// Extend the desktop across all displays ("Extend")
+    Extend,
+    /// Only the external displays ("Second screen only")
+    #[value(alias = "second-screen-only")]
+    External,
+}
+
+impl DisplayTopology {
+    pub fn from_topology_id(topology_id: DISPLAYCONFIG_TOPOLOGY_ID) -> Option<Self> {
+        match topology_id {
+            DISPLAYCONFIG_TOPOLOGY_INTERNAL => Some(DisplayTopology::Internal),
+            DISPLAYCONFIG_TOPOLOGY_CLONE => Some(DisplayTopology::Clone),
+            DISPLAYCONFIG_TOPOLOGY_EXTEND => Some(DisplayTopology::Extend),
+            DISPLAYCONFIG_TOPOLOGY_EXTERNAL => Some(DisplayTopology::External),
+            _ => None,
+        }
+    }
+
+    pub fn to_flags(self) -> SET_DISPLAY_CONFIG_FLAGS {
+        match self {
+            DisplayTopology::Internal => SDC_TOPOLOGY_INTERNAL,
+            DisplayTopology::Clone => SDC_TOPOLOGY_CLONE,
+            DisplayTopology::Extend => SDC_TOPOLOGY_EXTEND,
+            DisplayTopology::External => SDC_TOPOLOGY_EXTERNAL,
+        }
     }
 }
 
@@ -121,11 +267,18 @@ pub enum DisplayQueryType {
 }
 
 impl DisplayQueryType {
+    /// The flags to query with. Where supported, paths are queried with virtual modes, so that
+    /// desktop image modes (e.g. per-monitor scaling) are captured too.
     pub fn to_flags(self) -> QUERY_DISPLAY_CONFIG_FLAGS {
-        match self {
+        let flags = match self {
             DisplayQueryType::All => QDC_ALL_PATHS,
             DisplayQueryType::Active => QDC_ONLY_ACTIVE_PATHS,
             DisplayQueryType::Database => QDC_DATABASE_CURRENT,
+        };
+        if OsVersion::get() >= OsVersion::WINDOWS_10_1703 {
+            flags | QDC_VIRTUAL_MODE_AWARE
+        } else {
+            flags
         }
     }
 }
@@ -140,6 +293,16 @@ impl Hash for LuidWrapper {
     }
 }
 impl Eq for LuidWrapper {}
+impl PartialOrd for LuidWrapper {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for LuidWrapper {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.0.HighPart, self.0.LowPart).cmp(&(other.0.HighPart, other.0.LowPart))
+    }
+}
 impl From<windows::Win32::Foundation::LUID> for LuidWrapper {
     fn from(luid: windows::Win32::Foundation::LUID) -> Self {
         LuidWrapper(luid)
@@ -151,18 +314,25 @@ impl From<LuidWrapper> for windows::Win32::Foundation::LUID {
     }
 }
 
-#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+/// Ordered by adapter first, then by ID, so that sorted output groups devices by adapter
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct IdAndAdapterId {
-    pub id: u32,
     pub adapter_id: LuidWrapper,
+    pub id: u32,
 }
 
+/// The raw Windows display configuration, plus the device names referenced by it.
+///
+/// The device name maps are ordered so that iterating over them (e.g. when dumping the
+/// configuration) is reproducible between runs.
 pub struct WindowsDisplayConfig {
     pub paths: Vec<DISPLAYCONFIG_PATH_INFO>,
     pub modes: Vec<DISPLAYCONFIG_MODE_INFO>,
-    pub adapter_device_names: HashMap<LuidWrapper, OsString>,
-    pub source_device_names: HashMap<IdAndAdapterId, DISPLAYCONFIG_SOURCE_DEVICE_NAME>,
-    pub target_device_names: HashMap<IdAndAdapterId, DISPLAYCONFIG_TARGET_DEVICE_NAME>,
+    pub adapter_device_names: BTreeMap<LuidWrapper, OsString>,
+    pub source_device_names: BTreeMap<IdAndAdapterId, DISPLAYCONFIG_SOURCE_DEVICE_NAME>,
+    pub target_device_names: BTreeMap<IdAndAdapterId, DISPLAYCONFIG_TARGET_DEVICE_NAME>,
+    /// The topology of the configuration, only known when querying the database
+    pub topology: Option<DisplayTopology>,
 }
 
 impl WindowsDisplayConfig {
@@ -198,7 +368,7 @@ impl WindowsDisplayConfig {
                     paths.as_mut_ptr(),
                     &mut num_modes,
                     modes.as_mut_ptr(),
-                    if query_flags == QDC_DATABASE_CURRENT {
+                    if matches!(query, DisplayQueryType::Database) {
                         Some(&mut current_topology_id)
                     } else {
                         None
@@ -217,7 +387,13 @@ impl WindowsDisplayConfig {
                 paths.set_len(num_paths as usize);
                 modes.set_len(num_modes as usize);
 
-                return Ok(WindowsDisplayConfig::from_paths_and_modes(paths, modes)?);
+                let mut windows_display_config =
+                    WindowsDisplayConfig::from_paths_and_modes(paths, modes)?;
+                if matches!(query, DisplayQueryType::Database) {
+                    windows_display_config.topology =
+                        DisplayTopology::from_topology_id(current_topology_id);
+                }
+                return Ok(windows_display_config);
             }
         }
     }
@@ -235,8 +411,8 @@ impl WindowsDisplayConfig {
                     path.targetInfo.adapterId.into(),
                 ]
             }))
-            .collect::<HashSet<LuidWrapper>>();
-        let ids_and_adapter_ids: HashSet<IdAndAdapterId> = modes
+            .collect::<BTreeSet<LuidWrapper>>();
+        let ids_and_adapter_ids: BTreeSet<IdAndAdapterId> = modes
             .iter()
             .map(|m| IdAndAdapterId {
                 id: m.id,
@@ -256,21 +432,21 @@ impl WindowsDisplayConfig {
             }))
             .collect();
 
-        let mut adapter_device_names = HashMap::new();
+        let mut adapter_device_names = BTreeMap::new();
         for adapter_id in adapter_ids {
             match adapter_device_names.entry(adapter_id) {
-                hash_map::Entry::Vacant(entry) => {
+                btree_map::Entry::Vacant(entry) => {
                     entry.insert(get_adapter_device_path(adapter_id.into())?);
                 }
-                hash_map::Entry::Occupied(_) => {}
+                btree_map::Entry::Occupied(_) => {}
             }
         }
 
-        let mut source_device_names: HashMap<IdAndAdapterId, DISPLAYCONFIG_SOURCE_DEVICE_NAME> =
-            HashMap::new();
+        let mut source_device_names: BTreeMap<IdAndAdapterId, DISPLAYCONFIG_SOURCE_DEVICE_NAME> =
+            BTreeMap::new();
         for id_and_adapter_id in ids_and_adapter_ids.iter().copied() {
             match source_device_names.entry(id_and_adapter_id) {
-                hash_map::Entry::Vacant(entry) => {
+                btree_map::Entry::Vacant(entry) => {
                     if let Ok(source_device_name) = get_source_device_name(
                         id_and_adapter_id.id,
                         id_and_adapter_id.adapter_id.into(),
@@ -278,15 +454,15 @@ impl WindowsDisplayConfig {
                         entry.insert(source_device_name);
                     }
                 }
-                hash_map::Entry::Occupied(_) => {}
+                btree_map::Entry::Occupied(_) => {}
             }
         }
 
-        let mut target_device_names: HashMap<IdAndAdapterId, DISPLAYCONFIG_TARGET_DEVICE_NAME> =
-            HashMap::new();
+        let mut target_device_names: BTreeMap<IdAndAdapterId, DISPLAYCONFIG_TARGET_DEVICE_NAME> =
+            BTreeMap::new();
         for id_and_adapter_id in ids_and_adapter_ids.iter().copied() {
             match target_device_names.entry(id_and_adapter_id) {
-                hash_map::Entry::Vacant(entry) => {
+                btree_map::Entry::Vacant(entry) => {
                     if let Ok(target_device_name) = get_target_device_name(
                         id_and_adapter_id.id,
                         id_and_adapter_id.adapter_id.into(),
@@ -294,7 +470,7 @@ impl WindowsDisplayConfig {
                         entry.insert(target_device_name);
                     }
                 }
-                hash_map::Entry::Occupied(_) => {}
+                btree_map::Entry::Occupied(_) => {}
             }
         }
 
@@ -304,32 +480,141 @@ impl WindowsDisplayConfig {
             adapter_device_names,
             source_device_names,
             target_device_names,
+            topology: None,
         })
     }
 
     pub fn apply(&self, save_to_database: bool) -> Result<()> {
         unsafe {
-            let mut flags = SDC_APPLY | SDC_USE_SUPPLIED_DISPLAY_CONFIG;
+            let mut flags =
+                SDC_APPLY | SDC_USE_SUPPLIED_DISPLAY_CONFIG | virtual_mode_flags(&self.paths);
             if save_to_database {
                 flags |= SDC_SAVE_TO_DATABASE;
             }
             let result = SetDisplayConfig(Some(&self.paths), Some(&self.modes), flags);
-            if result as i64 != ERROR_SUCCESS.0 as i64 {
-                bail!(
-                    "SetDisplayConfig error: {}",
-                    windows_error_to_string(WIN32_ERROR(result as u32))
-                );
+            check_set_display_config_result(result)
+        }
+    }
+
+    /// Check whether Windows would accept this configuration, without applying it
+    pub fn validate(&self) -> Result<()> {
+        unsafe {
+            let result = SetDisplayConfig(
+                Some(&self.paths),
+                Some(&self.modes),
+                SDC_VALIDATE | SDC_USE_SUPPLIED_DISPLAY_CONFIG | virtual_mode_flags(&self.paths),
+            );
+            check_set_display_config_result(result)
+        }
+    }
+
+    /// Set the display configuration to one of Windows' default topologies, letting Windows
+    /// choose the paths and modes. Windows persists the topology by itself, as
+    /// `SDC_SAVE_TO_DATABASE` is only valid with a supplied configuration.
+    pub fn set_topology(topology: DisplayTopology) -> Result<()> {
+        unsafe {
+            let result = SetDisplayConfig(None, None, SDC_APPLY | topology.to_flags());
+            check_set_display_config_result(result)
+        }
+    }
+
+    /// Activate a connected target alongside the active paths, leaving them as they are. The
+    /// target is driven by a source that isn't already in use, and Windows chooses its modes and
+    /// position. `all` must be queried with [`DisplayQueryType::All`].
+    pub fn enable_target(
+        all: &WindowsDisplayConfig,
+        target: IdAndAdapterId,
+        save_to_database: bool,
+    ) -> Result<()> {
+        let active = Self::get(DisplayQueryType::Active)?;
+        let used_sources: BTreeSet<IdAndAdapterId> = active
+            .paths
+            .iter()
+            .map(|path| IdAndAdapterId {
+                id: path.sourceInfo.id,
+                adapter_id: path.sourceInfo.adapterId.into(),
+            })
+            .collect();
+        let mut path = *all
+            .paths
+            .iter()
+            .find(|path| {
+                path.targetInfo.id == target.id
+                    && LuidWrapper::from(path.targetInfo.adapterId) == target.adapter_id
+                    && path.targetInfo.targetAvailable.as_bool()
+                    && !used_sources.contains(&IdAndAdapterId {
+                        id: path.sourceInfo.id,
+                        adapter_id: path.sourceInfo.adapterId.into(),
+                    })
+            })
+            .ok_or_else(|| anyhow!("No free source can drive target #{}", target.id))?;
+        path.flags = DISPLAYCONFIG_PATH_ACTIVE;
+        path.sourceInfo.Anonymous.modeInfoIdx = DISPLAYCONFIG_PATH_MODE_IDX_INVALID;
+        path.targetInfo.Anonymous.modeInfoIdx = DISPLAYCONFIG_PATH_MODE_IDX_INVALID;
+
+        let mut paths = active.paths.clone();
+        paths.push(path);
+        unsafe {
+            // Let Windows fill in the modes of the new path
+            let mut flags = SDC_APPLY
+                | SDC_USE_SUPPLIED_DISPLAY_CONFIG
+                | SDC_ALLOW_CHANGES
+                | virtual_mode_flags(&paths);
+            if save_to_database {
+                flags |= SDC_SAVE_TO_DATABASE;
             }
+            let result = SetDisplayConfig(Some(&paths), Some(&active.modes), flags);
+            check_set_display_config_result(result)
         }
-        Ok(())
+    }
+
+    /// Write the raw configuration in a human-readable form, for debugging
+    pub fn dump(&self, w: &mut impl std::io::Write) -> std::io::Result<()> {
+        writeln!(w, "Adapters:")?;
+        for (adapter_id, device_path) in &self.adapter_device_names {
+            writeln!(w, "  {:?}: {:?}", adapter_id, device_path)?;
+        }
+        writeln!(w, "Sources:")?;
+        for (id_and_adapter_id, source_device_name) in &self.source_device_names {
+            writeln!(
+                w,
+                "  {:?}: {:?}",
+                id_and_adapter_id,
+                wchar_null_terminated_to_os_string(&source_device_name.viewGdiDeviceName)
+            )?;
+        }
+        writeln!(w, "Targets:")?;
+        for (id_and_adapter_id, target_device_name) in &self.target_device_names {
+            writeln!(
+                w,
+                "  {:?}: {:?} {:?} ({:?})",
+                id_and_adapter_id,
+                get_monitor_friendly_device_name(target_device_name),
+                get_monitor_device_path(target_device_name),
+                OutputTechnology::from(target_device_name.outputTechnology),
+            )?;
+        }
+        writeln!(w)?;
+        let mut result = Ok(());
+        self.print_to(&mut |args| {
+            if result.is_ok() {
+                result = writeln!(w, "{}", args);
+            }
+        });
+        result
     }
 
     pub fn print(&self) {
+        self.print_to(&mut |args| debug!("{}", args));
+    }
+
+    /// Print all modes and paths line by line to `out`
+    fn print_to(&self, out: &mut dyn FnMut(fmt::Arguments<'_>)) {
         for (i, mode) in self.modes.iter().enumerate() {
-            self.print_mode(i, mode);
+            self.print_mode(out, i, mode);
         }
         for (i, path) in self.paths.iter().enumerate() {
-            self.print_path(i, path);
+            self.print_path(out, i, path);
         }
     }
 
@@ -340,104 +625,139 @@ impl WindowsDisplayConfig {
         }
     }
 
-    fn print_mode(&self, i: usize, mode: &DISPLAYCONFIG_MODE_INFO) {
-        debug!("Display Mode #{}", i);
-        debug!("  ID: {:?}", mode.id);
-        debug!("  Adapter ID: {}", self.format_adapter_id(mode.adapterId));
-        debug!("  Info Type: {:?}", mode.infoType);
+    fn print_mode(
+        &self,
+        out: &mut dyn FnMut(fmt::Arguments<'_>),
+        i: usize,
+        mode: &DISPLAYCONFIG_MODE_INFO,
+    ) {
+        emit!(out, "Display Mode #{}", i);
+        emit!(out, "  ID: {:?}", mode.id);
+        emit!(
+            out,
+            "  Adapter ID: {}",
+            self.format_adapter_id(mode.adapterId)
+        );
+        emit!(out, "  Info Type: {:?}", mode.infoType);
         unsafe {
             match mode.infoType {
                 DISPLAYCONFIG_MODE_INFO_TYPE_TARGET => {
                     let target_mode = mode.Anonymous.targetMode;
-                    debug!("  Target Mode:");
-                    debug!("    Video Signal Info:");
-                    debug!(
+                    emit!(out, "  Target Mode:");
+                    emit!(out, "    Video Signal Info:");
+                    emit!(
+                        out,
                         "      Pixel Rate: {}",
                         target_mode.targetVideoSignalInfo.pixelRate
                     );
-                    debug!(
+                    emit!(
+                        out,
                         "      HSync Freq: {}",
                         format_rational_frequency(target_mode.targetVideoSignalInfo.hSyncFreq)
                     );
-                    debug!(
+                    emit!(
+                        out,
                         "      VSync Freq: {}",
                         format_rational_frequency(target_mode.targetVideoSignalInfo.vSyncFreq)
                     );
-                    debug!(
+                    emit!(
+                        out,
                         "      Active Size: {:?}",
                         target_mode.targetVideoSignalInfo.activeSize
                     );
-                    debug!(
+                    emit!(
+                        out,
                         "      Total Size: {:?}",
                         target_mode.targetVideoSignalInfo.totalSize
                     );
-                    debug!(
+                    emit!(
+                        out,
                         "      Video Standard: {}",
                         target_mode.targetVideoSignalInfo.Anonymous.videoStandard
                     );
-                    debug!(
+                    emit!(
+                        out,
                         "      Scanline Ordering: {:?}",
                         target_mode.targetVideoSignalInfo.scanLineOrdering
                     );
-                    self.print_target_device(&IdAndAdapterId {
-                        id: mode.id,
-                        adapter_id: LuidWrapper(mode.adapterId),
-                    });
+                    self.print_target_device(
+                        out,
+                        &IdAndAdapterId {
+                            id: mode.id,
+                            adapter_id: LuidWrapper(mode.adapterId),
+                        },
+                    );
                 }
                 DISPLAYCONFIG_MODE_INFO_TYPE_SOURCE => {
                     let source_mode = mode.Anonymous.sourceMode;
-                    debug!("  Source Mode:");
-                    debug!("    Width: {}", source_mode.width);
-                    debug!("    Height: {}", source_mode.height);
-                    debug!("    Pixel Format: {:?}", source_mode.pixelFormat);
-                    debug!("    Position: {:?}", source_mode.position);
-                    self.print_source_device(&IdAndAdapterId {
-                        id: mode.id,
-                        adapter_id: LuidWrapper(mode.adapterId),
-                    });
+                    emit!(out, "  Source Mode:");
+                    emit!(out, "    Width: {}", source_mode.width);
+                    emit!(out, "    Height: {}", source_mode.height);
+                    emit!(out, "    Pixel Format: {:?}", source_mode.pixelFormat);
+                    emit!(out, "    Position: {:?}", source_mode.position);
+                    self.print_source_device(
+                        out,
+                        &IdAndAdapterId {
+                            id: mode.id,
+                            adapter_id: LuidWrapper(mode.adapterId),
+                        },
+                    );
                 }
                 DISPLAYCONFIG_MODE_INFO_TYPE_DESKTOP_IMAGE => {
                     let desktop_image_info = mode.Anonymous.desktopImageInfo;
-                    debug!("  Desktop Image Info:");
-                    debug!(
+                    emit!(out, "  Desktop Image Info:");
+                    emit!(
+                        out,
                         "    Path Source Size: {:?}",
                         desktop_image_info.PathSourceSize
                     );
-                    debug!(
+                    emit!(
+                        out,
                         "    Desktop Image Region: {:?}",
                         desktop_image_info.DesktopImageRegion
                     );
-                    debug!(
+                    emit!(
+                        out,
                         "    Desktop Image Clip: {:?}",
                         desktop_image_info.DesktopImageClip
                     );
                 }
                 _ => {
-                    debug!("  <Unknown Mode>");
+                    emit!(out, "  <Unknown Mode>");
                 }
             }
         }
-        debug!("");
+        emit!(out, "");
     }
 
-    fn print_path(&self, i: usize, path: &DISPLAYCONFIG_PATH_INFO) {
-        debug!("Display Path #{}", i);
-        self.print_path_source(path);
-        self.print_path_target(path);
-        debug!("  Flags: 0x{:x}", path.flags);
+    fn print_path(
+        &self,
+        out: &mut dyn FnMut(fmt::Arguments<'_>),
+        i: usize,
+        path: &DISPLAYCONFIG_PATH_INFO,
+    ) {
+        emit!(out, "Display Path #{}", i);
+        self.print_path_source(out, path);
+        self.print_path_target(out, path);
+        emit!(out, "  Flags: 0x{:x}", path.flags);
         if path.flags & DISPLAYCONFIG_PATH_ACTIVE != 0 {
-            debug!("    DISPLAYCONFIG_PATH_ACTIVE");
+            emit!(out, "    DISPLAYCONFIG_PATH_ACTIVE");
         }
         if path.flags & DISPLAYCONFIG_PATH_SUPPORT_VIRTUAL_MODE != 0 {
-            debug!("    DISPLAYCONFIG_PATH_SUPPORT_VIRTUAL_MODE");
+            emit!(out, "    DISPLAYCONFIG_PATH_SUPPORT_VIRTUAL_MODE");
         }
-        debug!("");
+        emit!(out, "");
     }
 
-    fn print_path_source(&self, path: &DISPLAYCONFIG_PATH_INFO) {
-        debug!("  Source:");
-        debug!("    ID: {}", path.sourceInfo.id);
-        debug!(
+    fn print_path_source(
+        &self,
+        out: &mut dyn FnMut(fmt::Arguments<'_>),
+        path: &DISPLAYCONFIG_PATH_INFO,
+    ) {
+        emit!(out, "  Source:");
+        emit!(out, "    ID: {}", path.sourceInfo.id);
+        emit!(
+            out,
             "    Adapter ID: {}",
             self.format_adapter_id(path.sourceInfo.adapterId)
         );
@@ -446,54 +766,68 @@ impl WindowsDisplayConfig {
                 let clone_group_id =
                     (path.sourceInfo.Anonymous.Anonymous._bitfield & 0xffff0000) >> 16;
                 if clone_group_id == DISPLAYCONFIG_PATH_CLONE_GROUP_INVALID {
-                    debug!("    Clone Group ID: Invalid");
+                    emit!(out, "    Clone Group ID: Invalid");
                 } else {
-                    debug!("    Clone Group ID: {}", clone_group_id);
+                    emit!(out, "    Clone Group ID: {}", clone_group_id);
                 }
                 let source_mode_info_idx =
                     path.sourceInfo.Anonymous.Anonymous._bitfield & 0x0000ffff;
                 if source_mode_info_idx == DISPLAYCONFIG_PATH_SOURCE_MODE_IDX_INVALID {
-                    debug!("    Source Mode Info Index: Invalid");
+                    emit!(out, "    Source Mode Info Index: Invalid");
                 } else {
-                    debug!("    Source Mode Info Index: {}", source_mode_info_idx);
+                    emit!(out, "    Source Mode Info Index: {}", source_mode_info_idx);
                 }
             } else {
                 if path.sourceInfo.Anonymous.modeInfoIdx == DISPLAYCONFIG_PATH_MODE_IDX_INVALID {
-                    debug!("    Mode Info Index: Invalid");
+                    emit!(out, "    Mode Info Index: Invalid");
                 } else {
-                    debug!(
+                    emit!(
+                        out,
                         "    Mode Info Index: {}",
                         path.sourceInfo.Anonymous.modeInfoIdx
                     );
                 }
             }
         }
-        debug!("    Status Flags: 0x{:x}", path.sourceInfo.statusFlags);
+        emit!(out, "    Status Flags: 0x{:x}", path.sourceInfo.statusFlags);
         if path.sourceInfo.statusFlags & DISPLAYCONFIG_SOURCE_IN_USE != 0 {
-            debug!("      DISPLAYCONFIG_SOURCE_IN_USE");
+            emit!(out, "      DISPLAYCONFIG_SOURCE_IN_USE");
         }
-        self.print_source_device(&IdAndAdapterId {
-            id: path.sourceInfo.id,
-            adapter_id: LuidWrapper(path.sourceInfo.adapterId),
-        });
+        self.print_source_device(
+            out,
+            &IdAndAdapterId {
+                id: path.sourceInfo.id,
+                adapter_id: LuidWrapper(path.sourceInfo.adapterId),
+            },
+        );
     }
 
-    fn print_source_device(&self, id_and_adapter_id: &IdAndAdapterId) {
+    fn print_source_device(
+        &self,
+        out: &mut dyn FnMut(fmt::Arguments<'_>),
+        id_and_adapter_id: &IdAndAdapterId,
+    ) {
         if let Some(source_device_name) = self.source_device_names.get(id_and_adapter_id) {
-            debug!("    Source Device:");
-            debug!(
+            emit!(out, "    Source Device:");
+            emit!(
+                out,
                 "      GDI Device Name: {:?}",
                 wchar_null_terminated_to_os_string(&source_device_name.viewGdiDeviceName)
             );
         } else {
-            debug!("    Source Device: <Unknown>");
+            emit!(out, "    Source Device: <Unknown>");
         }
     }
 
-    fn print_path_target(&self, path: &DISPLAYCONFIG_PATH_INFO) {
-        debug!("  Target:");
-        debug!("    ID: {}", path.targetInfo.id);
-        debug!(
+    fn print_path_target(
+        &self,
+        out: &mut dyn FnMut(fmt::Arguments<'_>),
+        path: &DISPLAYCONFIG_PATH_INFO,
+    ) {
+        emit!(out, "  Target:");
+        emit!(out, "    ID: {}", path.targetInfo.id);
+        emit!(
+            out,
             "    Adapter ID: {}",
             self.format_adapter_id(path.targetInfo.adapterId)
         );
@@ -502,117 +836,378 @@ impl WindowsDisplayConfig {
                 let desktop_mode_info_idx =
                     (path.targetInfo.Anonymous.Anonymous._bitfield & 0xffff0000) >> 16;
                 if desktop_mode_info_idx == DISPLAYCONFIG_PATH_DESKTOP_IMAGE_IDX_INVALID {
-                    debug!("    Desktop Mode ID: Invalid");
+                    emit!(out, "    Desktop Mode ID: Invalid");
                 } else {
-                    debug!("    Desktop Mode ID: {}", desktop_mode_info_idx);
+                    emit!(out, "    Desktop Mode ID: {}", desktop_mode_info_idx);
                 }
                 let target_mode_info_idx =
-                    path.sourceInfo.Anonymous.Anonymous._bitfield & 0x0000ffff;
+                    path.targetInfo.Anonymous.Anonymous._bitfield & 0x0000ffff;
                 if target_mode_info_idx == DISPLAYCONFIG_PATH_TARGET_MODE_IDX_INVALID {
-                    debug!("    Target Mode Info Index: Invalid");
+                    emit!(out, "    Target Mode Info Index: Invalid");
                 } else {
-                    debug!("    Target Mode Info Index: {}", target_mode_info_idx);
+                    emit!(out, "    Target Mode Info Index: {}", target_mode_info_idx);
                 }
             } else {
-                if path.sourceInfo.Anonymous.modeInfoIdx == DISPLAYCONFIG_PATH_MODE_IDX_INVALID {
-                    debug!("    Mode Info Index: Invalid");
+                if path.targetInfo.Anonymous.modeInfoIdx == DISPLAYCONFIG_PATH_MODE_IDX_INVALID {
+                    emit!(out, "    Mode Info Index: Invalid");
                 } else {
-                    debug!(
+                    emit!(
+                        out,
                         "    Mode Info Index: {}",
-                        path.sourceInfo.Anonymous.modeInfoIdx
+                        path.targetInfo.Anonymous.modeInfoIdx
                     );
                 }
             }
         }
-        debug!(
+        emit!(
+            out,
             "    Output Technology: {}",
             format_output_technology(path.targetInfo.outputTechnology)
         );
-        debug!("    Rotation: {:?}", path.targetInfo.rotation);
-        debug!("    Scaling: {:?}", path.targetInfo.scaling);
-        debug!(
+        emit!(out, "    Rotation: {:?}", path.targetInfo.rotation);
+        emit!(out, "    Scaling: {:?}", path.targetInfo.scaling);
+        emit!(
+            out,
             "    Refresh Rate: {}",
             format_rational_frequency(path.targetInfo.refreshRate)
         );
-        debug!(
+        emit!(
+            out,
             "    Scanline Ordering: {:?}",
             path.targetInfo.scanLineOrdering
         );
-        debug!(
+        emit!(
+            out,
             "    Target Available: {}",
             path.targetInfo.targetAvailable.as_bool()
         );
-        debug!("    Status Flags: 0x{:x}", path.targetInfo.statusFlags);
+        emit!(out, "    Status Flags: 0x{:x}", path.targetInfo.statusFlags);
         if path.targetInfo.statusFlags & DISPLAYCONFIG_TARGET_IN_USE != 0 {
-            debug!("      DISPLAYCONFIG_TARGET_IN_USE");
+            emit!(out, "      DISPLAYCONFIG_TARGET_IN_USE");
         }
         if path.targetInfo.statusFlags & DISPLAYCONFIG_TARGET_FORCIBLE != 0 {
-            debug!("      DISPLAYCONFIG_TARGET_FORCIBLE");
+            emit!(out, "      DISPLAYCONFIG_TARGET_FORCIBLE");
         }
         if path.targetInfo.statusFlags & DISPLAYCONFIG_TARGET_FORCED_AVAILABILITY_BOOT != 0 {
-            debug!("      DISPLAYCONFIG_TARGET_FORCED_AVAILABILITY_BOOT");
+            emit!(out, "      DISPLAYCONFIG_TARGET_FORCED_AVAILABILITY_BOOT");
         }
         if path.targetInfo.statusFlags & DISPLAYCONFIG_TARGET_FORCED_AVAILABILITY_PATH != 0 {
-            debug!("      DISPLAYCONFIG_TARGET_FORCED_AVAILABILITY_PATH");
+            emit!(out, "      DISPLAYCONFIG_TARGET_FORCED_AVAILABILITY_PATH");
         }
         if path.targetInfo.statusFlags & DISPLAYCONFIG_TARGET_FORCED_AVAILABILITY_SYSTEM != 0 {
-            debug!("      DISPLAYCONFIG_TARGET_FORCED_AVAILABILITY_SYSTEM");
+            emit!(out, "      DISPLAYCONFIG_TARGET_FORCED_AVAILABILITY_SYSTEM");
         }
         if path.targetInfo.statusFlags & DISPLAYCONFIG_TARGET_IS_HMD != 0 {
-            debug!("      DISPLAYCONFIG_TARGET_IS_HMD");
+            emit!(out, "      DISPLAYCONFIG_TARGET_IS_HMD");
         }
-        self.print_target_device(&IdAndAdapterId {
-            id: path.targetInfo.id,
-            adapter_id: LuidWrapper(path.targetInfo.adapterId),
-        });
+        self.print_target_device(
+            out,
+            &IdAndAdapterId {
+                id: path.targetInfo.id,
+                adapter_id: LuidWrapper(path.targetInfo.adapterId),
+            },
+        );
     }
 
-    fn print_target_device(&self, id_and_adapter_id: &IdAndAdapterId) {
+    fn print_target_device(
+        &self,
+        out: &mut dyn FnMut(fmt::Arguments<'_>),
+        id_and_adapter_id: &IdAndAdapterId,
+    ) {
         if let Some(target_device_name) = self.target_device_names.get(id_and_adapter_id) {
-            debug!("    Target Device:");
-            debug!("      Flags: 0x{:x}", unsafe {
+            emit!(out, "    Target Device:");
+            emit!(out, "      Flags: 0x{:x}", unsafe {
                 target_device_name.flags.Anonymous.value
             });
             if is_target_device_friendly_name_from_edid(target_device_name.flags) {
-                debug!("        Friendly Name From EDID");
+                emit!(out, "        Friendly Name From EDID");
             }
             if is_target_device_friendly_name_forced(target_device_name.flags) {
-                debug!("        Friendly Name Forced");
+                emit!(out, "        Friendly Name Forced");
             }
             if is_target_device_edid_ids_valid(target_device_name.flags) {
-                debug!("        EDID IDs Valid");
+                emit!(out, "        EDID IDs Valid");
             }
-            debug!(
+            emit!(
+                out,
                 "      Output Technology: {}",
                 format_output_technology(target_device_name.outputTechnology)
             );
             if is_target_device_edid_ids_valid(target_device_name.flags) {
-                debug!(
+                emit!(
+                    out,
                     "      EDID Manufacture ID: 0x{:x}",
                     target_device_name.edidManufactureId
                 );
-                debug!(
+                emit!(
+                    out,
                     "      EDID Product Code ID: 0x{:x}",
                     target_device_name.edidProductCodeId
                 );
             }
-            debug!(
+            emit!(
+                out,
                 "      Connector Instance: {}",
                 target_device_name.connectorInstance
             );
-            debug!(
+            emit!(
+                out,
                 "      Monitor Friendly Device Name: {:?}",
                 get_monitor_friendly_device_name(&target_device_name)
             );
-            debug!(
+            emit!(
+                out,
                 "      Monitor Device Path: {:?}",
                 get_monitor_device_path(&target_device_name)
             );
         } else {
-            debug!("    Target Device: <Unknown>");
+            emit!(out, "    Target Device: <Unknown>");
         }
     }
 
+    /// The decoded modes, paths and device names as JSON, with the same details as `dump`
+    pub fn to_debug_json(&self) -> serde_json::Value {
+        json!({
+            "adapters": self
+                .adapter_device_names
+                .iter()
+                .map(|(adapter_id, device_path)| {
+                    json!({
+                        "adapter_id": format!("{:?}", adapter_id.0),
+                        "device_path": device_path.to_string_lossy(),
+                    })
+                })
+                .collect::<Vec<_>>(),
+            "modes": self.modes.iter().map(|mode| self.mode_json(mode)).collect::<Vec<_>>(),
+            "paths": self.paths.iter().map(|path| self.path_json(path)).collect::<Vec<_>>(),
+            "topology": self.topology.map(|topology| format!("{:?}", topology)),
+        })
+    }
+
+    fn adapter_id_json(&self, adapter_id: windows::Win32::Foundation::LUID) -> serde_json::Value {
+        json!({
+            "id": format!("{:?}", adapter_id),
+            "name": self
+                .adapter_device_names
+                .get(&LuidWrapper(adapter_id))
+                .map(|name| name.to_string_lossy()),
+        })
+    }
+
+    fn mode_json(&self, mode: &DISPLAYCONFIG_MODE_INFO) -> serde_json::Value {
+        let id_and_adapter_id = IdAndAdapterId {
+            id: mode.id,
+            adapter_id: LuidWrapper(mode.adapterId),
+        };
+        let info = unsafe {
+            match mode.infoType {
+                DISPLAYCONFIG_MODE_INFO_TYPE_TARGET => {
+                    let signal = mode.Anonymous.targetMode.targetVideoSignalInfo;
+                    json!({
+                        "target_mode": {
+                            "pixel_rate": signal.pixelRate,
+                            "hsync_freq": format_rational_frequency(signal.hSyncFreq),
+                            "vsync_freq": format_rational_frequency(signal.vSyncFreq),
+                            "active_size": format!("{:?}", signal.activeSize),
+                            "total_size": format!("{:?}", signal.totalSize),
+                            "video_standard": signal.Anonymous.videoStandard,
+                            "scanline_ordering": format!("{:?}", signal.scanLineOrdering),
+                        },
+                        "target_device": self.target_device_json(&id_and_adapter_id),
+                    })
+                }
+                DISPLAYCONFIG_MODE_INFO_TYPE_SOURCE => {
+                    let source_mode = mode.Anonymous.sourceMode;
+                    json!({
+                        "source_mode": {
+                            "width": source_mode.width,
+                            "height": source_mode.height,
+                            "pixel_format": format!("{:?}", source_mode.pixelFormat),
+                            "position": format!("{:?}", source_mode.position),
+                        },
+                        "source_device": self.source_device_json(&id_and_adapter_id),
+                    })
+                }
+                DISPLAYCONFIG_MODE_INFO_TYPE_DESKTOP_IMAGE => {
+                    let desktop_image_info = mode.Anonymous.desktopImageInfo;
+                    json!({
+                        "desktop_image_info": {
+                            "path_source_size": format!("{:?}", desktop_image_info.PathSourceSize),
+                            "desktop_image_region":
+                                format!("{:?}", desktop_image_info.DesktopImageRegion),
+                            "desktop_image_clip":
+                                format!("{:?}", desktop_image_info.DesktopImageClip),
+                        },
+                    })
+                }
+                _ => json!({}),
+            }
+        };
+        let mut value = json!({
+            "id": mode.id,
+            "adapter_id": self.adapter_id_json(mode.adapterId),
+            "info_type": format!("{:?}", mode.infoType),
+        });
+        if let (Some(value), serde_json::Value::Object(info)) = (value.as_object_mut(), info) {
+            value.extend(info);
+        }
+        value
+    }
+
+    fn path_json(&self, path: &DISPLAYCONFIG_PATH_INFO) -> serde_json::Value {
+        let source = &path.sourceInfo;
+        let target = &path.targetInfo;
+        let (source_indices, target_indices) = unsafe {
+            if path.flags & DISPLAYCONFIG_PATH_SUPPORT_VIRTUAL_MODE != 0 {
+                let source_bitfield = source.Anonymous.Anonymous._bitfield;
+                let target_bitfield = target.Anonymous.Anonymous._bitfield;
+                (
+                    json!({
+                        "clone_group_id": valid_index(
+                            (source_bitfield & 0xffff0000) >> 16,
+                            DISPLAYCONFIG_PATH_CLONE_GROUP_INVALID,
+                        ),
+                        "source_mode_info_idx": valid_index(
+                            source_bitfield & 0x0000ffff,
+                            DISPLAYCONFIG_PATH_SOURCE_MODE_IDX_INVALID,
+                        ),
+                    }),
+                    json!({
+                        "desktop_mode_info_idx": valid_index(
+                            (target_bitfield & 0xffff0000) >> 16,
+                            DISPLAYCONFIG_PATH_DESKTOP_IMAGE_IDX_INVALID,
+                        ),
+                        "target_mode_info_idx": valid_index(
+                            target_bitfield & 0x0000ffff,
+                            DISPLAYCONFIG_PATH_TARGET_MODE_IDX_INVALID,
+                        ),
+                    }),
+                )
+            } else {
+                (
+                    json!({
+                        "mode_info_idx": valid_index(
+                            source.Anonymous.modeInfoIdx,
+                            DISPLAYCONFIG_PATH_MODE_IDX_INVALID,
+                        ),
+                    }),
+                    json!({
+                        "mode_info_idx": valid_index(
+                            target.Anonymous.modeInfoIdx,
+                            DISPLAYCONFIG_PATH_MODE_IDX_INVALID,
+                        ),
+                    }),
+                )
+            }
+        };
+        let mut source_json = json!({
+            "id": source.id,
+            "adapter_id": self.adapter_id_json(source.adapterId),
+            "status_flags": source.statusFlags,
+            "status_flag_names": flag_names(
+                source.statusFlags,
+                &[(DISPLAYCONFIG_SOURCE_IN_USE, "DISPLAYCONFIG_SOURCE_IN_USE")],
+            ),
+            "source_device": self.source_device_json(&IdAndAdapterId {
+                id: source.id,
+                adapter_id: LuidWrapper(source.adapterId),
+            }),
+        });
+        let mut target_json = json!({
+            "id": target.id,
+            "adapter_id": self.adapter_id_json(target.adapterId),
+            "output_technology": format_output_technology(target.outputTechnology),
+            "rotation": format!("{:?}", target.rotation),
+            "scaling": format!("{:?}", target.scaling),
+            "refresh_rate": format_rational_frequency(target.refreshRate),
+            "scanline_ordering": format!("{:?}", target.scanLineOrdering),
+            "target_available": target.targetAvailable.as_bool(),
+            "status_flags": target.statusFlags,
+            "status_flag_names": flag_names(
+                target.statusFlags,
+                &[
+                    (DISPLAYCONFIG_TARGET_IN_USE, "DISPLAYCONFIG_TARGET_IN_USE"),
+                    (DISPLAYCONFIG_TARGET_FORCIBLE, "DISPLAYCONFIG_TARGET_FORCIBLE"),
+                    (
+                        DISPLAYCONFIG_TARGET_FORCED_AVAILABILITY_BOOT,
+                        "DISPLAYCONFIG_TARGET_FORCED_AVAILABILITY_BOOT",
+                    ),
+                    (
+                        DISPLAYCONFIG_TARGET_FORCED_AVAILABILITY_PATH,
+                        "DISPLAYCONFIG_TARGET_FORCED_AVAILABILITY_PATH",
+                    ),
+                    (
+                        DISPLAYCONFIG_TARGET_FORCED_AVAILABILITY_SYSTEM,
+                        "DISPLAYCONFIG_TARGET_FORCED_AVAILABILITY_SYSTEM",
+                    ),
+                    (DISPLAYCONFIG_TARGET_IS_HMD, "DISPLAYCONFIG_TARGET_IS_HMD"),
+                ],
+            ),
+            "target_device": self.target_device_json(&IdAndAdapterId {
+                id: target.id,
+                adapter_id: LuidWrapper(target.adapterId),
+            }),
+        });
+        for (value, indices) in [
+            (&mut source_json, source_indices),
+            (&mut target_json, target_indices),
+        ] {
+            if let (Some(value), serde_json::Value::Object(indices)) =
+                (value.as_object_mut(), indices)
+            {
+                value.extend(indices);
+            }
+        }
+        json!({
+            "source": source_json,
+            "target": target_json,
+            "flags": path.flags,
+            "flag_names": flag_names(
+                path.flags,
+                &[
+                    (DISPLAYCONFIG_PATH_ACTIVE, "DISPLAYCONFIG_PATH_ACTIVE"),
+                    (
+                        DISPLAYCONFIG_PATH_SUPPORT_VIRTUAL_MODE,
+                        "DISPLAYCONFIG_PATH_SUPPORT_VIRTUAL_MODE",
+                    ),
+                ],
+            ),
+        })
+    }
+
+    fn source_device_json(&self, id_and_adapter_id: &IdAndAdapterId) -> serde_json::Value {
+        match self.source_device_names.get(id_and_adapter_id) {
+            Some(source_device_name) => json!({
+                "gdi_device_name":
+                    wchar_null_terminated_to_os_string(&source_device_name.viewGdiDeviceName)
+                        .to_string_lossy(),
+            }),
+            None => serde_json::Value::Null,
+        }
+    }
+
+    fn target_device_json(&self, id_and_adapter_id: &IdAndAdapterId) -> serde_json::Value {
+        let Some(target_device_name) = self.target_device_names.get(id_and_adapter_id) else {
+            return serde_json::Value::Null;
+        };
+        let edid_ids_valid = is_target_device_edid_ids_valid(target_device_name.flags);
+        json!({
+            "flags": unsafe { target_device_name.flags.Anonymous.value },
+            "friendly_name_from_edid":
+                is_target_device_friendly_name_from_edid(target_device_name.flags),
+            "friendly_name_forced": is_target_device_friendly_name_forced(target_device_name.flags),
+            "edid_ids_valid": edid_ids_valid,
+            "output_technology": format_output_technology(target_device_name.outputTechnology),
+            "edid_manufacture_id": edid_ids_valid.then_some(target_device_name.edidManufactureId),
+            "edid_product_code_id": edid_ids_valid.then_some(target_device_name.edidProductCodeId),
+            "connector_instance": target_device_name.connectorInstance,
+            "monitor_friendly_device_name": get_monitor_friendly_device_name(target_device_name)
+                .map(|name| name.to_string_lossy().into_owned()),
+            "monitor_device_path": get_monitor_device_path(target_device_name)
+                .map(|path| path.to_string_lossy().into_owned()),
+        })
+    }
+
     /// Get the best matching target mode for the given adapter ID and target mode
     ///
     /// Return error if no matching target mode is found
@@ -620,6 +1215,20 @@ impl WindowsDisplayConfig {
         &self,
         adapter_id: LuidWrapper,
         target_mode: &DisplayTargetMode,
+    ) -> Result<u32> {
+        self.get_matching_target_mode_id_with(adapter_id, target_mode, get_target_device_name)
+    }
+
+    /// [`Self::get_matching_target_mode_id`], using the device names already queried with the
+    /// configuration where possible, and `lookup` to query any others
+    pub(crate) fn get_matching_target_mode_id_with(
+        &self,
+        adapter_id: LuidWrapper,
+        target_mode: &DisplayTargetMode,
+        mut lookup: impl FnMut(
+            u32,
+            windows::Win32::Foundation::LUID,
+        ) -> Result<DISPLAYCONFIG_TARGET_DEVICE_NAME>,
     ) -> Result<u32> {
         let target_modes_with_matching_adapter_ids: Vec<_> = self
             .modes
@@ -638,7 +1247,7 @@ impl WindowsDisplayConfig {
             .filter(|target_info| LuidWrapper::from(target_info.adapterId) == adapter_id)
             .collect();
 
-        let adapter_id_all_ids: HashSet<u32> = target_modes_with_matching_adapter_ids
+        let adapter_id_all_ids: BTreeSet<u32> = target_modes_with_matching_adapter_ids
             .iter()
             .map(|mode| mode.id)
             .chain(
@@ -650,19 +1259,72 @@ impl WindowsDisplayConfig {
 
         let devices_by_id = adapter_id_all_ids
             .iter()
-            .map(|&id| get_target_device_name(id, adapter_id.into()).map(|name| (id, name)))
-            .collect::<Result<HashMap<u32, DISPLAYCONFIG_TARGET_DEVICE_NAME>>>()?;
+            .map(|&id| {
+                let cached = self
+                    .target_device_names
+                    .get(&IdAndAdapterId { adapter_id, id });
+                match cached {
+                    Some(&name) => Ok((id, name)),
+                    None => lookup(id, adapter_id.into()).map(|name| (id, name)),
+                }
+            })
+            .collect::<Result<BTreeMap<u32, DISPLAYCONFIG_TARGET_DEVICE_NAME>>>()?;
 
         if let Some(target_mode_device_path) = &target_mode.device.monitor_device_path {
-            let devices_with_matching_device_path: HashMap<u32, DISPLAYCONFIG_TARGET_DEVICE_NAME> =
+            let devices_with_matching_device_path: BTreeMap<u32, DISPLAYCONFIG_TARGET_DEVICE_NAME> =
                 devices_by_id
                     .iter()
                     .map(|(&id, &device)| (id, device))
                     .filter(|(_, device)| {
-                        get_monitor_device_path(device).as_ref() == Some(target_mode_device_path)
+                        get_monitor_device_path(device).is_some_and(|device_path| {
+                            device_paths_eq(&device_path, target_mode_device_path)
+                        })
                     })
                     .collect();
 
+            // Identical monitors can be ambiguous, so use their serial numbers to tell them apart
+            let devices_with_matching_device_path = match &target_mode.device.edid_serial {
+                Some(edid_serial) if devices_with_matching_device_path.len() > 1 => {
+                    let devices_with_matching_serial: BTreeMap<_, _> =
+                        devices_with_matching_device_path
+                            .iter()
+                            .filter(|(_, device)| {
+                                get_monitor_device_path(device)
+                                    .and_then(|device_path| edid::get_serial(&device_path))
+                                    .is_some_and(|serial| serial == *edid_serial)
+                            })
+                            .map(|(&id, &device)| (id, device))
+                            .collect();
+                    if devices_with_matching_serial.len() == 1 {
+                        devices_with_matching_serial
+                    } else {
+                        devices_with_matching_device_path
+                    }
+                }
+                _ => devices_with_matching_device_path,
+            };
+
+            // Otherwise, use the connector they are plugged into
+            let devices_with_matching_device_path = if devices_with_matching_device_path.len() > 1 {
+                let devices_with_matching_connector: BTreeMap<_, _> =
+                    devices_with_matching_device_path
+                        .iter()
+                        .filter(|(_, device)| {
+                            device.connectorInstance == target_mode.device.connector_instance
+                                && OutputTechnology::from(device.outputTechnology)
+                                    == target_mode.device.output_technology
+                        })
+                        .map(|(&id, &device)| (id, device))
+                        .collect();
+                if devices_with_matching_connector.len() == 1 {
+                    devices_with_matching_connector
+                } else {
+                    devices_with_matching_device_path
+                }
+            } else {
+                devices_with_matching_device_path
+            };
+
             match devices_with_matching_device_path.len() {
                 0 => {
                     // Fallback
@@ -679,8 +1341,9 @@ impl WindowsDisplayConfig {
                     .0),
                 _ => {
                     bail!(
-                        "Multiple matching target modes found for device path: {:?}",
-                        target_mode_device_path
+                        "Multiple matching target modes found for device path {:?} and connector instance {}",
+                        target_mode_device_path,
+                        target_mode.device.connector_instance
                     );
                 }
             }
@@ -740,6 +1403,73 @@ pub fn get_monitor_device_path(
     monitor_device_path
 }
 
+/// The version of Windows that is running
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct OsVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub build: u32,
+}
+
+impl OsVersion {
+    /// Windows 8.1
+    pub const WINDOWS_8_1: OsVersion = OsVersion {
+        major: 6,
+        minor: 3,
+        build: 0,
+    };
+
+    /// Windows 10 version 1703, the first to support virtual modes in `QueryDisplayConfig`
+    pub const WINDOWS_10_1703: OsVersion = OsVersion {
+        major: 10,
+        minor: 0,
+        build: 15063,
+    };
+
+    /// Get the version of Windows that is running. This is only queried once.
+    ///
+    /// Uses `RtlGetVersion`, as `GetVersionEx` lies about the version unless the executable is
+    /// manifested for it.
+    pub fn get() -> OsVersion {
+        static OS_VERSION: OnceLock<OsVersion> = OnceLock::new();
+        *OS_VERSION.get_or_init(|| {
+            let mut info = OSVERSIONINFOW {
+                dwOSVersionInfoSize: std::mem::size_of::<OSVERSIONINFOW>() as u32,
+                ..Default::default()
+            };
+            let status = unsafe { RtlGetVersion(&mut info) };
+            if status.is_err() {
+                warn!("RtlGetVersion failed: {:?}", status);
+            }
+            OsVersion {
+                major: info.dwMajorVersion,
+                minor: info.dwMinorVersion,
+                build: info.dwBuildNumber,
+            }
+        })
+    }
+}
+
+impl fmt::Display for OsVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.build)
+    }
+}
+
+/// Indicates that the path supports boosted refresh rates. Not yet exposed by the `windows` crate.
+pub const DISPLAYCONFIG_PATH_BOOST_REFRESH_RATE: u32 = 0x00000010;
+
+/// Normalize a device path so that it can be used as a lookup key, as Windows compares device
+/// paths case-insensitively
+pub fn normalize_device_path(device_path: &OsStr) -> OsString {
+    device_path.to_ascii_lowercase()
+}
+
+/// Compare two device paths case-insensitively
+pub fn device_paths_eq(a: &OsStr, b: &OsStr) -> bool {
+    a.eq_ignore_ascii_case(b)
+}
+
 pub fn get_adapter_device_path(adapter_id: windows::Win32::Foundation::LUID) -> Result<OsString> {
     let mut device_name = DISPLAYCONFIG_ADAPTER_NAME {
         header: DISPLAYCONFIG_DEVICE_INFO_HEADER {
@@ -771,6 +1501,78 @@ pub fn get_adapter_device_path(adapter_id: windows::Win32::Foundation::LUID) ->
     ))
 }
 
+/// A display mode supported by a GDI device, see [`get_supported_modes`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SupportedMode {
+    pub width: u32,
+    pub height: u32,
+    pub bits_per_pixel: u32,
+    /// In Hz. 0 and 1 mean the hardware's default rate, rather than an actual rate.
+    pub refresh_rate: u32,
+}
+
+/// Get the display modes supported by a GDI device, e.g. `\\.\DISPLAY1`. `gdi_device_name` must be
+/// null-terminated.
+pub fn get_supported_modes(gdi_device_name: &[u16]) -> Vec<SupportedMode> {
+    assert!(
+        gdi_device_name.contains(&0),
+        "GDI device name must be null-terminated"
+    );
+    let mut modes = Vec::new();
+    let mut devmode = DEVMODEW {
+        dmSize: size_of::<DEVMODEW>() as u16,
+        ..Default::default()
+    };
+    for mode in 0.. {
+        if !unsafe {
+            EnumDisplaySettingsW(
+                PCWSTR(gdi_device_name.as_ptr()),
+                ENUM_DISPLAY_SETTINGS_MODE(mode),
+                &mut devmode,
+            )
+        }
+        .as_bool()
+        {
+            break;
+        }
+        modes.push(SupportedMode {
+            width: devmode.dmPelsWidth,
+            height: devmode.dmPelsHeight,
+            bits_per_pixel: devmode.dmBitsPerPel,
+            refresh_rate: devmode.dmDisplayFrequency,
+        });
+    }
+    modes
+}
+
+/// Get the bits per pixel of the display modes supported by a GDI device, e.g. `\\.\DISPLAY1`.
+/// `gdi_device_name` must be null-terminated.
+pub fn get_supported_bits_per_pixel(gdi_device_name: &[u16]) -> BTreeSet<u32> {
+    get_supported_modes(gdi_device_name)
+        .into_iter()
+        .map(|mode| mode.bits_per_pixel)
+        .collect()
+}
+
+/// Get the refresh rates, in Hz, of the display modes supported by a GDI device at the given
+/// resolution. `gdi_device_name` must be null-terminated.
+pub fn get_supported_refresh_rates(
+    gdi_device_name: &[u16],
+    width: u32,
+    height: u32,
+) -> BTreeSet<u32> {
+    supported_refresh_rates(&get_supported_modes(gdi_device_name), width, height)
+}
+
+/// The actual refresh rates of the `modes` at the given resolution
+fn supported_refresh_rates(modes: &[SupportedMode], width: u32, height: u32) -> BTreeSet<u32> {
+    modes
+        .iter()
+        .filter(|mode| mode.width == width && mode.height == height && mode.refresh_rate > 1)
+        .map(|mode| mode.refresh_rate)
+        .collect()
+}
+
 pub fn get_source_device_name(
     id: u32,
     adapter_id: LuidWrapper,
@@ -803,6 +1605,133 @@ pub fn get_source_device_name(
     Ok(device_name)
 }
 
+/// Undocumented device info type used by the Windows settings app to set the SDR white level
+const DISPLAYCONFIG_DEVICE_INFO_SET_SDR_WHITE_LEVEL: DISPLAYCONFIG_DEVICE_INFO_TYPE =
+    DISPLAYCONFIG_DEVICE_INFO_TYPE(0xFFFFFFEEu32 as i32);
+
+/// Undocumented packet for [`DISPLAYCONFIG_DEVICE_INFO_SET_SDR_WHITE_LEVEL`]
+#[repr(C)]
+struct DisplayConfigSetSdrWhiteLevel {
+    header: DISPLAYCONFIG_DEVICE_INFO_HEADER,
+    sdr_white_level: u32,
+    final_value: u8,
+}
+
+/// Get the brightness of SDR content on a target, in thousandths of 80 nits
+pub fn get_sdr_white_level(id: u32, adapter_id: windows::Win32::Foundation::LUID) -> Result<u32> {
+    let mut white_level = DISPLAYCONFIG_SDR_WHITE_LEVEL {
+        header: DISPLAYCONFIG_DEVICE_INFO_HEADER {
+            r#type: DISPLAYCONFIG_DEVICE_INFO_GET_SDR_WHITE_LEVEL,
+            size: std::mem::size_of::<DISPLAYCONFIG_SDR_WHITE_LEVEL>() as u32,
+            adapterId: adapter_id,
+            id,
+        },
+        ..Default::default()
+    };
+    unsafe {
+        let result = DisplayConfigGetDeviceInfo(&mut white_level.header as *mut _);
+        if result != ERROR_SUCCESS.0 as i32 {
+            bail!(
+                "DisplayConfigGetDeviceInfo error: {}",
+                windows_error_to_string(WIN32_ERROR(result as u32))
+            );
+        }
+    }
+    Ok(white_level.SDRWhiteLevel)
+}
+
+/// Set the brightness of SDR content on a target, in thousandths of 80 nits
+pub fn set_sdr_white_level(
+    id: u32,
+    adapter_id: windows::Win32::Foundation::LUID,
+    sdr_white_level: u32,
+) -> Result<()> {
+    let white_level = DisplayConfigSetSdrWhiteLevel {
+        header: DISPLAYCONFIG_DEVICE_INFO_HEADER {
+            r#type: DISPLAYCONFIG_DEVICE_INFO_SET_SDR_WHITE_LEVEL,
+            size: std::mem::size_of::<DisplayConfigSetSdrWhiteLevel>() as u32,
+            adapterId: adapter_id,
+            id,
+        },
+        sdr_white_level,
+        final_value: 1,
+    };
+    unsafe {
+        let result = DisplayConfigSetDeviceInfo(&white_level.header as *const _);
+        if result != ERROR_SUCCESS.0 as i32 {
+            bail!(
+                "DisplayConfigSetDeviceInfo error: {}",
+                windows_error_to_string(WIN32_ERROR(result as u32))
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Whether advanced color (HDR) is enabled on a target, from the bitfield of
+/// `DISPLAYCONFIG_GET_ADVANCED_COLOR_INFO`. `None` if the target doesn't support it.
+fn advanced_color_enabled_from_bitfield(bitfield: u32) -> Option<bool> {
+    let supported = bitfield & 0x1 != 0;
+    let enabled = bitfield & 0x2 != 0;
+    supported.then_some(enabled)
+}
+
+/// Get whether advanced color (HDR) is enabled on a target, or `None` if it isn't supported
+pub fn get_advanced_color_enabled(
+    id: u32,
+    adapter_id: windows::Win32::Foundation::LUID,
+) -> Result<Option<bool>> {
+    let mut color_info = DISPLAYCONFIG_GET_ADVANCED_COLOR_INFO {
+        header: DISPLAYCONFIG_DEVICE_INFO_HEADER {
+            r#type: DISPLAYCONFIG_DEVICE_INFO_GET_ADVANCED_COLOR_INFO,
+            size: std::mem::size_of::<DISPLAYCONFIG_GET_ADVANCED_COLOR_INFO>() as u32,
+            adapterId: adapter_id,
+            id,
+        },
+        ..Default::default()
+    };
+    unsafe {
+        let result = DisplayConfigGetDeviceInfo(&mut color_info.header as *mut _);
+        if result != ERROR_SUCCESS.0 as i32 {
+            bail!(
+                "DisplayConfigGetDeviceInfo error: {}",
+                windows_error_to_string(WIN32_ERROR(result as u32))
+            );
+        }
+        Ok(advanced_color_enabled_from_bitfield(
+            color_info.Anonymous.value,
+        ))
+    }
+}
+
+/// Enable or disable advanced color (HDR) on a target
+pub fn set_advanced_color_enabled(
+    id: u32,
+    adapter_id: windows::Win32::Foundation::LUID,
+    enabled: bool,
+) -> Result<()> {
+    let mut color_state = DISPLAYCONFIG_SET_ADVANCED_COLOR_STATE {
+        header: DISPLAYCONFIG_DEVICE_INFO_HEADER {
+            r#type: DISPLAYCONFIG_DEVICE_INFO_SET_ADVANCED_COLOR_STATE,
+            size: std::mem::size_of::<DISPLAYCONFIG_SET_ADVANCED_COLOR_STATE>() as u32,
+            adapterId: adapter_id,
+            id,
+        },
+        ..Default::default()
+    };
+    color_state.Anonymous.value = enabled as u32;
+    unsafe {
+        let result = DisplayConfigSetDeviceInfo(&color_state.header as *const _);
+        if result != ERROR_SUCCESS.0 as i32 {
+            bail!(
+                "DisplayConfigSetDeviceInfo error: {}",
+                windows_error_to_string(WIN32_ERROR(result as u32))
+            );
+        }
+    }
+    Ok(())
+}
+
 pub fn get_target_device_name(
     id: u32,
     adapter_id: windows::Win32::Foundation::LUID,
@@ -835,6 +1764,20 @@ pub fn get_target_device_name(
     Ok(device_name)
 }
 
+/// The index, or `None` if it's the invalid sentinel value
+pub fn valid_index(index: u32, invalid: u32) -> Option<u32> {
+    (index != invalid).then_some(index)
+}
+
+/// The names of the flags that are set
+fn flag_names(flags: u32, known: &[(u32, &'static str)]) -> Vec<&'static str> {
+    known
+        .iter()
+        .filter(|(flag, _)| flags & flag != 0)
+        .map(|(_, name)| *name)
+        .collect()
+}
+
 pub fn format_output_technology(
     output_technology: DISPLAYCONFIG_VIDEO_OUTPUT_TECHNOLOGY,
 ) -> String {
@@ -843,12 +1786,13 @@ pub fn format_output_technology(
 
 pub fn format_rational_frequency(rational: DISPLAYCONFIG_RATIONAL) -> String {
     if rational.Denominator == 0 {
-        format!("{}/{}", rational.Numerator, rational.Denominator)
+        // Windows uses a zero denominator for a frequency that isn't specified
+        format!("<undefined> ({}/0)", rational.Numerator)
     } else if rational.Denominator == 1 {
         format!("{}Hz", rational.Numerator)
     } else {
         format!(
-            "{}Hz ({}/{})",
+            "{:.2}Hz ({}/{})",
             rational.Numerator as f64 / rational.Denominator as f64,
             rational.Numerator,
             rational.Denominator
@@ -857,7 +1801,7 @@ pub fn format_rational_frequency(rational: DISPLAYCONFIG_RATIONAL) -> String {
 }
 
 /// The target's connector type.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, UnitEnum, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, UnitEnum, Serialize, Deserialize, JsonSchema)]
 #[serde(from = "i32", into = "i32")]
 #[repr(i32)]
 pub enum OutputTechnology {
@@ -905,6 +1849,62 @@ pub enum OutputTechnology {
     Other(i32),
 }
 
+impl clap::ValueEnum for OutputTechnology {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[
+            OutputTechnology::Hd15,
+            OutputTechnology::SVideo,
+            OutputTechnology::CompositeVideo,
+            OutputTechnology::ComponentVideo,
+            OutputTechnology::Dvi,
+            OutputTechnology::Hdmi,
+            OutputTechnology::Lvds,
+            OutputTechnology::Djpn,
+            OutputTechnology::Sdi,
+            OutputTechnology::DisplayPortExternal,
+            OutputTechnology::DisplayPortEmbedded,
+            OutputTechnology::UdiExternal,
+            OutputTechnology::UdiEmbedded,
+            OutputTechnology::SdtvDongle,
+            OutputTechnology::Miracast,
+            OutputTechnology::IndirectWired,
+            OutputTechnology::IndirectVirtual,
+            OutputTechnology::DisplayPortUsbTunnel,
+            OutputTechnology::Internal,
+        ]
+    }
+
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        use clap::builder::PossibleValue;
+        Some(match self {
+            OutputTechnology::Hd15 => PossibleValue::new("hd15").alias("vga"),
+            OutputTechnology::SVideo => PossibleValue::new("svideo"),
+            OutputTechnology::CompositeVideo => PossibleValue::new("composite-video"),
+            OutputTechnology::ComponentVideo => PossibleValue::new("component-video"),
+            OutputTechnology::Dvi => PossibleValue::new("dvi"),
+            OutputTechnology::Hdmi => PossibleValue::new("hdmi"),
+            OutputTechnology::Lvds => PossibleValue::new("lvds"),
+            OutputTechnology::Djpn => PossibleValue::new("djpn"),
+            OutputTechnology::Sdi => PossibleValue::new("sdi"),
+            OutputTechnology::DisplayPortExternal => {
+                PossibleValue::new("displayport-external").alias("dp")
+            }
+            OutputTechnology::DisplayPortEmbedded => {
+                PossibleValue::new("displayport-embedded").alias("edp")
+            }
+            OutputTechnology::UdiExternal => PossibleValue::new("udi-external"),
+            OutputTechnology::UdiEmbedded => PossibleValue::new("udi-embedded"),
+            OutputTechnology::SdtvDongle => PossibleValue::new("sdtv-dongle"),
+            OutputTechnology::Miracast => PossibleValue::new("miracast"),
+            OutputTechnology::IndirectWired => PossibleValue::new("indirect-wired"),
+            OutputTechnology::IndirectVirtual => PossibleValue::new("indirect-virtual"),
+            OutputTechnology::DisplayPortUsbTunnel => PossibleValue::new("displayport-usb-tunnel"),
+            OutputTechnology::Internal => PossibleValue::new("internal"),
+            OutputTechnology::Other(_) => return None,
+        })
+    }
+}
+
 impl From<DISPLAYCONFIG_VIDEO_OUTPUT_TECHNOLOGY> for OutputTechnology {
     fn from(value: DISPLAYCONFIG_VIDEO_OUTPUT_TECHNOLOGY) -> Self {
         OutputTechnology::from(value.0)
@@ -929,7 +1929,7 @@ impl From<OutputTechnology> for i32 {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 pub struct Rational {
     numerator: u32,
     denominator: u32,
@@ -941,6 +1941,31 @@ impl fmt::Debug for Rational {
     }
 }
 
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&format_rational_frequency((*self).into()))
+    }
+}
+
+impl Rational {
+    /// A whole number of hertz
+    pub fn from_hz(hz: u32) -> Self {
+        Self {
+            numerator: hz,
+            denominator: 1,
+        }
+    }
+
+    /// The value of the rational, or `None` if the denominator is zero
+    pub fn as_f64(&self) -> Option<f64> {
+        if self.denominator == 0 {
+            None
+        } else {
+            Some(self.numerator as f64 / self.denominator as f64)
+        }
+    }
+}
+
 impl From<DISPLAYCONFIG_RATIONAL> for Rational {
     fn from(rational: DISPLAYCONFIG_RATIONAL) -> Self {
         Self {
@@ -960,7 +1985,7 @@ impl From<Rational> for DISPLAYCONFIG_RATIONAL {
 }
 
 /// A point or an offset in a two-dimensional space
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 pub struct Region {
     pub x: u32,
     pub y: u32,
@@ -985,7 +2010,7 @@ impl From<Region> for DISPLAYCONFIG_2DREGION {
 }
 
 /// A point in a two-dimensional space
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 pub struct Point {
     pub x: i32,
     pub y: i32,
@@ -1009,8 +2034,41 @@ impl From<Point> for POINTL {
     }
 }
 
+/// A rectangle, from its top-left corner
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Rectangle {
+    pub position: Point,
+    pub size: Region,
+}
+
+impl From<RECTL> for Rectangle {
+    fn from(value: RECTL) -> Self {
+        Self {
+            position: Point {
+                x: value.left,
+                y: value.top,
+            },
+            size: Region {
+                x: (value.right - value.left) as u32,
+                y: (value.bottom - value.top) as u32,
+            },
+        }
+    }
+}
+
+impl From<Rectangle> for RECTL {
+    fn from(value: Rectangle) -> Self {
+        Self {
+            left: value.position.x,
+            top: value.position.y,
+            right: value.position.x + value.size.x as i32,
+            bottom: value.position.y + value.size.y as i32,
+        }
+    }
+}
+
 /// The clockwise rotation of the display.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, UnitEnum, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, UnitEnum, Serialize, Deserialize, JsonSchema)]
 #[serde(from = "i32", into = "i32")]
 #[repr(i32)]
 pub enum DisplayRotation {
@@ -1051,7 +2109,7 @@ impl From<DisplayRotation> for i32 {
 }
 
 // The scaling transformation applied to content displayed on a video present network (VidPN) present path.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, UnitEnum, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, UnitEnum, Serialize, Deserialize, JsonSchema)]
 #[serde(from = "i32", into = "i32")]
 #[repr(i32)]
 pub enum DisplayScaling {
@@ -1095,7 +2153,7 @@ impl From<DisplayScaling> for i32 {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, UnitEnum, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, UnitEnum, Serialize, Deserialize, JsonSchema)]
 #[serde(from = "i32", into = "i32")]
 #[repr(i32)]
 pub enum VideoStandard {
@@ -1193,7 +2251,7 @@ impl From<VideoStandard> for i32 {
 }
 
 /// The method that the display uses to create an image on a screen.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, UnitEnum, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, UnitEnum, Serialize, Deserialize, JsonSchema)]
 #[serde(from = "i32", into = "i32")]
 #[repr(i32)]
 pub enum ScanlineOrdering {
@@ -1246,7 +2304,7 @@ impl From<ScanlineOrdering> for i32 {
 }
 
 /// The pixel format of the display.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, UnitEnum, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, UnitEnum, Serialize, Deserialize, JsonSchema)]
 #[serde(from = "i32", into = "i32")]
 #[repr(i32)]
 pub enum PixelFormat {
@@ -1264,6 +2322,19 @@ pub enum PixelFormat {
     Unknown(i32),
 }
 
+impl PixelFormat {
+    /// The number of bits per pixel of a GDI desktop format
+    pub fn bits_per_pixel(self) -> Option<u32> {
+        match self {
+            PixelFormat::Bpp8 => Some(8),
+            PixelFormat::Bpp16 => Some(16),
+            PixelFormat::Bpp24 => Some(24),
+            PixelFormat::Bpp32 => Some(32),
+            PixelFormat::Nongdi | PixelFormat::Unknown(_) => None,
+        }
+    }
+}
+
 impl From<DISPLAYCONFIG_PIXELFORMAT> for PixelFormat {
     fn from(value: DISPLAYCONFIG_PIXELFORMAT) -> Self {
         PixelFormat::from(value.0)
@@ -1287,3 +2358,143 @@ impl From<PixelFormat> for i32 {
         value.discriminant()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, ffi::OsString};
+
+    use windows::Win32::{
+        Devices::Display::{DISPLAYCONFIG_PATH_INFO, DISPLAYCONFIG_RATIONAL},
+        Foundation::ERROR_FILE_NOT_FOUND,
+        Graphics::Gdi::DISPLAYCONFIG_PATH_SUPPORT_VIRTUAL_MODE,
+    };
+
+    use super::{
+        SupportedMode, WindowsDisplayConfig, advanced_color_enabled_from_bitfield, device_paths_eq,
+        format_rational_frequency, normalize_device_path, supported_refresh_rates,
+        windows_error_to_string,
+    };
+
+    /// The lines printed for the target of `path`
+    fn print_path_target(path: &DISPLAYCONFIG_PATH_INFO) -> Vec<String> {
+        let config = WindowsDisplayConfig {
+            paths: Vec::new(),
+            modes: Vec::new(),
+            adapter_device_names: BTreeMap::new(),
+            source_device_names: BTreeMap::new(),
+            target_device_names: BTreeMap::new(),
+            topology: None,
+        };
+        let mut lines = Vec::new();
+        config.print_path_target(&mut |args| lines.push(args.to_string()), path);
+        lines
+    }
+
+    #[test]
+    fn test_print_path_target_mode_index() {
+        let mut path = DISPLAYCONFIG_PATH_INFO::default();
+        path.sourceInfo.Anonymous.modeInfoIdx = 1;
+        path.targetInfo.Anonymous.modeInfoIdx = 2;
+        let lines = print_path_target(&path);
+        assert!(
+            lines.contains(&"    Mode Info Index: 2".to_string()),
+            "{:?}",
+            lines
+        );
+
+        // Virtual mode paths pack the desktop image and target mode indices into the bitfield
+        path.flags = DISPLAYCONFIG_PATH_SUPPORT_VIRTUAL_MODE;
+        path.sourceInfo.Anonymous.Anonymous._bitfield = 5;
+        path.targetInfo.Anonymous.Anonymous._bitfield = (3 << 16) | 4;
+        let lines = print_path_target(&path);
+        assert!(
+            lines.contains(&"    Target Mode Info Index: 4".to_string()),
+            "{:?}",
+            lines
+        );
+        assert!(
+            lines.contains(&"    Desktop Mode ID: 3".to_string()),
+            "{:?}",
+            lines
+        );
+    }
+
+    #[test]
+    fn test_windows_error_to_string_is_trimmed() {
+        let message = windows_error_to_string(ERROR_FILE_NOT_FOUND);
+        assert!(message.starts_with("0x2 "), "{:?}", message);
+        assert_eq!(message, message.trim_end());
+        assert!(!message.contains('\n'), "{:?}", message);
+    }
+
+    #[test]
+    fn test_device_paths_differing_in_case_match() {
+        let stored = OsString::from(
+            "\\\\?\\PCI#VEN_10DE&DEV_1E84&SUBSYS_450919DA&REV_A1#4&105c9c90&0&0009#{5b45201d-f2f2-4f3b-85bb-30ff1f953599}",
+        );
+        let live = OsString::from(
+            "\\\\?\\pci#ven_10de&dev_1e84&subsys_450919da&rev_a1#4&105C9C90&0&0009#{5B45201D-F2F2-4F3B-85BB-30FF1F953599}",
+        );
+        assert!(device_paths_eq(&stored, &live));
+        assert_eq!(normalize_device_path(&stored), normalize_device_path(&live));
+
+        let other = OsString::from(
+            "\\\\?\\PCI#VEN_10DE&DEV_1E84&SUBSYS_450919DA&REV_A1#4&105c9c90&0&0019#{5b45201d-f2f2-4f3b-85bb-30ff1f953599}",
+        );
+        assert!(!device_paths_eq(&stored, &other));
+        assert_ne!(
+            normalize_device_path(&stored),
+            normalize_device_path(&other)
+        );
+    }
+
+    #[test]
+    fn test_advanced_color_enabled_from_bitfield() {
+        assert_eq!(advanced_color_enabled_from_bitfield(0b0000), None);
+        assert_eq!(advanced_color_enabled_from_bitfield(0b0001), Some(false));
+        assert_eq!(advanced_color_enabled_from_bitfield(0b0011), Some(true));
+        // Wide color enforced and force disabled don't count as enabled
+        assert_eq!(advanced_color_enabled_from_bitfield(0b1101), Some(false));
+    }
+
+    #[test]
+    fn test_format_rational_frequency() {
+        let rational = |numerator, denominator| DISPLAYCONFIG_RATIONAL {
+            Numerator: numerator,
+            Denominator: denominator,
+        };
+        assert_eq!(
+            format_rational_frequency(rational(60000, 1001)),
+            "59.94Hz (60000/1001)"
+        );
+        assert_eq!(format_rational_frequency(rational(60, 1)), "60Hz");
+        assert_eq!(
+            format_rational_frequency(rational(60, 0)),
+            "<undefined> (60/0)"
+        );
+    }
+
+    #[test]
+    fn test_supported_refresh_rates() {
+        let mode = |width, height, refresh_rate| SupportedMode {
+            width,
+            height,
+            bits_per_pixel: 32,
+            refresh_rate,
+        };
+        let modes = [
+            mode(1920, 1080, 60),
+            mode(1920, 1080, 144),
+            mode(1920, 1080, 60),
+            mode(1920, 1080, 1),
+            mode(1280, 720, 75),
+        ];
+        assert_eq!(
+            supported_refresh_rates(&modes, 1920, 1080)
+                .into_iter()
+                .collect::<Vec<_>>(),
+            [60, 144]
+        );
+        assert!(supported_refresh_rates(&modes, 2560, 1440).is_empty());
+    }
+}
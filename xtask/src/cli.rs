@@ -3,7 +3,10 @@ use std::path::PathBuf;
 use anyhow::Result;
 use clap::Parser as _;
 
-use crate::{fs::normalize_path, print::print_cargo_style};
+use crate::{
+    fs::{canonicalize_or_original, normalize_path, strip_verbatim_prefix},
+    print::print_cargo_style,
+};
 
 #[derive(clap::Parser)]
 #[command(author, version, about, long_about = None)]
@@ -23,7 +26,8 @@ enum Commands {
         #[arg(short, long, default_value = "false")]
         debug: bool,
     },
-    /// Run the monitor service
+    /// Run the monitor service, running it again elevated if the arguments need administrator
+    /// rights (e.g. `service register`)
     Run {
         /// Whether to run the release binary
         #[arg(short, long, default_value = "false")]
@@ -37,6 +41,12 @@ enum Commands {
         /// Whether to run the release binary
         #[arg(short, long, default_value = "false")]
         release: bool,
+        /// The directory to watch, instead of the workspace root
+        #[arg(long)]
+        root: Option<PathBuf>,
+        /// Follow symlinks when finding the files to watch
+        #[arg(long)]
+        follow_symlinks: bool,
         /// Arguments to pass to the monitor service
         #[arg(last = true)]
         args: Vec<String>,
@@ -109,9 +119,29 @@ pub fn run() -> Result<i32> {
             crate::command::Command::new_bun_css_build(release).run()?;
 
             // Build & run the monitor service
-            let status = crate::command::Command::new_cargo_run(release, args).run_status()?;
+            let status =
+                crate::command::Command::new_cargo_run(release, args.clone()).run_status()?;
+
+            // Managing the service needs administrator rights, so run it again elevated if it
+            // says it needs them
+            if status.code() == Some(crate::elevate::NEEDS_ELEVATION_EXIT_CODE)
+                && !crate::elevate::is_elevated()?
+            {
+                let exe_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                    .join("..")
+                    .join("target")
+                    .join(if release { "release" } else { "debug" })
+                    .join("hagias.exe");
+                let exe_path = strip_verbatim_prefix(&canonicalize_or_original(&exe_path));
+                return crate::elevate::run_elevated(&exe_path, &args);
+            }
             Ok(status.code().unwrap_or(1))
         }
-        Commands::Watch { release, args } => crate::watch::run(release, args),
+        Commands::Watch {
+            release,
+            root,
+            follow_symlinks,
+            args,
+        } => crate::watch::run(release, root, follow_symlinks, args),
     }
 }
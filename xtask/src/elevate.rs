@@ -0,0 +1,115 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::print::print_cargo_style;
+
+/// The exit code of the monitor service when the command needs administrator rights that it
+/// doesn't have, see `exit_code::NEEDS_ELEVATION` in the `hagias` crate. The monitor service
+/// decides this from its parsed arguments, so `xtask` doesn't have to.
+pub const NEEDS_ELEVATION_EXIT_CODE: i32 = 7;
+
+/// Quote a string as a PowerShell single-quoted literal
+fn powershell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+/// Quote an argument for a Windows command line, so that it's split back into the same argument
+/// (see `CommandLineToArgvW`). Backslashes are only special before a double quote, so those, and
+/// any before the closing quote, are doubled.
+fn windows_quote(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '\n', '\x0b', '"']) {
+        return arg.to_string();
+    }
+    let mut quoted = String::from('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        if c == '\\' {
+            backslashes += 1;
+            continue;
+        }
+        if c == '"' {
+            quoted.extend(std::iter::repeat_n('\\', backslashes * 2 + 1));
+        } else {
+            quoted.extend(std::iter::repeat_n('\\', backslashes));
+        }
+        quoted.push(c);
+        backslashes = 0;
+    }
+    quoted.extend(std::iter::repeat_n('\\', backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+/// Whether the current process is running with administrator rights
+#[cfg(windows)]
+pub fn is_elevated() -> Result<bool> {
+    let status = std::process::Command::new("powershell")
+        .args([
+            "-NoProfile",
+            "-Command",
+            "exit [int]!([Security.Principal.WindowsPrincipal][Security.Principal.WindowsIdentity]::GetCurrent()).IsInRole([Security.Principal.WindowsBuiltInRole]::Administrator)",
+        ])
+        .status()
+        .context("failed to check for administrator rights")?;
+    Ok(status.success())
+}
+
+/// Whether the current process is running with administrator rights
+#[cfg(not(windows))]
+pub fn is_elevated() -> Result<bool> {
+    Ok(true)
+}
+
+/// Run `exe` with administrator rights via the UAC prompt, waiting for it to exit. The elevated
+/// process runs in its own console window, so its output isn't shown here.
+pub fn run_elevated(exe: &Path, args: &[String]) -> Result<i32> {
+    let current_dir = std::env::current_dir().context("failed to get current directory")?;
+    let command_line = args
+        .iter()
+        .map(|arg| windows_quote(arg))
+        .collect::<Vec<_>>()
+        .join(" ");
+    print_cargo_style("Elevating", format!("`{}` {}", exe.display(), command_line));
+    let mut script = format!(
+        "exit (Start-Process -FilePath {} -WorkingDirectory {} -Verb RunAs -Wait -PassThru",
+        powershell_quote(&exe.display().to_string()),
+        powershell_quote(&current_dir.display().to_string()),
+    );
+    if !args.is_empty() {
+        // A single string is passed to the process as its command line, as is
+        script.push_str(&format!(
+            " -ArgumentList {}",
+            powershell_quote(&command_line)
+        ));
+    }
+    script.push_str(").ExitCode");
+    let status = std::process::Command::new("powershell")
+        .args(["-NoProfile", "-Command", &script])
+        .status()
+        .context("failed to run elevated command")?;
+    Ok(status.code().unwrap_or(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{powershell_quote, windows_quote};
+
+    #[test]
+    fn test_windows_quote() {
+        assert_eq!(windows_quote("register"), "register");
+        assert_eq!(windows_quote(r"C:\path\file"), r"C:\path\file");
+        assert_eq!(windows_quote(""), r#""""#);
+        assert_eq!(windows_quote("two words"), r#""two words""#);
+        assert_eq!(windows_quote(r#"say "hi""#), r#""say \"hi\"""#);
+        // Backslashes are only doubled before a quote, including the closing one
+        assert_eq!(windows_quote(r"C:\My Dir\"), r#""C:\My Dir\\""#);
+        assert_eq!(windows_quote(r#"a\"b c"#), r#""a\\\"b c""#);
+    }
+
+    #[test]
+    fn test_powershell_quote() {
+        assert_eq!(powershell_quote("plain"), "'plain'");
+        assert_eq!(powershell_quote("it's"), "'it''s'");
+    }
+}
@@ -8,19 +8,40 @@ pub fn canonicalize_or_original(path: &Path) -> PathBuf {
     std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
 }
 
+/// Strip the Windows verbatim prefix from a path, e.g. `\\?\C:\foo` becomes `C:\foo` and
+/// `\\?\UNC\server\share` becomes `\\server\share`. `canonicalize` returns verbatim paths on
+/// Windows, which don't compare equal to their ordinary forms.
+pub fn strip_verbatim_prefix(path: &Path) -> PathBuf {
+    let Some(s) = path.to_str() else {
+        return path.to_path_buf();
+    };
+    if let Some(rest) = s.strip_prefix(r"\\?\UNC\") {
+        PathBuf::from(format!(r"\\{}", rest))
+    } else if let Some(rest) = s.strip_prefix(r"\\?\") {
+        PathBuf::from(rest)
+    } else {
+        path.to_path_buf()
+    }
+}
+
 /// Normalize a path to be relative to the current directory, to make it easier to read
 pub fn normalize_path(path: &Path) -> PathBuf {
     let current_dir = match std::env::current_dir() {
         Ok(dir) => canonicalize_or_original(&dir),
-        Err(_) => return path.to_path_buf(),
+        Err(_) => return strip_verbatim_prefix(path),
     };
+    normalize_path_in(path, &canonicalize_or_original(path), &current_dir)
+}
 
-    let canonical_path = canonicalize_or_original(path);
+/// Normalize `path`, whose canonical form is `canonical_path`, to be relative to `current_dir`
+fn normalize_path_in(path: &Path, canonical_path: &Path, current_dir: &Path) -> PathBuf {
+    let current_dir = strip_verbatim_prefix(current_dir);
+    let canonical_path = strip_verbatim_prefix(canonical_path);
 
     if let Ok(relative) = canonical_path.strip_prefix(&current_dir) {
         return relative.to_path_buf();
     }
-    if let Ok(relative) = path.strip_prefix(&current_dir) {
+    if let Ok(relative) = strip_verbatim_prefix(path).strip_prefix(&current_dir) {
         return relative.to_path_buf();
     }
     canonical_path
@@ -86,3 +107,82 @@ pub fn copy_dir_silent(src: &Path, dest: &Path) -> Result<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{normalize_path_in, strip_verbatim_prefix};
+
+    #[test]
+    fn test_strip_verbatim_prefix() {
+        let strip = |s: &str| strip_verbatim_prefix(Path::new(s));
+        assert_eq!(strip(r"\\?\C:\dev\hagias"), PathBuf::from(r"C:\dev\hagias"));
+        assert_eq!(
+            strip(r"\\?\UNC\server\share\hagias"),
+            PathBuf::from(r"\\server\share\hagias")
+        );
+        assert_eq!(strip(r"C:\dev\hagias"), PathBuf::from(r"C:\dev\hagias"));
+        assert_eq!(strip("target/debug"), PathBuf::from("target/debug"));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_normalize_path_verbatim() {
+        let normalize = |path: &str, canonical: &str, current_dir: &str| {
+            normalize_path_in(
+                Path::new(path),
+                Path::new(canonical),
+                Path::new(current_dir),
+            )
+        };
+        let expected = PathBuf::from(r"target\debug\hagias.exe");
+        // Verbatim canonical path, ordinary current directory
+        assert_eq!(
+            normalize(
+                r"C:\dev\hagias\target\debug\hagias.exe",
+                r"\\?\C:\dev\hagias\target\debug\hagias.exe",
+                r"C:\dev\hagias",
+            ),
+            expected
+        );
+        // Ordinary path, verbatim current directory
+        assert_eq!(
+            normalize(
+                r"C:\dev\hagias\target\debug\hagias.exe",
+                r"C:\dev\hagias\target\debug\hagias.exe",
+                r"\\?\C:\dev\hagias",
+            ),
+            expected
+        );
+        // Paths outside the current directory are shown without the prefix
+        assert_eq!(
+            normalize(r"D:\other", r"\\?\D:\other", r"\\?\C:\dev\hagias"),
+            PathBuf::from(r"D:\other")
+        );
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn test_normalize_path() {
+        let normalize = |path: &str, canonical: &str, current_dir: &str| {
+            normalize_path_in(
+                Path::new(path),
+                Path::new(canonical),
+                Path::new(current_dir),
+            )
+        };
+        assert_eq!(
+            normalize(
+                "../hagias/target/debug/hagias",
+                "/dev/hagias/target/debug/hagias",
+                "/dev/hagias",
+            ),
+            PathBuf::from("target/debug/hagias")
+        );
+        assert_eq!(
+            normalize("/other", "/other", "/dev/hagias"),
+            PathBuf::from("/other")
+        );
+    }
+}
@@ -2,15 +2,19 @@ use anyhow::{Context, Error, Result};
 use ignore::{DirEntry, WalkBuilder, WalkState};
 use std::{
     collections::HashSet,
+    io::ErrorKind,
     path::{Path, PathBuf},
     sync::mpsc::{Sender, channel},
 };
 
+use crate::print::print_warning;
+
 #[derive(Debug)]
 struct GatherResult {
     pub files: HashSet<PathBuf>,
     pub directories: HashSet<PathBuf>,
     pub errors: Vec<Error>,
+    pub skipped: Vec<ignore::Error>,
 }
 
 struct FileGathererBuilder {
@@ -24,6 +28,7 @@ impl<'s> ignore::ParallelVisitorBuilder<'s> for FileGathererBuilder {
             files: HashSet::new(),
             directories: HashSet::new(),
             errors: Vec::new(),
+            skipped: Vec::new(),
         })
     }
 }
@@ -33,6 +38,7 @@ struct FileGatherer {
     files: HashSet<PathBuf>,
     directories: HashSet<PathBuf>,
     errors: Vec<Error>,
+    skipped: Vec<ignore::Error>,
 }
 
 // Send results when the visitor is dropped
@@ -42,6 +48,7 @@ impl Drop for FileGatherer {
             files: std::mem::take(&mut self.files),
             directories: std::mem::take(&mut self.directories),
             errors: std::mem::take(&mut self.errors),
+            skipped: std::mem::take(&mut self.skipped),
         });
     }
 }
@@ -58,6 +65,9 @@ impl ignore::ParallelVisitor for FileGatherer {
                     }
                 }
             }
+            Err(err) if is_skippable(&err) => {
+                self.skipped.push(err);
+            }
             Err(err) => {
                 self.errors.push(anyhow::anyhow!(err));
             }
@@ -66,14 +76,48 @@ impl ignore::ParallelVisitor for FileGatherer {
     }
 }
 
+/// Whether a walk error is caused by an entry that can't be followed, e.g. a broken symlink, a
+/// symlink loop, or a transient file deleted during the walk, rather than a problem with the walk
+/// itself
+fn is_skippable(err: &ignore::Error) -> bool {
+    match err {
+        ignore::Error::WithPath { err, .. }
+        | ignore::Error::WithDepth { err, .. }
+        | ignore::Error::WithLineNumber { err, .. } => is_skippable(err),
+        ignore::Error::Loop { .. } => true,
+        ignore::Error::Io(err) => err.kind() == ErrorKind::NotFound,
+        _ => false,
+    }
+}
+
+/// Canonicalize paths, skipping with a warning any that can't be, e.g. broken symlinks or
+/// transient files that were deleted after being found
+fn canonicalize_all(paths: HashSet<PathBuf>) -> HashSet<PathBuf> {
+    paths
+        .into_iter()
+        .filter_map(|path| match path.canonicalize() {
+            Ok(path) => Some(path),
+            Err(err) => {
+                print_warning(format!("skipping `{}`: {}", path.display(), err));
+                None
+            }
+        })
+        .collect()
+}
+
+/// Get the files and directories under `root` that aren't ignored, canonicalized. Symlinks are
+/// only followed if `follow_symlinks` is set. Entries that can't be read or canonicalized are
+/// skipped with a warning.
 pub fn get_unignored_files_and_directories(
     root: &Path,
+    follow_symlinks: bool,
 ) -> Result<(HashSet<PathBuf>, HashSet<PathBuf>)> {
     // Create channel for collecting results
     let (result_tx, result_rx) = channel();
 
     // Create the walker and visit all entries
     WalkBuilder::new(root)
+        .follow_links(follow_symlinks)
         .build_parallel()
         .visit(&mut FileGathererBuilder {
             result_tx: result_tx.clone(),
@@ -91,6 +135,9 @@ pub fn get_unignored_files_and_directories(
         all_files.extend(result.files);
         all_directories.extend(result.directories);
         all_errors.extend(result.errors);
+        for err in result.skipped {
+            print_warning(format!("skipping entry: {}", err));
+        }
     }
 
     if !all_errors.is_empty() {
@@ -106,20 +153,42 @@ pub fn get_unignored_files_and_directories(
         return result;
     }
 
-    let all_files = all_files
-        .iter()
-        .map(|f| {
-            f.canonicalize()
-                .with_context(|| format!("failed to canonicalize path: {}", f.display()))
-        })
-        .collect::<Result<HashSet<_>>>()?;
-    let all_directories = all_directories
-        .iter()
-        .map(|d| {
-            d.canonicalize()
-                .with_context(|| format!("failed to canonicalize path: {}", d.display()))
-        })
-        .collect::<Result<HashSet<_>>>()?;
+    Ok((
+        canonicalize_all(all_files),
+        canonicalize_all(all_directories),
+    ))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{fs, os::unix::fs::symlink};
 
-    Ok((all_files, all_directories))
+    use super::get_unignored_files_and_directories;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_broken_symlinks_are_skipped() {
+        let temp_dir = TempDir::new("ignore");
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("linked")).unwrap();
+        fs::write(root.join("linked").join("file.txt"), "").unwrap();
+        symlink(root.join("missing"), root.join("broken")).unwrap();
+        symlink(root.join("linked"), root.join("link")).unwrap();
+        let root = root.canonicalize().unwrap();
+
+        let (files, _) = get_unignored_files_and_directories(&root, false).unwrap();
+        assert_eq!(
+            files,
+            [root.join("linked").join("file.txt")].into_iter().collect()
+        );
+
+        // Following symlinks finds the file through the link too, which canonicalizes to the same
+        // path, and skips the broken link
+        let (files, directories) = get_unignored_files_and_directories(&root, true).unwrap();
+        assert_eq!(
+            files,
+            [root.join("linked").join("file.txt")].into_iter().collect()
+        );
+        assert!(directories.contains(&root.join("linked")));
+    }
 }
@@ -2,9 +2,13 @@ use anyhow::Result;
 
 pub mod cli;
 pub mod command;
+pub mod elevate;
 pub mod fs;
 pub mod ignore;
 pub mod print;
+// Only the symlink tests need these so far, which are Unix-only
+#[cfg(all(test, unix))]
+pub(crate) mod test_util;
 pub mod watch;
 
 fn main() -> Result<()> {
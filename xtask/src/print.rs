@@ -1,9 +1,15 @@
 use std::fmt::Display;
 
 const GREEN_BOLD: &str = "\x1b[1;32m";
+const YELLOW_BOLD: &str = "\x1b[1;33m";
 const RESET: &str = "\x1b[0m";
 
 /// Print a message in cargo style
 pub fn print_cargo_style(action: impl Display, message: impl Display) {
     println!("{}{:>12} {}{}", GREEN_BOLD, action, RESET, message);
 }
+
+/// Print a warning in cargo style
+pub fn print_warning(message: impl Display) {
+    eprintln!("{}warning{}: {}", YELLOW_BOLD, RESET, message);
+}
@@ -0,0 +1,30 @@
+//! Helpers shared by the tests of several modules
+
+use std::path::{Path, PathBuf};
+
+/// A directory for a test's files, removed when dropped, even if the test fails
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Create an empty directory, unique to `name` and this process
+    pub(crate) fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("xtask-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub(crate) fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.0.join(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
@@ -1,13 +1,26 @@
 use std::{
     collections::HashSet,
     path::PathBuf,
-    sync::{Arc, Mutex},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
 };
 
 use anyhow::{Context as _, Result};
-use watchexec::Watchexec;
+use watchexec::{
+    Id, Watchexec,
+    command::{Command, Program, SpawnOptions},
+};
+use watchexec_events::Priority;
 use watchexec_signals::Signal;
 
+use crate::{
+    fs::canonicalize_or_original,
+    print::{print_cargo_style, print_warning},
+};
+
 #[derive(Debug)]
 pub struct PathChangedFilterer;
 
@@ -56,16 +69,49 @@ impl watchexec::filter::Filterer for PathChangedFilterer {
     }
 }
 
-pub fn run(_release: bool, _args: Vec<String>) -> Result<i32> {
-    let workspace_root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("..");
-    let (files, directories) = crate::ignore::get_unignored_files_and_directories(&workspace_root)?;
+/// How long to wait for file changes to settle before rebuilding, so that many changes at once
+/// (e.g. `cargo fmt` touching many files) cause a single rebuild
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// The build output that the pipeline writes into the workspace, which mustn't trigger a rebuild
+const GENERATED_FILES: &[&str] = &["static/css/output.css"];
+
+pub fn run(
+    release: bool,
+    root: Option<PathBuf>,
+    follow_symlinks: bool,
+    args: Vec<String>,
+) -> Result<i32> {
+    let workspace_root =
+        root.unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(".."));
+    let (mut files, directories) =
+        crate::ignore::get_unignored_files_and_directories(&workspace_root, follow_symlinks)?;
+    for file in GENERATED_FILES {
+        files.remove(&canonicalize_or_original(&workspace_root.join(file)));
+    }
+
+    // A single job builds and runs the service with `xtask run`, so restarting it kills the whole
+    // previous pipeline, whether it's still building or already running
+    let pipeline = Arc::new(pipeline_command(release, &args)?);
+    let pipeline_id = Id::default();
 
     let rt = tokio::runtime::Runtime::new().context("failed to create tokio runtime")?;
     rt.block_on(async {
         let error = Arc::new(Mutex::new(None::<anyhow::Error>));
         let error_clone = error.clone();
-        // let jobs_list =
+        let cycle = Arc::new(AtomicUsize::new(0));
         let wx = Watchexec::new(move |mut action| {
+            // If Ctrl-C is received, quit, killing the pipeline
+            if action.signals().any(|sig| sig == Signal::Interrupt) {
+                print_cargo_style("Stopping", "watch");
+                action.quit();
+                return action;
+            }
+
+            for end in action.completions().flatten() {
+                print_cargo_style("Exited", format!("{:?}", end));
+            }
+
             // Get the files that changed, If they are not ignored in the .gitignore, then rebuild everything
             let changed_directories = set_global_error_return(
                 error_clone.clone(),
@@ -83,46 +129,38 @@ pub fn run(_release: bool, _args: Vec<String>) -> Result<i32> {
                 ),
             )
             .unwrap_or_else(|| HashSet::new());
+            if let Some(error) = error_clone
+                .lock()
+                .expect("failed to lock global error")
+                .take()
+            {
+                print_warning(format!("{:#}", error));
+            }
 
             let have_any_unignored_paths_changed =
                 have_any_unignored_paths_changed(&files, &changed_files)
                     || have_any_unignored_paths_changed(&directories, &changed_directories);
+            // The initial event sent below has no tags
+            let is_initial = action.events.iter().any(|event| event.tags.is_empty());
 
-            if have_any_unignored_paths_changed {
-                // Kill all running builds
-                // TODO
-
-                // Create running builds again
-                // action.create_job(command);
-
-                // // Build the CSS
-                // build_css(release)?;
-
-                // // Build the binary
-                // let cargo_path = env!("CARGO");
-                // let args = if release {
-                //     vec!["build", "--release"]
-                // } else {
-                //     vec!["build"]
-                // };
-                // run_command(cargo_path, args)?;
-
-                // // Start service
-                // let mut service_path = workspace_root.join("target");
-                // if release {
-                //     service_path = service_path.join("release");
-                // } else {
-                //     service_path = service_path.join("debug");
-                // }
-                // service_path = service_path.join("hagias.exe");
-                // let mut child =
-                //     run_command_background::<&Path, &str, [&str; 0]>(&service_path, [])?;
+            if is_initial || have_any_unignored_paths_changed {
+                let cycle = cycle.fetch_add(1, Ordering::SeqCst);
+                if cycle == 0 {
+                    print_cargo_style("Starting", "build and run");
+                } else {
+                    print_cargo_style(
+                        "Rebuilding",
+                        format!(
+                            "after {} changed path(s) (rebuild #{})",
+                            changed_files.len() + changed_directories.len(),
+                            cycle
+                        ),
+                    );
+                }
+                let job = action.get_or_create_job(pipeline_id, || pipeline.clone());
+                job.restart();
             }
 
-            // If Ctrl-C is received, quit
-            if action.signals().any(|sig| sig == Signal::Interrupt) {
-                action.quit();
-            }
             action
         })
         .context("failed to create watchexec")?;
@@ -130,12 +168,18 @@ pub fn run(_release: bool, _args: Vec<String>) -> Result<i32> {
         // Set the filterer
         wx.config.filterer(crate::watch::PathChangedFilterer);
 
+        // Coalesce rapid changes into one rebuild
+        wx.config.throttle(DEBOUNCE);
+
         // Watch the current directory
         wx.config.pathset([workspace_root]);
 
-        // Run watchexec
-        wx.main()
+        // Run watchexec, starting the first build straight away
+        let main = wx.main();
+        wx.send_event(watchexec_events::Event::default(), Priority::Urgent)
             .await
+            .context("failed to start the first build")?;
+        main.await
             .context("failed to join watchexec")?
             .context("failed to run watchexec")?;
 
@@ -143,6 +187,28 @@ pub fn run(_release: bool, _args: Vec<String>) -> Result<i32> {
     })
 }
 
+/// The command that builds and runs the service, as `xtask run` does
+fn pipeline_command(release: bool, args: &[String]) -> Result<Command> {
+    let xtask_path = std::env::current_exe().context("failed to get the xtask path")?;
+    let mut xtask_args = vec!["run".to_string()];
+    if release {
+        xtask_args.push("--release".into());
+    }
+    xtask_args.push("--".into());
+    xtask_args.extend(args.iter().cloned());
+    Ok(Command {
+        program: Program::Exec {
+            prog: xtask_path,
+            args: xtask_args,
+        },
+        options: SpawnOptions {
+            // Kill cargo and the service along with the pipeline
+            grouped: true,
+            ..Default::default()
+        },
+    })
+}
+
 fn have_any_unignored_paths_changed(
     unignored_paths: &HashSet<PathBuf>,
     changed_paths: &HashSet<PathBuf>,
@@ -198,3 +264,26 @@ fn get_changed_paths_from_action(
         .collect::<Result<HashSet<_>>>()
         .context("failed to get changed paths")
 }
+
+#[cfg(test)]
+mod tests {
+    use watchexec::command::Program;
+
+    use super::pipeline_command;
+
+    #[test]
+    fn test_pipeline_command() {
+        let command = pipeline_command(true, &["layout".into(), "list".into()]).unwrap();
+        let Program::Exec { args, .. } = &command.program else {
+            panic!("expected an exec program");
+        };
+        assert_eq!(args, &["run", "--release", "--", "layout", "list"]);
+        assert!(command.options.grouped);
+
+        let command = pipeline_command(false, &[]).unwrap();
+        let Program::Exec { args, .. } = &command.program else {
+            panic!("expected an exec program");
+        };
+        assert_eq!(args, &["run", "--"]);
+    }
+}